@@ -44,10 +44,12 @@ async fn main() -> anyhow::Result<()> {
                 println!("  Segments: {}", health.segment_count);
                 println!("  Total size: {} MB", health.total_size_bytes / (1024 * 1024));
                 
-                if let Some(_last_cleanup) = health.last_cleanup {
-                    println!("  Last cleanup: Recently"); // Feature not fully implemented yet
-                } else {
-                    println!("  Last cleanup: Never");
+                match health.cleanup_history.last() {
+                    Some(run) => println!(
+                        "  Last cleanup: removed {} segment(s), freed {} bytes ({}), low watermark now {}",
+                        run.segments_removed, run.bytes_freed, run.reason, run.low_watermark
+                    ),
+                    None => println!("  Last cleanup: Never"),
                 }
             }
             Err(e) => println!("Error getting partition health: {}", e),
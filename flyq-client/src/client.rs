@@ -1,11 +1,17 @@
 use anyhow::Context;
 use bytes::{Bytes, BytesMut};
 use flyq_protocol::{
-    CommitOffsetRequest, ConsumerLagRequest, ConsumerLagResponse, ConsumeRequest, ConsumeResponse,
-    ConsumeWithGroupRequest, Frame, FrameType, Message, OpCode, PartitionHealthRequest,
-    PartitionHealthResponse, ProduceAck, ProduceRequest, ProtocolError, RequestPayload,
-    ResponsePayload, WatermarkRequest, WatermarkResponse,
+    Acks, AddPartitionsToTxnRequest, CommitOffsetRequest, Compression, ConsumeBatchResponse, ConsumerLagRequest, ConsumerLagResponse,
+    ConsumeRequest, ConsumeResponse, ConsumeWithGroupRequest, DeleteRecordsRequest, DeleteRecordsResponse, FetchRequest, FetchResponse, Frame,
+    FrameType,
+    HeaderQueryRequest, InitProducerIdRequest, InitProducerIdResponse, LeaseRecordsRequest, LeaseRecordsResponse,
+    LeasedRecord, Message, OpCode, PartitionHealthRequest,
+    PartitionHealthResponse, ProduceAck, ProduceBatchRequest, ProduceRequest, ProducerSequence,
+    ProtocolError, RecordBatch, RequestPayload, ResponsePayload, RetentionDryRunRequest,
+    RetentionDryRunResponse, SettleRecordsRequest, SettleRecordsResponse, TransactionRequest, TransactionResponse, TransactionStatus,
+    TxnOffsetCommitRequest, WatermarkRequest, WatermarkResponse,
 };
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 
@@ -60,16 +66,195 @@ impl FlyqClient {
         topic: &str,
         payload: &[u8],
     ) -> Result<ProduceAck, ProtocolError> {
+        let ack = self.send_produce(Self::produce_request(topic, payload)).await?;
+        Ok(ack.expect("acks=leader is always answered"))
+    }
+
+    /// Produce that waits for `acks` instead of the default leader write.
+    /// `Acks::None` returns `None` right after sending; otherwise the broker
+    /// answers within `timeout` (zero = the broker's default), with
+    /// `ProduceStatus::Timeout` if the guarantee was not reached in time.
+    pub async fn produce_with_acks(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        acks: Acks,
+        timeout: Duration,
+    ) -> Result<Option<ProduceAck>, ProtocolError> {
         let req = ProduceRequest {
+            acks,
+            timeout_ms: timeout.as_millis().min(u32::MAX as u128) as u32,
+            ..Self::produce_request(topic, payload)
+        };
+        self.send_produce(req).await
+    }
+
+    /// Produce that stays invisible to consumers until `deliver_at` (Unix
+    /// millis). Acked with `ProduceStatus::Scheduled` and the partition; the
+    /// record gets its offset once delivered.
+    pub async fn produce_delayed(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        deliver_at: u64,
+    ) -> Result<ProduceAck, ProtocolError> {
+        let req = ProduceRequest { deliver_at: Some(deliver_at), ..Self::produce_request(topic, payload) };
+        let ack = self.send_produce(req).await?;
+        Ok(ack.expect("acks=leader is always answered"))
+    }
+
+    /// Produce of a record that expires at `expires_at` (Unix millis, broker
+    /// clock): from then on no fetch returns it.
+    pub async fn produce_expiring(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        expires_at: u64,
+    ) -> Result<ProduceAck, ProtocolError> {
+        let req = ProduceRequest { expires_at: Some(expires_at), ..Self::produce_request(topic, payload) };
+        let ack = self.send_produce(req).await?;
+        Ok(ack.expect("acks=leader is always answered"))
+    }
+
+    /// Produce of a record carrying `headers`, which topics can index for
+    /// [`Self::query_by_header`]. Names starting with `flyq-` are the
+    /// broker's; the produce is refused if one is among them.
+    pub async fn produce_with_headers(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        headers: Vec<(String, Vec<u8>)>,
+    ) -> Result<ProduceAck, ProtocolError> {
+        let req = ProduceRequest { headers: Some(headers), ..Self::produce_request(topic, payload) };
+        let ack = self.send_produce(req).await?;
+        Ok(ack.expect("acks=leader is always answered"))
+    }
+
+    /// A producer id for [`Self::produce_idempotent`]; get one per producer
+    /// instance.
+    pub async fn init_producer_id(&mut self) -> Result<InitProducerIdResponse, ProtocolError> {
+        self.send_init_producer_id(InitProducerIdRequest { transactional_id: None }).await
+    }
+
+    /// The producer id of `transactional_id` at a new epoch. Fences off any
+    /// earlier instance using the same transactional id and aborts its open
+    /// transaction.
+    pub async fn init_transactional_producer(
+        &mut self,
+        transactional_id: &str,
+    ) -> Result<InitProducerIdResponse, ProtocolError> {
+        let req = InitProducerIdRequest { transactional_id: Some(transactional_id.to_string()) };
+        self.send_init_producer_id(req).await
+    }
+
+    async fn send_init_producer_id(
+        &mut self,
+        req: InitProducerIdRequest,
+    ) -> Result<InitProducerIdResponse, ProtocolError> {
+        let payload = RequestPayload {
+            op_code: OpCode::InitProducerId,
+            data: req.serialize(),
+        };
+
+        self.send_request(payload).await?;
+
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+
+        if resp_payload.op_code != OpCode::InitProducerId {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+
+        InitProducerIdResponse::deserialize(resp_payload.data)
+    }
+
+    /// Produces to `producer.partition` at most once per sequence: resending
+    /// after a timeout with the same sequence is safe and returns an ack with
+    /// [`ProduceStatus::Duplicate`](flyq_protocol::ProduceStatus::Duplicate)
+    /// and the original offset if the first attempt got through. Bump the
+    /// sequence only after an ack.
+    pub async fn produce_idempotent(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        producer: ProducerSequence,
+    ) -> Result<ProduceAck, ProtocolError> {
+        let req = ProduceRequest { producer: Some(producer), ..Self::produce_request(topic, payload) };
+        let ack = self.send_produce(req).await?;
+        Ok(ack.expect("acks=leader is always answered"))
+    }
+
+    pub async fn begin_transaction(&mut self, txn: &TransactionRequest) -> Result<TransactionStatus, ProtocolError> {
+        self.send_transaction(OpCode::BeginTransaction, txn.serialize()).await
+    }
+
+    /// Must be called for every partition before producing to it with
+    /// `transactional` set.
+    pub async fn add_partitions_to_transaction(
+        &mut self,
+        req: &AddPartitionsToTxnRequest,
+    ) -> Result<TransactionStatus, ProtocolError> {
+        self.send_transaction(OpCode::AddPartitionsToTxn, req.serialize()).await
+    }
+
+    /// Consumer group offsets that are committed together with the open
+    /// transaction, for consume-transform-produce loops: on abort they are
+    /// dropped and the group resumes from its previous position.
+    pub async fn commit_offsets_in_transaction(
+        &mut self,
+        req: &TxnOffsetCommitRequest,
+    ) -> Result<TransactionStatus, ProtocolError> {
+        self.send_transaction(OpCode::TxnOffsetCommit, req.serialize()).await
+    }
+
+    /// Makes every record of the open transaction visible to `read_committed`
+    /// consumers, on all its partitions at once.
+    pub async fn commit_transaction(&mut self, txn: &TransactionRequest) -> Result<TransactionStatus, ProtocolError> {
+        self.send_transaction(OpCode::CommitTransaction, txn.serialize()).await
+    }
+
+    pub async fn abort_transaction(&mut self, txn: &TransactionRequest) -> Result<TransactionStatus, ProtocolError> {
+        self.send_transaction(OpCode::AbortTransaction, txn.serialize()).await
+    }
+
+    async fn send_transaction(&mut self, op_code: OpCode, data: Bytes) -> Result<TransactionStatus, ProtocolError> {
+        self.send_request(RequestPayload { op_code, data }).await?;
+
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+
+        if resp_payload.op_code != op_code {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+
+        Ok(TransactionResponse::deserialize(resp_payload.data)?.status)
+    }
+
+    /// A plain produce with the broker's defaults, for the variants to adjust.
+    fn produce_request(topic: &str, payload: &[u8]) -> ProduceRequest {
+        ProduceRequest {
             topic: topic.to_string(),
             message: Bytes::copy_from_slice(payload),
-        };
+            producer: None,
+            acks: Acks::Leader,
+            timeout_ms: 0,
+            deliver_at: None,
+            expires_at: None,
+            headers: None,
+        }
+    }
+
+    async fn send_produce(&mut self, req: ProduceRequest) -> Result<Option<ProduceAck>, ProtocolError> {
+        let acks = req.acks;
         let payload = RequestPayload {
             op_code: OpCode::Produce,
             data: req.serialize(),
         };
 
         self.send_request(payload).await?;
+        if acks == Acks::None {
+            return Ok(None);
+        }
 
         let response = self.read_response().await?;
         let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
@@ -79,7 +264,160 @@ impl FlyqClient {
         }
 
         let ack = ProduceAck::deserialize(resp_payload.data)?;
-        Ok(ack)
+        Ok(Some(ack))
+    }
+
+    /// Produces `messages` to `partition` as one record batch compressed with
+    /// `compression`, which the broker writes and serves without re-encoding
+    /// it unless the topic is set to another codec. The ack carries the
+    /// offset of the first record; the others follow it. Batches are neither
+    /// idempotent nor transactional, and the broker refuses records due for
+    /// delivery later: use [`Self::produce_idempotent`] and
+    /// [`Self::produce_delayed`] for those.
+    pub async fn produce_batch(
+        &mut self,
+        topic: &str,
+        partition: u32,
+        messages: &[Message],
+        compression: Compression,
+        acks: Acks,
+    ) -> Result<Option<ProduceAck>, ProtocolError> {
+        if messages.is_empty() {
+            return Err(ProtocolError::PayloadError("A record batch needs at least one record".into()));
+        }
+        let req = ProduceBatchRequest {
+            topic: topic.to_string(),
+            partition,
+            acks,
+            timeout_ms: 0,
+            batch: RecordBatch::encode(0, messages).with_compression(compression)?,
+            producer: None,
+        };
+        let payload = RequestPayload {
+            op_code: OpCode::ProduceBatch,
+            data: req.serialize(),
+        };
+
+        self.send_request(payload).await?;
+        if acks == Acks::None {
+            return Ok(None);
+        }
+
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+
+        if resp_payload.op_code != OpCode::ProduceBatch {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+
+        Ok(Some(ProduceAck::deserialize(resp_payload.data)?))
+    }
+
+    /// Records from `offset` on, read as record batches until the response
+    /// reaches about `max_bytes`, along with the partition's log end offset.
+    /// Records below `offset` and expired ones, which batches from the log
+    /// may hold, are left out.
+    pub async fn fetch(
+        &mut self,
+        topic: &str,
+        partition: u32,
+        offset: u64,
+        max_bytes: u32,
+        read_committed: bool,
+    ) -> Result<(Vec<ConsumeResponse>, u64), ProtocolError> {
+        let req = FetchRequest {
+            topic: topic.to_string(),
+            partition,
+            offset,
+            max_bytes,
+            read_committed,
+        };
+        let payload = RequestPayload {
+            op_code: OpCode::Fetch,
+            data: req.serialize(),
+        };
+
+        self.send_request(payload).await?;
+
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+
+        if resp_payload.op_code != OpCode::Fetch {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+
+        let resp = FetchResponse::deserialize(resp_payload.data)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        let mut records = Vec::new();
+        for batch in resp.record_batches()? {
+            for (record_offset, message) in batch.records()? {
+                if record_offset >= offset && !message.is_expired(now) {
+                    records.push(ConsumeResponse { offset: record_offset, message });
+                }
+            }
+        }
+        Ok((records, resp.log_end_offset))
+    }
+
+    /// Queue mode: leases up to `max_records` records of the partition to
+    /// this member of `group`. Other members of the group never get the same
+    /// record at the same time; unless acked within `visibility_timeout` a
+    /// record is leased again, with a higher delivery count.
+    pub async fn lease_records(
+        &mut self,
+        group: &str,
+        topic: &str,
+        partition: u32,
+        max_records: u32,
+        visibility_timeout: Duration,
+    ) -> Result<Vec<LeasedRecord>, ProtocolError> {
+        let req = LeaseRecordsRequest {
+            group: group.to_string(),
+            topic: topic.to_string(),
+            partition,
+            max_records,
+            visibility_timeout_ms: visibility_timeout.as_millis().min(u32::MAX as u128) as u32,
+        };
+        self.send_request(RequestPayload { op_code: OpCode::LeaseRecords, data: req.serialize() }).await?;
+
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+
+        if resp_payload.op_code != OpCode::LeaseRecords {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+
+        Ok(LeaseRecordsResponse::deserialize(resp_payload.data)?.records)
+    }
+
+    /// Marks leased records done; `records` are (offset, delivery count) as
+    /// leased.
+    pub async fn ack_records(&mut self, req: &SettleRecordsRequest) -> Result<SettleRecordsResponse, ProtocolError> {
+        self.send_settle(OpCode::AckRecords, req).await
+    }
+
+    /// Hands leased records back to the group for immediate redelivery, with
+    /// `req.error` as the reason. Records that used up the group's delivery
+    /// attempts go to its dead-letter topic instead.
+    pub async fn nack_records(&mut self, req: &SettleRecordsRequest) -> Result<SettleRecordsResponse, ProtocolError> {
+        self.send_settle(OpCode::NackRecords, req).await
+    }
+
+    async fn send_settle(
+        &mut self,
+        op_code: OpCode,
+        req: &SettleRecordsRequest,
+    ) -> Result<SettleRecordsResponse, ProtocolError> {
+        self.send_request(RequestPayload { op_code, data: req.serialize() }).await?;
+
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+
+        if resp_payload.op_code != op_code {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+
+        SettleRecordsResponse::deserialize(resp_payload.data)
     }
 
     pub async fn consume(
@@ -91,8 +429,32 @@ impl FlyqClient {
             topic: topic.to_string(),
             partition: 0, // Hardcoded for now
             offset,
+            fetch_last: None,
+            read_committed: false,
+        };
+        self.send_consume(req).await
+    }
+
+    /// The first committed record at or after `offset`: nothing from aborted
+    /// or still open transactions. The response carries the record's own
+    /// offset, continue from the one after it.
+    pub async fn consume_committed(
+        &mut self,
+        topic: &str,
+        partition: u32,
+        offset: u64,
+    ) -> Result<Option<ConsumeResponse>, ProtocolError> {
+        let req = ConsumeRequest {
+            topic: topic.to_string(),
+            partition,
+            offset,
+            fetch_last: None,
+            read_committed: true,
         };
+        self.send_consume(req).await
+    }
 
+    async fn send_consume(&mut self, req: ConsumeRequest) -> Result<Option<ConsumeResponse>, ProtocolError> {
         let payload = RequestPayload {
             op_code: OpCode::Consume,
             data: req.serialize(),
@@ -115,6 +477,64 @@ impl FlyqClient {
         Ok(Some(consume))
     }
 
+    /// Up to `count` records before `offset`, newest first. Pass the log end
+    /// offset (see [`Self::get_watermarks`]) for the latest records.
+    pub async fn consume_last(
+        &mut self,
+        topic: &str,
+        partition: u32,
+        offset: u64,
+        count: u32,
+    ) -> Result<Vec<ConsumeResponse>, ProtocolError> {
+        let req = ConsumeRequest {
+            topic: topic.to_string(),
+            partition,
+            offset,
+            fetch_last: Some(count),
+            read_committed: false,
+        };
+
+        let payload = RequestPayload {
+            op_code: OpCode::Consume,
+            data: req.serialize(),
+        };
+
+        self.send_request(payload).await?;
+
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+
+        if resp_payload.op_code != OpCode::Consume {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+
+        let batch = ConsumeBatchResponse::deserialize(resp_payload.data)?;
+        Ok(batch.records)
+    }
+
+    /// Records whose header matches `req`, in offset order.
+    pub async fn query_by_header(
+        &mut self,
+        req: HeaderQueryRequest,
+    ) -> Result<Vec<ConsumeResponse>, ProtocolError> {
+        let payload = RequestPayload {
+            op_code: OpCode::QueryByHeader,
+            data: req.serialize(),
+        };
+
+        self.send_request(payload).await?;
+
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+
+        if resp_payload.op_code != OpCode::QueryByHeader {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+
+        let batch = ConsumeBatchResponse::deserialize(resp_payload.data)?;
+        Ok(batch.records)
+    }
+
     pub async fn consume_with_group(
         &mut self,
         topic: &str,
@@ -125,6 +545,7 @@ impl FlyqClient {
             topic: topic.to_string(),
             partition,
             group: group.to_string(),
+            read_committed: false,
         };
 
         let payload = RequestPayload {
@@ -243,6 +664,33 @@ impl FlyqClient {
         Ok(Some(watermark))
     }
     
+    /// Advances the partition's log start offset; records below `offset` are purged.
+    /// Returns the resulting low watermark.
+    pub async fn delete_records(
+        &mut self,
+        topic: &str,
+        partition: u32,
+        offset: u64,
+    ) -> Result<u64, ProtocolError> {
+        let req = DeleteRecordsRequest {
+            topic: topic.to_string(),
+            partition,
+            offset,
+        };
+        let payload = RequestPayload {
+            op_code: OpCode::DeleteRecords,
+            data: req.serialize(),
+        };
+        self.send_request(payload).await?;
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+        if resp_payload.op_code != OpCode::DeleteRecords {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+        let resp = DeleteRecordsResponse::deserialize(resp_payload.data)?;
+        Ok(resp.low_watermark)
+    }
+
     pub async fn get_consumer_lag(
         &mut self,
         consumer_group: &str,
@@ -288,4 +736,33 @@ impl FlyqClient {
         let health_response = PartitionHealthResponse::deserialize(resp_payload.data)?;
         Ok(health_response)
     }
+
+    /// Previews which segments retention would delete. `None` limits use the
+    /// broker's current config; nothing is deleted.
+    pub async fn retention_dry_run(
+        &mut self,
+        topic: &str,
+        partition: u32,
+        retention_ms: Option<u64>,
+        retention_bytes: Option<u64>,
+    ) -> Result<RetentionDryRunResponse, ProtocolError> {
+        let req = RetentionDryRunRequest {
+            topic: topic.to_string(),
+            partition,
+            retention_ms,
+            retention_bytes,
+        };
+        let payload = RequestPayload {
+            op_code: OpCode::RetentionDryRun,
+            data: req.serialize(),
+        };
+        self.send_request(payload).await?;
+        let response = self.read_response().await?;
+        let resp_payload = ResponsePayload::deserialize(Bytes::from(response.payload))?;
+        if resp_payload.op_code != OpCode::RetentionDryRun {
+            return Err(ProtocolError::UnknownOpCode(resp_payload.op_code as u8));
+        }
+        let dry_run = RetentionDryRunResponse::deserialize(resp_payload.data)?;
+        Ok(dry_run)
+    }
 }
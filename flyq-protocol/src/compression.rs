@@ -0,0 +1,125 @@
+use std::io::{Read, Write};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use crate::errors::DeserializeError;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Codec of the records section of a batch, stored in bits 0-2 of the batch
+/// attributes. The header stays uncompressed, so offsets, timestamps and
+/// record counts are known without decompressing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Gzip = 1,
+    Snappy = 2,
+    Lz4 = 3,
+    Zstd = 4,
+}
+
+impl Compression {
+    pub fn from_bits(bits: u16) -> Result<Self, DeserializeError> {
+        match bits {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Gzip),
+            2 => Ok(Compression::Snappy),
+            3 => Ok(Compression::Lz4),
+            4 => Ok(Compression::Zstd),
+            other => Err(DeserializeError::InvalidFormat(format!("unsupported compression codec {}", other))),
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => data.to_vec(),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).expect("writing to a Vec");
+                encoder.finish().expect("writing to a Vec")
+            }
+            Compression::Snappy => snap::raw::Encoder::new()
+                .compress_vec(data)
+                .expect("records section below the snappy size limit"),
+            Compression::Lz4 => lz4_flex::compress_prepend_size(data),
+            Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL).expect("compressing to a Vec"),
+        }
+    }
+
+    /// Decompresses `data`, failing once the output would exceed `limit`
+    /// bytes: compressed input comes from producers, and a few KB can claim
+    /// or expand to GBs.
+    pub fn decompress(self, data: &[u8], limit: usize) -> Result<Vec<u8>, DeserializeError> {
+        let invalid = |e: &dyn std::fmt::Display| DeserializeError::InvalidFormat(format!("{:?} records: {}", self, e));
+        let too_large = || DeserializeError::InvalidFormat(format!("{:?} records decompress to over {} bytes", self, limit));
+        let out = match self {
+            Compression::None => data.to_vec(),
+            Compression::Gzip => read_limited(GzDecoder::new(data), limit).map_err(|e| invalid(&e))?,
+            Compression::Snappy => {
+                // the decoder allocates the length the input announces
+                if snap::raw::decompress_len(data).map_err(|e| invalid(&e))? > limit {
+                    return Err(too_large());
+                }
+                snap::raw::Decoder::new().decompress_vec(data).map_err(|e| invalid(&e))?
+            }
+            Compression::Lz4 => {
+                // likewise, from a little-endian u32 prefix
+                let announced = data.get(..4).map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize);
+                if announced.is_some_and(|len| len > limit) {
+                    return Err(too_large());
+                }
+                lz4_flex::decompress_size_prepended(data).map_err(|e| invalid(&e))?
+            }
+            Compression::Zstd => {
+                let decoder = zstd::stream::read::Decoder::new(data).map_err(|e| invalid(&e))?;
+                read_limited(decoder, limit).map_err(|e| invalid(&e))?
+            }
+        };
+        match out.len() > limit {
+            true => Err(too_large()),
+            false => Ok(out),
+        }
+    }
+}
+
+/// Reads at most one byte past `limit`, enough to tell it was exceeded.
+fn read_limited(reader: impl Read, limit: usize) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    reader.take((limit as u64).saturating_add(1)).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codecs_roundtrip() {
+        let data = br#"{"event":"click","page":"/home"}"#.repeat(50);
+        for codec in [Compression::None, Compression::Gzip, Compression::Snappy, Compression::Lz4, Compression::Zstd] {
+            assert_eq!(Compression::from_bits(codec as u16).unwrap(), codec);
+            let compressed = codec.compress(&data);
+            if codec != Compression::None {
+                assert!(compressed.len() * 4 < data.len(), "{:?} compressed to {}", codec, compressed.len());
+                assert!(codec.decompress(&compressed[..compressed.len() / 2], usize::MAX).is_err());
+            }
+            assert_eq!(codec.decompress(&compressed, data.len()).unwrap(), data);
+            assert!(codec.decompress(&compressed, data.len() - 1).is_err());
+        }
+        assert!(Compression::from_bits(5).is_err());
+    }
+
+    #[test]
+    fn test_bombs_stop_at_the_limit() {
+        let zeros = vec![0u8; 16 << 20];
+        for codec in [Compression::Gzip, Compression::Snappy, Compression::Lz4, Compression::Zstd] {
+            let bomb = codec.compress(&zeros);
+            assert!(bomb.len() < 1 << 20);
+            assert!(codec.decompress(&bomb, 1 << 20).is_err(), "{:?}", codec);
+        }
+        // an lz4 prefix announcing 4 GB is refused before anything is allocated
+        let mut forged = u32::MAX.to_le_bytes().to_vec();
+        forged.extend_from_slice(&[0x10, 0]);
+        assert!(Compression::Lz4.decompress(&forged, 1 << 20).is_err());
+    }
+}
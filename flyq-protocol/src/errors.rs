@@ -43,4 +43,7 @@ pub enum DeserializeError {
 
     #[error("Offset {0} not found in any segment")]
     OffsetNotFound(u64),
+
+    #[error("Offset {offset} is below the log start offset {low_watermark}")]
+    OffsetOutOfRange { offset: u64, low_watermark: u64 },
 }
\ No newline at end of file
@@ -1,26 +1,34 @@
+pub mod compression;
 pub mod errors;
 pub mod frame;
 pub mod message;
 mod op_code;
 pub mod payload;
+pub mod record_batch;
 mod request;
 mod response;
 mod utils;
 
 // Public re-exports for easy access
+pub use compression::Compression;
 pub use errors::ProtocolError;
 pub use frame::{Frame, FrameType};
-pub use message::Message;
+pub use message::{DeadLetter, Message};
 pub use payload::{RequestPayload, ResponsePayload};
+pub use record_batch::{BatchHeader, RecordBatch};
 
 // Re-export common requests/responses
 pub use request::{
-    CommitOffsetRequest, ConsumeRequest, ConsumeWithGroupRequest, ConsumerLagRequest,
-    PartitionHealthRequest, ProduceRequest, WatermarkRequest,
+    Acks, AddPartitionsToTxnRequest, CommitOffsetRequest, ConsumeRequest, ConsumeWithGroupRequest, ConsumerLagRequest,
+    DeleteRecordsRequest, FetchRequest, HeaderQueryRequest, InitProducerIdRequest, LeaseRecordsRequest, PartitionHealthRequest,
+    ProduceBatchRequest, ProduceRequest, ProducerSequence, RetentionDryRunRequest, SettleRecordsRequest, TransactionRequest,
+    TxnOffsetCommitRequest, WatermarkRequest,
 };
 pub use response::{
-    ConsumerLagResponse, ConsumeResponse, PartitionHealthResponse, PartitionLag, ProduceAck,
-    WatermarkResponse,
+    CleanupRecord, ConsumeBatchResponse, ConsumerLagResponse, ConsumeResponse,
+    DeleteRecordsResponse, DryRunSegment, FetchResponse, InitProducerIdResponse, LeaseRecordsResponse, LeasedRecord,
+    PartitionHealthResponse, PartitionLag, ProduceAck, ProduceStatus, RetentionDryRunResponse, ScrubProblemRecord,
+    ScrubReport, SettleRecordsResponse, TransactionResponse, TransactionStatus, WatermarkResponse,
 };
 
 pub use op_code::OpCode;
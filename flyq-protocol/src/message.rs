@@ -13,6 +13,35 @@ use bytes::Bytes;
 use crate::errors::DeserializeError;
 use crate::utils::read_bytes;
 
+/// Prefix of the headers the broker sets itself. Producers cannot send them.
+pub const RESERVED_HEADER_PREFIX: &str = "flyq-";
+
+/// Header holding the time (Unix millis, u64 big-endian) before which the
+/// broker keeps a message out of the log.
+pub const DELIVER_AT_HEADER: &str = "flyq-deliver-at";
+
+/// Header holding the time (Unix millis, u64 big-endian) from which a message
+/// is expired: fetches skip it and compaction drops its contents.
+pub const EXPIRES_AT_HEADER: &str = "flyq-expires-at";
+
+/// Headers the broker adds to a record it moves to a dead-letter topic. The
+/// values are plain text so they can be read in dumps and indexed.
+pub const DLQ_TOPIC_HEADER: &str = "flyq-dlq-topic";
+pub const DLQ_PARTITION_HEADER: &str = "flyq-dlq-partition";
+pub const DLQ_OFFSET_HEADER: &str = "flyq-dlq-offset";
+pub const DLQ_ATTEMPTS_HEADER: &str = "flyq-dlq-attempts";
+pub const DLQ_ERROR_HEADER: &str = "flyq-dlq-error";
+
+/// Where a dead-lettered record came from and why it gave up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub topic: String,
+    pub partition: u32,
+    pub offset: u64,
+    pub attempts: u32,
+    pub error: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct Message {
     pub key: Option<Vec<u8>>, // Optional message key (used for partitioning)
@@ -22,6 +51,69 @@ pub struct Message {
 }
 
 impl Message {
+    /// Earliest delivery time from the [`DELIVER_AT_HEADER`], if set.
+    pub fn deliver_at(&self) -> Option<u64> {
+        let (_, value) = self.headers.as_ref()?.iter().find(|(name, _)| name == DELIVER_AT_HEADER)?;
+        Some(u64::from_be_bytes(value.as_slice().try_into().ok()?))
+    }
+
+    /// Sets the [`DELIVER_AT_HEADER`], replacing an earlier one.
+    pub fn set_deliver_at(&mut self, deliver_at: u64) {
+        let headers = self.headers.get_or_insert_with(Vec::new);
+        headers.retain(|(name, _)| name != DELIVER_AT_HEADER);
+        headers.push((DELIVER_AT_HEADER.to_string(), deliver_at.to_be_bytes().to_vec()));
+    }
+
+    /// Expiry time from the [`EXPIRES_AT_HEADER`], if set.
+    pub fn expires_at(&self) -> Option<u64> {
+        let (_, value) = self.headers.as_ref()?.iter().find(|(name, _)| name == EXPIRES_AT_HEADER)?;
+        Some(u64::from_be_bytes(value.as_slice().try_into().ok()?))
+    }
+
+    /// Sets the [`EXPIRES_AT_HEADER`], replacing an earlier one.
+    pub fn set_expires_at(&mut self, expires_at: u64) {
+        let headers = self.headers.get_or_insert_with(Vec::new);
+        headers.retain(|(name, _)| name != EXPIRES_AT_HEADER);
+        headers.push((EXPIRES_AT_HEADER.to_string(), expires_at.to_be_bytes().to_vec()));
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at().is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Origin of a record read from a dead-letter topic, from the DLQ headers.
+    pub fn dead_letter(&self) -> Option<DeadLetter> {
+        let header = |name: &str| -> Option<String> {
+            let (_, value) = self.headers.as_ref()?.iter().find(|(n, _)| n == name)?;
+            String::from_utf8(value.clone()).ok()
+        };
+        Some(DeadLetter {
+            topic: header(DLQ_TOPIC_HEADER)?,
+            partition: header(DLQ_PARTITION_HEADER)?.parse().ok()?,
+            offset: header(DLQ_OFFSET_HEADER)?.parse().ok()?,
+            attempts: header(DLQ_ATTEMPTS_HEADER)?.parse().ok()?,
+            error: header(DLQ_ERROR_HEADER)?,
+        })
+    }
+
+    /// Sets the DLQ headers, replacing those of an earlier trip to a
+    /// dead-letter topic.
+    pub fn set_dead_letter(&mut self, dead_letter: &DeadLetter) {
+        let names = [DLQ_TOPIC_HEADER, DLQ_PARTITION_HEADER, DLQ_OFFSET_HEADER, DLQ_ATTEMPTS_HEADER, DLQ_ERROR_HEADER];
+        let values = [
+            dead_letter.topic.clone(),
+            dead_letter.partition.to_string(),
+            dead_letter.offset.to_string(),
+            dead_letter.attempts.to_string(),
+            dead_letter.error.clone(),
+        ];
+        let headers = self.headers.get_or_insert_with(Vec::new);
+        headers.retain(|(name, _)| !names.contains(&name.as_str()));
+        for (name, value) in names.into_iter().zip(values) {
+            headers.push((name.to_string(), value.into_bytes()));
+        }
+    }
+
     pub fn serialize_body(&self) -> Vec<u8> {
         let mut buf = Vec::new();
 
@@ -70,9 +162,13 @@ impl Message {
         Bytes::copy_from_slice(&raw[4..4 + len])
     }
 
-    pub fn deserialize_body(mut buf: &[u8]) -> Result<Message, DeserializeError> {
-
+    pub fn deserialize_body(buf: &[u8]) -> Result<Message, DeserializeError> {
+        Self::deserialize_prefix(buf).map(|(message, _)| message)
+    }
 
+    /// Like [`Self::deserialize_body`], also returning whatever follows the
+    /// message in `buf`.
+    pub fn deserialize_prefix(mut buf: &[u8]) -> Result<(Message, &[u8]), DeserializeError> {
         // Timestamp
         let timestamp = {
             let b = read_bytes(&mut buf, 8)?;
@@ -117,7 +213,7 @@ impl Message {
             headers.push((k, v));
         }
 
-        Ok(Message {
+        let message = Message {
             key,
             value,
             timestamp,
@@ -126,7 +222,8 @@ impl Message {
             } else {
                 Some(headers)
             },
-        })
+        };
+        Ok((message, buf))
     }
 }
 
@@ -181,4 +278,62 @@ mod tests {
         assert_eq!(deserialized.value, msg.value);
         assert_eq!(deserialized.timestamp, 42);
     }
+
+    #[test]
+    fn test_deliver_at_header() {
+        let mut msg = Message {
+            key: None,
+            value: b"reminder".to_vec(),
+            timestamp: 42,
+            headers: Some(vec![("source".to_string(), b"web".to_vec())]),
+        };
+        assert_eq!(msg.deliver_at(), None);
+
+        msg.set_deliver_at(1_000);
+        msg.set_deliver_at(900_000);
+
+        assert_eq!(msg.deliver_at(), Some(900_000));
+        assert_eq!(msg.headers.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_expires_at_header() {
+        let mut msg = Message {
+            key: None,
+            value: b"otp:493021".to_vec(),
+            timestamp: 42,
+            headers: None,
+        };
+        assert!(!msg.is_expired(u64::MAX));
+
+        msg.set_expires_at(60_000);
+        assert_eq!(msg.expires_at(), Some(60_000));
+        assert!(!msg.is_expired(59_999));
+        assert!(msg.is_expired(60_000));
+    }
+
+    #[test]
+    fn test_dead_letter_headers() {
+        let mut msg = Message {
+            key: None,
+            value: b"resize:42".to_vec(),
+            timestamp: 42,
+            headers: Some(vec![("source".to_string(), b"web".to_vec())]),
+        };
+        assert_eq!(msg.dead_letter(), None);
+
+        let mut dead_letter = DeadLetter {
+            topic: "jobs".into(),
+            partition: 3,
+            offset: 1_234,
+            attempts: 5,
+            error: "image too large".into(),
+        };
+        msg.set_dead_letter(&dead_letter);
+        dead_letter.attempts = 6;
+        msg.set_dead_letter(&dead_letter);
+
+        assert_eq!(msg.dead_letter(), Some(dead_letter));
+        assert_eq!(msg.headers.as_ref().unwrap().len(), 6);
+    }
 }
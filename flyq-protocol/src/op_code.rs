@@ -8,8 +8,22 @@ pub enum OpCode {
     ConsumeWithGroup = 3,
     CommitOffset = 4,
     Watermark = 5,
+    DeleteRecords = 6,
     GetConsumerLag = 13,
     GetPartitionHealth = 14,
+    RetentionDryRun = 15,
+    QueryByHeader = 16,
+    InitProducerId = 17,
+    BeginTransaction = 18,
+    AddPartitionsToTxn = 19,
+    CommitTransaction = 20,
+    AbortTransaction = 21,
+    TxnOffsetCommit = 22,
+    LeaseRecords = 23,
+    AckRecords = 24,
+    NackRecords = 25,
+    ProduceBatch = 26,
+    Fetch = 27,
 }
 
 impl TryFrom<u8> for OpCode {
@@ -22,8 +36,22 @@ impl TryFrom<u8> for OpCode {
             3 => Ok(OpCode::ConsumeWithGroup),
             4 => Ok(OpCode::CommitOffset),
             5 => Ok(OpCode::Watermark),
+            6 => Ok(OpCode::DeleteRecords),
             13 => Ok(OpCode::GetConsumerLag),
             14 => Ok(OpCode::GetPartitionHealth),
+            15 => Ok(OpCode::RetentionDryRun),
+            16 => Ok(OpCode::QueryByHeader),
+            17 => Ok(OpCode::InitProducerId),
+            18 => Ok(OpCode::BeginTransaction),
+            19 => Ok(OpCode::AddPartitionsToTxn),
+            20 => Ok(OpCode::CommitTransaction),
+            21 => Ok(OpCode::AbortTransaction),
+            22 => Ok(OpCode::TxnOffsetCommit),
+            23 => Ok(OpCode::LeaseRecords),
+            24 => Ok(OpCode::AckRecords),
+            25 => Ok(OpCode::NackRecords),
+            26 => Ok(OpCode::ProduceBatch),
+            27 => Ok(OpCode::Fetch),
             _ => Err(ProtocolError::UnknownOpCode(value)),
         }
     }
@@ -0,0 +1,521 @@
+/*
+[ base_offset       : u64 ]
+[ magic             : u8  ]  2
+[ checksum          : u32 ]  xxh32 of everything after it
+[ attributes        : u16 ]
+[ last_offset_delta : u32 ]
+[ base_timestamp    : u64 ]
+[ max_timestamp     : u64 ]
+[ record_count      : u32 ]
+[ records... ]             compressed as a whole with the codec in attributes
+
+record:
+[ varint   length ]        bytes after this field
+[ varint   offset_delta ]
+[ zigzag   timestamp_delta ]
+[ zigzag   key_len ]       -1 = no key
+[ key bytes ]
+[ varint   value_len ]
+[ value bytes ]
+[ varint   header_count ]
+[ headers: (varint key_len, key, varint val_len, val)* ]
+
+*/
+use xxhash_rust::xxh32::xxh32;
+use crate::compression::Compression;
+use crate::errors::DeserializeError;
+use crate::message::Message;
+use crate::utils::read_bytes;
+
+pub const BATCH_MAGIC: u8 = 2;
+
+/// Set in the u32 length prefix of a segment or fetch entry holding a batch.
+/// Single records (magic 1) keep their plain length, always below it.
+pub const BATCH_FLAG: u32 = 0x8000_0000;
+
+pub const BATCH_HEADER_LEN: usize = 39;
+
+/// Largest batch an entry can frame, and so the most a compressed records
+/// section may expand to unless the reader sets a lower limit.
+pub const MAX_BATCH_LEN: usize = (BATCH_FLAG - 1) as usize;
+
+// the checksum leaves out the base offset, so the broker can assign one
+// without rehashing the batch
+const CHECKSUM_POS: usize = 9;
+const CHECKSUMMED_FROM: usize = 13;
+
+/// Smallest encoded record: a length and five fields of one byte each.
+const MIN_RECORD_LEN: usize = 6;
+
+/// Bits 0-2 of the attributes hold the [`Compression`] codec.
+pub const CODEC_MASK: u16 = 0x0007;
+
+/// Bit 3 of the attributes marks a batch the broker rewrote with tombstones
+/// in place of expired records. Those must be left out when it is read.
+pub const TOMBSTONES_FLAG: u16 = 0x0008;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchHeader {
+    pub base_offset: u64,
+    pub attributes: u16,
+    /// Offset of the last record minus `base_offset`.
+    pub last_offset_delta: u32,
+    pub base_timestamp: u64,
+    pub max_timestamp: u64,
+    pub record_count: u32,
+}
+
+impl BatchHeader {
+    pub fn last_offset(&self) -> u64 {
+        self.base_offset + self.last_offset_delta as u64
+    }
+
+    /// Checked when the batch is read, see [`RecordBatch::from_bytes`].
+    pub fn compression(&self) -> Compression {
+        Compression::from_bits(self.attributes & CODEC_MASK).unwrap_or_default()
+    }
+
+    pub fn has_tombstones(&self) -> bool {
+        self.attributes & TOMBSTONES_FLAG != 0
+    }
+}
+
+/// Records sharing one header, with offsets, timestamps and lengths delta
+/// and varint encoded, optionally compressed. Kept encoded: the broker writes
+/// and serves the bytes as they came in and only decompresses and decodes
+/// records where it has to look inside.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordBatch {
+    bytes: Vec<u8>,
+}
+
+impl RecordBatch {
+    /// Encodes `messages` with consecutive offsets from `base_offset`,
+    /// uncompressed. `messages` must not be empty.
+    pub fn encode(base_offset: u64, messages: &[Message]) -> Self {
+        let records: Vec<(u64, &Message)> = (base_offset..).zip(messages).collect();
+        Self::build(&records)
+    }
+
+    /// Encodes records with their offsets, which must be ascending but may
+    /// have gaps, e.g. where the broker left out records a consumer must not
+    /// see. `records` must not be empty.
+    pub fn from_records(records: &[(u64, Message)]) -> Self {
+        let records: Vec<(u64, &Message)> = records.iter().map(|(offset, message)| (*offset, message)).collect();
+        Self::build(&records)
+    }
+
+    fn build(records: &[(u64, &Message)]) -> Self {
+        assert!(!records.is_empty(), "a record batch holds at least one record");
+        let (base_offset, first) = records[0];
+        let base_timestamp = first.timestamp;
+        let max_timestamp = records.iter().map(|(_, m)| m.timestamp).max().unwrap_or(base_timestamp);
+        let last_offset_delta = records[records.len() - 1].0 - base_offset;
+
+        let mut bytes = Vec::with_capacity(BATCH_HEADER_LEN + records.len() * 16);
+        bytes.extend_from_slice(&base_offset.to_be_bytes());
+        bytes.push(BATCH_MAGIC);
+        bytes.extend_from_slice(&[0u8; 4]); // checksum, written last
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        bytes.extend_from_slice(&(last_offset_delta as u32).to_be_bytes());
+        bytes.extend_from_slice(&base_timestamp.to_be_bytes());
+        bytes.extend_from_slice(&max_timestamp.to_be_bytes());
+        bytes.extend_from_slice(&(records.len() as u32).to_be_bytes());
+
+        let mut record = Vec::new();
+        for &(offset, message) in records {
+            record.clear();
+            let timestamp_delta = message.timestamp.wrapping_sub(base_timestamp) as i64;
+            encode_record(&mut record, offset - base_offset, timestamp_delta, message);
+            put_varint(&mut bytes, record.len() as u64);
+            bytes.extend_from_slice(&record);
+        }
+
+        let mut batch = RecordBatch { bytes };
+        batch.seal();
+        batch
+    }
+
+    /// Writes the checksum over what follows it.
+    fn seal(&mut self) {
+        let checksum = xxh32(&self.bytes[CHECKSUMMED_FROM..], 0);
+        self.bytes[CHECKSUM_POS..CHECKSUMMED_FROM].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    /// The same records with their records section compressed with `codec`.
+    /// Only decompresses when the batch already uses another codec, and hands
+    /// the batch back as is when it uses `codec`.
+    pub fn with_compression(self, codec: Compression) -> Result<Self, DeserializeError> {
+        let header = self.header();
+        if header.compression() == codec {
+            return Ok(self);
+        }
+        let records = header.compression().decompress(&self.bytes[BATCH_HEADER_LEN..], MAX_BATCH_LEN)?;
+        let mut bytes = self.bytes;
+        bytes.truncate(BATCH_HEADER_LEN);
+        let attributes = (header.attributes & !CODEC_MASK) | codec as u16;
+        bytes[13..15].copy_from_slice(&attributes.to_be_bytes());
+        bytes.extend_from_slice(&codec.compress(&records));
+
+        let mut batch = RecordBatch { bytes };
+        batch.seal();
+        Ok(batch)
+    }
+
+    /// The same batch with [`TOMBSTONES_FLAG`] set.
+    pub fn with_tombstones(mut self) -> Self {
+        let attributes = self.header().attributes | TOMBSTONES_FLAG;
+        self.bytes[13..15].copy_from_slice(&attributes.to_be_bytes());
+        self.seal();
+        self
+    }
+
+    /// Takes an encoded batch, without the length prefix, checking its magic,
+    /// checksum and header. Records are not decoded.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, DeserializeError> {
+        if bytes.len() < BATCH_HEADER_LEN {
+            return Err(DeserializeError::UnexpectedEOF);
+        }
+        if bytes[8] != BATCH_MAGIC {
+            return Err(DeserializeError::InvalidFormat(format!("unknown batch magic {}", bytes[8])));
+        }
+        let stored = u32::from_be_bytes(bytes[CHECKSUM_POS..CHECKSUMMED_FROM].try_into().unwrap());
+        let computed = xxh32(&bytes[CHECKSUMMED_FROM..], 0);
+        if stored != computed {
+            return Err(DeserializeError::InvalidFormat(format!(
+                "batch checksum mismatch: stored {:#010x}, computed {:#010x}",
+                stored, computed
+            )));
+        }
+        let batch = RecordBatch { bytes };
+        let header = batch.header();
+        let codec = Compression::from_bits(header.attributes & CODEC_MASK)?;
+        if header.record_count == 0 || (header.last_offset_delta as u64) + 1 < header.record_count as u64 {
+            return Err(DeserializeError::InvalidFormat(format!(
+                "batch of {} records with last offset delta {}",
+                header.record_count, header.last_offset_delta
+            )));
+        }
+        if codec == Compression::None {
+            check_record_count(&header, batch.bytes.len() - BATCH_HEADER_LEN)?;
+        }
+        Ok(batch)
+    }
+
+    pub fn header(&self) -> BatchHeader {
+        let b = &self.bytes;
+        BatchHeader {
+            base_offset: u64::from_be_bytes(b[0..8].try_into().unwrap()),
+            attributes: u16::from_be_bytes(b[13..15].try_into().unwrap()),
+            last_offset_delta: u32::from_be_bytes(b[15..19].try_into().unwrap()),
+            base_timestamp: u64::from_be_bytes(b[19..27].try_into().unwrap()),
+            max_timestamp: u64::from_be_bytes(b[27..35].try_into().unwrap()),
+            record_count: u32::from_be_bytes(b[35..39].try_into().unwrap()),
+        }
+    }
+
+    /// Moves the batch to start at `base_offset`; the checksum stays valid.
+    pub fn set_base_offset(&mut self, base_offset: u64) {
+        self.bytes[0..8].copy_from_slice(&base_offset.to_be_bytes());
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The batch as an entry of a segment file or fetch response: length
+    /// prefix with [`BATCH_FLAG`] set, then the batch.
+    pub fn to_entry(&self) -> Vec<u8> {
+        let mut entry = Vec::with_capacity(4 + self.bytes.len());
+        entry.extend_from_slice(&(self.bytes.len() as u32 | BATCH_FLAG).to_be_bytes());
+        entry.extend_from_slice(&self.bytes);
+        entry
+    }
+
+    /// Whether the records have consecutive offsets, as in batches producers send.
+    pub fn is_contiguous(&self) -> bool {
+        let header = self.header();
+        header.last_offset_delta as u64 + 1 == header.record_count as u64
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.header().compression()
+    }
+
+    /// Decompresses and decodes every record with its offset, in offset order.
+    pub fn records(&self) -> Result<Vec<(u64, Message)>, DeserializeError> {
+        self.records_within(MAX_BATCH_LEN)
+    }
+
+    /// Like [`Self::records`], failing if the records section decompresses to
+    /// more than `limit` bytes.
+    pub fn records_within(&self, limit: usize) -> Result<Vec<(u64, Message)>, DeserializeError> {
+        let header = self.header();
+        let section = match header.compression() {
+            Compression::None => None,
+            codec => Some(codec.decompress(&self.bytes[BATCH_HEADER_LEN..], limit)?),
+        };
+        let mut buf = section.as_deref().unwrap_or(&self.bytes[BATCH_HEADER_LEN..]);
+        // the count comes from the producer, the checksum does not vouch for it
+        check_record_count(&header, buf.len())?;
+        let mut records = Vec::with_capacity((header.record_count as usize).min(buf.len() / MIN_RECORD_LEN));
+        for _ in 0..header.record_count {
+            let len = get_varint(&mut buf)? as usize;
+            let mut record = read_bytes(&mut buf, len)?;
+            let offset_delta = get_varint(&mut record)?;
+            let timestamp_delta = get_zigzag(&mut record)?;
+            let message = decode_record(&mut record, header.base_timestamp.wrapping_add_signed(timestamp_delta))?;
+            records.push((header.base_offset + offset_delta, message));
+        }
+        if !buf.is_empty() {
+            return Err(DeserializeError::InvalidFormat(format!(
+                "{} bytes after the last record of the batch",
+                buf.len()
+            )));
+        }
+        Ok(records)
+    }
+}
+
+fn check_record_count(header: &BatchHeader, section_len: usize) -> Result<(), DeserializeError> {
+    if header.record_count as usize > section_len / MIN_RECORD_LEN {
+        return Err(DeserializeError::InvalidFormat(format!(
+            "batch claims {} records in {} bytes",
+            header.record_count, section_len
+        )));
+    }
+    Ok(())
+}
+
+/// Splits consecutive length-prefixed batches, as written by
+/// [`RecordBatch::to_entry`].
+pub fn read_batches(mut buf: &[u8]) -> Result<Vec<RecordBatch>, DeserializeError> {
+    let mut batches = Vec::new();
+    while !buf.is_empty() {
+        let len = u32::from_be_bytes(read_bytes(&mut buf, 4)?.try_into().unwrap());
+        if len & BATCH_FLAG == 0 {
+            return Err(DeserializeError::InvalidFormat("entry is not a record batch".into()));
+        }
+        let bytes = read_bytes(&mut buf, (len & !BATCH_FLAG) as usize)?;
+        batches.push(RecordBatch::from_bytes(bytes.to_vec())?);
+    }
+    Ok(batches)
+}
+
+fn encode_record(buf: &mut Vec<u8>, offset_delta: u64, timestamp_delta: i64, message: &Message) {
+    put_varint(buf, offset_delta);
+    put_zigzag(buf, timestamp_delta);
+    match &message.key {
+        Some(key) => {
+            put_zigzag(buf, key.len() as i64);
+            buf.extend_from_slice(key);
+        }
+        None => put_zigzag(buf, -1),
+    }
+    put_varint(buf, message.value.len() as u64);
+    buf.extend_from_slice(&message.value);
+    let headers = message.headers.as_deref().unwrap_or_default();
+    put_varint(buf, headers.len() as u64);
+    for (name, value) in headers {
+        put_varint(buf, name.len() as u64);
+        buf.extend_from_slice(name.as_bytes());
+        put_varint(buf, value.len() as u64);
+        buf.extend_from_slice(value);
+    }
+}
+
+fn decode_record(buf: &mut &[u8], timestamp: u64) -> Result<Message, DeserializeError> {
+    let key = match get_zigzag(buf)? {
+        -1 => None,
+        len if len < 0 => return Err(DeserializeError::InvalidFormat(format!("key length {}", len))),
+        len => Some(read_bytes(buf, len as usize)?.to_vec()),
+    };
+    let value_len = get_varint(buf)? as usize;
+    let value = read_bytes(buf, value_len)?.to_vec();
+    let header_count = get_varint(buf)? as usize;
+    let mut headers = Vec::with_capacity(header_count.min(buf.len()));
+    for _ in 0..header_count {
+        let name_len = get_varint(buf)? as usize;
+        let name = String::from_utf8(read_bytes(buf, name_len)?.to_vec()).map_err(|_| DeserializeError::InvalidUtf8)?;
+        let value_len = get_varint(buf)? as usize;
+        headers.push((name, read_bytes(buf, value_len)?.to_vec()));
+    }
+    Ok(Message {
+        key,
+        value,
+        timestamp,
+        headers: if headers.is_empty() { None } else { Some(headers) },
+    })
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn get_varint(buf: &mut &[u8]) -> Result<u64, DeserializeError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_bytes(buf, 1)?[0];
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DeserializeError::InvalidFormat("varint longer than 10 bytes".into()))
+}
+
+fn put_zigzag(buf: &mut Vec<u8>, value: i64) {
+    put_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn get_zigzag(buf: &mut &[u8]) -> Result<i64, DeserializeError> {
+    let value = get_varint(buf)?;
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(i: u64, timestamp: u64) -> Message {
+        Message {
+            key: (i != 1).then(|| format!("key-{}", i).into_bytes()),
+            value: format!("value-{}", i).into_bytes(),
+            timestamp,
+            headers: (i == 1).then(|| vec![("trace".to_string(), b"abc".to_vec())]),
+        }
+    }
+
+    #[test]
+    fn test_batch_roundtrip_with_out_of_order_timestamps() {
+        let messages = vec![message(0, 5_000), message(1, 4_990), message(2, 5_020)];
+        let batch = RecordBatch::encode(0, &messages);
+
+        let mut batch = RecordBatch::from_bytes(batch.into_bytes()).unwrap();
+        batch.set_base_offset(100);
+        let batch = RecordBatch::from_bytes(batch.into_bytes()).unwrap(); // checksum still holds
+
+        let header = batch.header();
+        assert_eq!(header.base_offset, 100);
+        assert_eq!(header.last_offset(), 102);
+        assert_eq!((header.base_timestamp, header.max_timestamp), (5_000, 5_020));
+        assert_eq!(header.record_count, 3);
+
+        let records = batch.records().unwrap();
+        assert_eq!(records.iter().map(|(o, _)| *o).collect::<Vec<_>>(), vec![100, 101, 102]);
+        assert_eq!(records[1].1.timestamp, 4_990);
+        assert_eq!(records[1].1.key, None);
+        assert_eq!(records[1].1.headers, messages[1].headers);
+        assert_eq!(records[2].1.key, Some(b"key-2".to_vec()));
+        assert_eq!(records[2].1.value, b"value-2");
+
+        // far smaller than the same records as single entries
+        let single: usize = messages.iter().map(|m| 4 + 8 + m.serialize_for_wire().len()).sum();
+        assert!(batch.to_entry().len() < single);
+    }
+
+    #[test]
+    fn test_batch_with_offset_gaps() {
+        let batch = RecordBatch::from_records(&[(10, message(0, 1)), (13, message(3, 2))]);
+        let batch = RecordBatch::from_bytes(batch.into_bytes()).unwrap();
+
+        assert_eq!(batch.header().last_offset(), 13);
+        assert!(!batch.is_contiguous());
+        let offsets: Vec<u64> = batch.records().unwrap().into_iter().map(|(o, _)| o).collect();
+        assert_eq!(offsets, vec![10, 13]);
+        assert!(RecordBatch::encode(0, &[message(0, 1), message(1, 1)]).is_contiguous());
+
+        // the tombstone mark outlives a change of codec
+        assert!(!batch.header().has_tombstones());
+        let marked = batch.with_tombstones().with_compression(Compression::Gzip).unwrap();
+        let marked = RecordBatch::from_bytes(marked.into_bytes()).unwrap();
+        assert!(marked.header().has_tombstones());
+        assert_eq!(marked.compression(), Compression::Gzip);
+    }
+
+    #[test]
+    fn test_corrupt_batch_and_entry_framing() {
+        let batch = RecordBatch::encode(7, &[message(0, 1), message(1, 2)]);
+        let mut bytes = batch.to_entry();
+        bytes.extend_from_slice(&RecordBatch::encode(9, &[message(2, 3)]).to_entry());
+
+        let batches = read_batches(&bytes).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].header().base_offset, 9);
+
+        let mut flipped = batch.into_bytes();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 0xff;
+        assert!(RecordBatch::from_bytes(flipped).is_err());
+        assert!(read_batches(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_forged_record_count_is_rejected() {
+        let plain = RecordBatch::encode(0, &[message(0, 1), message(1, 2)]);
+        for codec in [Compression::None, Compression::Lz4] {
+            let mut forged = plain.clone().with_compression(codec).unwrap();
+            forged.bytes[15..19].copy_from_slice(&u32::MAX.to_be_bytes()); // last_offset_delta
+            forged.bytes[35..39].copy_from_slice(&u32::MAX.to_be_bytes()); // record_count
+            forged.seal(); // a sender computes the checksum itself
+            match RecordBatch::from_bytes(forged.bytes.clone()) {
+                // a compressed section is only sized once it is decompressed
+                Ok(batch) => assert!(codec != Compression::None && batch.records().is_err()),
+                Err(_) => assert_eq!(codec, Compression::None),
+            }
+        }
+    }
+
+    #[test]
+    fn test_compressed_batch() {
+        let messages: Vec<Message> = (0..20).map(|i| message(i, 1_000 + i)).collect();
+        let plain = RecordBatch::encode(0, &messages);
+        let mut batch = plain.clone().with_compression(Compression::Zstd).unwrap();
+        batch.set_base_offset(40);
+        let batch = RecordBatch::from_bytes(batch.into_bytes()).unwrap();
+
+        let header = batch.header();
+        assert_eq!(batch.compression(), Compression::Zstd);
+        assert_eq!((header.base_offset, header.last_offset(), header.record_count), (40, 59, 20));
+        assert!(batch.as_bytes().len() < plain.as_bytes().len());
+        let records = batch.records().unwrap();
+        assert!(batch.records_within(plain.as_bytes().len() / 2).is_err());
+        assert_eq!(records[19].0, 59);
+        assert_eq!(records[19].1.value, messages[19].value);
+
+        // switching codecs goes through the plain records, back to the same bytes
+        let mut back = batch.with_compression(Compression::Lz4).unwrap().with_compression(Compression::None).unwrap();
+        back.set_base_offset(0);
+        assert_eq!(back, plain);
+
+        let mut unknown = plain;
+        unknown.bytes[14] |= 0x07;
+        unknown.seal();
+        assert!(RecordBatch::from_bytes(unknown.into_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_varints() {
+        for value in [0u64, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut buf = Vec::new();
+            put_varint(&mut buf, value);
+            assert_eq!(get_varint(&mut &buf[..]).unwrap(), value);
+        }
+        for value in [0i64, -1, 1, -64, 64, i64::MIN, i64::MAX] {
+            let mut buf = Vec::new();
+            put_zigzag(&mut buf, value);
+            assert_eq!(get_zigzag(&mut &buf[..]).unwrap(), value);
+        }
+        let mut small = Vec::new();
+        put_zigzag(&mut small, -1);
+        assert_eq!(small, vec![1]);
+    }
+}
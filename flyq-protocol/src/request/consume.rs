@@ -6,8 +6,17 @@ pub struct ConsumeRequest {
     pub topic: String,
     pub partition: u32,
     pub offset: u64,
+    /// When set, asks for up to this many records *before* `offset`, newest
+    /// first, answered with a [`ConsumeBatchResponse`](crate::ConsumeBatchResponse).
+    /// Pass the log end offset as `offset` for the latest N records.
+    pub fetch_last: Option<u32>,
+    /// Only return records below the last stable offset, skipping those of
+    /// aborted transactions.
+    pub read_committed: bool,
 }
 
+//frame: [u32 topic_len][topic bytes][u32 partition][u64 offset][u8 has_last][u32 last?][u8 read_committed]
+
 impl ConsumeRequest {
     pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
         if buf.remaining() < 4 {
@@ -23,7 +32,18 @@ impl ConsumeRequest {
         let partition = buf.get_u32();
         let offset = buf.get_u64();
 
-        Ok(ConsumeRequest { topic, partition, offset })
+        // Older clients end the payload here
+        let fetch_last = if buf.remaining() >= 1 && buf.get_u8() == 1 {
+            if buf.remaining() < 4 {
+                return Err(ProtocolError::PayloadError("Incomplete fetch-last count".into()));
+            }
+            Some(buf.get_u32())
+        } else {
+            None
+        };
+        let read_committed = buf.remaining() >= 1 && buf.get_u8() == 1;
+
+        Ok(ConsumeRequest { topic, partition, offset, fetch_last, read_committed })
     }
 
     pub fn serialize(&self) -> Bytes {
@@ -35,6 +55,55 @@ impl ConsumeRequest {
         buf.put_u32(self.partition);
         buf.put_u64(self.offset);
 
+        match self.fetch_last {
+            Some(count) => {
+                buf.put_u8(1);
+                buf.put_u32(count);
+            }
+            None => buf.put_u8(0),
+        }
+        buf.put_u8(self.read_committed as u8);
+
         buf.freeze()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consume_fetch_last_roundtrip() {
+        let req = ConsumeRequest {
+            topic: "events".into(),
+            partition: 2,
+            offset: 500,
+            fetch_last: Some(50),
+            read_committed: true,
+        };
+
+        let parsed = ConsumeRequest::deserialize(req.serialize()).unwrap();
+
+        assert_eq!(parsed.topic, "events");
+        assert_eq!(parsed.partition, 2);
+        assert_eq!(parsed.offset, 500);
+        assert_eq!(parsed.fetch_last, Some(50));
+        assert!(parsed.read_committed);
+    }
+
+    #[test]
+    fn test_consume_without_fetch_last_flag() {
+        // payload from a client that predates the flag
+        let mut buf = BytesMut::new();
+        buf.put_u32(6);
+        buf.extend_from_slice(b"events");
+        buf.put_u32(0);
+        buf.put_u64(7);
+
+        let parsed = ConsumeRequest::deserialize(buf.freeze()).unwrap();
+
+        assert_eq!(parsed.offset, 7);
+        assert_eq!(parsed.fetch_last, None);
+        assert!(!parsed.read_committed);
+    }
+}
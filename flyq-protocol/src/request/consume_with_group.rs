@@ -5,10 +5,13 @@ pub struct ConsumeWithGroupRequest {
     pub topic: String,
     pub partition: u32,  // Todo: support for making it option 
     pub group: String,
+    /// See [`ConsumeRequest::read_committed`](crate::ConsumeRequest::read_committed).
+    pub read_committed: bool,
 }
 
 /*
-frame: [u32 topic_len][topic bytes][u32 partition][u32 group_len][group bytes]
+frame: [u32 topic_len][topic bytes][u32 partition][u32 group_len][group bytes][u8 read_committed]
+the flag is only written when set, older brokers ignore it
 */
 impl ConsumeWithGroupRequest {
     
@@ -21,6 +24,9 @@ impl ConsumeWithGroupRequest {
         buf.put_u32(self.partition);
         buf.put_u32(self.group.len() as u32);
         buf.extend_from_slice(self.group.as_bytes());
+        if self.read_committed {
+            buf.put_u8(1);
+        }
         
         buf.freeze()
     }
@@ -50,10 +56,13 @@ impl ConsumeWithGroupRequest {
         let group = String::from_utf8(buf.split_to(group_len as usize).to_vec())
             .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in group".into()))?;
         
+        let read_committed = buf.remaining() >= 1 && buf.get_u8() == 1;
+        
         Ok(ConsumeWithGroupRequest{
             topic,
             partition,
             group,
+            read_committed,
         })
     }
     
@@ -71,6 +80,7 @@ mod tests{
             topic: topic.into(),
             partition: 3,
             group: group.into(),
+            read_committed: false,
         };
 
         let bytes = req.serialize();
@@ -85,6 +95,7 @@ mod tests{
             topic: "orders".into(),
             partition: 2,
             group: "email-worker".into(),
+            read_committed: true,
         };
 
         let bytes = req.serialize();
@@ -93,5 +104,6 @@ mod tests{
         assert_eq!(deserialized.topic, req.topic);
         assert_eq!(deserialized.partition, req.partition);
         assert_eq!(deserialized.group, req.group);
+        assert_eq!(deserialized.read_committed, req.read_committed);
     }
 }
\ No newline at end of file
@@ -0,0 +1,70 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Advances the log start offset of a partition; records below `offset`
+/// become unreadable and fully covered segments are deleted.
+#[derive(Debug)]
+pub struct DeleteRecordsRequest {
+    pub topic: String,
+    pub partition: u32,
+    pub offset: u64,
+}
+
+//frame: [u32 topic_len][topic bytes][u32 partition][u64 offset]
+
+impl DeleteRecordsRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32(self.topic.len() as u32);
+        buf.extend_from_slice(self.topic.as_bytes());
+        buf.put_u32(self.partition);
+        buf.put_u64(self.offset);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError(
+                "Insufficient data for topic length".into(),
+            ));
+        }
+        let topic_len = buf.get_u32();
+
+        if buf.remaining() < (topic_len + 4 + 8) as usize {
+            return Err(ProtocolError::PayloadError(
+                "Insufficient data for topic + partition + offset".into(),
+            ));
+        }
+        let topic = String::from_utf8(buf.split_to(topic_len as usize).to_vec())
+            .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in topic".into()))?;
+
+        let partition = buf.get_u32();
+        let offset = buf.get_u64();
+
+        Ok(Self {
+            topic,
+            partition,
+            offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_records_roundtrip() {
+        let req = DeleteRecordsRequest {
+            topic: "payments".into(),
+            partition: 2,
+            offset: 4242,
+        };
+
+        let parsed = DeleteRecordsRequest::deserialize(req.serialize()).unwrap();
+
+        assert_eq!(parsed.topic, req.topic);
+        assert_eq!(parsed.partition, req.partition);
+        assert_eq!(parsed.offset, req.offset);
+    }
+}
@@ -0,0 +1,74 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use crate::errors::ProtocolError;
+
+/// Reads a partition as record batches, answered with a
+/// [`FetchResponse`](crate::FetchResponse).
+#[derive(Debug)]
+pub struct FetchRequest {
+    pub topic: String,
+    pub partition: u32,
+    pub offset: u64,
+    /// The broker stops adding batches once the response reaches this size;
+    /// it always returns at least one if there is a record to return.
+    pub max_bytes: u32,
+    /// Only return records below the last stable offset, skipping those of
+    /// aborted transactions.
+    pub read_committed: bool,
+}
+
+//frame: [u32 topic_len][topic][u32 partition][u64 offset][u32 max_bytes][u8 read_committed]
+
+impl FetchRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(4 + self.topic.len() + 17);
+        buf.put_u32(self.topic.len() as u32);
+        buf.extend_from_slice(self.topic.as_bytes());
+        buf.put_u32(self.partition);
+        buf.put_u64(self.offset);
+        buf.put_u32(self.max_bytes);
+        buf.put_u8(self.read_committed as u8);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Incomplete fetch payload".into()));
+        }
+        let topic_len = buf.get_u32() as usize;
+        if buf.remaining() < topic_len + 17 {
+            return Err(ProtocolError::PayloadError("Incomplete fetch payload".into()));
+        }
+        let topic = String::from_utf8(buf.split_to(topic_len).to_vec())
+            .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in topic".into()))?;
+
+        Ok(FetchRequest {
+            topic,
+            partition: buf.get_u32(),
+            offset: buf.get_u64(),
+            max_bytes: buf.get_u32(),
+            read_committed: buf.get_u8() == 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_roundtrip() {
+        let req = FetchRequest {
+            topic: "events".into(),
+            partition: 1,
+            offset: 4_096,
+            max_bytes: 1 << 20,
+            read_committed: true,
+        };
+
+        let parsed = FetchRequest::deserialize(req.serialize()).unwrap();
+
+        assert_eq!((parsed.topic.as_str(), parsed.partition, parsed.offset), ("events", 1, 4_096));
+        assert_eq!(parsed.max_bytes, 1 << 20);
+        assert!(parsed.read_committed);
+    }
+}
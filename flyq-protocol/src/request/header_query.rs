@@ -0,0 +1,142 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Finds records of a partition whose header `header` equals `value`,
+/// answered with a [`ConsumeBatchResponse`](crate::ConsumeBatchResponse) in
+/// offset order. Time bounds are record timestamps in ms; `end_offset` and
+/// `end_time` are exclusive.
+#[derive(Debug)]
+pub struct HeaderQueryRequest {
+    pub topic: String,
+    pub partition: u32,
+    pub header: String,
+    pub value: Vec<u8>,
+    pub start_offset: u64,
+    pub end_offset: Option<u64>,
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+    pub max_results: u32,
+}
+
+//frame: [u32 topic_len][topic][u32 partition][u32 header_len][header][u32 value_len][value]
+//       [u64 start_offset][u8 has][u64 end_offset?][u8 has][u64 start_time?][u8 has][u64 end_time?][u32 max_results]
+
+impl HeaderQueryRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32(self.topic.len() as u32);
+        buf.extend_from_slice(self.topic.as_bytes());
+        buf.put_u32(self.partition);
+        buf.put_u32(self.header.len() as u32);
+        buf.extend_from_slice(self.header.as_bytes());
+        buf.put_u32(self.value.len() as u32);
+        buf.extend_from_slice(&self.value);
+        buf.put_u64(self.start_offset);
+        for bound in [self.end_offset, self.start_time, self.end_time] {
+            match bound {
+                Some(value) => {
+                    buf.put_u8(1);
+                    buf.put_u64(value);
+                }
+                None => buf.put_u8(0),
+            }
+        }
+        buf.put_u32(self.max_results);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        let topic = read_string(&mut buf, "topic")?;
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Insufficient data for partition".into()));
+        }
+        let partition = buf.get_u32();
+        let header = read_string(&mut buf, "header name")?;
+        let value = read_bytes(&mut buf, "header value")?.to_vec();
+        if buf.remaining() < 8 {
+            return Err(ProtocolError::PayloadError("Insufficient data for start offset".into()));
+        }
+        let start_offset = buf.get_u64();
+        let end_offset = read_optional_u64(&mut buf)?;
+        let start_time = read_optional_u64(&mut buf)?;
+        let end_time = read_optional_u64(&mut buf)?;
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Insufficient data for max results".into()));
+        }
+        let max_results = buf.get_u32();
+
+        Ok(Self {
+            topic,
+            partition,
+            header,
+            value,
+            start_offset,
+            end_offset,
+            start_time,
+            end_time,
+            max_results,
+        })
+    }
+}
+
+fn read_bytes(buf: &mut Bytes, what: &str) -> Result<Bytes, ProtocolError> {
+    if buf.remaining() < 4 {
+        return Err(ProtocolError::PayloadError(format!("Insufficient data for {} length", what)));
+    }
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len {
+        return Err(ProtocolError::PayloadError(format!("Insufficient data for {}", what)));
+    }
+    Ok(buf.split_to(len))
+}
+
+fn read_string(buf: &mut Bytes, what: &str) -> Result<String, ProtocolError> {
+    let bytes = read_bytes(buf, what)?;
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| ProtocolError::PayloadError(format!("Invalid UTF-8 in {}", what)))
+}
+
+fn read_optional_u64(buf: &mut Bytes) -> Result<Option<u64>, ProtocolError> {
+    if buf.remaining() < 1 {
+        return Err(ProtocolError::PayloadError("Insufficient data for range flag".into()));
+    }
+    if buf.get_u8() == 0 {
+        return Ok(None);
+    }
+    if buf.remaining() < 8 {
+        return Err(ProtocolError::PayloadError("Insufficient data for range bound".into()));
+    }
+    Ok(Some(buf.get_u64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_query_roundtrip() {
+        let req = HeaderQueryRequest {
+            topic: "events".into(),
+            partition: 1,
+            header: "tenant".into(),
+            value: b"acme".to_vec(),
+            start_offset: 10,
+            end_offset: None,
+            start_time: Some(1_700_000_000_000),
+            end_time: Some(1_700_003_600_000),
+            max_results: 500,
+        };
+
+        let parsed = HeaderQueryRequest::deserialize(req.serialize()).unwrap();
+
+        assert_eq!(parsed.topic, "events");
+        assert_eq!(parsed.partition, 1);
+        assert_eq!(parsed.header, "tenant");
+        assert_eq!(parsed.value, b"acme");
+        assert_eq!(parsed.start_offset, 10);
+        assert_eq!(parsed.end_offset, None);
+        assert_eq!(parsed.start_time, Some(1_700_000_000_000));
+        assert_eq!(parsed.end_time, Some(1_700_003_600_000));
+        assert_eq!(parsed.max_results, 500);
+    }
+}
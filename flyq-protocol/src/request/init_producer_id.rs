@@ -0,0 +1,64 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Asks the broker for a producer id, answered with an
+/// [`InitProducerIdResponse`](crate::InitProducerIdResponse). A producer calls
+/// this once at startup and stamps every produce with the id and its own
+/// sequence numbers.
+///
+/// With a `transactional_id` the same producer id is returned across restarts
+/// with a bumped epoch, which fences off the previous instance and aborts any
+/// transaction it left open.
+#[derive(Debug, Default)]
+pub struct InitProducerIdRequest {
+    pub transactional_id: Option<String>,
+}
+
+//frame: [u8 has][u32 transactional_id_len][transactional_id]
+//       an empty payload, from producers that predate transactions, has no transactional id
+
+impl InitProducerIdRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        match &self.transactional_id {
+            Some(id) => {
+                buf.put_u8(1);
+                buf.put_u32(id.len() as u32);
+                buf.extend_from_slice(id.as_bytes());
+            }
+            None => buf.put_u8(0),
+        }
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 1 || buf.get_u8() == 0 {
+            return Ok(InitProducerIdRequest { transactional_id: None });
+        }
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Incomplete transactional id".into()));
+        }
+        let len = buf.get_u32() as usize;
+        if buf.remaining() < len {
+            return Err(ProtocolError::PayloadError("Incomplete transactional id".into()));
+        }
+        let transactional_id = String::from_utf8(buf.split_to(len).to_vec())
+            .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in transactional id".into()))?;
+        Ok(InitProducerIdRequest { transactional_id: Some(transactional_id) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_producer_id_roundtrip() {
+        let req = InitProducerIdRequest { transactional_id: Some("order-service".into()) };
+        let parsed = InitProducerIdRequest::deserialize(req.serialize()).unwrap();
+        assert_eq!(parsed.transactional_id.as_deref(), Some("order-service"));
+
+        let legacy = InitProducerIdRequest::deserialize(Bytes::new()).unwrap();
+        assert_eq!(legacy.transactional_id, None);
+    }
+}
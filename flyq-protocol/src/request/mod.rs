@@ -2,14 +2,30 @@ mod commit_offset;
 pub mod consume;
 mod consume_with_group;
 mod consumer_lag;
+mod delete_records;
+mod fetch;
+mod header_query;
+mod init_producer_id;
 mod partition_health;
 pub mod produce;
+mod produce_batch;
+mod queue;
+mod retention_dry_run;
+mod transaction;
 mod watermark;
 
 pub use commit_offset::CommitOffsetRequest;
 pub use consume::ConsumeRequest;
 pub use consume_with_group::ConsumeWithGroupRequest;
 pub use consumer_lag::ConsumerLagRequest;
+pub use delete_records::DeleteRecordsRequest;
+pub use fetch::FetchRequest;
+pub use header_query::HeaderQueryRequest;
+pub use init_producer_id::InitProducerIdRequest;
 pub use partition_health::PartitionHealthRequest;
-pub use produce::ProduceRequest;
+pub use produce::{Acks, ProduceRequest, ProducerSequence};
+pub use produce_batch::ProduceBatchRequest;
+pub use queue::{LeaseRecordsRequest, SettleRecordsRequest};
+pub use retention_dry_run::RetentionDryRunRequest;
+pub use transaction::{AddPartitionsToTxnRequest, TransactionRequest, TxnOffsetCommitRequest};
 pub use watermark::WatermarkRequest;
@@ -5,8 +5,75 @@ use crate::errors::ProtocolError;
 pub struct ProduceRequest {
     pub topic: String,
     pub message: Bytes,
+    /// Set by idempotent producers: the record goes to `partition`, and the
+    /// broker appends it only if `sequence` is the next one it expects from
+    /// this producer there. Retries of an appended record are acked with the
+    /// offset it got the first time.
+    pub producer: Option<ProducerSequence>,
+    /// When the broker acknowledges the record.
+    pub acks: Acks,
+    /// How long the broker may take to reach `acks` before it answers with
+    /// [`ProduceStatus::Timeout`](crate::ProduceStatus::Timeout). 0 = the
+    /// broker's `produce_timeout`.
+    pub timeout_ms: u32,
+    /// Unix millis before which the record stays out of the log, invisible
+    /// to every fetch; it is appended, and gets its offset, once due. Not
+    /// supported together with `producer`.
+    pub deliver_at: Option<u64>,
+    /// Unix millis from which the record is expired: fetches skip it and
+    /// compaction drops its contents. Its offset stays taken.
+    pub expires_at: Option<u64>,
+    /// Headers stored with the record, e.g. for the topic's header index.
+    /// Names starting with [`RESERVED_HEADER_PREFIX`](crate::message::RESERVED_HEADER_PREFIX)
+    /// are the broker's and get the produce refused.
+    pub headers: Option<Vec<(String, Vec<u8>)>>,
 }
 
+/// Durability a producer waits for before its record counts as acknowledged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum Acks {
+    /// Fire and forget: the broker sends no `ProduceAck` at all.
+    None = 0,
+    /// Acked once the record is written to the partition's log file.
+    #[default]
+    Leader = 1,
+    /// Acked once the record is fsynced to disk.
+    Fsync = 2,
+}
+
+impl TryFrom<u8> for Acks {
+    type Error = ProtocolError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Acks::None),
+            1 => Ok(Acks::Leader),
+            2 => Ok(Acks::Fsync),
+            _ => Err(ProtocolError::PayloadError(format!("Unknown acks mode: {}", value))),
+        }
+    }
+}
+
+/// Identity and sequence number of a record from an idempotent producer.
+/// Sequences count per (producer id, partition), starting at 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProducerSequence {
+    pub producer_id: u64,
+    pub producer_epoch: u16,
+    pub partition: u32,
+    pub sequence: u32,
+    /// Part of the producer's open transaction; the partition must have been
+    /// added to it first.
+    pub transactional: bool,
+}
+
+//frame: [u32 topic_len][topic][u32 message_len][message]
+//       [u8 has][u64 producer_id][u16 producer_epoch][u32 partition][u32 sequence][u8 transactional]
+//       [u8 acks][u32 timeout_ms][u64 deliver_at, 0 = right away]
+//       [u64 expires_at, 0 = never], only when set or headers follow
+//       [u32 header_count][headers: (u32 key_len, key, u32 val_len, val)*], only when set
+
 impl ProduceRequest {
     pub fn serialize(&self) -> Bytes {
         let mut buf = BytesMut::new();
@@ -14,6 +81,32 @@ impl ProduceRequest {
         buf.extend_from_slice(self.topic.as_bytes());
         buf.put_u32(self.message.len() as u32);
         buf.extend_from_slice(&self.message);
+        match &self.producer {
+            Some(producer) => {
+                buf.put_u8(1);
+                buf.put_u64(producer.producer_id);
+                buf.put_u16(producer.producer_epoch);
+                buf.put_u32(producer.partition);
+                buf.put_u32(producer.sequence);
+                buf.put_u8(producer.transactional as u8);
+            }
+            None => buf.put_u8(0),
+        }
+        buf.put_u8(self.acks as u8);
+        buf.put_u32(self.timeout_ms);
+        buf.put_u64(self.deliver_at.unwrap_or(0));
+        if self.expires_at.is_some() || self.headers.is_some() {
+            buf.put_u64(self.expires_at.unwrap_or(0));
+        }
+        if let Some(headers) = &self.headers {
+            buf.put_u32(headers.len() as u32);
+            for (name, value) in headers {
+                buf.put_u32(name.len() as u32);
+                buf.extend_from_slice(name.as_bytes());
+                buf.put_u32(value.len() as u32);
+                buf.extend_from_slice(value);
+            }
+        }
         buf.freeze()
     }
 
@@ -34,6 +127,138 @@ impl ProduceRequest {
         }
         let message = buf.split_to(message_len);
 
-        Ok(ProduceRequest { topic, message })
+        // Older clients end the payload here
+        let producer = if buf.remaining() >= 1 && buf.get_u8() == 1 {
+            if buf.remaining() < 18 {
+                return Err(ProtocolError::PayloadError("Incomplete producer sequence".into()));
+            }
+            Some(ProducerSequence {
+                producer_id: buf.get_u64(),
+                producer_epoch: buf.get_u16(),
+                partition: buf.get_u32(),
+                sequence: buf.get_u32(),
+                // producers from before transactions end here
+                transactional: buf.remaining() >= 1 && buf.get_u8() == 1,
+            })
+        } else {
+            None
+        };
+
+        // Clients from before acks modes end the payload here
+        let (acks, timeout_ms) = match buf.remaining() {
+            0 => (Acks::Leader, 0),
+            1..=4 => return Err(ProtocolError::PayloadError("Incomplete acks and timeout".into())),
+            _ => (Acks::try_from(buf.get_u8())?, buf.get_u32()),
+        };
+        // Clients from before delayed delivery end the payload here
+        let deliver_at = match buf.remaining() {
+            0 => None,
+            1..=7 => return Err(ProtocolError::PayloadError("Incomplete delivery time".into())),
+            _ => Some(buf.get_u64()).filter(|&at| at > 0),
+        };
+        // Records without a TTL or headers end the payload here
+        let expires_at = match buf.remaining() {
+            0 => None,
+            1..=7 => return Err(ProtocolError::PayloadError("Incomplete expiry time".into())),
+            _ => Some(buf.get_u64()).filter(|&at| at > 0),
+        };
+        // Records without headers end the payload here
+        let headers = match buf.remaining() {
+            0 => None,
+            1..=3 => return Err(ProtocolError::PayloadError("Incomplete headers".into())),
+            _ => Some(Self::read_headers(&mut buf)?),
+        };
+
+        Ok(ProduceRequest { topic, message, producer, acks, timeout_ms, deliver_at, expires_at, headers })
+    }
+
+    fn read_headers(buf: &mut Bytes) -> Result<Vec<(String, Vec<u8>)>, ProtocolError> {
+        let incomplete = || ProtocolError::PayloadError("Incomplete headers".into());
+        let count = buf.get_u32() as usize;
+        let mut headers = Vec::with_capacity(count.min(buf.remaining() / 8));
+        for _ in 0..count {
+            if buf.remaining() < 4 {
+                return Err(incomplete());
+            }
+            let name_len = buf.get_u32() as usize;
+            if buf.remaining() < name_len + 4 {
+                return Err(incomplete());
+            }
+            let name = String::from_utf8(buf.split_to(name_len).to_vec())
+                .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in header name".into()))?;
+            let value_len = buf.get_u32() as usize;
+            if buf.remaining() < value_len {
+                return Err(incomplete());
+            }
+            headers.push((name, buf.split_to(value_len).to_vec()));
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_produce_with_producer_sequence_roundtrip() {
+        let req = ProduceRequest {
+            topic: "payments".into(),
+            message: Bytes::from_static(b"charge"),
+            producer: Some(ProducerSequence {
+                producer_id: 42,
+                producer_epoch: 1,
+                partition: 3,
+                sequence: 7,
+                transactional: true,
+            }),
+            acks: Acks::Fsync,
+            timeout_ms: 5000,
+            deliver_at: Some(1_700_000_900_000),
+            expires_at: Some(1_700_000_960_000),
+            headers: Some(vec![("order-id".into(), b"A-17".to_vec()), ("region".into(), Vec::new())]),
+        };
+
+        let parsed = ProduceRequest::deserialize(req.serialize()).unwrap();
+
+        assert_eq!(parsed.topic, "payments");
+        assert_eq!(parsed.message, Bytes::from_static(b"charge"));
+        assert_eq!(parsed.producer, req.producer);
+        assert_eq!((parsed.acks, parsed.timeout_ms), (Acks::Fsync, 5000));
+        assert_eq!(parsed.deliver_at, req.deliver_at);
+        assert_eq!(parsed.expires_at, req.expires_at);
+        assert_eq!(parsed.headers, req.headers);
+
+        // headers without a TTL
+        let without_ttl = ProduceRequest { expires_at: None, ..req };
+        let parsed = ProduceRequest::deserialize(without_ttl.serialize()).unwrap();
+        assert_eq!((parsed.expires_at, parsed.headers.as_ref()), (None, without_ttl.headers.as_ref()));
+        let mut cut = without_ttl.serialize().to_vec();
+        cut.pop();
+        assert!(ProduceRequest::deserialize(Bytes::from(cut)).is_err());
+
+        // without a TTL or headers the request ends after the delivery time
+        let with_ttl = ProduceRequest { expires_at: req.expires_at, headers: None, ..without_ttl };
+        let with_ttl_len = with_ttl.serialize().len();
+        let plain = ProduceRequest { expires_at: None, ..with_ttl };
+        assert_eq!(plain.serialize().len(), with_ttl_len - 8);
+        assert_eq!(ProduceRequest::deserialize(plain.serialize()).unwrap().expires_at, None);
+    }
+
+    #[test]
+    fn test_produce_without_producer_sequence() {
+        // payload from a client that predates idempotent produce
+        let mut buf = BytesMut::new();
+        buf.put_u32(6);
+        buf.extend_from_slice(b"events");
+        buf.put_u32(2);
+        buf.extend_from_slice(b"hi");
+
+        let parsed = ProduceRequest::deserialize(buf.freeze()).unwrap();
+
+        assert_eq!(parsed.message, Bytes::from_static(b"hi"));
+        assert_eq!(parsed.producer, None);
+        assert_eq!((parsed.acks, parsed.timeout_ms), (Acks::Leader, 0));
+        assert_eq!(parsed.headers, None);
     }
 }
@@ -0,0 +1,116 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use crate::errors::ProtocolError;
+use crate::record_batch::RecordBatch;
+use crate::request::{Acks, ProducerSequence};
+
+/// Appends a v2 record batch to one partition as it is. Answered with a
+/// [`ProduceAck`](crate::ProduceAck) carrying the offset of the first record.
+#[derive(Debug)]
+pub struct ProduceBatchRequest {
+    pub topic: String,
+    pub partition: u32,
+    pub acks: Acks,
+    /// As for [`ProduceRequest`](crate::ProduceRequest); 0 = the broker's `produce_timeout`.
+    pub timeout_ms: u32,
+    /// Its base offset is ignored, the broker assigns the next ones.
+    pub batch: RecordBatch,
+    /// Idempotent or transactional producer the batch is from. Batches do not
+    /// carry producer sequences in the log yet, so the broker refuses batches
+    /// that set it; such producers send their records with `Produce`.
+    pub producer: Option<ProducerSequence>,
+}
+
+//frame: [u32 topic_len][topic][u32 partition][u8 acks][u32 timeout_ms][u32 batch_len][batch]
+//       [u64 producer_id][u16 producer_epoch][u32 partition][u32 sequence][u8 transactional], only when set
+
+impl ProduceBatchRequest {
+    pub fn serialize(&self) -> Bytes {
+        let batch = self.batch.as_bytes();
+        let mut buf = BytesMut::with_capacity(4 + self.topic.len() + 13 + batch.len());
+        buf.put_u32(self.topic.len() as u32);
+        buf.extend_from_slice(self.topic.as_bytes());
+        buf.put_u32(self.partition);
+        buf.put_u8(self.acks as u8);
+        buf.put_u32(self.timeout_ms);
+        buf.put_u32(batch.len() as u32);
+        buf.extend_from_slice(batch);
+        if let Some(producer) = &self.producer {
+            buf.put_u64(producer.producer_id);
+            buf.put_u16(producer.producer_epoch);
+            buf.put_u32(producer.partition);
+            buf.put_u32(producer.sequence);
+            buf.put_u8(producer.transactional as u8);
+        }
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Incomplete produce batch payload".into()));
+        }
+        let topic_len = buf.get_u32() as usize;
+        if buf.remaining() < topic_len + 13 {
+            return Err(ProtocolError::PayloadError("Incomplete produce batch payload".into()));
+        }
+        let topic = String::from_utf8(buf.split_to(topic_len).to_vec())
+            .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in topic".into()))?;
+        let partition = buf.get_u32();
+        let acks = Acks::try_from(buf.get_u8())?;
+        let timeout_ms = buf.get_u32();
+        let batch_len = buf.get_u32() as usize;
+        if buf.remaining() < batch_len {
+            return Err(ProtocolError::PayloadError("Incomplete record batch".into()));
+        }
+        let batch = RecordBatch::from_bytes(buf.split_to(batch_len).to_vec())?;
+        // Batches without a producer end the payload here
+        let producer = match buf.remaining() {
+            0 => None,
+            1..=18 => return Err(ProtocolError::PayloadError("Incomplete producer sequence".into())),
+            _ => Some(ProducerSequence {
+                producer_id: buf.get_u64(),
+                producer_epoch: buf.get_u16(),
+                partition: buf.get_u32(),
+                sequence: buf.get_u32(),
+                transactional: buf.get_u8() == 1,
+            }),
+        };
+
+        Ok(ProduceBatchRequest { topic, partition, acks, timeout_ms, batch, producer })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    #[test]
+    fn test_produce_batch_roundtrip() {
+        let messages: Vec<Message> = (0..3)
+            .map(|i| Message { key: None, value: vec![b'a' + i], timestamp: 1_000 + i as u64, headers: None })
+            .collect();
+        let req = ProduceBatchRequest {
+            topic: "metrics".into(),
+            partition: 2,
+            acks: Acks::Fsync,
+            timeout_ms: 250,
+            batch: RecordBatch::encode(0, &messages),
+            producer: None,
+        };
+
+        let parsed = ProduceBatchRequest::deserialize(req.serialize()).unwrap();
+
+        assert_eq!((parsed.topic.as_str(), parsed.partition), ("metrics", 2));
+        assert_eq!((parsed.acks, parsed.timeout_ms), (Acks::Fsync, 250));
+        assert_eq!(parsed.batch, req.batch);
+        assert_eq!(parsed.producer, None);
+
+        let mut corrupt = req.serialize().to_vec();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(ProduceBatchRequest::deserialize(Bytes::from(corrupt)).is_err());
+
+        let producer = ProducerSequence { producer_id: 9, producer_epoch: 1, partition: 2, sequence: 4, transactional: true };
+        let stamped = ProduceBatchRequest { producer: Some(producer), ..req };
+        assert_eq!(ProduceBatchRequest::deserialize(stamped.serialize()).unwrap().producer, Some(producer));
+    }
+}
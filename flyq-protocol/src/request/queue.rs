@@ -0,0 +1,147 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Leases up to `max_records` records of a partition to a queue-mode group
+/// member. Each record is handed to one member at a time; it comes back to
+/// the group unless acked within `visibility_timeout_ms`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseRecordsRequest {
+    pub group: String,
+    pub topic: String,
+    pub partition: u32,
+    pub max_records: u32,
+    pub visibility_timeout_ms: u32,
+}
+
+//frame: [u32 group_len][group][u32 topic_len][topic][u32 partition][u32 max_records][u32 visibility_timeout_ms]
+
+impl LeaseRecordsRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        put_group_partition(&mut buf, &self.group, &self.topic, self.partition);
+        buf.put_u32(self.max_records);
+        buf.put_u32(self.visibility_timeout_ms);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        let (group, topic, partition) = read_group_partition(&mut buf)?;
+        if buf.remaining() < 8 {
+            return Err(ProtocolError::PayloadError("Insufficient data for max records + visibility timeout".into()));
+        }
+        Ok(Self {
+            group,
+            topic,
+            partition,
+            max_records: buf.get_u32(),
+            visibility_timeout_ms: buf.get_u32(),
+        })
+    }
+}
+
+/// Settles leased records, depending on the opcode it is sent with:
+/// `AckRecords` marks them done, `NackRecords` hands them back for
+/// redelivery right away. Each record is named by its offset and the
+/// delivery count it was leased with, so a member whose lease expired cannot
+/// settle the record for the member holding it now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettleRecordsRequest {
+    pub group: String,
+    pub topic: String,
+    pub partition: u32,
+    pub records: Vec<(u64, u32)>, // (offset, delivery_count)
+    /// Why the records failed, for nacks; kept with a record that ends up in
+    /// a dead-letter topic. Empty = no reason given.
+    pub error: String,
+}
+
+//frame: [u32 group_len][group][u32 topic_len][topic][u32 partition][u32 count]([u64 offset][u32 delivery_count])*
+//       [u32 error_len][error]
+
+impl SettleRecordsRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        put_group_partition(&mut buf, &self.group, &self.topic, self.partition);
+        buf.put_u32(self.records.len() as u32);
+        for (offset, delivery_count) in &self.records {
+            buf.put_u64(*offset);
+            buf.put_u32(*delivery_count);
+        }
+        buf.put_u32(self.error.len() as u32);
+        buf.extend_from_slice(self.error.as_bytes());
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        let (group, topic, partition) = read_group_partition(&mut buf)?;
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Insufficient data for record count".into()));
+        }
+        let count = buf.get_u32() as usize;
+        if buf.remaining() < count * 12 {
+            return Err(ProtocolError::PayloadError("Insufficient data for records".into()));
+        }
+        let records = (0..count).map(|_| (buf.get_u64(), buf.get_u32())).collect();
+        // Older clients end the payload here
+        let error = match buf.has_remaining() {
+            true => read_string(&mut buf, "error")?,
+            false => String::new(),
+        };
+        Ok(Self { group, topic, partition, records, error })
+    }
+}
+
+fn put_group_partition(buf: &mut BytesMut, group: &str, topic: &str, partition: u32) {
+    buf.put_u32(group.len() as u32);
+    buf.extend_from_slice(group.as_bytes());
+    buf.put_u32(topic.len() as u32);
+    buf.extend_from_slice(topic.as_bytes());
+    buf.put_u32(partition);
+}
+
+fn read_group_partition(buf: &mut Bytes) -> Result<(String, String, u32), ProtocolError> {
+    let group = read_string(buf, "group")?;
+    let topic = read_string(buf, "topic")?;
+    if buf.remaining() < 4 {
+        return Err(ProtocolError::PayloadError("Insufficient data for partition".into()));
+    }
+    Ok((group, topic, buf.get_u32()))
+}
+
+fn read_string(buf: &mut Bytes, field: &str) -> Result<String, ProtocolError> {
+    if buf.remaining() < 4 {
+        return Err(ProtocolError::PayloadError(format!("Insufficient data for {} length", field)));
+    }
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len {
+        return Err(ProtocolError::PayloadError(format!("Insufficient data for {}", field)));
+    }
+    String::from_utf8(buf.split_to(len).to_vec())
+        .map_err(|_| ProtocolError::PayloadError(format!("Invalid UTF-8 in {}", field)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_requests_roundtrip() {
+        let lease = LeaseRecordsRequest {
+            group: "thumbnailers".into(),
+            topic: "jobs".into(),
+            partition: 2,
+            max_records: 10,
+            visibility_timeout_ms: 30_000,
+        };
+        let settle = SettleRecordsRequest {
+            group: "thumbnailers".into(),
+            topic: "jobs".into(),
+            partition: 2,
+            records: vec![(41, 1), (43, 3)],
+            error: "timeout talking to resizer".into(),
+        };
+
+        assert_eq!(LeaseRecordsRequest::deserialize(lease.serialize()).unwrap(), lease);
+        assert_eq!(SettleRecordsRequest::deserialize(settle.serialize()).unwrap(), settle);
+    }
+}
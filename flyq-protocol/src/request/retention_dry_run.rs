@@ -0,0 +1,101 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Asks which segments a cleanup pass would delete. Unset limits fall back to
+/// the broker's current retention config, so a request with both unset
+/// previews the next real cleanup.
+#[derive(Debug)]
+pub struct RetentionDryRunRequest {
+    pub topic: String,
+    pub partition: u32,
+    pub retention_ms: Option<u64>,
+    pub retention_bytes: Option<u64>,
+}
+
+//frame: [u32 topic_len][topic bytes][u32 partition][u8 has_ms][u64 ms?][u8 has_bytes][u64 bytes?]
+
+impl RetentionDryRunRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32(self.topic.len() as u32);
+        buf.extend_from_slice(self.topic.as_bytes());
+        buf.put_u32(self.partition);
+        for limit in [self.retention_ms, self.retention_bytes] {
+            match limit {
+                Some(value) => {
+                    buf.put_u8(1);
+                    buf.put_u64(value);
+                }
+                None => buf.put_u8(0),
+            }
+        }
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError(
+                "Insufficient data for topic length".into(),
+            ));
+        }
+        let topic_len = buf.get_u32();
+
+        if buf.remaining() < (topic_len + 4) as usize {
+            return Err(ProtocolError::PayloadError(
+                "Insufficient data for topic + partition".into(),
+            ));
+        }
+        let topic = String::from_utf8(buf.split_to(topic_len as usize).to_vec())
+            .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in topic".into()))?;
+        let partition = buf.get_u32();
+
+        let retention_ms = read_optional_u64(&mut buf)?;
+        let retention_bytes = read_optional_u64(&mut buf)?;
+
+        Ok(Self {
+            topic,
+            partition,
+            retention_ms,
+            retention_bytes,
+        })
+    }
+}
+
+fn read_optional_u64(buf: &mut Bytes) -> Result<Option<u64>, ProtocolError> {
+    if buf.remaining() < 1 {
+        return Err(ProtocolError::PayloadError(
+            "Insufficient data for retention override flag".into(),
+        ));
+    }
+    if buf.get_u8() == 0 {
+        return Ok(None);
+    }
+    if buf.remaining() < 8 {
+        return Err(ProtocolError::PayloadError(
+            "Insufficient data for retention override".into(),
+        ));
+    }
+    Ok(Some(buf.get_u64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention_dry_run_roundtrip() {
+        let req = RetentionDryRunRequest {
+            topic: "events".into(),
+            partition: 3,
+            retention_ms: Some(60_000),
+            retention_bytes: None,
+        };
+
+        let parsed = RetentionDryRunRequest::deserialize(req.serialize()).unwrap();
+
+        assert_eq!(parsed.topic, req.topic);
+        assert_eq!(parsed.partition, req.partition);
+        assert_eq!(parsed.retention_ms, Some(60_000));
+        assert_eq!(parsed.retention_bytes, None);
+    }
+}
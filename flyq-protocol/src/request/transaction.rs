@@ -0,0 +1,192 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Begins, commits or aborts the transaction of a transactional producer,
+/// depending on the opcode it is sent with (`BeginTransaction`,
+/// `CommitTransaction`, `AbortTransaction`). Answered with a
+/// [`TransactionResponse`](crate::TransactionResponse).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionRequest {
+    pub transactional_id: String,
+    pub producer_id: u64,
+    pub producer_epoch: u16,
+}
+
+//frame: [u32 transactional_id_len][transactional_id][u64 producer_id][u16 producer_epoch]
+
+impl TransactionRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32(self.transactional_id.len() as u32);
+        buf.extend_from_slice(self.transactional_id.as_bytes());
+        buf.put_u64(self.producer_id);
+        buf.put_u16(self.producer_epoch);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        let transactional_id = read_transactional_id(&mut buf)?;
+        if buf.remaining() < 10 {
+            return Err(ProtocolError::PayloadError("Insufficient data for producer id and epoch".into()));
+        }
+        let producer_id = buf.get_u64();
+        let producer_epoch = buf.get_u16();
+        Ok(Self { transactional_id, producer_id, producer_epoch })
+    }
+}
+
+/// Adds partitions to the producer's open transaction. Transactional produces
+/// to a partition are rejected until it has been added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddPartitionsToTxnRequest {
+    pub transaction: TransactionRequest,
+    pub partitions: Vec<(String, u32)>, // (topic, partition)
+}
+
+//frame: [transaction request][u32 count]([u32 topic_len][topic][u32 partition])*
+
+impl AddPartitionsToTxnRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::from(&self.transaction.serialize()[..]);
+        buf.put_u32(self.partitions.len() as u32);
+        for (topic, partition) in &self.partitions {
+            buf.put_u32(topic.len() as u32);
+            buf.extend_from_slice(topic.as_bytes());
+            buf.put_u32(*partition);
+        }
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        let transactional_id = read_transactional_id(&mut buf)?;
+        if buf.remaining() < 14 {
+            return Err(ProtocolError::PayloadError("Insufficient data for producer id and epoch".into()));
+        }
+        let transaction = TransactionRequest {
+            transactional_id,
+            producer_id: buf.get_u64(),
+            producer_epoch: buf.get_u16(),
+        };
+        let count = buf.get_u32();
+        let mut partitions = Vec::new();
+        for _ in 0..count {
+            if buf.remaining() < 4 {
+                return Err(ProtocolError::PayloadError("Insufficient data for topic length".into()));
+            }
+            let topic_len = buf.get_u32() as usize;
+            if buf.remaining() < topic_len + 4 {
+                return Err(ProtocolError::PayloadError("Insufficient data for topic + partition".into()));
+            }
+            let topic = String::from_utf8(buf.split_to(topic_len).to_vec())
+                .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in topic".into()))?;
+            partitions.push((topic, buf.get_u32()));
+        }
+        Ok(Self { transaction, partitions })
+    }
+}
+
+/// Consumer group offsets to commit as part of the producer's open
+/// transaction: they are applied when it commits and dropped if it aborts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxnOffsetCommitRequest {
+    pub transaction: TransactionRequest,
+    pub group: String,
+    pub offsets: Vec<(String, u32, u64)>, // (topic, partition, offset)
+}
+
+//frame: [transaction request][u32 group_len][group][u32 count]([u32 topic_len][topic][u32 partition][u64 offset])*
+
+impl TxnOffsetCommitRequest {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::from(&self.transaction.serialize()[..]);
+        buf.put_u32(self.group.len() as u32);
+        buf.extend_from_slice(self.group.as_bytes());
+        buf.put_u32(self.offsets.len() as u32);
+        for (topic, partition, offset) in &self.offsets {
+            buf.put_u32(topic.len() as u32);
+            buf.extend_from_slice(topic.as_bytes());
+            buf.put_u32(*partition);
+            buf.put_u64(*offset);
+        }
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        let transactional_id = read_transactional_id(&mut buf)?;
+        if buf.remaining() < 10 {
+            return Err(ProtocolError::PayloadError("Insufficient data for producer id and epoch".into()));
+        }
+        let transaction = TransactionRequest {
+            transactional_id,
+            producer_id: buf.get_u64(),
+            producer_epoch: buf.get_u16(),
+        };
+        let group = read_string(&mut buf, "group")?;
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Insufficient data for offset count".into()));
+        }
+        let count = buf.get_u32();
+        let mut offsets = Vec::new();
+        for _ in 0..count {
+            let topic = read_string(&mut buf, "topic")?;
+            if buf.remaining() < 12 {
+                return Err(ProtocolError::PayloadError("Insufficient data for partition + offset".into()));
+            }
+            offsets.push((topic, buf.get_u32(), buf.get_u64()));
+        }
+        Ok(Self { transaction, group, offsets })
+    }
+}
+
+fn read_transactional_id(buf: &mut Bytes) -> Result<String, ProtocolError> {
+    read_string(buf, "transactional id")
+}
+
+fn read_string(buf: &mut Bytes, field: &str) -> Result<String, ProtocolError> {
+    if buf.remaining() < 4 {
+        return Err(ProtocolError::PayloadError(format!("Insufficient data for {} length", field)));
+    }
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len {
+        return Err(ProtocolError::PayloadError(format!("Insufficient data for {}", field)));
+    }
+    String::from_utf8(buf.split_to(len).to_vec())
+        .map_err(|_| ProtocolError::PayloadError(format!("Invalid UTF-8 in {}", field)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_partitions_to_txn_roundtrip() {
+        let req = AddPartitionsToTxnRequest {
+            transaction: TransactionRequest {
+                transactional_id: "order-service".into(),
+                producer_id: 9,
+                producer_epoch: 2,
+            },
+            partitions: vec![("orders".into(), 0), ("ledger".into(), 3)],
+        };
+
+        let parsed = AddPartitionsToTxnRequest::deserialize(req.serialize()).unwrap();
+
+        assert_eq!(parsed, req);
+        assert_eq!(TransactionRequest::deserialize(req.transaction.serialize()).unwrap(), req.transaction);
+    }
+
+    #[test]
+    fn test_txn_offset_commit_roundtrip() {
+        let req = TxnOffsetCommitRequest {
+            transaction: TransactionRequest {
+                transactional_id: "enricher".into(),
+                producer_id: 4,
+                producer_epoch: 0,
+            },
+            group: "enricher-group".into(),
+            offsets: vec![("clicks".into(), 0, 120), ("clicks".into(), 1, 87)],
+        };
+
+        assert_eq!(TxnOffsetCommitRequest::deserialize(req.serialize()).unwrap(), req);
+    }
+}
@@ -0,0 +1,73 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use crate::errors::ProtocolError;
+use crate::response::ConsumeResponse;
+
+/// Answer to a [`ConsumeRequest`](crate::ConsumeRequest) with `fetch_last`
+/// set: records in descending offset order.
+#[derive(Debug)]
+pub struct ConsumeBatchResponse {
+    pub records: Vec<ConsumeResponse>,
+}
+
+//frame: [u32 count] then per record [u32 len][ConsumeResponse bytes]
+
+impl ConsumeBatchResponse {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32(self.records.len() as u32);
+        for record in &self.records {
+            let bytes = record.serialize();
+            buf.put_u32(bytes.len() as u32);
+            buf.extend_from_slice(&bytes);
+        }
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Insufficient data for record count".into()));
+        }
+        let count = buf.get_u32();
+        let mut records = Vec::new();
+        for _ in 0..count {
+            if buf.remaining() < 4 {
+                return Err(ProtocolError::PayloadError("Insufficient data for record length".into()));
+            }
+            let len = buf.get_u32() as usize;
+            if buf.remaining() < len {
+                return Err(ProtocolError::PayloadError("Insufficient data for record".into()));
+            }
+            records.push(ConsumeResponse::deserialize(buf.split_to(len))?);
+        }
+        Ok(Self { records })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    #[test]
+    fn test_consume_batch_response_roundtrip() {
+        let record = |offset: u64| ConsumeResponse {
+            offset,
+            message: Message {
+                key: Some(b"k".to_vec()),
+                value: format!("value-{}", offset).into_bytes(),
+                timestamp: 1000 + offset,
+                headers: None,
+            },
+        };
+        let original = ConsumeBatchResponse {
+            records: vec![record(9), record(8), record(7)],
+        };
+
+        let parsed = ConsumeBatchResponse::deserialize(original.serialize()).unwrap();
+
+        let offsets: Vec<u64> = parsed.records.iter().map(|r| r.offset).collect();
+        assert_eq!(offsets, vec![9, 8, 7]);
+        assert_eq!(parsed.records[1].message.value, b"value-8");
+        assert_eq!(parsed.records[2].message.timestamp, 1007);
+    }
+}
@@ -0,0 +1,26 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+#[derive(Debug)]
+pub struct DeleteRecordsResponse {
+    pub low_watermark: u64, // log start offset after the request was applied
+}
+
+impl DeleteRecordsResponse {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(8);
+        buf.put_u64(self.low_watermark);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 8 {
+            return Err(ProtocolError::PayloadError(
+                "Incomplete delete records response payload".into(),
+            ));
+        }
+        let low_watermark = buf.get_u64();
+
+        Ok(Self { low_watermark })
+    }
+}
@@ -0,0 +1,59 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use crate::errors::ProtocolError;
+use crate::record_batch::{read_batches, RecordBatch};
+
+/// Answer to a [`FetchRequest`](crate::FetchRequest).
+#[derive(Debug)]
+pub struct FetchResponse {
+    pub log_end_offset: u64,
+    /// Length-prefixed batches as written by [`RecordBatch::to_entry`],
+    /// copied from the segments as they are. The first one may start below
+    /// the requested offset and any of them may hold expired records; both
+    /// are for the consumer to skip.
+    pub batches: Bytes,
+}
+
+//frame: [u64 log_end_offset][batches...]
+
+impl FetchResponse {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(8 + self.batches.len());
+        buf.put_u64(self.log_end_offset);
+        buf.extend_from_slice(&self.batches);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 8 {
+            return Err(ProtocolError::PayloadError("Incomplete fetch response".into()));
+        }
+        let log_end_offset = buf.get_u64();
+        Ok(FetchResponse { log_end_offset, batches: buf })
+    }
+
+    /// Splits and checks the batches; records are not decoded.
+    pub fn record_batches(&self) -> Result<Vec<RecordBatch>, ProtocolError> {
+        Ok(read_batches(&self.batches)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+
+    #[test]
+    fn test_fetch_response_roundtrip() {
+        let message = |value: &[u8]| Message { key: None, value: value.to_vec(), timestamp: 1, headers: None };
+        let mut batches = RecordBatch::encode(5, &[message(b"a"), message(b"b")]).to_entry();
+        batches.extend(RecordBatch::from_records(&[(9, message(b"c"))]).to_entry());
+        let original = FetchResponse { log_end_offset: 10, batches: Bytes::from(batches) };
+
+        let parsed = FetchResponse::deserialize(original.serialize()).unwrap();
+
+        assert_eq!(parsed.log_end_offset, 10);
+        let batches = parsed.record_batches().unwrap();
+        let offsets: Vec<u64> = batches.iter().flat_map(|b| b.records().unwrap()).map(|(o, _)| o).collect();
+        assert_eq!(offsets, vec![5, 6, 9]);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitProducerIdResponse {
+    pub producer_id: u64,
+    pub producer_epoch: u16,
+}
+
+//frame: [u64 producer_id][u16 producer_epoch]
+
+impl InitProducerIdResponse {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(10);
+        buf.put_u64(self.producer_id);
+        buf.put_u16(self.producer_epoch);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 10 {
+            return Err(ProtocolError::PayloadError("Incomplete init producer id payload".into()));
+        }
+        Ok(Self {
+            producer_id: buf.get_u64(),
+            producer_epoch: buf.get_u16(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_producer_id_response_roundtrip() {
+        let resp = InitProducerIdResponse { producer_id: 1 << 40, producer_epoch: 3 };
+
+        assert_eq!(InitProducerIdResponse::deserialize(resp.serialize()).unwrap(), resp);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::message::Message;
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Answer to [`LeaseRecordsRequest`](crate::LeaseRecordsRequest): the leased
+/// records, redeliveries first. Empty when nothing is available right now.
+#[derive(Debug)]
+pub struct LeaseRecordsResponse {
+    pub records: Vec<LeasedRecord>,
+}
+
+#[derive(Debug)]
+pub struct LeasedRecord {
+    pub offset: u64,
+    /// 1 on the first delivery, counting up with every redelivery. Needed to
+    /// ack or nack the record.
+    pub delivery_count: u32,
+    pub message: Message,
+}
+
+//frame: [u32 count] then per record [u64 offset][u32 delivery_count][u32 len][message]
+
+impl LeaseRecordsResponse {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32(self.records.len() as u32);
+        for record in &self.records {
+            let message = record.message.serialize_for_wire();
+            buf.put_u64(record.offset);
+            buf.put_u32(record.delivery_count);
+            buf.put_u32(message.len() as u32);
+            buf.extend_from_slice(&message);
+        }
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 4 {
+            return Err(ProtocolError::PayloadError("Insufficient data for record count".into()));
+        }
+        let count = buf.get_u32();
+        let mut records = Vec::new();
+        for _ in 0..count {
+            if buf.remaining() < 16 {
+                return Err(ProtocolError::PayloadError("Insufficient data for leased record".into()));
+            }
+            let offset = buf.get_u64();
+            let delivery_count = buf.get_u32();
+            let len = buf.get_u32() as usize;
+            if buf.remaining() < len {
+                return Err(ProtocolError::PayloadError("Insufficient data for message".into()));
+            }
+            let message = Message::deserialize_body(&buf.split_to(len))?;
+            records.push(LeasedRecord { offset, delivery_count, message });
+        }
+        Ok(Self { records })
+    }
+}
+
+/// Answer to `AckRecords` and `NackRecords`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SettleRecordsResponse {
+    /// Records settled. The rest were no longer leased with the given
+    /// delivery count, typically because the lease expired.
+    pub settled: u32,
+    /// Offset the group has committed after this request: every record
+    /// below it is acked.
+    pub committed_offset: u64,
+}
+
+//frame: [u32 settled][u64 committed_offset]
+
+impl SettleRecordsResponse {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(12);
+        buf.put_u32(self.settled);
+        buf.put_u64(self.committed_offset);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 12 {
+            return Err(ProtocolError::PayloadError("Incomplete settle response payload".into()));
+        }
+        Ok(Self { settled: buf.get_u32(), committed_offset: buf.get_u64() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_records_response_roundtrip() {
+        let original = LeaseRecordsResponse {
+            records: vec![LeasedRecord {
+                offset: 12,
+                delivery_count: 2,
+                message: Message {
+                    key: Some(b"job-12".to_vec()),
+                    value: b"resize".to_vec(),
+                    timestamp: 1000,
+                    headers: None,
+                },
+            }],
+        };
+
+        let parsed = LeaseRecordsResponse::deserialize(original.serialize()).unwrap();
+
+        assert_eq!(parsed.records.len(), 1);
+        assert_eq!((parsed.records[0].offset, parsed.records[0].delivery_count), (12, 2));
+        assert_eq!(parsed.records[0].message.value, b"resize");
+        let settled = SettleRecordsResponse { settled: 3, committed_offset: 40 };
+        assert_eq!(SettleRecordsResponse::deserialize(settled.serialize()).unwrap(), settled);
+    }
+}
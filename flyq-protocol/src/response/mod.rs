@@ -1,11 +1,27 @@
+mod consume_batch_response;
 mod consumer_lag_response;
 pub mod consume_response;
+mod delete_records_response;
+mod fetch_response;
+mod init_producer_id_response;
+mod lease_records_response;
 mod partition_health_response;
 pub mod produce_ack;
+mod retention_dry_run_response;
+mod transaction_response;
 mod watermark_response;
 
+pub use consume_batch_response::ConsumeBatchResponse;
 pub use consumer_lag_response::{ConsumerLagResponse, PartitionLag};
 pub use consume_response::ConsumeResponse;
-pub use partition_health_response::PartitionHealthResponse;
-pub use produce_ack::ProduceAck;
+pub use delete_records_response::DeleteRecordsResponse;
+pub use fetch_response::FetchResponse;
+pub use init_producer_id_response::InitProducerIdResponse;
+pub use lease_records_response::{LeaseRecordsResponse, LeasedRecord, SettleRecordsResponse};
+pub use partition_health_response::{
+    CleanupRecord, PartitionHealthResponse, ScrubProblemRecord, ScrubReport,
+};
+pub use produce_ack::{ProduceAck, ProduceStatus};
+pub use retention_dry_run_response::{DryRunSegment, RetentionDryRunResponse};
+pub use transaction_response::{TransactionResponse, TransactionStatus};
 pub use watermark_response::WatermarkResponse;
@@ -11,6 +11,36 @@ pub struct PartitionHealthResponse {
     pub high_watermark: u64,
     pub log_end_offset: u64,
     pub last_cleanup: Option<u64>, // Unix timestamp in nanoseconds
+    pub cleanup_history: Vec<CleanupRecord>, // oldest first
+    pub scrub: Option<ScrubReport>, // None until a scrub pass finished
+    pub delayed_messages: u64, // scheduled for later delivery, not in the log yet
+    pub expired_records: u64, // dropped by compaction since the broker started
+}
+
+/// One retention or delete-records run that removed segments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CleanupRecord {
+    pub timestamp_ns: u64,
+    pub segments_removed: u32,
+    pub bytes_freed: u64,
+    pub reason: String,
+    pub low_watermark: u64, // low watermark right after the run
+}
+
+/// Outcome of the broker's background integrity scrub of sealed segments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrubReport {
+    pub last_pass_ns: u64, // when the last complete pass finished
+    pub segments_scrubbed: u64, // totals since the broker started
+    pub bytes_scrubbed: u64,
+    pub problems: Vec<ScrubProblemRecord>, // found by the last pass
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrubProblemRecord {
+    pub segment_base_offset: u64,
+    pub detected_ns: u64,
+    pub description: String,
 }
 
 impl PartitionHealthResponse {
@@ -43,6 +73,46 @@ impl PartitionHealthResponse {
                 buf.put_u8(0); // No timestamp
             }
         }
+
+        // Cleanup history: [u32 count] then per run
+        // [u64 timestamp][u32 segments][u64 bytes][u32 reason_len][reason][u64 low_watermark]
+        buf.put_u32(self.cleanup_history.len() as u32);
+        for run in &self.cleanup_history {
+            buf.put_u64(run.timestamp_ns);
+            buf.put_u32(run.segments_removed);
+            buf.put_u64(run.bytes_freed);
+            buf.put_u32(run.reason.len() as u32);
+            buf.extend_from_slice(run.reason.as_bytes());
+            buf.put_u64(run.low_watermark);
+        }
+
+        // Scrub: [u8 has][u64 last_pass][u64 segments][u64 bytes][u32 count] then per problem
+        // [u64 segment][u64 detected][u32 len][description]
+        match &self.scrub {
+            Some(scrub) => {
+                buf.put_u8(1);
+                buf.put_u64(scrub.last_pass_ns);
+                buf.put_u64(scrub.segments_scrubbed);
+                buf.put_u64(scrub.bytes_scrubbed);
+                buf.put_u32(scrub.problems.len() as u32);
+                for problem in &scrub.problems {
+                    buf.put_u64(problem.segment_base_offset);
+                    buf.put_u64(problem.detected_ns);
+                    buf.put_u32(problem.description.len() as u32);
+                    buf.extend_from_slice(problem.description.as_bytes());
+                }
+            }
+            None => buf.put_u8(0),
+        }
+
+        // Delayed messages, then expired records: [u64 count][u64 count], the
+        // expired count left out when zero, both when both are
+        if self.delayed_messages > 0 || self.expired_records > 0 {
+            buf.put_u64(self.delayed_messages);
+        }
+        if self.expired_records > 0 {
+            buf.put_u64(self.expired_records);
+        }
         
         buf.freeze()
     }
@@ -87,6 +157,86 @@ impl PartitionHealthResponse {
         } else {
             None
         };
+
+        // Brokers predating cleanup history end the payload here
+        let mut cleanup_history = Vec::new();
+        if buf.remaining() >= 4 {
+            let count = buf.get_u32();
+            for _ in 0..count {
+                if buf.remaining() < 8 + 4 + 8 + 4 {
+                    return Err(ProtocolError::PayloadError(
+                        "Insufficient data for cleanup record".into(),
+                    ));
+                }
+                let timestamp_ns = buf.get_u64();
+                let segments_removed = buf.get_u32();
+                let bytes_freed = buf.get_u64();
+                let reason_len = buf.get_u32() as usize;
+                if buf.remaining() < reason_len + 8 {
+                    return Err(ProtocolError::PayloadError(
+                        "Insufficient data for cleanup reason".into(),
+                    ));
+                }
+                let reason = String::from_utf8(buf.split_to(reason_len).to_vec())
+                    .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in cleanup reason".into()))?;
+                let low_watermark = buf.get_u64();
+                cleanup_history.push(CleanupRecord {
+                    timestamp_ns,
+                    segments_removed,
+                    bytes_freed,
+                    reason,
+                    low_watermark,
+                });
+            }
+        }
+        
+        // Brokers predating the scrubber end the payload here
+        let scrub = if buf.remaining() >= 1 && buf.get_u8() == 1 {
+            if buf.remaining() < 8 + 8 + 8 + 4 {
+                return Err(ProtocolError::PayloadError(
+                    "Insufficient data for scrub status".into(),
+                ));
+            }
+            let last_pass_ns = buf.get_u64();
+            let segments_scrubbed = buf.get_u64();
+            let bytes_scrubbed = buf.get_u64();
+            let count = buf.get_u32();
+            let mut problems = Vec::new();
+            for _ in 0..count {
+                if buf.remaining() < 8 + 8 + 4 {
+                    return Err(ProtocolError::PayloadError(
+                        "Insufficient data for scrub problem".into(),
+                    ));
+                }
+                let segment_base_offset = buf.get_u64();
+                let detected_ns = buf.get_u64();
+                let len = buf.get_u32() as usize;
+                if buf.remaining() < len {
+                    return Err(ProtocolError::PayloadError(
+                        "Insufficient data for scrub problem description".into(),
+                    ));
+                }
+                let description = String::from_utf8(buf.split_to(len).to_vec())
+                    .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in scrub problem".into()))?;
+                problems.push(ScrubProblemRecord {
+                    segment_base_offset,
+                    detected_ns,
+                    description,
+                });
+            }
+            Some(ScrubReport {
+                last_pass_ns,
+                segments_scrubbed,
+                bytes_scrubbed,
+                problems,
+            })
+        } else {
+            None
+        };
+
+        // Brokers predating delayed delivery, or with nothing scheduled, end the payload here
+        let delayed_messages = if buf.remaining() >= 8 { buf.get_u64() } else { 0 };
+        let expired_records = if buf.remaining() >= 8 { buf.get_u64() } else { 0 };
         
         Ok(Self {
             topic,
@@ -97,6 +247,10 @@ impl PartitionHealthResponse {
             high_watermark,
             log_end_offset,
             last_cleanup,
+            cleanup_history,
+            scrub,
+            delayed_messages,
+            expired_records,
         })
     }
 }
@@ -116,6 +270,25 @@ mod tests {
             high_watermark: 1000,
             log_end_offset: 1000,
             last_cleanup: Some(1234567890),
+            cleanup_history: vec![CleanupRecord {
+                timestamp_ns: 1234567890,
+                segments_removed: 2,
+                bytes_freed: 2048,
+                reason: "time-based, size-based".to_string(),
+                low_watermark: 400,
+            }],
+            scrub: Some(ScrubReport {
+                last_pass_ns: 1234567999,
+                segments_scrubbed: 12,
+                bytes_scrubbed: 1024 * 1024 * 60,
+                problems: vec![ScrubProblemRecord {
+                    segment_base_offset: 500,
+                    detected_ns: 1234567990,
+                    description: "corrupt tail at byte 4096".to_string(),
+                }],
+            }),
+            delayed_messages: 7,
+            expired_records: 3,
         };
         
         let bytes = original.serialize();
@@ -129,6 +302,10 @@ mod tests {
         assert_eq!(original.high_watermark, parsed.high_watermark);
         assert_eq!(original.log_end_offset, parsed.log_end_offset);
         assert_eq!(original.last_cleanup, parsed.last_cleanup);
+        assert_eq!(original.cleanup_history, parsed.cleanup_history);
+        assert_eq!(original.scrub, parsed.scrub);
+        assert_eq!(original.delayed_messages, parsed.delayed_messages);
+        assert_eq!(original.expired_records, parsed.expired_records);
     }
     
     #[test]
@@ -142,6 +319,10 @@ mod tests {
             high_watermark: 10,
             log_end_offset: 10,
             last_cleanup: None,
+            cleanup_history: Vec::new(),
+            scrub: None,
+            delayed_messages: 0,
+            expired_records: 0,
         };
         
         let bytes = original.serialize();
@@ -149,4 +330,30 @@ mod tests {
         
         assert_eq!(original.last_cleanup, parsed.last_cleanup);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_partition_health_without_history_section() {
+        let original = PartitionHealthResponse {
+            topic: "logs".to_string(),
+            partition: 0,
+            segment_count: 1,
+            total_size_bytes: 10,
+            low_watermark: 0,
+            high_watermark: 0,
+            log_end_offset: 1,
+            last_cleanup: None,
+            cleanup_history: Vec::new(),
+            scrub: None,
+            delayed_messages: 0,
+            expired_records: 0,
+        };
+
+        // Drop the trailing history count and scrub flag to mimic an older broker
+        let bytes = original.serialize();
+        let legacy = bytes.slice(..bytes.len() - 5);
+        let parsed = PartitionHealthResponse::deserialize(legacy).unwrap();
+
+        assert!(parsed.cleanup_history.is_empty());
+        assert!(parsed.scrub.is_none());
+    }
+}
@@ -4,14 +4,61 @@ use crate::errors::ProtocolError;
 #[derive(Debug)]
 pub struct ProduceAck {
     pub partition: u32,
+    /// Offset of the record; for [`ProduceStatus::Duplicate`] the offset the
+    /// record got when it was first appended. Meaningless for rejections.
     pub offset: u64,
+    pub status: ProduceStatus,
 }
 
+/// Outcome of a produce. Only idempotent produces can be rejected; any
+/// produce can time out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ProduceStatus {
+    Ok = 0,
+    /// The sequence was appended before; nothing was written this time.
+    Duplicate = 1,
+    /// The sequence is not the next one the broker expects from the producer,
+    /// records in between were lost. Nothing was written.
+    OutOfOrderSequence = 2,
+    /// A newer epoch of this producer id exists. Nothing was written.
+    ProducerFenced = 3,
+    /// A transactional produce to a partition that is not part of the
+    /// producer's open transaction. Nothing was written.
+    NotInTransaction = 4,
+    /// The requested acks were not reached within the produce timeout. The
+    /// record may still have been written; `offset` is meaningless.
+    Timeout = 5,
+    /// Held back until its delivery time; the record gets its offset when it
+    /// is appended then, `offset` is meaningless.
+    Scheduled = 6,
+}
+
+impl TryFrom<u8> for ProduceStatus {
+    type Error = ProtocolError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ProduceStatus::Ok),
+            1 => Ok(ProduceStatus::Duplicate),
+            2 => Ok(ProduceStatus::OutOfOrderSequence),
+            3 => Ok(ProduceStatus::ProducerFenced),
+            4 => Ok(ProduceStatus::NotInTransaction),
+            5 => Ok(ProduceStatus::Timeout),
+            6 => Ok(ProduceStatus::Scheduled),
+            _ => Err(ProtocolError::PayloadError(format!("Unknown produce status: {}", value))),
+        }
+    }
+}
+
+//frame: [u32 partition][u64 offset][u8 status]
+
 impl ProduceAck {
     pub fn serialize(&self) -> Bytes {
-        let mut buf = BytesMut::with_capacity(12);
+        let mut buf = BytesMut::with_capacity(13);
         buf.put_u32(self.partition);
         buf.put_u64(self.offset);
+        buf.put_u8(self.status as u8);
         buf.freeze()
     }
 
@@ -21,7 +68,42 @@ impl ProduceAck {
         }
         let partition = buf.get_u32();
         let offset = buf.get_u64();
+        // Older brokers end the payload here
+        let status = match buf.remaining() {
+            0 => ProduceStatus::Ok,
+            _ => ProduceStatus::try_from(buf.get_u8())?,
+        };
+
+        Ok(ProduceAck { partition, offset, status })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_produce_ack_roundtrip() {
+        let ack = ProduceAck {
+            partition: 1,
+            offset: 99,
+            status: ProduceStatus::Duplicate,
+        };
+
+        let parsed = ProduceAck::deserialize(ack.serialize()).unwrap();
+
+        assert_eq!(parsed.partition, 1);
+        assert_eq!(parsed.offset, 99);
+        assert_eq!(parsed.status, ProduceStatus::Duplicate);
+    }
+
+    #[test]
+    fn test_produce_ack_without_status() {
+        let legacy = ProduceAck { partition: 0, offset: 5, status: ProduceStatus::Ok }.serialize().slice(..12);
+
+        let parsed = ProduceAck::deserialize(legacy).unwrap();
 
-        Ok(ProduceAck { partition, offset })
+        assert_eq!(parsed.offset, 5);
+        assert_eq!(parsed.status, ProduceStatus::Ok);
     }
 }
@@ -0,0 +1,114 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+#[derive(Debug, Clone)]
+pub struct RetentionDryRunResponse {
+    pub segments: Vec<DryRunSegment>, // oldest first
+    pub bytes_freed: u64,
+    pub low_watermark: u64, // low watermark the partition would end up with
+}
+
+/// A segment the dry run would delete.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunSegment {
+    pub base_offset: u64,
+    pub last_offset: u64,
+    pub size_bytes: u64,
+    pub reason: String,
+}
+
+impl RetentionDryRunResponse {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u64(self.bytes_freed);
+        buf.put_u64(self.low_watermark);
+
+        buf.put_u32(self.segments.len() as u32);
+        for seg in &self.segments {
+            buf.put_u64(seg.base_offset);
+            buf.put_u64(seg.last_offset);
+            buf.put_u64(seg.size_bytes);
+            buf.put_u32(seg.reason.len() as u32);
+            buf.extend_from_slice(seg.reason.as_bytes());
+        }
+
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 8 + 8 + 4 {
+            return Err(ProtocolError::PayloadError(
+                "Incomplete retention dry run response payload".into(),
+            ));
+        }
+        let bytes_freed = buf.get_u64();
+        let low_watermark = buf.get_u64();
+
+        let count = buf.get_u32();
+        let mut segments = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            if buf.remaining() < 8 + 8 + 8 + 4 {
+                return Err(ProtocolError::PayloadError(
+                    "Insufficient data for dry run segment".into(),
+                ));
+            }
+            let base_offset = buf.get_u64();
+            let last_offset = buf.get_u64();
+            let size_bytes = buf.get_u64();
+            let reason_len = buf.get_u32() as usize;
+            if buf.remaining() < reason_len {
+                return Err(ProtocolError::PayloadError(
+                    "Insufficient data for dry run reason".into(),
+                ));
+            }
+            let reason = String::from_utf8(buf.split_to(reason_len).to_vec())
+                .map_err(|_| ProtocolError::PayloadError("Invalid UTF-8 in dry run reason".into()))?;
+
+            segments.push(DryRunSegment {
+                base_offset,
+                last_offset,
+                size_bytes,
+                reason,
+            });
+        }
+
+        Ok(Self {
+            segments,
+            bytes_freed,
+            low_watermark,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention_dry_run_response_roundtrip() {
+        let original = RetentionDryRunResponse {
+            segments: vec![
+                DryRunSegment {
+                    base_offset: 0,
+                    last_offset: 99,
+                    size_bytes: 4096,
+                    reason: "time-based (age: 8d)".into(),
+                },
+                DryRunSegment {
+                    base_offset: 100,
+                    last_offset: 199,
+                    size_bytes: 4096,
+                    reason: "size-based (total: 8192 > limit: 4096)".into(),
+                },
+            ],
+            bytes_freed: 8192,
+            low_watermark: 200,
+        };
+
+        let parsed = RetentionDryRunResponse::deserialize(original.serialize()).unwrap();
+
+        assert_eq!(parsed.segments, original.segments);
+        assert_eq!(parsed.bytes_freed, original.bytes_freed);
+        assert_eq!(parsed.low_watermark, original.low_watermark);
+    }
+}
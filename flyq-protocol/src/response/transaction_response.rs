@@ -0,0 +1,52 @@
+use crate::ProtocolError;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Answer to `BeginTransaction`, `AddPartitionsToTxn`, `CommitTransaction`
+/// and `AbortTransaction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionResponse {
+    pub status: TransactionStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TransactionStatus {
+    Ok = 0,
+    /// The transactional id was initialised again, by a newer producer.
+    ProducerFenced = 1,
+    /// E.g. committing without an open transaction, or beginning a second one.
+    InvalidState = 2,
+    /// `InitProducerId` was never called with this transactional id.
+    UnknownTransactionalId = 3,
+}
+
+impl TryFrom<u8> for TransactionStatus {
+    type Error = ProtocolError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TransactionStatus::Ok),
+            1 => Ok(TransactionStatus::ProducerFenced),
+            2 => Ok(TransactionStatus::InvalidState),
+            3 => Ok(TransactionStatus::UnknownTransactionalId),
+            _ => Err(ProtocolError::PayloadError(format!("Unknown transaction status: {}", value))),
+        }
+    }
+}
+
+//frame: [u8 status]
+
+impl TransactionResponse {
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(1);
+        buf.put_u8(self.status as u8);
+        buf.freeze()
+    }
+
+    pub fn deserialize(mut buf: Bytes) -> Result<Self, ProtocolError> {
+        if buf.remaining() < 1 {
+            return Err(ProtocolError::PayloadError("Incomplete transaction response payload".into()));
+        }
+        Ok(Self { status: TransactionStatus::try_from(buf.get_u8())? })
+    }
+}
@@ -6,14 +6,20 @@ pub struct WatermarkResponse {
     pub log_end_offset: u64,
     pub low_watermark: u64,
     pub high_watermark: u64,
+    /// Offset below which every transaction is decided; `read_committed`
+    /// consumers read up to here.
+    pub last_stable_offset: u64,
 }
 
+//frame: [u64 low_watermark][u64 high_watermark][u64 log_end_offset][u64 last_stable_offset]
+
 impl WatermarkResponse {
     pub fn serialize(&self) -> Bytes {
-        let mut buf = BytesMut::with_capacity(24);
+        let mut buf = BytesMut::with_capacity(32);
         buf.put_u64(self.low_watermark);
         buf.put_u64(self.high_watermark);
         buf.put_u64(self.log_end_offset);
+        buf.put_u64(self.last_stable_offset);
         buf.freeze()
     }
 
@@ -26,11 +32,17 @@ impl WatermarkResponse {
         let low_watermark = buf.get_u64();
         let high_watermark = buf.get_u64();
         let log_end_offset = buf.get_u64();
+        // older brokers have no transactions, everything is stable
+        let last_stable_offset = match buf.remaining() >= 8 {
+            true => buf.get_u64(),
+            false => log_end_offset,
+        };
 
         Ok(Self {
             log_end_offset,
             low_watermark,
             high_watermark,
+            last_stable_offset,
         })
     }
 }
@@ -45,6 +57,7 @@ mod tests {
             low_watermark: 1,
             high_watermark: 2,
             log_end_offset: 3,
+            last_stable_offset: 2,
         };
 
         let bytes = original.serialize();
@@ -53,5 +66,6 @@ mod tests {
         assert_eq!(original.low_watermark, parsed.low_watermark);
         assert_eq!(original.high_watermark, parsed.high_watermark);
         assert_eq!(original.log_end_offset, parsed.log_end_offset);
+        assert_eq!(original.last_stable_offset, parsed.last_stable_offset);
     }
 }
@@ -0,0 +1,251 @@
+//! Offline inspection of a partition's files: prints records, validates the
+//! offset index against the records it points at, reports offset gaps,
+//! duplicates and reordering, and compares `meta.json` with the segments.
+//!
+//! Read-only, so it is safe to run against the data directory of a live broker.
+//! Exits with status 1 if any problem was found.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use anyhow::{Context, Result};
+use clap::Parser;
+use flyQ::core::inspect::{
+    check_meta, list_segments, DerivedWatermarks, Finding, RecordScanner, SegmentCheck, SegmentSpan,
+};
+use flyQ::core::partiton_meta::PartitionMeta;
+use flyQ::core::segment::Segment;
+use flyQ::core::stored_record::StoredRecord;
+
+#[derive(Parser, Debug)]
+#[command(name = "flyq-dump")]
+struct Params {
+    /// A partition directory or a `segment_*.log` file
+    path: PathBuf,
+
+    /// Only run the checks, don't print records
+    #[arg(long)]
+    checks_only: bool,
+
+    /// Print records from this offset on
+    #[arg(long, default_value_t = 0)]
+    from: u64,
+
+    /// Print at most this many records per segment
+    #[arg(long)]
+    limit: Option<u64>,
+
+    /// Bytes of each key, header and value to show
+    #[arg(long, default_value_t = 64)]
+    preview: usize,
+}
+
+fn main() -> Result<ExitCode> {
+    let params = Params::parse();
+
+    let (dir, segments) = if params.path.is_dir() {
+        let segments = list_segments(&params.path).with_context(|| format!("reading {:?}", params.path))?;
+        (Some(params.path.as_path()), segments)
+    } else {
+        let name = Segment::scan_path(&params.path)
+            .with_context(|| format!("{:?} is neither a directory nor a segment_*.log file", params.path))?;
+        let base_offset = Segment::parse_base_offset(&name).context("segment file name without a base offset")?;
+        (None, vec![(base_offset, params.path.clone())])
+    };
+
+    let mut problems = 0;
+    let mut spans: Vec<SegmentSpan> = Vec::new();
+    for (base_offset, path) in &segments {
+        let (span, findings) = dump_segment(*base_offset, path, &params)?;
+        if let (Some(previous), Some(first)) = (spans.iter().rev().find_map(|s| s.last_offset), span.first_offset) {
+            if first != previous + 1 {
+                println!("  ! segment starts at offset {} but the previous one ended at {}", first, previous);
+                problems += 1;
+            }
+        }
+        problems += report(&findings);
+        spans.push(span);
+    }
+
+    if let Some(dir) = dir {
+        println!();
+        println!("partition {}: {} segments", dir.display(), segments.len());
+        problems += dump_meta(dir, &spans)?;
+    }
+
+    if problems == 0 {
+        println!("no problems found");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("{} problem(s) found", problems);
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn dump_segment(base_offset: u64, path: &Path, params: &Params) -> Result<(SegmentSpan, Vec<Finding>)> {
+    let mut scanner = RecordScanner::open(path).with_context(|| format!("opening {:?}", path))?;
+    println!("{} (base offset {}, {} bytes)", path.display(), base_offset, scanner.file_len());
+
+    let [index_path, ..] = Segment::index_paths(path);
+    let index = if index_path.exists() {
+        let index = Segment::read_index_file(&index_path).with_context(|| format!("reading {:?}", index_path))?;
+        match index.strategy {
+            Some(strategy) => println!("  index: {:?}, {} entries", strategy, index.entries.len()),
+            None => println!("  index: legacy format without header, {} entries", index.entries.len()),
+        }
+        Some(index)
+    } else {
+        None
+    };
+
+    let mut check = SegmentCheck::new(base_offset, index.as_ref());
+    let mut printed = 0;
+    let mut corrupt = None;
+    for item in scanner.by_ref() {
+        match item {
+            Ok(raw) => {
+                let wanted = !params.checks_only
+                    && raw.record.offset >= params.from
+                    && params.limit.is_none_or(|limit| printed < limit);
+                if wanted {
+                    if let Some(batch) = raw.batch.filter(|_| raw.len > 0) {
+                        println!(
+                            "  batch base={} last={} records={} bytes={} codec={:?}",
+                            batch.base_offset,
+                            batch.last_offset(),
+                            batch.record_count,
+                            raw.len,
+                            batch.compression()
+                        );
+                    }
+                    println!("  {}", format_record(raw.position, &raw.record, params.preview));
+                    printed += 1;
+                }
+                check.record(&raw);
+            }
+            Err(e) => corrupt = Some(e),
+        }
+    }
+
+    match (check.first_offset, check.last_offset) {
+        (Some(first), Some(last)) => println!("  {} records, offsets {}..={}", check.records, first, last),
+        _ => println!("  no records"),
+    }
+    let span = SegmentSpan {
+        base_offset,
+        first_offset: check.first_offset,
+        last_offset: check.last_offset,
+    };
+    Ok((span, check.finish(corrupt, scanner.file_len())))
+}
+
+fn report(findings: &[Finding]) -> usize {
+    for finding in findings {
+        println!("  ! {}", finding);
+    }
+    findings.len()
+}
+
+fn dump_meta(dir: &Path, spans: &[SegmentSpan]) -> Result<usize> {
+    let derived = DerivedWatermarks::from_spans(spans);
+    println!(
+        "  derived:   first_offset={} log_end_offset={}",
+        derived.first_offset, derived.log_end_offset
+    );
+
+    let meta_path = dir.join("meta.json");
+    match PartitionMeta::load(&meta_path) {
+        Ok(Some(meta)) => {
+            println!(
+                "  meta.json: low_watermark={} high_watermark={} log_end_offset={}",
+                meta.low_watermark, meta.high_watermark, meta.log_end_offset
+            );
+            Ok(report(&check_meta(&meta, derived)))
+        }
+        Ok(None) => {
+            println!("  meta.json: missing");
+            Ok(0)
+        }
+        Err(e) => {
+            println!("  ! meta.json unreadable: {}", e);
+            Ok(1)
+        }
+    }
+}
+
+fn format_record(position: u64, record: &StoredRecord, preview: usize) -> String {
+    let message = &record.message;
+    let time = chrono::DateTime::from_timestamp_millis(message.timestamp as i64)
+        .map(|t| t.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+        .unwrap_or_else(|| "invalid".to_string());
+    let mut line = format!(
+        "offset={} pos={} ts={} ({})",
+        record.offset, position, message.timestamp, time
+    );
+    if let Some(stamp) = &record.producer {
+        line.push_str(&format!(
+            " producer={}/{} seq={}",
+            stamp.producer_id, stamp.producer_epoch, stamp.sequence
+        ));
+        if stamp.transactional {
+            line.push_str(" transactional");
+        }
+    }
+    if let Some(marker) = record.control_marker() {
+        line.push_str(&format!(" control={:?}", marker));
+        return line;
+    }
+    if let Some(key) = &message.key {
+        line.push_str(&format!(" key={}", format_bytes(key, preview)));
+    }
+    if let Some(headers) = message.headers.as_ref().filter(|h| !h.is_empty()) {
+        let headers: Vec<String> = headers
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, format_bytes(value, preview)))
+            .collect();
+        line.push_str(&format!(" headers={{{}}}", headers.join(", ")));
+    }
+    line.push_str(&format!(" value={}", format_bytes(&message.value, preview)));
+    line
+}
+
+/// JSON is shown compacted, other UTF-8 quoted and anything else as hex,
+/// each cut to `preview` bytes.
+fn format_bytes(bytes: &[u8], preview: usize) -> String {
+    let shown = match serde_json::from_slice::<serde_json::Value>(bytes) {
+        Ok(json) if json.is_object() || json.is_array() => json.to_string(),
+        _ => match std::str::from_utf8(bytes) {
+            Ok(text) => format!("{:?}", text),
+            Err(_) => return format_hex(bytes, preview),
+        },
+    };
+    if shown.len() <= preview {
+        return shown;
+    }
+    let mut end = preview;
+    while !shown.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} bytes)", &shown[..end], bytes.len())
+}
+
+fn format_hex(bytes: &[u8], preview: usize) -> String {
+    let hex: String = bytes.iter().take(preview).map(|b| format!("{:02x}", b)).collect();
+    if bytes.len() > preview {
+        format!("0x{}... ({} bytes)", hex, bytes.len())
+    } else {
+        format!("0x{}", hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_bytes;
+
+    #[test]
+    fn test_format_bytes_previews() {
+        assert_eq!(format_bytes(br#"{ "a": [1, 2] }"#, 64), r#"{"a":[1,2]}"#);
+        assert_eq!(format_bytes(b"hello", 64), r#""hello""#);
+        assert_eq!(format_bytes(b"hello world", 4), r#""hel... (11 bytes)"#);
+        assert_eq!(format_bytes(&[0xff, 0x00, 0x10], 2), "0xff00... (3 bytes)");
+    }
+}
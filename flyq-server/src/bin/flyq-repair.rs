@@ -0,0 +1,78 @@
+//! Offline repair of a partition directory: rebuilds `.index` files from the
+//! segment records with the topic's configured index strategy, recomputes
+//! `meta.json`, and with `--truncate` cuts segments at a corrupt tail.
+//!
+//! Stop the broker first. `--check` reports what would be done without
+//! writing anything, and exits with status 1 if anything would change.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use anyhow::{Context, Result};
+use clap::Parser;
+use flyQ::core::repair::{repair_partition, RepairOptions};
+use flyQ::BrokerConfig;
+
+#[derive(Parser, Debug)]
+#[command(name = "flyq-repair")]
+struct Params {
+    /// A partition directory, e.g. ./data/orders/partition_0
+    path: PathBuf,
+
+    /// Broker config the index strategy is taken from
+    #[arg(long, env = "FLYQ_CONFIG")]
+    config: Option<String>,
+
+    /// Topic the partition belongs to, by default its parent directory's name
+    #[arg(long)]
+    topic: Option<String>,
+
+    /// Cut segment files at their first unreadable record
+    #[arg(long)]
+    truncate: bool,
+
+    /// Only report what would be done
+    #[arg(long)]
+    check: bool,
+}
+
+fn main() -> Result<ExitCode> {
+    let params = Params::parse();
+    let cfg = BrokerConfig::load_or_default(params.config.as_deref())?;
+    let topic = match &params.topic {
+        Some(topic) => topic.clone(),
+        None => params
+            .path
+            .canonicalize()
+            .with_context(|| format!("resolving {:?}", params.path))?
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .and_then(|name| name.to_str())
+            .map(str::to_string)
+            .context("can't tell the topic from the path, pass --topic")?,
+    };
+
+    let options = RepairOptions {
+        index_strategy: cfg.index_strategy_for(&topic),
+        truncate: params.truncate,
+        check: params.check,
+    };
+    let report = repair_partition(&params.path, &options)
+        .with_context(|| format!("repairing {:?}", params.path))?;
+
+    for (path, finding) in &report.findings {
+        println!("! {}: {}", path.display(), finding);
+    }
+    let verb = if params.check { "would" } else { "did" };
+    for action in &report.actions {
+        println!("{} {}", verb, action);
+    }
+    for (path, finding) in &report.unrepaired {
+        println!("left as is: {}: {}", path.display(), finding);
+    }
+
+    if report.actions.is_empty() && report.unrepaired.is_empty() {
+        println!("nothing to repair");
+    }
+    let clean = report.unrepaired.is_empty() && (!params.check || report.actions.is_empty());
+    Ok(if clean { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
@@ -1,8 +1,11 @@
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use anyhow::{Context, Result};
+use flyq_protocol::Compression;
+use crate::core::index_strategy::IndexStrategyConfig;
 
 /// Global broker-wide knobs that every partition inherits.
 /// Todo: topic to override it .
@@ -12,6 +15,10 @@ pub struct BrokerConfig {
     /// Upper bound for a single segment’s size before we rotate.
     pub segment_max_bytes: u64,
 
+    /// Seal the active segment once its first record was appended this long
+    /// ago (broker clock), even if it is not full. `None` = only roll on size.
+    pub segment_roll_interval: Option<Duration>,
+
     /// Keep data newer than this age. Time wins over size.
     pub retention: Duration,
 
@@ -20,15 +27,159 @@ pub struct BrokerConfig {
 
     /// How often the background cleaner wakes up.
     pub cleanup_interval: Duration,
+
+    /// How long a produce may wait for its acks before the broker answers
+    /// with a timeout, for producers that do not set their own.
+    pub produce_timeout: Duration,
+
+    /// How long a transaction may stay open before the broker aborts it and
+    /// fences its producer.
+    pub transaction_timeout: Duration,
+
+    /// Which record timestamp drives time-based retention.
+    pub timestamp_type: TimestampType,
+
+    /// Per-topic overrides, keyed by topic name (`[topics.<name>]` in TOML).
+    pub topics: HashMap<String, TopicConfig>,
+
+    /// Offload of old sealed segments to remote storage. `None` = keep
+    /// everything on local disk.
+    pub tiered_storage: Option<TieredStorageConfig>,
+
+    /// Background re-reading of sealed segments to catch corruption before
+    /// consumers do. `None` = off.
+    pub scrub: Option<ScrubConfig>,
+}
+
+/// Where offloaded segments go and when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TieredStorageConfig {
+    /// Root of the filesystem-backed remote store (e.g. a mounted bucket).
+    pub remote_dir: PathBuf,
+
+    /// Sealed segments whose newest record is older than this are uploaded
+    /// and deleted locally.
+    pub local_retention: Duration,
+
+    /// How often the offload task wakes up.
+    pub offload_interval: Duration,
+}
+
+/// How hard the scrubber works.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubConfig {
+    /// Pause between the start of two passes over every sealed segment.
+    pub interval: Duration,
+
+    /// Broker-wide read budget of the scrubber.
+    pub bytes_per_sec: u64,
+}
+
+/// Knobs a single topic can set on top of the broker-wide defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicConfig {
+    /// Consumer groups whose committed offsets hold back retention: segments
+    /// with records they have not consumed yet are never deleted.
+    pub protected_groups: Vec<String>,
+
+    /// Hard cap on partition bytes; above it retention ignores the protected
+    /// groups. `None` = protection is unconditional.
+    pub protected_max_bytes: Option<u64>,
+
+    /// Overrides the broker-wide `segment_roll_interval` for this topic.
+    pub segment_roll_interval: Option<Duration>,
+
+    /// How densely segments of this topic are indexed. `None` = an entry
+    /// every 100 messages. Applies to segments created after a change.
+    pub index: Option<IndexStrategyConfig>,
+
+    /// Header names to keep a secondary index for (header value → offsets),
+    /// so header queries skip the full scan. Applies to new segments.
+    pub indexed_headers: Vec<String>,
+
+    /// Dead-letter policy per queue-mode consumer group
+    /// (`[topics.<name>.dead_letter.<group>]` in TOML).
+    pub dead_letter: HashMap<String, DeadLetterConfig>,
+
+    /// Codec record batches of this topic are stored with; the broker
+    /// recompresses batches produced with another one on append.
+    pub compression: CompressionType,
+}
+
+/// When a queue-mode group gives up on a record and where it goes then.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterConfig {
+    /// Deliveries a record gets; once the last one is nacked or times out the
+    /// record moves to the dead-letter topic.
+    pub max_attempts: u32,
+
+    /// Topic failed records are appended to. `None` = `<topic>.dlq`.
+    #[serde(default)]
+    pub topic: Option<String>,
+}
+
+impl DeadLetterConfig {
+    pub fn topic_for(&self, source_topic: &str) -> String {
+        self.topic
+            .clone()
+            .unwrap_or_else(|| format!("{}.dlq", source_topic))
+    }
+}
+
+/// Source of the timestamp stored with each record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampType {
+    /// Keep the timestamp supplied by the producer.
+    #[default]
+    CreateTime,
+    /// Broker stamps every record with its wall clock at append time.
+    LogAppendTime,
+}
+
+/// Codec a topic stores record batches with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionType {
+    /// Keep whatever codec the producer chose.
+    #[default]
+    Producer,
+    Uncompressed,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl CompressionType {
+    /// The codec to store batches with, `None` = the producer's.
+    pub fn codec(self) -> Option<Compression> {
+        match self {
+            CompressionType::Producer => None,
+            CompressionType::Uncompressed => Some(Compression::None),
+            CompressionType::Gzip => Some(Compression::Gzip),
+            CompressionType::Snappy => Some(Compression::Snappy),
+            CompressionType::Lz4 => Some(Compression::Lz4),
+            CompressionType::Zstd => Some(Compression::Zstd),
+        }
+    }
 }
 
 impl Default for BrokerConfig {
     fn default() -> Self {
         Self {
             segment_max_bytes: 1024 * 1024 * 1024,          // 1 GiB
+            segment_roll_interval: None,                        // roll on size only
             retention: Duration::from_secs(7 * 24 * 60 * 60),   // 7 days
             retention_bytes: None,                              // size-based retention off
             cleanup_interval: Duration::from_secs(60),          // 1 minute
+            produce_timeout: Duration::from_secs(30),
+            transaction_timeout: Duration::from_secs(60),
+            timestamp_type: TimestampType::CreateTime,
+            topics: HashMap::new(),
+            tiered_storage: None,
+            scrub: None,
         }
     }
     
@@ -36,6 +187,36 @@ impl Default for BrokerConfig {
 
 impl BrokerConfig {
 
+    pub fn topic_config(&self, topic: &str) -> Option<&TopicConfig> {
+        self.topics.get(topic)
+    }
+
+    pub fn segment_roll_interval_for(&self, topic: &str) -> Option<Duration> {
+        self.topic_config(topic)
+            .and_then(|t| t.segment_roll_interval)
+            .or(self.segment_roll_interval)
+    }
+
+    pub fn index_strategy_for(&self, topic: &str) -> IndexStrategyConfig {
+        self.topic_config(topic)
+            .and_then(|t| t.index)
+            .unwrap_or_default()
+    }
+
+    pub fn indexed_headers_for(&self, topic: &str) -> &[String] {
+        self.topic_config(topic)
+            .map(|t| t.indexed_headers.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn compression_for(&self, topic: &str) -> Option<Compression> {
+        self.topic_config(topic).and_then(|t| t.compression.codec())
+    }
+
+    pub fn dead_letter_for(&self, topic: &str, group: &str) -> Option<&DeadLetterConfig> {
+        self.topic_config(topic)?.dead_letter.get(group)
+    }
+
     pub fn load_or_default<P: AsRef<Path>>(path: Option<P>) -> Result<Self> {
         match path {
             Some(p) => Self::read_from_file(p), // propagate errors unchanged
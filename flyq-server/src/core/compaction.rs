@@ -0,0 +1,172 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use flyq_protocol::message::{Message, EXPIRES_AT_HEADER};
+use flyq_protocol::record_batch::RecordBatch;
+use crate::core::inspect::RecordScanner;
+use crate::core::sealed_segment::SealedSegment;
+use crate::core::segment::Segment;
+use crate::core::topic::SharedPartition;
+
+/// Scratch directory inside the partition directory for rewritten segments.
+const COMPACT_DIR: &str = "compacting";
+
+/// Removes rewrites a crash left behind in the scratch directory of the
+/// partition at `partition_dir`. Only a rewrite whose log was renamed into
+/// the partition directory counts, so nothing in there is needed.
+pub fn clear_scratch(partition_dir: &Path) -> io::Result<()> {
+    let scratch = partition_dir.join(COMPACT_DIR);
+    if scratch.exists() {
+        fs::remove_dir_all(&scratch)?;
+    }
+    Ok(())
+}
+
+/// What a read of a sealed segment found about record expiry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpiryScan {
+    /// Records expired at the time of the scan that still have contents.
+    pub expired: u64,
+    /// Earliest expiry of the records still live, `u64::MAX` if none expire.
+    pub next_expiry: u64,
+}
+
+/// What is left of an expired record: its offset, timestamp, producer stamp
+/// and expiry, so offsets, retention and producer state stay as they were.
+fn tombstone(message: &Message) -> Message {
+    Message {
+        key: None,
+        value: Vec::new(),
+        timestamp: message.timestamp,
+        headers: message.expires_at().map(|at| vec![(EXPIRES_AT_HEADER.to_string(), at.to_be_bytes().to_vec())]),
+    }
+}
+
+fn is_tombstone(message: &Message) -> bool {
+    message.key.is_none()
+        && message.value.is_empty()
+        && message.headers.as_ref().is_some_and(|h| h.len() == 1 && h[0].0 == EXPIRES_AT_HEADER)
+}
+
+fn corrupt(segment: &SealedSegment, reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}: {}", segment.segment_path, reason))
+}
+
+/// Reads every record of `segment`. Blocking; run it off the async runtime.
+pub fn scan_expiry(segment: &SealedSegment, now: u64) -> io::Result<ExpiryScan> {
+    let mut scan = ExpiryScan { expired: 0, next_expiry: u64::MAX };
+    for item in RecordScanner::open(&segment.segment_path)? {
+        let message = item.map_err(|e| corrupt(segment, e.reason))?.record.message;
+        match message.expires_at() {
+            Some(at) if at <= now => scan.expired += !is_tombstone(&message) as u64,
+            Some(at) => scan.next_expiry = scan.next_expiry.min(at),
+            None => {}
+        }
+    }
+    Ok(scan)
+}
+
+/// Writes the next generation of `segment` with every record expired at
+/// `now` reduced to a tombstone, and opens it. Returns the new segment and
+/// how many records were dropped. The files of `segment` are left as they
+/// are: readers keep using them until the partition swaps in the new
+/// segment and the last of them drops the old one. Segments with an
+/// unreadable record are left alone. The header index is rebuilt from the
+/// tombstones, which keep no headers but their expiry.
+pub fn compact_segment(segment: &SealedSegment, now: u64) -> io::Result<(SealedSegment, u64)> {
+    let dir = segment.segment_path.parent().expect("segment in a partition directory");
+    let scratch = dir.join(COMPACT_DIR);
+    fs::create_dir_all(&scratch)?;
+    let file_name = Segment::generation_filename(segment.base_offset, segment.generation() + 1);
+    let scratch_log = scratch.join(&file_name);
+    let [_, _, scratch_header_index] = Segment::index_paths(&scratch_log);
+    if segment.header_index_path.exists() {
+        fs::copy(&segment.header_index_path, &scratch_header_index)?; // keeps the indexed names
+    }
+
+    let mut dropped = 0;
+    let mut next_expiry = u64::MAX;
+    {
+        let mut out = BufWriter::new(File::create(&scratch_log)?);
+        let mut batched = Vec::new(); // records of the batch being read
+        for item in RecordScanner::open(&segment.segment_path)? {
+            let raw = item.map_err(|e| corrupt(segment, e.reason))?;
+            let mut record = raw.record;
+            match record.message.expires_at() {
+                Some(at) if at > now => next_expiry = next_expiry.min(at),
+                Some(_) if !is_tombstone(&record.message) => {
+                    record.message = tombstone(&record.message);
+                    dropped += 1;
+                }
+                _ => {}
+            }
+            // batches stay batches, re-encoded with their codec once their
+            // last record is in. Those holding tombstones are marked: fetches
+            // look inside them even while no live record has expired
+            match raw.batch {
+                None => out.write_all(&record.serialize())?,
+                Some(header) => {
+                    batched.push((record.offset, record.message));
+                    if batched.len() == header.record_count as usize {
+                        let mut batch = RecordBatch::from_records(&batched)
+                            .with_compression(header.compression())
+                            .map_err(|e| corrupt(segment, e.to_string()))?;
+                        if batched.iter().any(|(_, message)| is_tombstone(message)) {
+                            batch = batch.with_tombstones();
+                        }
+                        out.write_all(&batch.to_entry())?;
+                        batched.clear();
+                    }
+                }
+            }
+        }
+        out.flush()?;
+        out.get_ref().sync_data()?;
+    }
+    Segment::rebuild_index_files(&scratch_log, segment.index_strategy.config())?;
+
+    // The log goes last: recovery only sees the new generation once it is
+    // complete, and then drops the old one
+    let log_path = dir.join(&file_name);
+    for (from, to) in Segment::index_paths(&scratch_log).iter().zip(Segment::index_paths(&log_path)) {
+        if from.exists() {
+            fs::rename(from, to)?;
+        }
+    }
+    fs::rename(&scratch_log, &log_path)?;
+    let _ = fs::remove_dir(&scratch); // other segments may be mid-rewrite
+
+    let (_, _, reopened) = Segment::recover_from_disk(log_path, &file_name)
+        .ok_or_else(|| corrupt(segment, "could not reopen the compacted segment".into()))?;
+    let compacted = reopened.seal();
+    compacted.next_expiry.store(next_expiry, Ordering::Release);
+    Ok((compacted, dropped))
+}
+
+/// Drops the contents of records that expired by `now` from the sealed
+/// segments of `partition`. Segments are read and rewritten on the blocking
+/// pool without the partition lock; the write lock is only taken to swap a
+/// rewritten segment in. Returns how many records were dropped.
+pub async fn compact_partition(partition: &SharedPartition, now: u64) -> io::Result<u64> {
+    let candidates = partition.read().await.expiring_segments(now);
+
+    let mut dropped = 0;
+    for segment in candidates {
+        let scanned = Arc::clone(&segment);
+        let scan = tokio::task::spawn_blocking(move || scan_expiry(&scanned, now))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)))?;
+        if scan.expired == 0 {
+            segment.next_expiry.store(scan.next_expiry, Ordering::Release);
+            continue;
+        }
+        let rewritten = Arc::clone(&segment);
+        let (compacted, expired) = tokio::task::spawn_blocking(move || compact_segment(&rewritten, now))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)))?;
+        dropped += partition.write().await.install_compacted(&segment, compacted, expired);
+    }
+    Ok(dropped)
+}
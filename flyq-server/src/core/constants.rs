@@ -3,3 +3,17 @@
 pub const DEFAULT_INDEX_INTERVAL: u32 = 100;
 pub const DEFAULT_AUTO_CREATE_TOPICS_ENABLE: bool = true;
 pub const DEFAULT_PARTITION_CNT: u32 = 1;
+
+/// How many cleanup runs each partition remembers for health reporting.
+pub const CLEANUP_HISTORY_LEN: usize = 16;
+
+/// Remote segments a partition keeps downloaded for reads at once.
+pub const REMOTE_CACHE_SEGMENTS: usize = 4;
+
+/// Recent sequences kept per idempotent producer and partition, so retries of
+/// records still in flight are acked with their original offsets.
+pub const PRODUCER_SEQUENCE_WINDOW: usize = 5;
+
+/// Most bytes a backward scan reads into memory at once. Stretches between
+/// index entries that are longer are split at record boundaries.
+pub const BACKWARD_CHUNK_BYTES: u64 = 1 << 20;
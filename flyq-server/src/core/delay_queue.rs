@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use flyq_protocol::message::Message;
+
+pub const DELAYED_FILE: &str = "delayed.log";
+
+const SCHEDULED: u8 = 1;
+const DELIVERED: u8 = 2;
+
+//journal: [u8 1][u64 id][u64 deliver_at][u32 message_len][message]   scheduled
+//         [u8 2][u64 id]                                             delivered
+
+/// Identifies a pending message: ordered by delivery time, then by when it
+/// was scheduled.
+pub type DelayKey = (u64, u64); // (deliver_at, id)
+
+/// Messages of one partition waiting for their delivery time, kept out of the
+/// log until then. Backed by an append-only journal next to the segments,
+/// rewritten with only the still pending messages on open.
+#[derive(Debug)]
+pub struct DelayQueue {
+    journal: File,
+    pending: BTreeMap<DelayKey, Message>,
+    next_id: u64,
+}
+
+impl DelayQueue {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(DELAYED_FILE);
+        let pending = match path.exists() {
+            true => replay(&fs::read(&path)?),
+            false => BTreeMap::new(),
+        };
+        let next_id = pending.keys().map(|&(_, id)| id + 1).max().unwrap_or(0);
+
+        // compact: delivered messages and a torn tail are dropped here
+        let tmp_path = path.with_extension("log.tmp");
+        {
+            let mut tmp_file = File::create(&tmp_path)?;
+            for (key, message) in &pending {
+                tmp_file.write_all(&scheduled_entry(*key, message))?;
+            }
+            tmp_file.sync_data()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+
+        let journal = OpenOptions::new().append(true).open(&path)?;
+        Ok(DelayQueue { journal, pending, next_id })
+    }
+
+    /// Holds `message` back until `deliver_at` (Unix millis). Synced to disk
+    /// before returning: until delivery the journal is its only copy.
+    pub fn schedule(&mut self, deliver_at: u64, message: Message) -> io::Result<DelayKey> {
+        let key = (deliver_at, self.next_id);
+        self.journal.write_all(&scheduled_entry(key, &message))?;
+        self.journal.sync_data()?;
+        self.next_id += 1;
+        self.pending.insert(key, message);
+        Ok(key)
+    }
+
+    /// Messages due at `now`, earliest first. They stay pending until
+    /// [`Self::delivered`] is called for them.
+    pub fn due(&self, now: u64) -> Vec<(DelayKey, Message)> {
+        self.pending
+            .range(..(now + 1, 0))
+            .map(|(key, message)| (*key, message.clone()))
+            .collect()
+    }
+
+    pub fn delivered(&mut self, key: DelayKey) -> io::Result<()> {
+        let mut entry = vec![DELIVERED];
+        entry.extend_from_slice(&key.1.to_be_bytes());
+        self.journal.write_all(&entry)?;
+        self.pending.remove(&key);
+        Ok(())
+    }
+
+    /// Delivery time of the earliest pending message.
+    pub fn next_due(&self) -> Option<u64> {
+        self.pending.keys().next().map(|&(deliver_at, _)| deliver_at)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+fn scheduled_entry((deliver_at, id): DelayKey, message: &Message) -> Vec<u8> {
+    let mut entry = vec![SCHEDULED];
+    entry.extend_from_slice(&id.to_be_bytes());
+    entry.extend_from_slice(&deliver_at.to_be_bytes());
+    entry.extend_from_slice(&message.serialize_body()); // length-prefixed
+    entry
+}
+
+/// Pending messages of a journal, up to the first entry that is cut short or
+/// does not parse.
+fn replay(mut buf: &[u8]) -> BTreeMap<DelayKey, Message> {
+    let mut scheduled = BTreeMap::new();
+    let mut keys = BTreeMap::new(); // id → key
+    while let Some((&kind, rest)) = buf.split_first() {
+        let Some(id) = read_u64(rest) else { break };
+        let rest = &rest[8..];
+        match kind {
+            SCHEDULED => {
+                let Some(deliver_at) = read_u64(rest) else { break };
+                let rest = &rest[8..];
+                let Some(len) = rest.get(..4).map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize) else { break };
+                let Some(body) = rest.get(4..4 + len) else { break };
+                let Ok(message) = Message::deserialize_body(body) else { break };
+                scheduled.insert((deliver_at, id), message);
+                keys.insert(id, (deliver_at, id));
+                buf = &rest[4 + len..];
+            }
+            DELIVERED => {
+                if let Some(key) = keys.remove(&id) {
+                    scheduled.remove(&key);
+                }
+                buf = rest;
+            }
+            _ => break,
+        }
+    }
+    scheduled
+}
+
+fn read_u64(buf: &[u8]) -> Option<u64> {
+    buf.get(..8).map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(value: &str) -> Message {
+        Message {
+            key: None,
+            value: value.as_bytes().to_vec(),
+            timestamp: 1,
+            headers: None,
+        }
+    }
+
+    #[test]
+    fn test_journal_replays_pending_and_drops_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let mut queue = DelayQueue::open(dir).unwrap();
+        let first = queue.schedule(2_000, message("late")).unwrap();
+        let second = queue.schedule(1_000, message("early")).unwrap();
+        queue.schedule(3_000, message("later")).unwrap();
+        assert_eq!(queue.next_due(), Some(1_000));
+        let due: Vec<_> = queue.due(2_000).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(due, vec![second, first]);
+        queue.delivered(second).unwrap();
+        drop(queue);
+
+        // a crash in the middle of writing an entry
+        let mut journal = OpenOptions::new().append(true).open(dir.join(DELAYED_FILE)).unwrap();
+        journal.write_all(&[SCHEDULED, 0, 0, 0]).unwrap();
+
+        let mut queue = DelayQueue::open(dir).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.due(2_000)[0].1.value, b"late");
+        // ids keep counting past the replayed ones
+        assert_eq!(queue.schedule(2_000, message("again")).unwrap(), (2_000, 3));
+    }
+}
@@ -11,12 +11,18 @@ pub enum EngineError {
     #[error("Partition does not exist")]
     NoPartition,
 
+    #[error("Offset {offset} is beyond the log end offset {log_end_offset}")]
+    OffsetOutOfRange { offset: u64, log_end_offset: u64 },
+
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
 
     #[error("Deserialization error: {0}")]
     Deserialize(#[from] DeserializeError),
 
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+
     #[error("Unexpected engine error: {0}")]
     Other(String),
 }
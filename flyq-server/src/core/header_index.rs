@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use flyq_protocol::errors::DeserializeError;
+use flyq_protocol::message::Message;
+use crate::core::segment::SegmentIterator;
+
+/// Per-segment secondary index: for each configured header name, the offsets
+/// of the records carrying each value. Persisted next to the segment in a
+/// `.hindex` file that starts with the list of indexed names, followed by one
+/// `[u64 offset][u16 name][u32 value_len][value]` entry per match.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderIndex {
+    names: Vec<String>,
+    values: HashMap<String, HashMap<Vec<u8>, Vec<u64>>>, // name → value → ascending offsets
+    last_offset: Option<u64>, // newest record seen by `record`
+}
+
+const HINDEX_MAGIC: &[u8; 4] = b"FQHX";
+const HINDEX_VERSION: u8 = 1;
+
+impl HeaderIndex {
+    pub fn new(names: Vec<String>) -> Self {
+        HeaderIndex {
+            names,
+            ..Default::default()
+        }
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Whether lookups for `name` are complete for this segment.
+    pub fn covers(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    /// Offsets of records whose `name` header equals `value`, ascending.
+    pub fn lookup(&self, name: &str, value: &[u8]) -> &[u64] {
+        self.values
+            .get(name)
+            .and_then(|by_value| by_value.get(value))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Whether the record at `offset` was already indexed, e.g. before a restart.
+    pub fn has_seen(&self, offset: u64) -> bool {
+        self.last_offset.is_some_and(|last| offset <= last)
+    }
+
+    /// Adds the record's indexed headers and returns the bytes to append to
+    /// the `.hindex` file (empty when nothing matched).
+    pub fn record(&mut self, offset: u64, message: &Message) -> Vec<u8> {
+        self.last_offset = Some(offset);
+        let mut encoded = Vec::new();
+        for (name, value) in message.headers.iter().flatten() {
+            let Some(name_idx) = self.names.iter().position(|n| n == name) else {
+                continue;
+            };
+            self.insert(name_idx, value.clone(), offset);
+            encoded.extend_from_slice(&offset.to_be_bytes());
+            encoded.extend_from_slice(&(name_idx as u16).to_be_bytes());
+            encoded.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            encoded.extend_from_slice(value);
+        }
+        encoded
+    }
+
+    fn insert(&mut self, name_idx: usize, value: Vec<u8>, offset: u64) {
+        let offsets = self
+            .values
+            .entry(self.names[name_idx].clone())
+            .or_default()
+            .entry(value)
+            .or_default();
+        if offsets.last() != Some(&offset) {
+            offsets.push(offset);
+        }
+    }
+
+    /// Start of a `.hindex` file: magic, version and the indexed names.
+    pub fn encode_file_header(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(HINDEX_MAGIC);
+        header.push(HINDEX_VERSION);
+        header.extend_from_slice(&(self.names.len() as u16).to_be_bytes());
+        for name in &self.names {
+            header.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            header.extend_from_slice(name.as_bytes());
+        }
+        header
+    }
+
+    /// Parses a `.hindex` file. A torn entry at the end (crash mid-append) is
+    /// dropped; recovery re-indexes that record from the log.
+    pub fn decode_file(bytes: &[u8]) -> Option<HeaderIndex> {
+        let mut rest = bytes;
+        if take(&mut rest, 4)? != HINDEX_MAGIC || take(&mut rest, 1)?[0] != HINDEX_VERSION {
+            return None;
+        }
+        let count = u16::from_be_bytes(take(&mut rest, 2)?.try_into().ok()?);
+        let mut names = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let len = u16::from_be_bytes(take(&mut rest, 2)?.try_into().ok()?) as usize;
+            names.push(String::from_utf8(take(&mut rest, len)?.to_vec()).ok()?);
+        }
+
+        let mut index = HeaderIndex::new(names);
+        while let Some((offset, name_idx, value)) = decode_entry(&mut rest) {
+            if name_idx < index.names.len() {
+                index.insert(name_idx, value, offset);
+                index.last_offset = Some(index.last_offset.map_or(offset, |last| last.max(offset)));
+            }
+        }
+        Some(index)
+    }
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if rest.len() < len {
+        return None;
+    }
+    let (head, tail) = rest.split_at(len);
+    *rest = tail;
+    Some(head)
+}
+
+fn decode_entry(rest: &mut &[u8]) -> Option<(u64, usize, Vec<u8>)> {
+    let offset = u64::from_be_bytes(take(rest, 8)?.try_into().ok()?);
+    let name_idx = u16::from_be_bytes(take(rest, 2)?.try_into().ok()?) as usize;
+    let len = u32::from_be_bytes(take(rest, 4)?.try_into().ok()?) as usize;
+    Some((offset, name_idx, take(rest, len)?.to_vec()))
+}
+
+/// Records with header `name` equal to `value`, within an offset range and
+/// optionally a record timestamp range (ms, inclusive start, exclusive end).
+#[derive(Debug, Clone)]
+pub struct HeaderQuery {
+    pub name: String,
+    pub value: Vec<u8>,
+    pub start_offset: u64,
+    pub end_offset: Option<u64>, // exclusive
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+    pub max_results: usize,
+}
+
+impl HeaderQuery {
+    pub fn offset_in_range(&self, offset: u64) -> bool {
+        offset >= self.start_offset && self.end_offset.is_none_or(|end| offset < end)
+    }
+
+    pub fn time_in_range(&self, timestamp: u64) -> bool {
+        self.start_time.is_none_or(|start| timestamp >= start)
+            && self.end_time.is_none_or(|end| timestamp < end)
+    }
+
+    /// Whether a segment holding `base_offset..=last_offset` with records no
+    /// newer than `max_timestamp` (0 if unknown) can contain a match.
+    pub fn may_match_segment(&self, base_offset: u64, last_offset: u64, max_timestamp: u64) -> bool {
+        last_offset >= self.start_offset
+            && self.end_offset.is_none_or(|end| base_offset < end)
+            && (max_timestamp == 0 || self.start_time.is_none_or(|start| max_timestamp >= start))
+    }
+
+    fn matches(&self, message: &Message) -> bool {
+        message
+            .headers
+            .iter()
+            .flatten()
+            .any(|(name, value)| *name == self.name && *value == self.value)
+    }
+
+    pub fn is_full(&self, results: &[(u64, Message)]) -> bool {
+        results.len() >= self.max_results
+    }
+
+    /// Appends this segment's matches to `results`, in offset order. Uses the
+    /// header index when it covers the queried name, otherwise scans the
+    /// segment (e.g. segments written before the header was configured).
+    pub(crate) fn search_segment(
+        &self,
+        index: &HeaderIndex,
+        open: impl Fn(u64) -> Result<SegmentIterator, DeserializeError>,
+        results: &mut Vec<(u64, Message)>,
+    ) -> Result<(), DeserializeError> {
+        if index.covers(&self.name) {
+            for &offset in index.lookup(&self.name, &self.value) {
+                if self.is_full(results) {
+                    break;
+                }
+                if !self.offset_in_range(offset) {
+                    continue;
+                }
+                if let Some(record) = open(offset)?.next() {
+                    let (found, message) = record?;
+                    if found == offset && self.time_in_range(message.timestamp) {
+                        results.push((found, message));
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        for record in open(self.start_offset)? {
+            let (offset, message) = record?;
+            if self.end_offset.is_some_and(|end| offset >= end) || self.is_full(results) {
+                break;
+            }
+            if self.matches(&message) && self.time_in_range(message.timestamp) {
+                results.push((offset, message));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderIndex;
+    use flyq_protocol::message::Message;
+
+    fn with_headers(headers: &[(&str, &str)]) -> Message {
+        Message {
+            key: None,
+            value: b"v".to_vec(),
+            timestamp: 1,
+            headers: Some(headers.iter().map(|(n, v)| (n.to_string(), v.as_bytes().to_vec())).collect()),
+        }
+    }
+
+    #[test]
+    fn test_header_index_file_roundtrip() {
+        let mut index = HeaderIndex::new(vec!["tenant".into(), "region".into()]);
+        let mut file = index.encode_file_header();
+        file.extend(index.record(0, &with_headers(&[("tenant", "acme"), ("trace", "abc")])));
+        file.extend(index.record(1, &with_headers(&[("tenant", "globex")])));
+        file.extend(index.record(2, &with_headers(&[("region", "eu"), ("tenant", "acme")])));
+        file.extend(index.record(3, &with_headers(&[])));
+
+        assert_eq!(index.lookup("tenant", b"acme"), &[0, 2]);
+        assert!(!index.covers("trace"));
+
+        // torn last entry: the rest still loads
+        file.extend_from_slice(&[0, 0, 0]);
+        let loaded = HeaderIndex::decode_file(&file).unwrap();
+        assert_eq!(loaded.names(), index.names());
+        assert_eq!(loaded.lookup("tenant", b"acme"), &[0, 2]);
+        assert_eq!(loaded.lookup("tenant", b"globex"), &[1]);
+        assert_eq!(loaded.lookup("region", b"eu"), &[2]);
+        assert!(loaded.has_seen(2));
+        assert!(!loaded.has_seen(3), "records without indexed headers leave no trace on disk");
+    }
+}
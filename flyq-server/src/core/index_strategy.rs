@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::core::constants::DEFAULT_INDEX_INTERVAL;
+
+/// Decides which records of a segment get an offset index entry, how entries
+/// are laid out in the `.index` file and where a read starts scanning.
+///
+/// Strategies are stateless: everything they need comes from the newest
+/// existing entry, so one instance is shared by every segment of a partition.
+/// Each segment records the strategy it was written with in its index header,
+/// and recovery keeps using that one even if the topic config changed since.
+pub trait IndexStrategy: Send + Sync + Debug {
+    /// Config this strategy was built from, persisted in the index header.
+    fn config(&self) -> IndexStrategyConfig;
+
+    /// Whether the record at `offset`, starting at file position `pos`, gets an
+    /// entry. `last` is the newest entry as `(offset, pos)`, `None` if there is none.
+    fn should_index(&self, last: Option<(u64, u64)>, offset: u64, pos: u64) -> bool;
+
+    /// Size of one persisted entry.
+    fn entry_len(&self) -> usize {
+        16
+    }
+
+    fn encode_entry(&self, offset: u64, pos: u64) -> Vec<u8> {
+        let mut entry = Vec::with_capacity(16);
+        entry.extend_from_slice(&offset.to_be_bytes());
+        entry.extend_from_slice(&pos.to_be_bytes());
+        entry
+    }
+
+    /// Inverse of [`Self::encode_entry`]; `bytes` is exactly `entry_len` long.
+    fn decode_entry(&self, bytes: &[u8]) -> (u64, u64) {
+        let offset = u64::from_be_bytes(bytes[0..8].try_into().expect("index offset slice must be 8 bytes"));
+        let pos = u64::from_be_bytes(bytes[8..16].try_into().expect("index position slice must be 8 bytes"));
+        (offset, pos)
+    }
+
+    /// File position to start scanning from to reach `offset`: the closest
+    /// entry at or below it, or the start of the file.
+    fn lookup(&self, index: &BTreeMap<u64, u64>, offset: u64) -> u64 {
+        index.range(..=offset).next_back().map(|(_, &pos)| pos).unwrap_or(0)
+    }
+}
+
+/// Which [`IndexStrategy`] a topic uses, e.g. in TOML
+/// `index = { type = "every_bytes", interval = 65536 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IndexStrategyConfig {
+    /// An entry every `interval` records. Suits topics of similarly sized, small records.
+    EveryMessages { interval: u32 },
+    /// An entry once `interval` bytes were written since the last one, so a
+    /// lookup never scans more than about that much. Suits large or uneven records.
+    EveryBytes { interval: u64 },
+    /// An entry for every record; lookups never scan.
+    Dense,
+}
+
+impl Default for IndexStrategyConfig {
+    fn default() -> Self {
+        IndexStrategyConfig::EveryMessages { interval: DEFAULT_INDEX_INTERVAL }
+    }
+}
+
+impl IndexStrategyConfig {
+    pub fn build(self) -> Arc<dyn IndexStrategy> {
+        match self {
+            IndexStrategyConfig::EveryMessages { interval } => Arc::new(EveryNMessages { interval }),
+            IndexStrategyConfig::EveryBytes { interval } => Arc::new(EveryNBytes { interval }),
+            IndexStrategyConfig::Dense => Arc::new(DenseIndex),
+        }
+    }
+
+    /// Header written at the start of every `.index` file.
+    pub(crate) fn encode_header(self) -> [u8; INDEX_HEADER_LEN] {
+        let (kind, param) = match self {
+            IndexStrategyConfig::EveryMessages { interval } => (1, interval as u64),
+            IndexStrategyConfig::EveryBytes { interval } => (2, interval),
+            IndexStrategyConfig::Dense => (3, 0),
+        };
+        let mut header = [0u8; INDEX_HEADER_LEN];
+        header[0..4].copy_from_slice(INDEX_MAGIC);
+        header[4] = INDEX_VERSION;
+        header[5] = kind;
+        header[8..16].copy_from_slice(&param.to_be_bytes());
+        header
+    }
+
+    /// `None` when `bytes` is not a header, i.e. the file predates headers and
+    /// starts straight with 16 byte entries.
+    pub(crate) fn decode_header(bytes: &[u8; INDEX_HEADER_LEN]) -> Option<Self> {
+        if &bytes[0..4] != INDEX_MAGIC || bytes[4] != INDEX_VERSION {
+            return None;
+        }
+        let param = u64::from_be_bytes(bytes[8..16].try_into().unwrap());
+        match bytes[5] {
+            1 => Some(IndexStrategyConfig::EveryMessages { interval: param as u32 }),
+            2 => Some(IndexStrategyConfig::EveryBytes { interval: param }),
+            3 => Some(IndexStrategyConfig::Dense),
+            _ => None,
+        }
+    }
+}
+
+// an index entry starting with these bytes would need an offset above 5 * 10^18
+const INDEX_MAGIC: &[u8; 4] = b"FQIX";
+const INDEX_VERSION: u8 = 1;
+pub(crate) const INDEX_HEADER_LEN: usize = 16;
+
+#[derive(Debug)]
+pub struct EveryNMessages {
+    pub interval: u32,
+}
+
+impl IndexStrategy for EveryNMessages {
+    fn config(&self) -> IndexStrategyConfig {
+        IndexStrategyConfig::EveryMessages { interval: self.interval }
+    }
+
+    fn should_index(&self, last: Option<(u64, u64)>, offset: u64, _pos: u64) -> bool {
+        match last {
+            Some((last_offset, _)) => offset.saturating_sub(last_offset) >= self.interval as u64,
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EveryNBytes {
+    pub interval: u64,
+}
+
+impl IndexStrategy for EveryNBytes {
+    fn config(&self) -> IndexStrategyConfig {
+        IndexStrategyConfig::EveryBytes { interval: self.interval }
+    }
+
+    fn should_index(&self, last: Option<(u64, u64)>, _offset: u64, pos: u64) -> bool {
+        match last {
+            Some((_, last_pos)) => pos.saturating_sub(last_pos) >= self.interval,
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DenseIndex;
+
+impl IndexStrategy for DenseIndex {
+    fn config(&self) -> IndexStrategyConfig {
+        IndexStrategyConfig::Dense
+    }
+
+    fn should_index(&self, _last: Option<(u64, u64)>, _offset: u64, _pos: u64) -> bool {
+        true
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use crate::core::partiton_meta::PartitionMeta;
+use crate::core::segment::{IndexFile, Segment};
+use crate::core::stored_record::{entry_len, StoredRecord};
+use flyq_protocol::record_batch::BatchHeader;
+
+/// `segment_*.log` files of a partition directory, by base offset. Of a
+/// compacted segment only the newest generation is listed; older ones are
+/// leftovers of a crash that the broker removes on open.
+pub fn list_segments(dir: &Path) -> std::io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if let Some((base_offset, generation)) = Segment::scan_path(&path).and_then(|name| Segment::parse_generation(&name)) {
+            let newer_known = segments.get(&base_offset).is_some_and(|(known, _)| *known > generation);
+            if !newer_known {
+                segments.insert(base_offset, (generation, path));
+            }
+        }
+    }
+    Ok(segments.into_iter().map(|(base_offset, (_, path))| (base_offset, path)).collect())
+}
+
+/// A record as laid out in a segment file. The records of a v2 batch share
+/// the batch's position; the first one carries its length, the others 0.
+#[derive(Debug, Clone)]
+pub struct RawRecord {
+    pub position: u64, // file position of the length prefix
+    pub len: u64,      // bytes on disk, length prefix included
+    pub record: StoredRecord,
+    pub batch: Option<BatchHeader>, // header of the batch the record came in
+}
+
+/// Where and why a segment file stops being readable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptRecord {
+    pub position: u64,
+    pub reason: String,
+}
+
+/// Reads every record of a segment file in file order, including the ones
+/// `SegmentIterator` would skip (duplicates, offsets going backwards), and
+/// reports the first unreadable record instead of treating it as the end.
+/// Never writes to the file.
+pub struct RecordScanner {
+    reader: BufReader<File>,
+    pos: u64,
+    file_len: u64,
+    done: bool,
+    pending: VecDeque<RawRecord>, // rest of the batch read last
+}
+
+impl RecordScanner {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        Ok(RecordScanner {
+            reader: BufReader::new(file),
+            pos: 0,
+            file_len,
+            done: false,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// End of the readable prefix so far: after the last good record.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    fn corrupt(&mut self, reason: String) -> Option<Result<RawRecord, CorruptRecord>> {
+        self.done = true;
+        Some(Err(CorruptRecord { position: self.pos, reason }))
+    }
+}
+
+impl Iterator for RecordScanner {
+    type Item = Result<RawRecord, CorruptRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(raw) = self.pending.pop_front() {
+            return Some(Ok(raw));
+        }
+        if self.done || self.pos >= self.file_len {
+            return None;
+        }
+        let mut len_buf = [0u8; 4];
+        if let Err(e) = self.reader.read_exact(&mut len_buf) {
+            return match e.kind() {
+                ErrorKind::UnexpectedEof => self.corrupt("torn length prefix".into()),
+                _ => self.corrupt(e.to_string()),
+            };
+        }
+        let len_word = u32::from_be_bytes(len_buf);
+        let len = entry_len(len_word) as u64;
+        if self.pos + 4 + len > self.file_len {
+            return self.corrupt(format!(
+                "record of {} bytes runs past the end of the file ({} bytes)",
+                len, self.file_len
+            ));
+        }
+
+        let mut buf = vec![0u8; len as usize];
+        if let Err(e) = self.reader.read_exact(&mut buf) {
+            return self.corrupt(e.to_string());
+        }
+        match StoredRecord::deserialize_entry(len_word, &buf) {
+            Ok((records, batch)) => {
+                let position = self.pos;
+                self.pos += 4 + len;
+                self.pending.extend(records.into_iter().enumerate().map(|(i, record)| RawRecord {
+                    position,
+                    len: if i == 0 { 4 + len } else { 0 },
+                    record,
+                    batch,
+                }));
+                self.pending.pop_front().map(Ok)
+            }
+            Err(e) => self.corrupt(format!("undecodable record: {:?}", e)),
+        }
+    }
+}
+
+/// Something wrong with a partition's files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    Gap { after: u64, next: u64, position: u64 },
+    Duplicate { offset: u64, position: u64 },
+    OutOfOrder { offset: u64, previous: u64, position: u64 },
+    BelowBaseOffset { offset: u64, base_offset: u64, position: u64 },
+    CorruptTail { position: u64, reason: String, trailing_bytes: u64 },
+    IndexMissing,
+    IndexTornEntry { bytes: usize },
+    IndexUnsorted { offset: u64, position: u64 },
+    /// No record starts at the indexed position.
+    IndexMisaligned { offset: u64, position: u64 },
+    /// A record starts there, but with another offset.
+    IndexWrongOffset { offset: u64, position: u64, actual: u64 },
+    IndexPastEnd { offset: u64, position: u64 },
+    WatermarkMismatch { field: &'static str, meta: u64, derived: u64 },
+    /// The records end before the last offset the segment was sealed with.
+    RecordsMissing { expected_last: u64, found_last: Option<u64> },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::Gap { after, next, position } => {
+                write!(f, "gap: offset {} follows {} (at byte {})", next, after, position)
+            }
+            Finding::Duplicate { offset, position } => {
+                write!(f, "duplicate offset {} (at byte {})", offset, position)
+            }
+            Finding::OutOfOrder { offset, previous, position } => {
+                write!(f, "out of order: offset {} after {} (at byte {})", offset, previous, position)
+            }
+            Finding::BelowBaseOffset { offset, base_offset, position } => write!(
+                f,
+                "offset {} is below the segment base offset {} (at byte {})",
+                offset, base_offset, position
+            ),
+            Finding::CorruptTail { position, reason, trailing_bytes } => write!(
+                f,
+                "corrupt tail at byte {} ({} bytes unreadable): {}",
+                position, trailing_bytes, reason
+            ),
+            Finding::IndexMissing => write!(f, "index file is missing"),
+            Finding::IndexTornEntry { bytes } => write!(f, "index ends with a torn entry of {} bytes", bytes),
+            Finding::IndexUnsorted { offset, position } => write!(
+                f,
+                "index entry {} -> {} does not follow the previous entry",
+                offset, position
+            ),
+            Finding::IndexMisaligned { offset, position } => write!(
+                f,
+                "index entry {} -> {} does not point at the start of a record",
+                offset, position
+            ),
+            Finding::IndexWrongOffset { offset, position, actual } => write!(
+                f,
+                "index entry {} -> {} points at offset {}",
+                offset, position, actual
+            ),
+            Finding::IndexPastEnd { offset, position } => write!(
+                f,
+                "index entry {} -> {} points past the readable records",
+                offset, position
+            ),
+            Finding::WatermarkMismatch { field, meta, derived } => write!(
+                f,
+                "meta.json {} is {}, segments say {}",
+                field, meta, derived
+            ),
+            Finding::RecordsMissing { expected_last, found_last: Some(found) } => write!(
+                f,
+                "records end at offset {}, the segment was sealed at {}",
+                found, expected_last
+            ),
+            Finding::RecordsMissing { expected_last, found_last: None } => {
+                write!(f, "no records left, the segment was sealed at offset {}", expected_last)
+            }
+        }
+    }
+}
+
+/// Validates one segment while its records are scanned in file order: offset
+/// continuity, and that every index entry points at the start of the record
+/// it names. Only the index entries are kept in memory, not the records.
+pub struct SegmentCheck {
+    base_offset: u64,
+    pending_index: VecDeque<(u64, u64)>, // (offset, position), by position
+    pub records: u64,
+    pub first_offset: Option<u64>,
+    pub last_offset: Option<u64>, // largest offset seen
+    previous: Option<u64>,
+    pub findings: Vec<Finding>,
+}
+
+impl SegmentCheck {
+    /// `index` is `None` when the segment has no index file.
+    pub fn new(base_offset: u64, index: Option<&IndexFile>) -> Self {
+        let mut findings = Vec::new();
+        let mut pending_index = VecDeque::new();
+        match index {
+            None => findings.push(Finding::IndexMissing),
+            Some(index) => {
+                if index.trailing_bytes > 0 {
+                    findings.push(Finding::IndexTornEntry { bytes: index.trailing_bytes });
+                }
+                let mut last: Option<(u64, u64)> = None;
+                for &(offset, position) in &index.entries {
+                    if last.is_some_and(|(o, p)| offset <= o || position <= p) {
+                        findings.push(Finding::IndexUnsorted { offset, position });
+                        continue;
+                    }
+                    last = Some((offset, position));
+                    pending_index.push_back((offset, position));
+                }
+            }
+        }
+
+        SegmentCheck {
+            base_offset,
+            pending_index,
+            records: 0,
+            first_offset: None,
+            last_offset: None,
+            previous: None,
+            findings,
+        }
+    }
+
+    pub fn record(&mut self, raw: &RawRecord) {
+        let offset = raw.record.offset;
+        let position = raw.position;
+
+        while let Some(&(indexed, at)) = self.pending_index.front() {
+            if at > position {
+                break;
+            }
+            self.pending_index.pop_front();
+            if at < position {
+                self.findings.push(Finding::IndexMisaligned { offset: indexed, position: at });
+            } else if indexed != offset {
+                self.findings.push(Finding::IndexWrongOffset { offset: indexed, position: at, actual: offset });
+            }
+        }
+
+        if offset < self.base_offset {
+            self.findings.push(Finding::BelowBaseOffset { offset, base_offset: self.base_offset, position });
+        }
+        match self.previous {
+            Some(previous) if offset == previous => {
+                self.findings.push(Finding::Duplicate { offset, position })
+            }
+            Some(previous) if offset < previous => {
+                self.findings.push(Finding::OutOfOrder { offset, previous, position })
+            }
+            Some(previous) if offset > previous + 1 => {
+                self.findings.push(Finding::Gap { after: previous, next: offset, position })
+            }
+            _ => {}
+        }
+
+        self.previous = Some(offset);
+        self.records += 1;
+        self.first_offset.get_or_insert(offset);
+        self.last_offset = Some(self.last_offset.map_or(offset, |last| last.max(offset)));
+    }
+
+    /// Finishes the check once the scanner stopped, at `corrupt` if it hit an
+    /// unreadable record. `file_len` is the size of the segment file.
+    pub fn finish(mut self, corrupt: Option<CorruptRecord>, file_len: u64) -> Vec<Finding> {
+        for (offset, position) in self.pending_index.drain(..) {
+            self.findings.push(Finding::IndexPastEnd { offset, position });
+        }
+        if let Some(corrupt) = corrupt {
+            self.findings.push(Finding::CorruptTail {
+                trailing_bytes: file_len.saturating_sub(corrupt.position),
+                position: corrupt.position,
+                reason: corrupt.reason,
+            });
+        }
+        self.findings
+    }
+}
+
+impl Finding {
+    /// Whether rebuilding the segment's index files fixes this.
+    pub fn is_index_problem(&self) -> bool {
+        matches!(
+            self,
+            Finding::IndexMissing
+                | Finding::IndexTornEntry { .. }
+                | Finding::IndexUnsorted { .. }
+                | Finding::IndexMisaligned { .. }
+                | Finding::IndexWrongOffset { .. }
+                | Finding::IndexPastEnd { .. }
+        )
+    }
+}
+
+/// Offsets a scanned segment holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentSpan {
+    pub base_offset: u64,
+    pub first_offset: Option<u64>, // None while empty
+    pub last_offset: Option<u64>,
+}
+
+/// Watermarks as the segment files imply them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DerivedWatermarks {
+    pub first_offset: u64,   // oldest record still on disk
+    pub log_end_offset: u64, // one past the newest record
+}
+
+impl DerivedWatermarks {
+    /// From a partition's segments, by base offset.
+    pub fn from_spans(spans: &[SegmentSpan]) -> Self {
+        // an empty newest segment still tells where the log ends
+        let log_end_offset = spans
+            .iter()
+            .filter_map(|s| s.last_offset)
+            .max()
+            .map_or(0, |last| last + 1)
+            .max(spans.last().map_or(0, |s| s.base_offset));
+        DerivedWatermarks {
+            first_offset: spans.iter().find_map(|s| s.first_offset).unwrap_or(log_end_offset),
+            log_end_offset,
+        }
+    }
+}
+
+/// Compares `meta.json` against the segments. A low watermark above the first
+/// record on disk is normal (DeleteRecords does not rewrite segments), one
+/// below it means records the partition claims to have are gone.
+pub fn check_meta(meta: &PartitionMeta, derived: DerivedWatermarks) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if meta.log_end_offset != derived.log_end_offset {
+        findings.push(Finding::WatermarkMismatch {
+            field: "log_end_offset",
+            meta: meta.log_end_offset,
+            derived: derived.log_end_offset,
+        });
+    }
+    if meta.low_watermark < derived.first_offset || meta.low_watermark > derived.log_end_offset {
+        findings.push(Finding::WatermarkMismatch {
+            field: "low_watermark",
+            meta: meta.low_watermark,
+            derived: derived.first_offset,
+        });
+    }
+    if meta.high_watermark > derived.log_end_offset {
+        findings.push(Finding::WatermarkMismatch {
+            field: "high_watermark",
+            meta: meta.high_watermark,
+            derived: derived.log_end_offset,
+        });
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Finding, RecordScanner, SegmentCheck};
+    use crate::core::segment::IndexFile;
+    use crate::core::stored_record::StoredRecord;
+    use flyq_protocol::message::Message;
+    use std::io::Write;
+
+    fn record(offset: u64) -> Vec<u8> {
+        StoredRecord {
+            offset,
+            message: Message { key: None, value: b"value".to_vec(), timestamp: 1, headers: None },
+            producer: None,
+        }
+        .serialize()
+    }
+
+    #[test]
+    fn test_segment_check_reports_offset_and_index_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("segment_00000000000000000010.log");
+        let mut file = std::fs::File::create(&path).unwrap();
+        let mut positions = Vec::new();
+        let mut pos = 0;
+        for offset in [10, 11, 11, 14, 13] {
+            let bytes = record(offset);
+            positions.push(pos);
+            pos += bytes.len() as u64;
+            file.write_all(&bytes).unwrap();
+        }
+        file.write_all(&[0, 0, 0, 99, 1, 2]).unwrap(); // torn last record
+
+        let index = IndexFile {
+            strategy: None,
+            entries: vec![(10, 0), (11, positions[1] + 3), (14, positions[4]), (20, pos + 100)],
+            trailing_bytes: 0,
+        };
+        let mut check = SegmentCheck::new(10, Some(&index));
+        let mut scanner = RecordScanner::open(&path).unwrap();
+        let mut corrupt = None;
+        for item in scanner.by_ref() {
+            match item {
+                Ok(raw) => check.record(&raw),
+                Err(e) => corrupt = Some(e),
+            }
+        }
+        assert_eq!(scanner.position(), pos);
+        assert_eq!(check.records, 5);
+        assert_eq!(check.last_offset, Some(14));
+
+        let findings = check.finish(corrupt, scanner.file_len());
+        assert_eq!(
+            findings,
+            vec![
+                Finding::IndexMisaligned { offset: 11, position: positions[1] + 3 },
+                Finding::Duplicate { offset: 11, position: positions[2] },
+                Finding::Gap { after: 11, next: 14, position: positions[3] },
+                Finding::IndexWrongOffset { offset: 14, position: positions[4], actual: 13 },
+                Finding::OutOfOrder { offset: 13, previous: 14, position: positions[4] },
+                Finding::IndexPastEnd { offset: 20, position: pos + 100 },
+                Finding::CorruptTail {
+                    position: pos,
+                    reason: format!("record of 99 bytes runs past the end of the file ({} bytes)", pos + 6),
+                    trailing_bytes: 6,
+                },
+            ]
+        );
+    }
+}
@@ -3,23 +3,62 @@ use crate::core::constants::{
     DEFAULT_PARTITION_CNT,
 };
 use crate::core::error::EngineError;
+use crate::core::header_index::HeaderQuery;
 use crate::core::offset_tracker::OffsetTracker;
+use crate::core::partition::{ConsumerProtection, Partition, PartitionHealth};
+use crate::core::partition_reader::PartitionReader;
+use crate::core::producer_state::{ProduceOutcome, ProducerIdAllocator};
+use crate::core::queue::{Delivery, QueueCursor};
+use crate::core::retention::{CleanupCandidate, RetentionPolicy};
 use crate::core::storage::Storage;
-use crate::core::topic::Topic;
+use crate::core::stored_record::{ControlMarker, ProducerStamp};
+use crate::core::transaction::{TransactionCoordinator, TransactionEntry, TransactionError, TransactionState, TxnOffset};
+use crate::core::topic::{SharedPartition, Topic};
 use flyq_protocol::errors::DeserializeError;
-use flyq_protocol::message::Message;
-use std::collections::HashMap;
+use flyq_protocol::message::{DeadLetter, Message};
+use flyq_protocol::record_batch::RecordBatch;
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio::sync::Mutex;
 use crate::broker_config;
 
+type QueueKey = (String, String, u32); // (group, topic, partition)
+
+/// The record at exactly `offset`, if it is still in the log.
+fn read_at(partition: &Partition, offset: u64) -> Result<Option<Message>, EngineError> {
+    let mut stream = match partition.stream_from_offset(offset) {
+        Ok(s) => s,
+        Err(DeserializeError::OffsetNotFound(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match stream.next().transpose()? {
+        Some((found, message)) if found == offset => Ok(Some(message)),
+        _ => Ok(None),
+    }
+}
+
+/// Shared by every connection without an outer lock. The topic map is only
+/// written when a topic is created; everything else takes the read lock just
+/// long enough to clone out an `Arc`, then locks the one partition it needs.
 pub struct LogEngine {
     storage: Storage,
-    pub topics: HashMap<String, Topic>,
+    pub topics: RwLock<HashMap<String, Arc<Topic>>>,
     // optional config knobs:
     auto_create_topic: bool,
     pub offset_tracker: Arc<Mutex<OffsetTracker>>,
+    producer_ids: std::sync::Mutex<ProducerIdAllocator>,
+    // held while markers are written and while transactional records are
+    // checked against their transaction
+    transactions: Mutex<TransactionCoordinator>,
+    // producer id → gate shared by its transactional appends from the
+    // coordinator check until they are in the log, taken exclusively while
+    // its markers are written, so a transaction cannot end under an append
+    txn_appends: std::sync::Mutex<HashMap<u64, Arc<tokio::sync::RwLock<()>>>>,
+    // (group, topic, partition) → cursor; the map lock is only held to look a
+    // cursor up, each cursor has its own
+    queues: std::sync::Mutex<HashMap<QueueKey, Arc<Mutex<QueueCursor>>>>,
 }
 
 impl LogEngine {
@@ -27,11 +66,19 @@ impl LogEngine {
         let storage = Storage::new(&base_dir);
         let offset_file = base_dir.as_ref().join("consumer_offsets.json");
 
-        let mut engine = LogEngine {
+        let engine = LogEngine {
             storage,
-            topics: HashMap::new(),
+            topics: RwLock::new(HashMap::new()),
             auto_create_topic: DEFAULT_AUTO_CREATE_TOPICS_ENABLE,
             offset_tracker: Arc::new(Mutex::new(OffsetTracker::new(offset_file))),
+            producer_ids: std::sync::Mutex::new(
+                ProducerIdAllocator::load(base_dir.as_ref()).expect("Failed to load producer ids"),
+            ),
+            transactions: Mutex::new(
+                TransactionCoordinator::load(base_dir.as_ref()).expect("Failed to load transactions"),
+            ),
+            txn_appends: std::sync::Mutex::new(HashMap::new()),
+            queues: std::sync::Mutex::new(HashMap::new()),
         };
 
         let _ = engine.offset_tracker.lock().await.load_from_file();
@@ -40,94 +87,488 @@ impl LogEngine {
             .scan_topics()
             .expect("Failed to scan topic directories");
         engine
+            .complete_prepared_transactions()
+            .await
+            .expect("Failed to complete prepared transactions");
+        engine
+    }
+
+    /// Writes the markers of transactions decided before the last shutdown.
+    async fn complete_prepared_transactions(&self) -> Result<(), EngineError> {
+        let mut coordinator = self.transactions.lock().await;
+        for (transactional_id, entry) in coordinator.prepared() {
+            self.complete_transaction(&mut coordinator, &transactional_id, entry).await?;
+        }
+        Ok(())
     }
 
-    fn scan_topics(&mut self) -> std::io::Result<()> {
+    fn scan_topics(&self) -> std::io::Result<()> {
         let entries = self.storage.scan_base();
         let cfg = broker_config();
+        let mut topics = self.topics.write().expect("topic map poisoned");
         for entry in entries {
             let path = entry?.path();
             if path.is_dir() {
                 if let Some(topic) = Topic::scan_existing(path, cfg.segment_max_bytes) {
-                    self.topics.insert(topic.name.clone(), topic);
+                    topics.insert(topic.name.clone(), Arc::new(topic));
                 }
             }
         }
         Ok(())
     }
 
+    pub fn topic(&self, name: &str) -> Option<Arc<Topic>> {
+        self.topics.read().expect("topic map poisoned").get(name).cloned()
+    }
+
+    /// Every topic at this moment; topics created afterwards are not included.
+    pub fn topics_snapshot(&self) -> Vec<Arc<Topic>> {
+        self.topics.read().expect("topic map poisoned").values().cloned().collect()
+    }
+
+    pub fn partition(&self, topic: &str, partition_id: u32) -> Result<SharedPartition, EngineError> {
+        let topic = self.topic(topic).ok_or(EngineError::NoTopic)?;
+        topic
+            .partitions
+            .get(&partition_id)
+            .cloned()
+            .ok_or(EngineError::NoPartition)
+    }
+
     // returns (partition_id, offset)
-    pub async fn produce(&mut self, topic_name: &str, msg: Message) -> std::io::Result<(u32, u64)> {
-        if !self.topics.contains_key(topic_name) {
-            self.ensure_topic(topic_name)
-                .expect("topic creation failed");
-        }
-        let topic = self
-            .topics
-            .get_mut(topic_name)
-            .expect("topic should exist now");
+    pub async fn produce(&self, topic_name: &str, msg: Message) -> std::io::Result<(u32, u64)> {
+        let topic = match self.topic(topic_name) {
+            Some(topic) => topic,
+            None => self.ensure_topic(topic_name).expect("topic creation failed"),
+        };
         topic.produce(msg).await
     }
 
+    /// Produces `msg` for delivery at `deliver_at` (Unix millis): until then
+    /// it is in no partition log, so neither plain nor group consumers see it.
+    /// Returns the partition it was routed to.
+    pub async fn schedule(&self, topic_name: &str, msg: Message, deliver_at: u64) -> std::io::Result<u32> {
+        let topic = match self.topic(topic_name) {
+            Some(topic) => topic,
+            None => self.ensure_topic(topic_name).expect("topic creation failed"),
+        };
+        topic.schedule(msg, deliver_at).await
+    }
+
+    /// A producer id no producer has had before, at epoch 0. With a
+    /// `transactional_id` seen before, its producer id at the next epoch
+    /// instead: the previous owner is fenced and its open transaction aborted.
+    pub async fn init_producer_id(&self, transactional_id: Option<&str>) -> Result<(u64, u16), EngineError> {
+        let Some(transactional_id) = transactional_id else {
+            return Ok((self.allocate_producer_id()?, 0));
+        };
+
+        let mut coordinator = self.transactions.lock().await;
+        let entry = match coordinator.get(transactional_id).cloned() {
+            Some(entry) => self.fence_transaction(&mut coordinator, transactional_id, entry).await?,
+            None => TransactionEntry {
+                producer_id: self.allocate_producer_id()?,
+                producer_epoch: 0,
+                state: TransactionState::Empty,
+                partitions: BTreeSet::new(),
+                offsets: Vec::new(),
+                started_at: 0,
+            },
+        };
+        coordinator.put(transactional_id, entry.clone())?;
+        Ok((entry.producer_id, entry.producer_epoch))
+    }
+
+    /// Moves `entry` to the next producer epoch, aborting its open
+    /// transaction if any, and returns it persisted. The current owner is
+    /// fenced; once the epochs run out the id gets a new producer id instead.
+    async fn fence_transaction(
+        &self,
+        coordinator: &mut TransactionCoordinator,
+        transactional_id: &str,
+        mut entry: TransactionEntry,
+    ) -> Result<TransactionEntry, EngineError> {
+        // the abort markers carry the new epoch, fencing appends of
+        // the previous owner that already got past the coordinator
+        let bumped = entry.producer_epoch.checked_add(1);
+        if let Some(epoch) = bumped {
+            entry.producer_epoch = epoch;
+        }
+        if entry.state == TransactionState::Ongoing {
+            entry.state = TransactionState::PrepareAbort;
+        }
+        coordinator.put(transactional_id, entry.clone())?;
+        let mut entry = self.complete_transaction(coordinator, transactional_id, entry).await?;
+        if bumped.is_none() {
+            (entry.producer_id, entry.producer_epoch) = (self.allocate_producer_id()?, 0);
+            coordinator.put(transactional_id, entry.clone())?;
+        }
+        Ok(entry)
+    }
+
+    /// Aborts transactions open for longer than the broker's transaction
+    /// timeout at `now` (Unix millis), fencing their producers. Returns how
+    /// many were aborted.
+    pub async fn abort_expired_transactions(&self, now: u64) -> Result<u32, EngineError> {
+        let mut coordinator = self.transactions.lock().await;
+        let expired = coordinator.expired(now, broker_config().transaction_timeout);
+        for (transactional_id, entry) in &expired {
+            tracing::warn!(transactional_id, producer_id = entry.producer_id, "Aborting timed out transaction");
+            self.fence_transaction(&mut coordinator, transactional_id, entry.clone()).await?;
+        }
+        Ok(expired.len() as u32)
+    }
+
+    fn allocate_producer_id(&self) -> std::io::Result<u64> {
+        self.producer_ids.lock().expect("mutex poisoned").allocate()
+    }
+
+    pub async fn begin_transaction(
+        &self,
+        transactional_id: &str,
+        producer_id: u64,
+        producer_epoch: u16,
+    ) -> Result<(), TransactionError> {
+        let mut coordinator = self.transactions.lock().await;
+        let mut entry = coordinator.owned(transactional_id, producer_id, producer_epoch)?.clone();
+        if entry.state != TransactionState::Empty {
+            return Err(TransactionError::InvalidState(format!("transaction already {:?}", entry.state)));
+        }
+        entry.state = TransactionState::Ongoing;
+        entry.partitions.clear();
+        entry.offsets.clear();
+        entry.started_at = chrono::Utc::now().timestamp_millis() as u64;
+        coordinator.put(transactional_id, entry)?;
+        Ok(())
+    }
+
+    /// Lets the producer write transactional records to `partitions`; topics
+    /// are created like a produce would.
+    pub async fn add_partitions_to_transaction(
+        &self,
+        transactional_id: &str,
+        producer_id: u64,
+        producer_epoch: u16,
+        partitions: &[(String, u32)],
+    ) -> Result<(), TransactionError> {
+        let mut coordinator = self.transactions.lock().await;
+        let mut entry = coordinator.owned(transactional_id, producer_id, producer_epoch)?.clone();
+        if entry.state != TransactionState::Ongoing {
+            return Err(TransactionError::InvalidState("no open transaction".into()));
+        }
+        for (topic_name, partition_id) in partitions {
+            let topic = match self.topic(topic_name) {
+                Some(topic) => topic,
+                None => self.ensure_topic(topic_name)?,
+            };
+            if !topic.partitions.contains_key(partition_id) {
+                return Err(EngineError::NoPartition.into());
+            }
+            entry.partitions.insert((topic_name.clone(), *partition_id));
+        }
+        coordinator.put(transactional_id, entry)?;
+        Ok(())
+    }
+
+    /// Stages consumer group offsets in the producer's open transaction. They
+    /// reach the offset tracker only if the transaction commits; a later
+    /// offset for the same group and partition replaces an earlier one.
+    pub async fn add_offsets_to_transaction(
+        &self,
+        transactional_id: &str,
+        producer_id: u64,
+        producer_epoch: u16,
+        group: &str,
+        offsets: &[(String, u32, u64)],
+    ) -> Result<(), TransactionError> {
+        let mut coordinator = self.transactions.lock().await;
+        let mut entry = coordinator.owned(transactional_id, producer_id, producer_epoch)?.clone();
+        if entry.state != TransactionState::Ongoing {
+            return Err(TransactionError::InvalidState("no open transaction".into()));
+        }
+        for (topic, partition, offset) in offsets {
+            self.partition(topic, *partition)?;
+            entry
+                .offsets
+                .retain(|staged| !(staged.group == group && staged.partition == *partition && &staged.topic == topic));
+            entry.offsets.push(TxnOffset {
+                group: group.to_string(),
+                topic: topic.clone(),
+                partition: *partition,
+                offset: *offset,
+            });
+        }
+        coordinator.put(transactional_id, entry)?;
+        Ok(())
+    }
+
+    /// Commits or aborts the producer's open transaction: the decision is
+    /// persisted first, then a marker is appended to every partition in the
+    /// transaction. A broker that stops in between writes the rest on startup.
+    pub async fn end_transaction(
+        &self,
+        transactional_id: &str,
+        producer_id: u64,
+        producer_epoch: u16,
+        marker: ControlMarker,
+    ) -> Result<(), TransactionError> {
+        let mut coordinator = self.transactions.lock().await;
+        let mut entry = coordinator.owned(transactional_id, producer_id, producer_epoch)?.clone();
+        if entry.state != TransactionState::Ongoing {
+            return Err(TransactionError::InvalidState("no open transaction".into()));
+        }
+        entry.state = match marker {
+            ControlMarker::Commit => TransactionState::PrepareCommit,
+            ControlMarker::Abort => TransactionState::PrepareAbort,
+        };
+        coordinator.put(transactional_id, entry.clone())?;
+        self.complete_transaction(&mut coordinator, transactional_id, entry).await?;
+        Ok(())
+    }
+
+    /// Writes the marker `entry` still owes its partitions, if any, and
+    /// returns it emptied and persisted.
+    async fn complete_transaction(
+        &self,
+        coordinator: &mut TransactionCoordinator,
+        transactional_id: &str,
+        mut entry: TransactionEntry,
+    ) -> Result<TransactionEntry, EngineError> {
+        if let Some(marker) = entry.pending_marker() {
+            // appends already past the coordinator land before the markers
+            let _appends = self.txn_append_gate(entry.producer_id).write_owned().await;
+            for (topic, partition_id) in &entry.partitions {
+                let Ok(partition) = self.partition(topic, *partition_id) else {
+                    tracing::warn!(topic, partition_id, "Partition of a transaction is gone, no marker written");
+                    continue;
+                };
+                partition
+                    .write()
+                    .await
+                    .append_control(entry.producer_id, entry.producer_epoch, marker)?;
+            }
+            if marker == ControlMarker::Commit && !entry.offsets.is_empty() {
+                // on disk before the transaction is forgotten, so a restart
+                // cannot lose them
+                let mut tracker = self.offset_tracker.lock().await;
+                for staged in &entry.offsets {
+                    tracker.commit(&staged.group, &staged.topic, staged.partition, staged.offset);
+                }
+                tracker.flush_dirty_offsets()?;
+            }
+        }
+        entry.state = TransactionState::Empty;
+        entry.partitions.clear();
+        entry.offsets.clear();
+        coordinator.put(transactional_id, entry.clone())?;
+        Ok(entry)
+    }
+
+    /// Produces to an explicit partition on behalf of an idempotent producer;
+    /// see [`Partition::append_idempotent`](crate::core::partition::Partition::append_idempotent).
+    pub async fn produce_idempotent(
+        &self,
+        topic_name: &str,
+        partition_id: u32,
+        msg: Message,
+        stamp: ProducerStamp,
+    ) -> Result<ProduceOutcome, EngineError> {
+        let topic = match self.topic(topic_name) {
+            Some(topic) => topic,
+            None => self.ensure_topic(topic_name)?,
+        };
+        let partition = topic.partitions.get(&partition_id).ok_or(EngineError::NoPartition)?;
+        if !stamp.transactional {
+            return Ok(partition.write().await.append_idempotent(&msg, stamp)?);
+        }
+
+        let coordinator = self.transactions.lock().await;
+        if let Some(rejected) = coordinator.check_produce(stamp.producer_id, stamp.producer_epoch, topic_name, partition_id) {
+            return Ok(rejected);
+        }
+        // never contended here: only marker writers hold it exclusively, and
+        // they hold the coordinator too
+        let _in_flight = self.txn_append_gate(stamp.producer_id).read_owned().await;
+        drop(coordinator);
+        let outcome = partition.write().await.append_idempotent(&msg, stamp)?;
+        Ok(outcome)
+    }
+
+    fn txn_append_gate(&self, producer_id: u64) -> Arc<tokio::sync::RwLock<()>> {
+        let mut gates = self.txn_appends.lock().expect("mutex poisoned");
+        Arc::clone(gates.entry(producer_id).or_default())
+    }
+
+    /// Appends a v2 batch to one partition of `topic_name`; see
+    /// [`Partition::append_batch`]. Returns the batch's base offset.
+    pub async fn produce_batch(&self, topic_name: &str, partition_id: u32, batch: RecordBatch) -> Result<u64, EngineError> {
+        let topic = match self.topic(topic_name) {
+            Some(topic) => topic,
+            None => self.ensure_topic(topic_name)?,
+        };
+        let partition = topic.partitions.get(&partition_id).ok_or(EngineError::NoPartition)?;
+        let offset = partition.write().await.append_batch(batch)?;
+        Ok(offset)
+    }
+
+    /// Record batches from `offset` on, see [`Partition::fetch`], with the
+    /// partition's log end offset.
+    pub async fn fetch(
+        &self,
+        topic_name: &str,
+        partition_id: u32,
+        offset: u64,
+        max_bytes: usize,
+        read_committed: bool,
+    ) -> Result<(Vec<u8>, u64), EngineError> {
+        let partition = self.partition(topic_name, partition_id)?;
+        let partition = partition.read().await;
+        let batches = partition.fetch(offset, max_bytes, read_committed)?;
+        Ok((batches, partition.state.log_end_offset()))
+    }
+
     pub fn offset_tracker_handle(&self) -> Arc<Mutex<OffsetTracker>> {
         Arc::clone(&self.offset_tracker)
     }
     pub async fn consume(
-        &mut self,
+        &self,
         topic_name: &str,
         partition_id: u32,
         offset: u64,
     ) -> Result<Option<Message>, EngineError> {
-        tracing::debug!(topic = %topic_name, partition_id, offset, "consume request");
-        let topic = self
-            .topics
-            .get_mut(topic_name)
-            .ok_or(EngineError::NoTopic)?;
-        let partition = topic
-            .partitions
-            .get_mut(&partition_id)
-            .ok_or(EngineError::NoPartition)?;
-        let mut partition_guard = partition.lock().await;
-        let mut stream = match partition_guard.stream_from_offset(offset) {
+        let record = self.consume_record(topic_name, partition_id, offset, false).await?;
+        Ok(record.map(|(_, msg)| msg))
+    }
+
+    /// The first record at or after `offset`, with its offset. With
+    /// `read_committed`, records at or past the last stable offset are not
+    /// returned yet and those of aborted transactions are skipped.
+    pub async fn consume_record(
+        &self,
+        topic_name: &str,
+        partition_id: u32,
+        offset: u64,
+        read_committed: bool,
+    ) -> Result<Option<(u64, Message)>, EngineError> {
+        tracing::debug!(topic = %topic_name, partition_id, offset, read_committed, "consume request");
+        let partition = self.partition(topic_name, partition_id)?;
+        let partition_guard = partition.read().await;
+        let stable_end = read_committed.then(|| partition_guard.last_stable_offset());
+        let stream = match partition_guard.stream_from_offset(offset) {
             Ok(s) => s,
             Err(DeserializeError::OffsetNotFound(_)) => return Ok(None), // 👈 graceful EOF
             Err(e) => return Err(e.into()), // 👈 other deserialization errors
         };
 
-        match stream.next() {
-            Some(Ok((_offset, msg))) => Ok(Some(msg)),
-            Some(Err(e)) => Err(e.into()),
-            None => Ok(None),
+        for item in stream {
+            let (offset, msg) = item?;
+            if let Some(stable_end) = stable_end {
+                if offset >= stable_end {
+                    return Ok(None);
+                }
+                if partition_guard.is_aborted(offset) {
+                    continue;
+                }
+            }
+            return Ok(Some((offset, msg)));
         }
+        Ok(None)
     }
+
+    /// Up to `max_records` records before `offset`, newest first. Offsets past
+    /// the log end return the latest records; nothing below the log start is read.
+    /// With `read_committed`, the last stable offset takes the place of the log end
+    /// and records of aborted transactions are left out.
+    pub async fn consume_backward(
+        &self,
+        topic_name: &str,
+        partition_id: u32,
+        offset: u64,
+        max_records: usize,
+        read_committed: bool,
+    ) -> Result<Vec<(u64, Message)>, EngineError> {
+        let partition = self.partition(topic_name, partition_id)?;
+        let partition = partition.read().await;
+        let (low_watermark, _, log_end_offset) = partition.get_watermark();
+        let end = match read_committed {
+            true => partition.last_stable_offset(),
+            false => log_end_offset,
+        };
+        let offset = offset.min(end);
+        if offset <= low_watermark || max_records == 0 {
+            return Ok(Vec::new());
+        }
+
+        let records = partition
+            .stream_backward_from(offset - 1)?
+            .filter(|item| !read_committed || item.as_ref().map_or(true, |(offset, _)| !partition.is_aborted(*offset)))
+            .take(max_records)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// Records of one partition whose header matches `query`, oldest first.
+    pub async fn query_headers(
+        &self,
+        topic_name: &str,
+        partition_id: u32,
+        query: &HeaderQuery,
+    ) -> Result<Vec<(u64, Message)>, EngineError> {
+        let partition = self.partition(topic_name, partition_id)?;
+        let records = partition.read().await.query_headers(query)?;
+        Ok(records)
+    }
+
+    /// Tails a partition from `offset`, waiting for new records at the log end.
+    pub fn reader(
+        &self,
+        topic_name: &str,
+        partition_id: u32,
+        offset: u64,
+    ) -> Result<PartitionReader, EngineError> {
+        let partition = self.partition(topic_name, partition_id)?;
+        Ok(PartitionReader::new(partition, offset))
+    }
+
+    /// Creates the topic, replacing any existing one of the same name.
     pub fn create_topic(
-        &mut self,
+        &self,
         name: impl Into<String>,
         partition_count: Option<u32>,
-    ) -> &Topic {
+    ) -> Arc<Topic> {
         let name = name.into();
         let cfg = broker_config();
 
-        let topic = Topic::new(
+        let topic = Arc::new(Topic::new(
             name.clone(),
             &self.storage,
             partition_count.unwrap_or(DEFAULT_PARTITION_CNT),
             cfg.segment_max_bytes
-        );
-        self.topics.insert(name.clone(), topic);
-        self.topics.get(&name).unwrap()
+        ));
+        self.topics
+            .write()
+            .expect("topic map poisoned")
+            .insert(name, Arc::clone(&topic));
+        topic
     }
 
-    fn ensure_topic(&mut self, name: &str) -> Result<&Topic, EngineError> {
-        if self.topics.contains_key(name) {
-            return Ok(self.topics.get(name).unwrap());
+    fn ensure_topic(&self, name: &str) -> Result<Arc<Topic>, EngineError> {
+        if !self.auto_create_topic {
+            return self.topic(name).ok_or(EngineError::NoTopic);
         }
-
-        if self.auto_create_topic {
-            Ok(self.create_topic(name, None))
-        } else {
-            Err(EngineError::NoTopic)
+        // check again under the write lock, another producer may have won the race
+        let mut topics = self.topics.write().expect("topic map poisoned");
+        if let Some(topic) = topics.get(name) {
+            return Ok(Arc::clone(topic));
         }
+        let topic = Arc::new(Topic::new(
+            name.to_string(),
+            &self.storage,
+            DEFAULT_PARTITION_CNT,
+            broker_config().segment_max_bytes,
+        ));
+        topics.insert(name.to_string(), Arc::clone(&topic));
+        Ok(topic)
     }
 
     pub async fn get_watermark(
@@ -135,45 +576,288 @@ impl LogEngine {
         topic: &str,
         partition_id: u32,
     ) -> Result<(u64, u64, u64), EngineError> {
-        let topic = self.topics.get(topic).ok_or(EngineError::NoTopic)?;
-        let partition = topic
-            .partitions
-            .get(&partition_id)
-            .ok_or(EngineError::NoPartition)?;
-        Ok(partition.lock().await.get_watermark())
+        let partition = self.partition(topic, partition_id)?;
+        let watermark = partition.read().await.get_watermark();
+        Ok(watermark)
+    }
+
+    /// Returns once every record appended to the partition so far is fsynced.
+    pub async fn sync(&self, topic: &str, partition_id: u32) -> Result<(), EngineError> {
+        let partition = self.partition(topic, partition_id)?;
+        let partition = partition.read().await;
+        partition.sync()?;
+        Ok(())
+    }
+
+    /// See [`Partition::last_stable_offset`](crate::core::partition::Partition::last_stable_offset).
+    pub async fn last_stable_offset(&self, topic: &str, partition_id: u32) -> Result<u64, EngineError> {
+        let partition = self.partition(topic, partition_id)?;
+        let last_stable_offset = partition.read().await.last_stable_offset();
+        Ok(last_stable_offset)
+    }
+
+    pub async fn delete_records(
+        &self,
+        topic: &str,
+        partition_id: u32,
+        offset: u64,
+    ) -> Result<u64, EngineError> {
+        let partition = self.partition(topic, partition_id)?;
+        let low_watermark = partition.write().await.delete_records(offset)?;
+        Ok(low_watermark)
+    }
+
+    /// Retention floor for a partition of a topic that lists protected consumer
+    /// groups; `None` when the topic has no such policy. A protected group that
+    /// has never committed protects the whole partition.
+    pub async fn consumer_protection(
+        &self,
+        topic: &str,
+        partition_id: u32,
+    ) -> Option<ConsumerProtection> {
+        let topic_cfg = broker_config().topic_config(topic)?;
+        if topic_cfg.protected_groups.is_empty() {
+            return None;
+        }
+        let tracker = self.offset_tracker.lock().await;
+        let floor = topic_cfg
+            .protected_groups
+            .iter()
+            .map(|group| tracker.fetch(group, topic, partition_id).unwrap_or(0))
+            .min()
+            .unwrap_or(0);
+
+        Some(ConsumerProtection {
+            floor,
+            max_bytes: topic_cfg.protected_max_bytes,
+        })
     }
 
     pub async fn consume_with_group(
-        &mut self,
+        &self,
         topic: &str,
         partition: u32,
         group: &str,
+        read_committed: bool,
     ) -> Result<Option<(u64, Message)>, EngineError> {
-        let offset = self
+        let committed = self
             .offset_tracker
             .lock()
             .await
-            .fetch(group, partition)
-            .unwrap_or(0); // default to beginning
+            .fetch(group, topic, partition);
+        // default to log start, and resume there if retention or a delete
+        // removed records the group had not consumed yet
+        let low_watermark = self.get_watermark(topic, partition).await?.0;
+        let offset = committed.map_or(low_watermark, |offset| offset.max(low_watermark));
 
-        let message = self.consume(topic, partition, offset).await?;
-        Ok(message.map(|msg| (offset, msg)))
+        self.consume_record(topic, partition, offset, read_committed).await
+    }
+
+    /// Leases up to `max_records` committed records of the partition to a
+    /// member of queue-mode `group`, records due for redelivery first. Each
+    /// stays with that member until acked, nacked or `visibility` passes.
+    pub async fn lease_records(
+        &self,
+        group: &str,
+        topic: &str,
+        partition_id: u32,
+        max_records: usize,
+        visibility: Duration,
+    ) -> Result<Vec<Delivery>, EngineError> {
+        let shared = self.partition(topic, partition_id)?;
+        let queue = self.queue_cursor(group, topic, partition_id, &shared).await;
+
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        let deadline_ms = now + visibility.as_millis() as u64;
+        queue.lock().await.expire(now);
+        self.dead_letter_exhausted(&queue, group, topic, partition_id, &shared).await?;
+
+        let mut cursor = queue.lock().await;
+        let cursor = &mut *cursor;
+        let partition = shared.read().await;
+        cursor.skip_to(partition.get_watermark().0);
+
+        let stable_end = partition.last_stable_offset();
+        let mut leased = Vec::new();
+        for offset in cursor.redeliveries().into_iter().take(max_records) {
+            match read_at(&partition, offset)? {
+                Some(message) => {
+                    let delivery_count = cursor.lease(offset, deadline_ms);
+                    leased.push(Delivery { offset, delivery_count, message });
+                }
+                None => cursor.forget(offset),
+            }
+        }
+        if leased.len() < max_records && cursor.next_offset() < stable_end {
+            let stream = match partition.stream_from_offset(cursor.next_offset()) {
+                Ok(s) => Some(s),
+                Err(DeserializeError::OffsetNotFound(_)) => None,
+                Err(e) => return Err(e.into()),
+            };
+            let mut full = false;
+            for item in stream.into_iter().flatten() {
+                let (offset, message) = item?;
+                if offset >= stable_end {
+                    break;
+                }
+                if leased.len() == max_records {
+                    full = true;
+                    break;
+                }
+                if partition.is_aborted(offset) {
+                    continue;
+                }
+                let delivery_count = cursor.lease(offset, deadline_ms);
+                leased.push(Delivery { offset, delivery_count, message });
+            }
+            // everything below the stable offset was read: aborted records
+            // and markers after the last lease are not scanned again
+            if !full {
+                cursor.pass_to(stable_end);
+            }
+        }
+
+        drop(partition);
+        self.commit_queue(cursor, group, topic, partition_id).await;
+        Ok(leased)
+    }
+
+    /// Acks (`ack`) or nacks leased records, each named by its offset and the
+    /// delivery count it was leased with; stale leases are skipped. `error`
+    /// is why nacked records failed. Returns how many were settled and the
+    /// group's committed offset, which moves up to the lowest record not
+    /// acked or dead-lettered yet.
+    pub async fn settle_records(
+        &self,
+        group: &str,
+        topic: &str,
+        partition_id: u32,
+        records: &[(u64, u32)],
+        ack: bool,
+        error: &str,
+    ) -> Result<(u32, u64), EngineError> {
+        let shared = self.partition(topic, partition_id)?;
+        let key = (group.to_string(), topic.to_string(), partition_id);
+        let cursor = self.queues.lock().expect("queue map poisoned").get(&key).cloned();
+        let Some(cursor) = cursor else {
+            // nothing leased since the broker started
+            let committed = self.offset_tracker.lock().await.fetch(group, topic, partition_id);
+            return Ok((0, committed.unwrap_or(0)));
+        };
+        let settled = {
+            let mut cursor = cursor.lock().await;
+            records
+                .iter()
+                .filter(|&&(offset, delivery_count)| cursor.settle(offset, delivery_count, ack, error))
+                .count() as u32
+        };
+        if !ack {
+            self.dead_letter_exhausted(&cursor, group, topic, partition_id, &shared).await?;
+        }
+        let committed_offset = self.commit_queue(&*cursor.lock().await, group, topic, partition_id).await;
+        Ok((settled, committed_offset))
+    }
+
+    /// Saves the committed offset and delivery attempts of queue-mode `group`
+    /// on a partition, returning the offset.
+    async fn commit_queue(&self, cursor: &QueueCursor, group: &str, topic: &str, partition_id: u32) -> u64 {
+        let committed_offset = cursor.committed_offset();
+        let mut tracker = self.offset_tracker.lock().await;
+        tracker.commit(group, topic, partition_id, committed_offset);
+        tracker.commit_attempts(group, topic, partition_id, cursor.attempts());
+        committed_offset
+    }
+
+    /// The cursor of queue-mode `group` on a partition, starting at the
+    /// group's committed offset there the first time it is asked for.
+    async fn queue_cursor(
+        &self,
+        group: &str,
+        topic: &str,
+        partition_id: u32,
+        partition: &SharedPartition,
+    ) -> Arc<Mutex<QueueCursor>> {
+        let key = (group.to_string(), topic.to_string(), partition_id);
+        if let Some(cursor) = self.queues.lock().expect("queue map poisoned").get(&key) {
+            return Arc::clone(cursor);
+        }
+        let (committed, attempts) = {
+            let tracker = self.offset_tracker.lock().await;
+            (tracker.fetch(group, topic, partition_id), tracker.fetch_attempts(group, topic, partition_id))
+        };
+        let start = match committed {
+            Some(offset) => offset,
+            None => partition.read().await.get_watermark().0,
+        };
+        // another member may have got there while we looked the offset up
+        let mut queues = self.queues.lock().expect("queue map poisoned");
+        let cursor = queues.entry(key).or_insert_with(|| Arc::new(Mutex::new(QueueCursor::resume(start, attempts))));
+        Arc::clone(cursor)
+    }
+
+    /// Moves records of the cursor that used up the group's delivery attempts
+    /// to its dead-letter topic, if the group has a dead-letter policy for
+    /// `topic`. Records that are gone from the log are dropped. The cursor is
+    /// only locked to take the records and settle each one, so other members
+    /// keep leasing while the dead-letter topic is written.
+    async fn dead_letter_exhausted(
+        &self,
+        cursor: &Mutex<QueueCursor>,
+        group: &str,
+        topic: &str,
+        partition_id: u32,
+        partition: &SharedPartition,
+    ) -> Result<(), EngineError> {
+        let Some(config) = broker_config().dead_letter_for(topic, group) else {
+            return Ok(());
+        };
+        let exhausted = cursor.lock().await.take_exhausted(config.max_attempts);
+        if exhausted.is_empty() {
+            return Ok(());
+        }
+        let mut done = 0;
+        let result: Result<(), EngineError> = async {
+            let dead_letter_topic = self.ensure_topic(&config.topic_for(topic))?;
+            for failed in &exhausted {
+                // the source lock is released before the dead-letter append
+                let record = read_at(&*partition.read().await, failed.offset)?;
+                if let Some(mut message) = record {
+                    message.set_dead_letter(&DeadLetter {
+                        topic: topic.to_string(),
+                        partition: partition_id,
+                        offset: failed.offset,
+                        attempts: failed.attempts,
+                        error: failed.error.clone(),
+                    });
+                    dead_letter_topic.produce(message).await?;
+                }
+                cursor.lock().await.forget(failed.offset);
+                done += 1;
+            }
+            Ok(())
+        }
+        .await;
+        if result.is_err() {
+            let mut cursor = cursor.lock().await;
+            exhausted[done..].iter().for_each(|failed| cursor.retry(failed.offset));
+        }
+        result
     }
 
     pub async fn commit_offset(
-        &mut self,
+        &self,
         topic: &str,
         partition: u32,
         group: &str,
         offset: u64,
     ) -> Result<(), EngineError> {
-        if !self.topics.contains_key(topic) {
+        if self.topic(topic).is_none() {
             return Err(EngineError::NoTopic);
         }
         self.offset_tracker
             .lock()
             .await
-            .commit(group, partition, offset);
+            .commit(group, topic, partition, offset);
 
         Ok(())
     }
@@ -193,17 +877,17 @@ impl LogEngine {
             topics
         } else {
             // If no specific topics provided, check all topics that have committed offsets
-            self.topics.keys().cloned().collect()
+            self.topics.read().expect("topic map poisoned").keys().cloned().collect()
         };
         
         for topic_name in topics_to_check {
-            if let Some(topic) = self.topics.get(&topic_name) {
+            if let Some(topic) = self.topic(&topic_name) {
                 for (&partition_id, partition) in &topic.partitions {
                     // Get committed offset for this consumer group
-                    let committed_offset = tracker.fetch(consumer_group, partition_id).unwrap_or(0);
+                    let committed_offset = tracker.fetch(consumer_group, &topic_name, partition_id).unwrap_or(0);
                     
                     // Get high watermark for the partition
-                    let (_, high_watermark, _) = partition.lock().await.get_watermark();
+                    let (_, high_watermark, _) = partition.read().await.get_watermark();
                     
                     // Calculate lag
                     let lag = high_watermark.saturating_sub(committed_offset);
@@ -227,31 +911,24 @@ impl LogEngine {
         &self,
         topic: &str,
         partition_id: u32,
-    ) -> Result<(u32, u64, u64, u64, u64, Option<u64>), EngineError> {
-        let topic = self.topics.get(topic).ok_or(EngineError::NoTopic)?;
-        let partition = topic
-            .partitions
-            .get(&partition_id)
-            .ok_or(EngineError::NoPartition)?;
-        
-        let partition = partition.lock().await;
-        let (low_watermark, high_watermark, log_end_offset) = partition.get_watermark();
-        
-        // Get segment count and total size
-        let segment_count = partition.segment_count();
-        let total_size_bytes = partition.total_size_bytes();
-        
-        // For now, we don't track last cleanup timestamp
-        // This could be added to PartitionState in the future
-        let last_cleanup: Option<u64> = None;
-        
-        Ok((
-            segment_count,
-            total_size_bytes,
-            low_watermark,
-            high_watermark,
-            log_end_offset,
-            last_cleanup,
-        ))
+    ) -> Result<PartitionHealth, EngineError> {
+        let partition = self.partition(topic, partition_id)?;
+        let partition = partition.read().await;
+        Ok(partition.health())
+    }
+
+    /// Segments the next cleanup pass would delete if `policy` were in effect,
+    /// honouring the same consumer protection as the real cleanup.
+    pub async fn retention_dry_run(
+        &self,
+        topic_name: &str,
+        partition_id: u32,
+        policy: &RetentionPolicy,
+    ) -> Result<Vec<CleanupCandidate>, EngineError> {
+        let partition = self.partition(topic_name, partition_id)?;
+
+        let protection = self.consumer_protection(topic_name, partition_id).await;
+        let partition = partition.read().await;
+        Ok(partition.plan_cleanup(policy, protection.as_ref()))
     }
 }
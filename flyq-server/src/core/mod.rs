@@ -1,11 +1,25 @@
 mod constants;
-mod error;
+pub mod error;
 pub mod log_engine;
 pub mod offset_tracker;
 pub mod partition;
-mod segment;
+pub mod index_strategy;
+pub mod header_index;
+pub mod delay_queue;
+pub mod transaction;
+pub mod partition_reader;
+pub mod producer_state;
+pub mod queue;
+pub mod compaction;
+pub mod inspect;
+pub mod repair;
+pub mod scrub;
+pub mod segment;
+mod sealed_segment;
 mod storage;
-mod stored_record;
+pub mod stored_record;
 mod topic;
 mod partition_state;
-mod partiton_meta;
+pub mod partiton_meta;
+pub mod retention;
+pub mod remote_storage;
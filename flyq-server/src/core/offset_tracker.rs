@@ -1,10 +1,26 @@
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::core::queue::Attempts;
+
+type PartitionOffsets = HashMap<u32, u64>; // partition -> offset
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct OffsetFile {
+    offsets: HashMap<String, HashMap<String, PartitionOffsets>>, // group -> topic -> partition -> offset
+    /// Offsets committed before they were kept per topic, read for a
+    /// partition the group has not committed on since.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    unscoped: HashMap<String, PartitionOffsets>,
+    /// Delivery attempts of queue-mode groups above their committed offsets.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    attempts: HashMap<String, HashMap<String, HashMap<u32, Attempts>>>, // group -> topic -> partition -> attempts
+}
 
 #[derive(Debug, Clone)]
 pub struct OffsetTracker {
-    store: HashMap<String, HashMap<u32, u64>>, // group -> partition -> offset
+    store: OffsetFile,
     dirty: HashSet<String>,
     path: PathBuf,
 }
@@ -12,29 +28,57 @@ pub struct OffsetTracker {
 impl OffsetTracker {
     pub fn new<P: Into<PathBuf>>(path: P) -> Self {
         Self {
-            store: HashMap::new(),
+            store: OffsetFile::default(),
             dirty: HashSet::new(),
             path: path.into(),
         }
     }
 
-    pub fn commit(&mut self, group: &str, partition: u32, offset: u64) {
+    pub fn commit(&mut self, group: &str, topic: &str, partition: u32, offset: u64) {
         self.store
+            .offsets
             .entry(group.to_string())
             .or_default()
+            .entry(topic.to_string())
+            .or_default()
             .insert(partition, offset);
         self.dirty.insert(group.to_string());
     }
 
-    pub fn fetch(&self, group: &str, partition: u32) -> Option<u64> {
-        self.store
-            .get(group)
+    pub fn fetch(&self, group: &str, topic: &str, partition: u32) -> Option<u64> {
+        let scoped = self.store.offsets.get(group).and_then(|topics| topics.get(topic));
+        scoped
             .and_then(|m| m.get(&partition))
+            .or_else(|| self.store.unscoped.get(group).and_then(|m| m.get(&partition)))
             .copied()
     }
 
+    /// Saves the delivery attempts of queue-mode `group` on a partition along
+    /// with its committed offset.
+    pub fn commit_attempts(&mut self, group: &str, topic: &str, partition: u32, attempts: Attempts) {
+        let topics = self.store.attempts.entry(group.to_string()).or_default();
+        let partitions = topics.entry(topic.to_string()).or_default();
+        if partitions.get(&partition).map_or(attempts.is_empty(), |saved| *saved == attempts) {
+            return;
+        }
+        match attempts.is_empty() {
+            true => partitions.remove(&partition),
+            false => partitions.insert(partition, attempts),
+        };
+        self.dirty.insert(group.to_string());
+    }
+
+    pub fn fetch_attempts(&self, group: &str, topic: &str, partition: u32) -> Attempts {
+        self.store
+            .attempts
+            .get(group)
+            .and_then(|topics| topics.get(topic))
+            .and_then(|partitions| partitions.get(&partition))
+            .cloned()
+            .unwrap_or_default()
+    }
+
     pub fn save_to_file(&self) -> Result<(), std::io::Error> {
-        // Serialize the internal store (HashMap<String, HashMap<u32, u64>>)
         let json = serde_json::to_string_pretty(&self.store)?;
 
         // Atomically write to disk (optional: temp file + rename)
@@ -46,8 +90,15 @@ impl OffsetTracker {
     pub fn load_from_file(&mut self) -> Result<(), std::io::Error> {
         if self.path.exists() {
             let json = fs::read_to_string(&self.path)?;
-            let data: HashMap<String, HashMap<u32, u64>> = serde_json::from_str(&json)?;
-            self.store = data;
+            self.store = match serde_json::from_str(&json) {
+                Ok(store) => store,
+                // group -> partition -> offset, from before topics were kept
+                Err(_) => OffsetFile {
+                    offsets: HashMap::new(),
+                    unscoped: serde_json::from_str(&json)?,
+                    attempts: HashMap::new(),
+                },
+            };
         }
 
         Ok(())
@@ -66,3 +117,31 @@ impl OffsetTracker {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_are_kept_per_topic_and_old_files_still_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("offsets.json");
+        fs::write(&path, r#"{"billing": {"0": 7}}"#).unwrap();
+
+        let mut tracker = OffsetTracker::new(&path);
+        tracker.load_from_file().unwrap();
+        assert_eq!(tracker.fetch("billing", "invoices", 0), Some(7));
+
+        tracker.commit("billing", "invoices", 0, 9);
+        tracker.commit("billing", "refunds", 0, 2);
+        tracker.flush_dirty_offsets().unwrap();
+
+        let mut reloaded = OffsetTracker::new(&path);
+        reloaded.load_from_file().unwrap();
+        assert_eq!(reloaded.fetch("billing", "invoices", 0), Some(9));
+        assert_eq!(reloaded.fetch("billing", "refunds", 0), Some(2));
+        assert_eq!(reloaded.fetch("billing", "refunds", 1), None);
+        // until the group commits on a topic, it resumes from the old offset
+        assert_eq!(reloaded.fetch("billing", "audit", 0), Some(7));
+    }
+}
@@ -0,0 +1,121 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt};
+use tokio::sync::watch;
+use flyq_protocol::errors::DeserializeError;
+use flyq_protocol::message::Message;
+use crate::core::sealed_segment::SealedSegment;
+use crate::core::segment::SegmentIterator;
+use crate::core::topic::SharedPartition;
+
+/// Position inside one segment, opened by `Partition::open_cursor`.
+pub(crate) struct SegmentCursor {
+    pub(crate) iter: SegmentIterator,
+    pub(crate) end: u64, // exclusive, records at or past it are not read from this cursor
+    // no live record expires before this (Unix ms); 0 = not known. Tombstones
+    // compaction left are not counted, their batches are marked instead
+    pub(crate) next_expiry: u64,
+    // keeps a sealed segment's files on disk while we read them, even if
+    // retention or DeleteRecords drops it from the partition meanwhile
+    pub(crate) _segment: Option<Arc<SealedSegment>>,
+}
+
+/// Owned, async counterpart of [`PartitionIterator`](crate::core::partition::PartitionIterator).
+///
+/// Holds the partition handle instead of a borrow, so it can live across
+/// awaits and outlast any lock. The partition is only read-locked for the
+/// moment it takes to open the next segment; records are read without it.
+/// Segment rolls are followed transparently, and once the reader catches up
+/// with the log end it waits for the next append instead of ending.
+///
+/// If retention or DeleteRecords moves the log start past the reader, the
+/// segment it is in is still read to its end, after which the stream yields
+/// [`DeserializeError::OffsetOutOfRange`] and ends. It also ends after any
+/// other error, or when the partition itself is dropped.
+pub struct PartitionReader {
+    inner: BoxStream<'static, Result<(u64, Message), DeserializeError>>,
+}
+
+impl PartitionReader {
+    /// Reads `partition` starting at `offset`.
+    pub fn new(partition: SharedPartition, offset: u64) -> Self {
+        let state = ReaderState {
+            partition,
+            appended: None,
+            cursor: None,
+            next_offset: offset,
+            done: false,
+        };
+        let inner = stream::unfold(state, |mut state| async move {
+            let item = state.next_record().await?;
+            Some((item, state))
+        });
+        PartitionReader { inner: inner.boxed() }
+    }
+}
+
+impl Stream for PartitionReader {
+    type Item = Result<(u64, Message), DeserializeError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+struct ReaderState {
+    partition: SharedPartition,
+    appended: Option<watch::Receiver<u64>>, // subscribed on first open
+    cursor: Option<SegmentCursor>,
+    next_offset: u64,
+    done: bool,
+}
+
+impl ReaderState {
+    async fn next_record(&mut self) -> Option<Result<(u64, Message), DeserializeError>> {
+        if self.done {
+            return None;
+        }
+        loop {
+            if let Some(cursor) = &mut self.cursor {
+                if self.next_offset < cursor.end {
+                    match cursor.iter.next() {
+                        Some(Ok((offset, msg))) if offset < cursor.end => {
+                            self.next_offset = offset + 1;
+                            return Some(Ok((offset, msg)));
+                        }
+                        Some(Err(e)) => {
+                            self.done = true;
+                            return Some(Err(e));
+                        }
+                        _ => {}
+                    }
+                }
+                // done with this segment, as far as it was written when opened
+                self.next_offset = self.next_offset.max(cursor.end);
+                self.cursor = None;
+            }
+
+            let partition = Arc::clone(&self.partition);
+            let partition = partition.read().await;
+            let appended = self.appended.get_or_insert_with(|| partition.subscribe());
+            // appends after this point wake the `changed()` below
+            appended.borrow_and_update();
+
+            match partition.open_cursor(self.next_offset) {
+                Ok(Some(cursor)) => self.cursor = Some(cursor),
+                Ok(None) => {
+                    drop(partition);
+                    if appended.changed().await.is_err() {
+                        return None; // partition dropped
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
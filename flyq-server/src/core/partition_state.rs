@@ -5,6 +5,7 @@ pub struct PartitionState {
     log_end_offset: AtomicU64,
     low_watermark: AtomicU64,
     high_watermark: AtomicU64,
+    protection_overrides: AtomicU64,
 }
 
 impl PartitionState {
@@ -13,6 +14,7 @@ impl PartitionState {
             log_end_offset: AtomicU64::new(start_offset),
             low_watermark: AtomicU64::new(start_offset),
             high_watermark: AtomicU64::new(start_offset),
+            protection_overrides: AtomicU64::new(0),
         }
     }
 
@@ -21,6 +23,7 @@ impl PartitionState {
             log_end_offset: AtomicU64::new(meta.log_end_offset),
             low_watermark: AtomicU64::new(meta.low_watermark),
             high_watermark: AtomicU64::new(meta.high_watermark),
+            protection_overrides: AtomicU64::new(0),
         }
     }
     
@@ -28,6 +31,11 @@ impl PartitionState {
         self.log_end_offset.fetch_add(1, Ordering::SeqCst)
     }
 
+    // takes `count` offsets at once, for a record batch
+    pub fn fetch_and_advance_log_end(&self, count: u64) -> u64 {
+        self.log_end_offset.fetch_add(count, Ordering::SeqCst)
+    }
+
     pub fn log_end_offset(&self) -> u64 {
         self.log_end_offset.load(Ordering::SeqCst)
     }
@@ -40,12 +48,15 @@ impl PartitionState {
         self.low_watermark.load(Ordering::SeqCst)
     }
     
-    // this is always set to 0 for now
-    // will be used when we intro log truncation
     pub fn set_low_watermark(&self, val: u64) {
         self.low_watermark.store(val, Ordering::SeqCst)
     }
 
+    // retention and DeleteRecords only ever move the log start forward
+    pub fn advance_low_watermark(&self, val: u64) -> u64 {
+        self.low_watermark.fetch_max(val, Ordering::SeqCst).max(val)
+    }
+
     pub fn high_watermark(&self) -> u64 {
         self.high_watermark.load(Ordering::SeqCst)
     }
@@ -55,4 +66,13 @@ impl PartitionState {
     pub fn set_high_watermark(&self, val: u64) {
         self.high_watermark.store(val, Ordering::SeqCst)
     }
+
+    // segments deleted despite consumer protection because the hard cap was hit
+    pub fn protection_overrides(&self) -> u64 {
+        self.protection_overrides.load(Ordering::Relaxed)
+    }
+
+    pub fn record_protection_override(&self) {
+        self.protection_overrides.fetch_add(1, Ordering::Relaxed);
+    }
 }
\ No newline at end of file
@@ -5,7 +5,7 @@ use std::io::Write;
 use std::path::Path;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PartitionMeta{
     pub low_watermark: u64,
     pub high_watermark: u64,
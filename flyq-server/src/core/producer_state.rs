@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::core::constants::PRODUCER_SEQUENCE_WINDOW;
+use crate::core::inspect::{list_segments, RecordScanner};
+use crate::core::stored_record::{ControlMarker, ProducerStamp, StoredRecord};
+
+pub const PRODUCER_STATE_FILE: &str = "producer_state.json";
+const PRODUCER_IDS_FILE: &str = "producer_ids.json";
+
+/// What an idempotent append did, or why it did nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProduceOutcome {
+    Appended(u64),
+    /// Appended before, at this offset.
+    Duplicate(u64),
+    /// Records between the last appended sequence and this one are missing.
+    OutOfOrder { expected: u32 },
+    /// The producer id has moved on to a newer epoch.
+    Fenced { current_epoch: u16 },
+    /// A transactional record for a partition outside the producer's open
+    /// transaction.
+    NotInTransaction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ProducerEntry {
+    epoch: u16,
+    recent: VecDeque<(u32, u64)>, // (sequence, offset) of the last appends, oldest first
+}
+
+/// Sequence numbers of every idempotent producer that wrote to one partition,
+/// and where its transactions stand there. Snapshotted to
+/// `producer_state.json` with the partition metadata; on open, records
+/// appended after the snapshot are replayed from the segments.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProducerState {
+    producers: BTreeMap<u64, ProducerEntry>,
+    /// producer id -> offsets it wrote in its open transaction
+    #[serde(default)]
+    ongoing: BTreeMap<u64, Vec<u64>>,
+    /// records of aborted transactions still in the log
+    #[serde(default)]
+    aborted: BTreeSet<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProducerSnapshot {
+    /// Log end offset when the snapshot was taken.
+    offset: u64,
+    state: ProducerState,
+}
+
+impl ProducerState {
+    pub fn is_empty(&self) -> bool {
+        self.producers.is_empty()
+    }
+
+    /// First offset of the oldest transaction still open in the partition.
+    pub fn first_unstable_offset(&self) -> Option<u64> {
+        self.ongoing.values().filter_map(|offsets| offsets.first()).min().copied()
+    }
+
+    pub fn has_ongoing(&self, producer_id: u64) -> bool {
+        self.ongoing.contains_key(&producer_id)
+    }
+
+    pub fn is_aborted(&self, offset: u64) -> bool {
+        self.aborted.contains(&offset)
+    }
+
+    /// What appending `stamp` would amount to, or `None` if it is the next
+    /// sequence and should be appended. A new producer, or a known one with a
+    /// higher epoch, starts at sequence 0. Retries older than the last
+    /// [`PRODUCER_SEQUENCE_WINDOW`] appends can no longer be told apart from
+    /// a confused producer and count as out of order.
+    pub fn check(&self, stamp: &ProducerStamp) -> Option<ProduceOutcome> {
+        let Some(entry) = self.producers.get(&stamp.producer_id) else {
+            return (stamp.sequence != 0).then_some(ProduceOutcome::OutOfOrder { expected: 0 });
+        };
+        if stamp.producer_epoch < entry.epoch {
+            return Some(ProduceOutcome::Fenced { current_epoch: entry.epoch });
+        }
+        if stamp.producer_epoch > entry.epoch {
+            return (stamp.sequence != 0).then_some(ProduceOutcome::OutOfOrder { expected: 0 });
+        }
+
+        let expected = entry.recent.back().map_or(0, |(sequence, _)| sequence.wrapping_add(1));
+        if stamp.sequence == expected {
+            return None;
+        }
+        match entry.recent.iter().find(|(sequence, _)| *sequence == stamp.sequence) {
+            Some((_, offset)) => Some(ProduceOutcome::Duplicate(*offset)),
+            None => Some(ProduceOutcome::OutOfOrder { expected }),
+        }
+    }
+
+    /// Remembers that `stamp` was appended at `offset`.
+    pub fn record(&mut self, stamp: &ProducerStamp, offset: u64) {
+        let entry = self.producers.entry(stamp.producer_id).or_insert_with(|| ProducerEntry {
+            epoch: stamp.producer_epoch,
+            recent: VecDeque::new(),
+        });
+        if stamp.producer_epoch > entry.epoch {
+            entry.epoch = stamp.producer_epoch;
+            entry.recent.clear();
+        }
+        if entry.recent.len() == PRODUCER_SEQUENCE_WINDOW {
+            entry.recent.pop_front();
+        }
+        entry.recent.push_back((stamp.sequence, offset));
+        if stamp.transactional {
+            self.ongoing.entry(stamp.producer_id).or_default().push(offset);
+        }
+    }
+
+    /// Ends the producer's open transaction with `marker`. Returns false, and
+    /// changes nothing, if it had none here.
+    pub fn complete(&mut self, producer_id: u64, marker: ControlMarker) -> bool {
+        let Some(offsets) = self.ongoing.remove(&producer_id) else {
+            return false;
+        };
+        if marker == ControlMarker::Abort {
+            self.aborted.extend(offsets);
+        }
+        true
+    }
+
+    /// Moves the producer to `producer_epoch` if it is behind, so appends of
+    /// older epochs are [`ProduceOutcome::Fenced`] from then on.
+    pub fn fence(&mut self, producer_id: u64, producer_epoch: u16) {
+        let entry = self.producers.entry(producer_id).or_insert_with(|| ProducerEntry {
+            epoch: producer_epoch,
+            recent: VecDeque::new(),
+        });
+        if producer_epoch > entry.epoch {
+            entry.epoch = producer_epoch;
+            entry.recent.clear();
+        }
+    }
+
+    /// Drops what is known about aborted records below the log start.
+    pub fn forget_before(&mut self, low_watermark: u64) {
+        self.aborted = self.aborted.split_off(&low_watermark);
+    }
+
+    fn replay(&mut self, record: &StoredRecord) {
+        let Some(stamp) = record.producer else {
+            return;
+        };
+        match record.control_marker() {
+            Some(marker) => {
+                self.fence(stamp.producer_id, stamp.producer_epoch);
+                self.complete(stamp.producer_id, marker);
+            }
+            None => self.record(&stamp, record.offset),
+        }
+    }
+
+    /// Writes the state as of `log_end_offset` into the partition directory.
+    pub fn save(&self, dir: &Path, log_end_offset: u64) -> std::io::Result<()> {
+        let snapshot = ProducerSnapshot { offset: log_end_offset, state: self.clone() };
+        write_json(&dir.join(PRODUCER_STATE_FILE), &snapshot)
+    }
+
+    /// Loads the snapshot in `dir` and replays the producer stamps of records
+    /// appended after it. A snapshot ahead of `log_end_offset` may know
+    /// records lost in a crash and is ignored: the state is then rebuilt from
+    /// every local segment. Offloaded segments are never replayed; the
+    /// partition saves a snapshot past them before dropping the local copy.
+    pub fn recover(dir: &Path, log_end_offset: u64) -> std::io::Result<ProducerState> {
+        let path = dir.join(PRODUCER_STATE_FILE);
+        let snapshot = match path.exists() {
+            true => Some(serde_json::from_reader::<_, ProducerSnapshot>(File::open(&path)?)?),
+            false => None,
+        };
+        let (mut state, from) = match snapshot {
+            Some(snapshot) if snapshot.offset <= log_end_offset => (snapshot.state, snapshot.offset),
+            _ => (ProducerState::default(), 0),
+        };
+
+        let segments = list_segments(dir)?;
+        for (i, (_, path)) in segments.iter().enumerate() {
+            if segments.get(i + 1).is_some_and(|(next_base, _)| *next_base <= from) {
+                continue; // everything in it predates the snapshot
+            }
+            // stops at the first unreadable record, like recovery does
+            for raw in RecordScanner::open(path)?.map_while(Result::ok) {
+                if raw.record.offset >= from {
+                    state.replay(&raw.record);
+                }
+            }
+        }
+        Ok(state)
+    }
+}
+
+/// Hands out broker-wide unique producer ids, persisting the next one in
+/// `producer_ids.json` before an id is returned so a restart never reuses it.
+#[derive(Debug)]
+pub struct ProducerIdAllocator {
+    path: PathBuf,
+    next: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProducerIds {
+    next: u64,
+}
+
+impl ProducerIdAllocator {
+    pub fn load(base_dir: &Path) -> std::io::Result<Self> {
+        let path = base_dir.join(PRODUCER_IDS_FILE);
+        let next = match path.exists() {
+            true => serde_json::from_reader::<_, ProducerIds>(File::open(&path)?)?.next,
+            false => 0,
+        };
+        Ok(ProducerIdAllocator { path, next })
+    }
+
+    pub fn allocate(&mut self) -> std::io::Result<u64> {
+        let id = self.next;
+        write_json(&self.path, &ProducerIds { next: id + 1 })?;
+        self.next = id + 1;
+        Ok(id)
+    }
+}
+
+pub(crate) fn write_json<T: Serialize>(path: &Path, value: &T) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    {
+        let mut tmp_file = File::create(&tmp_path)?;
+        serde_json::to_writer_pretty(&mut tmp_file, value)?;
+        tmp_file.flush()?;
+    }
+    fs::rename(&tmp_path, path) // atomic replace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamp(producer_id: u64, producer_epoch: u16, sequence: u32) -> ProducerStamp {
+        ProducerStamp { producer_id, producer_epoch, sequence, transactional: false, control: false }
+    }
+
+    #[test]
+    fn test_sequence_checks() {
+        let mut state = ProducerState::default();
+        assert_eq!(state.check(&stamp(1, 0, 1)), Some(ProduceOutcome::OutOfOrder { expected: 0 }));
+        for sequence in 0..8 {
+            assert_eq!(state.check(&stamp(1, 0, sequence)), None);
+            state.record(&stamp(1, 0, sequence), 100 + sequence as u64);
+        }
+
+        assert_eq!(state.check(&stamp(1, 0, 8)), None);
+        assert_eq!(state.check(&stamp(1, 0, 6)), Some(ProduceOutcome::Duplicate(106)));
+        // fell out of the window
+        assert_eq!(state.check(&stamp(1, 0, 2)), Some(ProduceOutcome::OutOfOrder { expected: 8 }));
+        assert_eq!(state.check(&stamp(1, 0, 10)), Some(ProduceOutcome::OutOfOrder { expected: 8 }));
+
+        // a new epoch starts over and fences the old one
+        assert_eq!(state.check(&stamp(1, 1, 3)), Some(ProduceOutcome::OutOfOrder { expected: 0 }));
+        state.record(&stamp(1, 1, 0), 200);
+        assert_eq!(state.check(&stamp(1, 0, 8)), Some(ProduceOutcome::Fenced { current_epoch: 1 }));
+        assert_eq!(state.check(&stamp(1, 1, 1)), None);
+    }
+
+    #[test]
+    fn test_transactions_track_stable_offset_and_aborted_records() {
+        let mut state = ProducerState::default();
+        let txn = |producer_id, sequence| ProducerStamp { transactional: true, ..stamp(producer_id, 0, sequence) };
+        state.record(&txn(1, 0), 10);
+        state.record(&stamp(3, 0, 0), 11);
+        state.record(&txn(2, 0), 12);
+        state.record(&txn(1, 1), 13);
+        assert_eq!(state.first_unstable_offset(), Some(10));
+
+        assert!(state.complete(1, ControlMarker::Abort));
+        assert!(!state.complete(1, ControlMarker::Abort));
+        assert_eq!(state.first_unstable_offset(), Some(12));
+        assert!(state.is_aborted(10) && state.is_aborted(13) && !state.is_aborted(11));
+
+        assert!(state.complete(2, ControlMarker::Commit));
+        assert_eq!(state.first_unstable_offset(), None);
+        assert!(!state.is_aborted(12));
+
+        state.forget_before(11);
+        assert!(!state.is_aborted(10) && state.is_aborted(13));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use flyq_protocol::message::Message;
+
+/// A record handed to a member of a queue-mode group.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub offset: u64,
+    /// 1 the first time the record is leased, +1 for every redelivery.
+    pub delivery_count: u32,
+    pub message: Message,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lease {
+    delivery_count: u32,
+    deadline_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Failure {
+    delivery_count: u32, // deliveries so far
+    error: String,
+}
+
+/// A record that failed its last allowed delivery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exhausted {
+    pub offset: u64,
+    pub attempts: u32,
+    pub error: String,
+}
+
+const EXPIRED: &str = "visibility timeout expired";
+const NACKED: &str = "nacked";
+
+/// Deliveries so far of records not acked yet, by offset.
+pub type Attempts = BTreeMap<u64, u32>;
+
+/// Lease bookkeeping of one queue-mode group on one partition. Every offset
+/// below `next_offset` is either in flight, waiting for redelivery, being
+/// dead-lettered or acked, so the group's committed offset is the lowest of
+/// the first three and `next_offset`. Only the committed offset and
+/// [`Self::attempts`] outlive a restart: the group starts again from its
+/// committed offset, records acked above it are delivered again, and delivery
+/// counts carry on from where they were.
+#[derive(Debug)]
+pub struct QueueCursor {
+    next_offset: u64, // first offset never leased
+    in_flight: BTreeMap<u64, Lease>,
+    redeliver: BTreeMap<u64, Failure>,
+    dead_lettering: BTreeMap<u64, Failure>,
+    resumed: Attempts, // deliveries before the restart, of offsets not leased since
+}
+
+impl QueueCursor {
+    pub fn new(committed_offset: u64) -> Self {
+        Self::resume(committed_offset, Attempts::new())
+    }
+
+    /// A cursor picking up after a restart, with the attempts saved from the
+    /// previous one.
+    pub fn resume(committed_offset: u64, mut attempts: Attempts) -> Self {
+        QueueCursor {
+            next_offset: committed_offset,
+            in_flight: BTreeMap::new(),
+            redeliver: BTreeMap::new(),
+            dead_lettering: BTreeMap::new(),
+            resumed: attempts.split_off(&committed_offset),
+        }
+    }
+
+    /// Lowest offset not acked yet.
+    pub fn committed_offset(&self) -> u64 {
+        let in_flight = self.in_flight.keys().next().copied();
+        let redeliver = self.redeliver.keys().next().copied();
+        let dead_lettering = self.dead_lettering.keys().next().copied();
+        [in_flight, redeliver, dead_lettering].into_iter().flatten().fold(self.next_offset, u64::min)
+    }
+
+    /// Deliveries so far of every record leased and not acked yet, to be
+    /// saved with the committed offset. A record whose lease was out when
+    /// the broker stopped counts that lease as a failed attempt.
+    pub fn attempts(&self) -> Attempts {
+        let mut attempts = self.resumed.clone();
+        attempts.extend(self.in_flight.iter().map(|(&offset, lease)| (offset, lease.delivery_count)));
+        attempts.extend(self.redeliver.iter().map(|(&offset, failure)| (offset, failure.delivery_count)));
+        attempts.extend(self.dead_lettering.iter().map(|(&offset, failure)| (offset, failure.delivery_count)));
+        attempts
+    }
+
+    pub fn next_offset(&self) -> u64 {
+        self.next_offset
+    }
+
+    /// Returns leases whose visibility timeout passed at `now` to the group.
+    pub fn expire(&mut self, now: u64) {
+        let expired: Vec<u64> = self
+            .in_flight
+            .iter()
+            .filter(|(_, lease)| lease.deadline_ms <= now)
+            .map(|(&offset, _)| offset)
+            .collect();
+        for offset in expired {
+            let lease = self.in_flight.remove(&offset).expect("expired lease");
+            self.redeliver.insert(offset, Failure { delivery_count: lease.delivery_count, error: EXPIRED.into() });
+        }
+    }
+
+    /// Records waiting for redelivery, lowest offset first.
+    pub fn redeliveries(&self) -> Vec<u64> {
+        self.redeliver.keys().copied().collect()
+    }
+
+    /// Forgets everything below `offset`, e.g. records retention removed.
+    pub fn skip_to(&mut self, offset: u64) {
+        self.next_offset = self.next_offset.max(offset);
+        self.in_flight = self.in_flight.split_off(&offset);
+        self.redeliver = self.redeliver.split_off(&offset);
+        self.dead_lettering = self.dead_lettering.split_off(&offset);
+        self.resumed = self.resumed.split_off(&offset);
+    }
+
+    /// Failed records that have had `max_attempts` deliveries, lowest offset
+    /// first. They wait for redelivery until [`Self::forget`] is called.
+    pub fn exhausted(&self, max_attempts: u32) -> Vec<Exhausted> {
+        self.redeliver
+            .iter()
+            .filter(|(_, failure)| failure.delivery_count >= max_attempts)
+            .map(|(&offset, failure)| Exhausted {
+                offset,
+                attempts: failure.delivery_count,
+                error: failure.error.clone(),
+            })
+            .collect()
+    }
+
+    /// Like [`Self::exhausted`], but also holds the records back from
+    /// redelivery while they are written to the dead-letter topic. Each is
+    /// then dropped with [`Self::forget`] or handed back with [`Self::retry`].
+    pub fn take_exhausted(&mut self, max_attempts: u32) -> Vec<Exhausted> {
+        let exhausted = self.exhausted(max_attempts);
+        for failed in &exhausted {
+            let failure = self.redeliver.remove(&failed.offset).expect("exhausted redelivery");
+            self.dead_lettering.insert(failed.offset, failure);
+        }
+        exhausted
+    }
+
+    /// Returns a record taken by [`Self::take_exhausted`] to redelivery, when
+    /// it could not be dead-lettered.
+    pub fn retry(&mut self, offset: u64) {
+        if let Some(failure) = self.dead_lettering.remove(&offset) {
+            self.redeliver.insert(offset, failure);
+        }
+    }
+
+    /// Drops a redelivery whose record is no longer in the log, or was
+    /// dead-lettered.
+    pub fn forget(&mut self, offset: u64) {
+        self.redeliver.remove(&offset);
+        self.dead_lettering.remove(&offset);
+    }
+
+    /// Moves past every offset below `offset` that was never leased: records
+    /// no member gets, like those of aborted transactions and markers.
+    pub fn pass_to(&mut self, offset: u64) {
+        self.next_offset = self.next_offset.max(offset);
+    }
+
+    /// Leases `offset` until `deadline_ms` and returns its delivery count.
+    pub fn lease(&mut self, offset: u64, deadline_ms: u64) -> u32 {
+        let before = self.redeliver.remove(&offset).map(|f| f.delivery_count);
+        let delivery_count = before.or_else(|| self.resumed.remove(&offset)).unwrap_or(0) + 1;
+        self.in_flight.insert(offset, Lease { delivery_count, deadline_ms });
+        self.next_offset = self.next_offset.max(offset + 1);
+        delivery_count
+    }
+
+    /// Settles `offset` if it is still leased with `delivery_count`: acked
+    /// records are done, nacked ones wait for redelivery with `error` (empty =
+    /// none given) as the reason they failed.
+    pub fn settle(&mut self, offset: u64, delivery_count: u32, ack: bool, error: &str) -> bool {
+        match self.in_flight.get(&offset) {
+            Some(lease) if lease.delivery_count == delivery_count => {
+                self.in_flight.remove(&offset);
+                if !ack {
+                    let error = match error.is_empty() {
+                        true => NACKED.to_string(),
+                        false => error.to_string(),
+                    };
+                    self.redeliver.insert(offset, Failure { delivery_count, error });
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_committed_offset_waits_for_lowest_unacked() {
+        let mut cursor = QueueCursor::new(10);
+        for offset in 10..14 {
+            assert_eq!(cursor.lease(offset, 1_000), 1);
+        }
+        assert!(cursor.settle(11, 1, true, ""));
+        assert!(cursor.settle(12, 1, false, ""));
+        assert_eq!(cursor.committed_offset(), 10);
+
+        assert!(cursor.settle(10, 1, true, ""));
+        assert_eq!(cursor.committed_offset(), 12); // nacked, not done
+
+        cursor.expire(1_000);
+        assert_eq!(cursor.redeliveries(), vec![12, 13]);
+        assert_eq!(cursor.lease(13, 2_000), 2);
+        // the member whose lease on 13 expired can no longer settle it
+        assert!(!cursor.settle(13, 1, true, ""));
+        assert!(cursor.settle(13, 2, true, ""));
+        assert_eq!(cursor.lease(12, 2_000), 2);
+        assert!(cursor.settle(12, 2, true, ""));
+        assert_eq!(cursor.committed_offset(), 14);
+    }
+
+    #[test]
+    fn test_exhausted_keeps_last_error() {
+        let mut cursor = QueueCursor::new(0);
+        cursor.lease(0, 1_000);
+        cursor.lease(1, 1_000);
+        assert!(cursor.settle(0, 1, false, "bad input"));
+        assert!(cursor.exhausted(2).is_empty());
+
+        assert_eq!(cursor.lease(0, 2_000), 2);
+        cursor.expire(2_000);
+        let exhausted = cursor.exhausted(2);
+        assert_eq!(exhausted, vec![Exhausted { offset: 0, attempts: 2, error: EXPIRED.into() }]);
+
+        cursor.forget(0);
+        assert_eq!(cursor.committed_offset(), 1);
+        assert_eq!(cursor.exhausted(1)[0].offset, 1);
+    }
+
+    #[test]
+    fn test_records_being_dead_lettered_are_held_back() {
+        let mut cursor = QueueCursor::new(0);
+        cursor.lease(0, 1_000);
+        cursor.lease(1, 1_000);
+        cursor.expire(1_000);
+        assert_eq!(cursor.take_exhausted(1).len(), 2);
+        assert!(cursor.redeliveries().is_empty());
+        assert_eq!(cursor.committed_offset(), 0);
+
+        cursor.forget(0);
+        cursor.retry(1);
+        assert_eq!(cursor.redeliveries(), vec![1]);
+        assert_eq!(cursor.committed_offset(), 1);
+    }
+
+    #[test]
+    fn test_attempts_carry_over_a_restart() {
+        let mut cursor = QueueCursor::new(5);
+        cursor.lease(5, 1_000);
+        cursor.lease(6, 1_000);
+        cursor.lease(7, 1_000);
+        assert!(cursor.settle(5, 1, true, ""));
+        assert!(cursor.settle(6, 1, false, ""));
+        assert_eq!(cursor.lease(6, 2_000), 2);
+        assert_eq!(cursor.attempts(), Attempts::from([(6, 2), (7, 1)]));
+
+        let mut resumed = QueueCursor::resume(cursor.committed_offset(), cursor.attempts());
+        assert_eq!(resumed.lease(6, 3_000), 3);
+        assert_eq!(resumed.lease(7, 3_000), 2);
+        assert_eq!(resumed.lease(8, 3_000), 1);
+    }
+}
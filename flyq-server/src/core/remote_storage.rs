@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::core::constants::REMOTE_CACHE_SEGMENTS;
+use crate::core::sealed_segment::SealedSegment;
+use crate::core::segment::Segment;
+use crate::core::topic::SharedPartition;
+
+/// Object store that sealed segments are offloaded to. Keys are `/` separated
+/// paths such as `topic_orders/partition_0/segment_00000000000000000000.log`.
+pub trait RemoteStorage: Send + Sync {
+    /// Uploads the file at `src` under `key`, replacing any existing object.
+    fn put(&self, key: &str, src: &Path) -> io::Result<()>;
+
+    /// Downloads the object at `key` into `dest`.
+    fn get(&self, key: &str, dest: &Path) -> io::Result<()>;
+
+    /// Keys starting with `prefix`, in lexicographic order.
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>>;
+
+    /// Removes the object at `key`; missing objects are not an error.
+    fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// [`RemoteStorage`] backed by a directory, e.g. a mounted network share.
+/// Also what tests use in place of a real object store.
+pub struct LocalFsRemoteStorage {
+    root: PathBuf,
+}
+
+impl LocalFsRemoteStorage {
+    pub fn new<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn collect_keys(&self, dir: &Path, keys: &mut Vec<String>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.collect_keys(&path, keys)?;
+            } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                let key: Vec<_> = relative.iter().map(|c| c.to_string_lossy()).collect();
+                keys.push(key.join("/"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RemoteStorage for LocalFsRemoteStorage {
+    fn put(&self, key: &str, src: &Path) -> io::Result<()> {
+        let dest = self.root.join(key);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // copy next to the target and rename so readers never see half an object
+        let tmp = PathBuf::from(format!("{}.upload.tmp", dest.display()));
+        fs::copy(src, &tmp)?;
+        fs::rename(&tmp, &dest)
+    }
+
+    fn get(&self, key: &str, dest: &Path) -> io::Result<()> {
+        fs::copy(self.root.join(key), dest).map(|_| ())
+    }
+
+    fn list(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        self.collect_keys(&self.root, &mut keys)?;
+        keys.retain(|k| k.starts_with(prefix) && !k.ends_with(".upload.tmp"));
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.root.join(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+const MANIFEST_FILE: &str = "remote.json";
+
+/// What the partition remembers about a segment that now only lives remotely.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteSegment {
+    pub base_offset: u64,
+    pub last_offset: u64,
+    pub size: u64,
+    pub max_timestamp: u64, // largest record timestamp (ms), drives retention
+    /// When the segment was offloaded (ms), the retention clock of segments
+    /// without record timestamps. 0 in manifests from before it was kept.
+    #[serde(default)]
+    pub uploaded_at: u64,
+}
+
+/// Puts a sealed segment's files in the store. Cloned out of the
+/// [`RemoteTier`] so that uploads run without the partition lock.
+#[derive(Clone)]
+pub struct Uploader {
+    store: Arc<dyn RemoteStorage>,
+    prefix: String,
+}
+
+impl Uploader {
+    pub fn upload(&self, segment: &SealedSegment) -> io::Result<()> {
+        // a compacted segment's local files carry its generation, remote ones never do
+        let local = [
+            &segment.segment_path,
+            &segment.index_path,
+            &segment.time_index_path,
+            &segment.header_index_path,
+        ];
+        for (filename, path) in RemoteTier::segment_files(segment.base_offset).iter().zip(local) {
+            if path.exists() {
+                self.store.put(&format!("{}/{}", self.prefix, filename), path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Uploads the sealed segments of `partition` that [`Partition::offload_candidates`]
+/// picks, each on a blocking thread and without the partition lock, which is
+/// only taken to move the segment into the remote manifest. Returns how many
+/// segments were offloaded.
+///
+/// [`Partition::offload_candidates`]: crate::core::partition::Partition::offload_candidates
+pub async fn offload_partition(partition: &SharedPartition, min_age: Duration) -> io::Result<u32> {
+    let (uploader, candidates) = {
+        let partition = partition.read().await;
+        match partition.remote_uploader() {
+            Some(uploader) => (uploader, partition.offload_candidates(min_age)),
+            None => return Ok(0),
+        }
+    };
+
+    let mut offloaded = 0;
+    for segment in candidates {
+        let uploaded = Arc::clone(&segment);
+        let uploader = uploader.clone();
+        tokio::task::spawn_blocking(move || uploader.upload(&uploaded))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e)))?;
+        if !partition.write().await.install_offloaded(&segment)? {
+            break; // the partition changed underneath, next pass starts over
+        }
+        offloaded += 1;
+    }
+    Ok(offloaded)
+}
+
+/// Remote half of a partition's log: the manifest of offloaded segments plus a
+/// small cache of ones downloaded for reads.
+pub struct RemoteTier {
+    store: Arc<dyn RemoteStorage>,
+    prefix: String,
+    manifest_path: PathBuf,
+    cache_dir: PathBuf,
+    pub(crate) segments: BTreeMap<u64, RemoteSegment>,
+    cache: Mutex<VecDeque<(u64, Arc<SealedSegment>)>>, // oldest download first
+}
+
+impl RemoteTier {
+    /// Loads the manifest kept in `partition_dir` and starts with an empty cache.
+    pub fn open(store: Arc<dyn RemoteStorage>, prefix: String, partition_dir: &Path) -> io::Result<Self> {
+        let manifest_path = partition_dir.join(MANIFEST_FILE);
+        let segments = Self::read_manifest(partition_dir)?
+            .into_iter()
+            .map(|s| (s.base_offset, s))
+            .collect();
+
+        // downloads from a previous run are not tracked, start clean
+        let cache_dir = partition_dir.join("remote_cache");
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir)?;
+        }
+        fs::create_dir_all(&cache_dir)?;
+
+        Ok(Self {
+            store,
+            prefix,
+            manifest_path,
+            cache_dir,
+            segments,
+            cache: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    fn key(&self, filename: &str) -> String {
+        format!("{}/{}", self.prefix, filename)
+    }
+
+    fn segment_prefix(&self, base_offset: u64) -> String {
+        self.key(&format!("segment_{:020}.", base_offset))
+    }
+
+    fn segment_files(base_offset: u64) -> [String; 4] {
+        [
+            Segment::segment_filename(base_offset),
+            Segment::index_filename(base_offset),
+            Segment::time_index_filename(base_offset),
+            Segment::header_index_filename(base_offset),
+        ]
+    }
+
+    /// Segments the manifest in `partition_dir` lists as offloaded, without
+    /// opening the store.
+    pub fn read_manifest(partition_dir: &Path) -> io::Result<Vec<RemoteSegment>> {
+        let manifest_path = partition_dir.join(MANIFEST_FILE);
+        if !manifest_path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = fs::File::open(&manifest_path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    fn save_manifest(&self) -> io::Result<()> {
+        let list: Vec<&RemoteSegment> = self.segments.values().collect();
+        let tmp_path = self.manifest_path.with_extension("json.tmp");
+        {
+            let tmp_file = fs::File::create(&tmp_path)?;
+            serde_json::to_writer_pretty(tmp_file, &list)?;
+        }
+        fs::rename(&tmp_path, &self.manifest_path) // atomic replace
+    }
+
+    /// Uploads a sealed segment's files and records it in the manifest. The
+    /// caller drops the local copy once this returns.
+    pub fn upload(&mut self, segment: &SealedSegment) -> io::Result<()> {
+        self.uploader().upload(segment)?;
+        self.record(segment)
+    }
+
+    /// Handle for uploading segment files without holding the tier.
+    pub fn uploader(&self) -> Uploader {
+        Uploader { store: Arc::clone(&self.store), prefix: self.prefix.clone() }
+    }
+
+    /// Records a segment whose files were uploaded in the manifest.
+    pub fn record(&mut self, segment: &SealedSegment) -> io::Result<()> {
+        self.segments.insert(segment.base_offset, RemoteSegment {
+            base_offset: segment.base_offset,
+            last_offset: segment.last_offset,
+            size: segment.size,
+            max_timestamp: segment.max_timestamp,
+            uploaded_at: chrono::Utc::now().timestamp_millis() as u64,
+        });
+        self.save_manifest()
+    }
+
+    /// Deletes objects uploaded for a segment that never made it into the
+    /// manifest, e.g. because retention or compaction replaced it meanwhile.
+    pub fn discard(&self, base_offset: u64) -> io::Result<()> {
+        if self.segments.contains_key(&base_offset) {
+            return Ok(());
+        }
+        for key in self.store.list(&self.segment_prefix(base_offset))? {
+            self.store.delete(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes an offloaded segment from the store, the manifest and the cache.
+    pub fn remove(&mut self, base_offset: u64) -> io::Result<Option<RemoteSegment>> {
+        let Some(removed) = self.segments.remove(&base_offset) else {
+            return Ok(None);
+        };
+        self.save_manifest()?;
+
+        for key in self.store.list(&self.segment_prefix(base_offset))? {
+            self.store.delete(&key)?;
+        }
+
+        let mut cache = self.cache.lock().expect("mutex poisoned");
+        if let Some(pos) = cache.iter().position(|(base, _)| *base == base_offset) {
+            if let Some((_, seg)) = cache.remove(pos) {
+                seg.mark_deleted();
+            }
+        }
+        Ok(Some(removed))
+    }
+
+    /// Returns the segment starting at `base_offset`, downloading it into the
+    /// cache first if needed. The least recently downloaded segment is evicted
+    /// once the cache is full.
+    pub fn open_segment(&self, base_offset: u64) -> io::Result<Arc<SealedSegment>> {
+        let mut cache = self.cache.lock().expect("mutex poisoned");
+        if let Some((_, seg)) = cache.iter().find(|(base, _)| *base == base_offset) {
+            return Ok(Arc::clone(seg));
+        }
+
+        let filename = Segment::segment_filename(base_offset);
+        let keys = self.store.list(&self.segment_prefix(base_offset))?;
+        if !keys.contains(&self.key(&filename)) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("remote segment {} missing", filename)));
+        }
+        // index files are optional, the segment rebuilds what is missing
+        for name in Self::segment_files(base_offset) {
+            let key = self.key(&name);
+            if keys.contains(&key) {
+                self.store.get(&key, &self.cache_dir.join(&name))?;
+            }
+        }
+
+        let (_, _, segment) = Segment::recover_from_disk(self.cache_dir.join(&filename), &filename)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("bad remote segment {}", filename)))?;
+        tracing::debug!(base_offset, prefix = %self.prefix, "Fetched remote segment");
+
+        let segment = Arc::new(segment.seal());
+        if cache.len() >= REMOTE_CACHE_SEGMENTS {
+            if let Some((_, evicted)) = cache.pop_front() {
+                // files go away once the last reader drops its handle
+                evicted.mark_deleted();
+            }
+        }
+        cache.push_back((base_offset, Arc::clone(&segment)));
+        Ok(segment)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.segments.values().map(|s| s.size).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LocalFsRemoteStorage, RemoteStorage};
+    use std::fs;
+
+    #[test]
+    fn test_local_fs_storage_roundtrip() {
+        let root = tempfile::tempdir().unwrap();
+        let scratch = tempfile::tempdir().unwrap();
+        let store = LocalFsRemoteStorage::new(root.path()).unwrap();
+
+        let src = scratch.path().join("segment.log");
+        fs::write(&src, b"payload").unwrap();
+        store.put("topic_a/partition_0/segment_1.log", &src).unwrap();
+        store.put("topic_a/partition_0/segment_1.index", &src).unwrap();
+        store.put("topic_b/partition_0/segment_1.log", &src).unwrap();
+
+        assert_eq!(
+            store.list("topic_a/").unwrap(),
+            vec!["topic_a/partition_0/segment_1.index", "topic_a/partition_0/segment_1.log"]
+        );
+
+        let dest = scratch.path().join("downloaded.log");
+        store.get("topic_a/partition_0/segment_1.log", &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"payload");
+
+        store.delete("topic_a/partition_0/segment_1.log").unwrap();
+        store.delete("topic_a/partition_0/segment_1.log").unwrap(); // already gone is fine
+        assert_eq!(store.list("topic_a/").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use crate::core::index_strategy::IndexStrategyConfig;
+use crate::core::inspect::{list_segments, DerivedWatermarks, Finding, RecordScanner, SegmentCheck, SegmentSpan};
+use crate::core::partiton_meta::PartitionMeta;
+use crate::core::remote_storage::RemoteTier;
+use crate::core::segment::Segment;
+
+/// How [`repair_partition`] treats a partition directory.
+#[derive(Debug, Clone, Copy)]
+pub struct RepairOptions {
+    /// Strategy the rebuilt `.index` files are written with.
+    pub index_strategy: IndexStrategyConfig,
+    /// Cut segment files at their first unreadable record.
+    pub truncate: bool,
+    /// Only work out what would be done, write nothing.
+    pub check: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairAction {
+    TruncateTail { segment: PathBuf, position: u64, bytes: u64 },
+    RebuildIndexes { segment: PathBuf },
+    WriteMeta { old: Option<PartitionMeta>, new: PartitionMeta },
+}
+
+impl fmt::Display for RepairAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepairAction::TruncateTail { segment, position, bytes } => write!(
+                f,
+                "truncate {} at byte {} ({} bytes dropped)",
+                segment.display(),
+                position,
+                bytes
+            ),
+            RepairAction::RebuildIndexes { segment } => write!(f, "rebuild the index files of {}", segment.display()),
+            RepairAction::WriteMeta { old, new } => {
+                let describe = |meta: &PartitionMeta| {
+                    format!(
+                        "low_watermark={} high_watermark={} log_end_offset={}",
+                        meta.low_watermark, meta.high_watermark, meta.log_end_offset
+                    )
+                };
+                match old {
+                    Some(old) => write!(f, "rewrite meta.json: {} -> {}", describe(old), describe(new)),
+                    None => write!(f, "write meta.json: {}", describe(new)),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// Everything found wrong, with the file it is about.
+    pub findings: Vec<(PathBuf, Finding)>,
+    /// What was done, or in check mode what would be.
+    pub actions: Vec<RepairAction>,
+    /// Findings the actions do not fix, e.g. duplicate offsets, or a corrupt
+    /// tail without [`RepairOptions::truncate`].
+    pub unrepaired: Vec<(PathBuf, Finding)>,
+}
+
+/// Brings a partition directory back to a state broker startup trusts:
+/// segment files cut at their first unreadable record (if asked to), index
+/// files rebuilt where they disagree with the records or were written with
+/// another strategy, and `meta.json` recomputed from the segments.
+///
+/// The broker must not have the partition open. Index files are rebuilt by
+/// the same replay the broker runs when it opens a segment. Segments already
+/// offloaded to tiered storage are not touched, but count for the watermarks.
+pub fn repair_partition(dir: &Path, options: &RepairOptions) -> std::io::Result<RepairReport> {
+    let mut report = RepairReport::default();
+    // offloaded segments still hold readable records, the log may start there
+    let mut spans: Vec<SegmentSpan> = RemoteTier::read_manifest(dir)?
+        .into_iter()
+        .map(|s| SegmentSpan {
+            base_offset: s.base_offset,
+            first_offset: Some(s.base_offset),
+            last_offset: Some(s.last_offset),
+        })
+        .collect();
+
+    for (base_offset, path) in list_segments(dir)? {
+        let [index_path, ..] = Segment::index_paths(&path);
+        let index = match index_path.exists() {
+            true => Some(Segment::read_index_file(&index_path)?),
+            false => None,
+        };
+
+        let mut check = SegmentCheck::new(base_offset, index.as_ref());
+        let mut scanner = RecordScanner::open(&path)?;
+        let mut corrupt = None;
+        for item in scanner.by_ref() {
+            match item {
+                Ok(raw) => check.record(&raw),
+                Err(e) => corrupt = Some(e),
+            }
+        }
+        spans.push(SegmentSpan {
+            base_offset,
+            first_offset: check.first_offset,
+            last_offset: check.last_offset,
+        });
+        let valid_len = scanner.position();
+        let findings = check.finish(corrupt, scanner.file_len());
+
+        let truncate = options.truncate && valid_len < scanner.file_len();
+        if truncate {
+            report.actions.push(RepairAction::TruncateTail {
+                segment: path.clone(),
+                position: valid_len,
+                bytes: scanner.file_len() - valid_len,
+            });
+            if !options.check {
+                OpenOptions::new().write(true).open(&path)?.set_len(valid_len)?;
+            }
+        }
+
+        let other_strategy = index.as_ref().and_then(|index| index.strategy) != Some(options.index_strategy);
+        if truncate || other_strategy || findings.iter().any(Finding::is_index_problem) {
+            report.actions.push(RepairAction::RebuildIndexes { segment: path.clone() });
+            if !options.check {
+                Segment::rebuild_index_files(&path, options.index_strategy)?;
+            }
+        }
+
+        for finding in findings {
+            let fixed = finding.is_index_problem() || (truncate && matches!(finding, Finding::CorruptTail { .. }));
+            if !fixed {
+                report.unrepaired.push((path.clone(), finding.clone()));
+            }
+            report.findings.push((path.clone(), finding));
+        }
+    }
+
+    let meta_path = dir.join("meta.json");
+    let old = PartitionMeta::load(&meta_path).ok().flatten();
+    let new = derive_meta(old.as_ref(), DerivedWatermarks::from_spans(&spans));
+    if old.as_ref() != Some(&new) {
+        report.actions.push(RepairAction::WriteMeta { old, new: new.clone() });
+        if !options.check {
+            PartitionMeta::save(&meta_path, &new)?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// The log end comes from the records on disk, the log start from `meta.json`
+/// as long as those records still exist (DeleteRecords moves it past records
+/// that stay in their segment). Everything on disk counts as committed.
+fn derive_meta(old: Option<&PartitionMeta>, derived: DerivedWatermarks) -> PartitionMeta {
+    let low_watermark = old
+        .map_or(derived.first_offset, |meta| meta.low_watermark.max(derived.first_offset))
+        .min(derived.log_end_offset);
+    PartitionMeta {
+        low_watermark,
+        high_watermark: derived.log_end_offset.saturating_sub(1), // last offset, as `append` sets it
+        log_end_offset: derived.log_end_offset,
+    }
+}
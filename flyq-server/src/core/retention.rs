@@ -0,0 +1,65 @@
+use std::time::Duration;
+use crate::BrokerConfig;
+
+/// Limits a cleanup pass enforces on a partition.
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// Segments whose newest record is older than this are deleted.
+    pub retention: Duration,
+
+    /// Oldest segments are deleted while the partition is above this size.
+    pub retention_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn from_config(cfg: &BrokerConfig) -> Self {
+        Self {
+            retention: cfg.retention,
+            retention_bytes: cfg.retention_bytes,
+        }
+    }
+}
+
+/// A sealed segment that a cleanup pass would delete, and why.
+#[derive(Debug, Clone)]
+pub struct CleanupCandidate {
+    pub base_offset: u64,
+    pub last_offset: u64,
+    pub size: u64,
+    pub time_expired: bool,
+    pub size_exceeded: bool,
+    /// Deleted even though protected consumer groups have not read it,
+    /// because the partition is above the protection's hard cap.
+    pub protection_overridden: bool,
+    /// Human readable explanation, including ages and sizes.
+    pub detail: String,
+}
+
+impl CleanupCandidate {
+    pub fn labels(&self) -> Vec<&'static str> {
+        let mut labels = Vec::new();
+        if self.time_expired {
+            labels.push("time-based");
+        }
+        if self.size_exceeded {
+            labels.push("size-based");
+        }
+        if self.protection_overridden {
+            labels.push("protection-cap");
+        }
+        labels
+    }
+}
+
+/// Outcome of a cleanup run that removed data.
+#[derive(Debug, Clone)]
+pub struct CleanupRun {
+    /// Unix timestamp in nanoseconds.
+    pub timestamp_ns: u64,
+    pub segments_removed: u32,
+    pub bytes_freed: u64,
+    /// Comma separated triggers, e.g. "time-based, size-based" or "delete-records".
+    pub reason: String,
+    /// Low watermark right after the run.
+    pub low_watermark: u64,
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use flyq_protocol::errors::DeserializeError;
+use crate::core::header_index::HeaderIndex;
+use crate::core::index_strategy::IndexStrategy;
+use crate::core::segment::{Segment, SegmentBackwardIterator, SegmentIterator};
+
+/// A segment that no longer takes appends. Everything about it is fixed at
+/// seal time, so readers share it through an `Arc` without locking; each read
+/// opens its own file handle and never disturbs another reader's position.
+pub struct SealedSegment {
+    pub(crate) base_offset: u64,
+    pub last_offset: u64,
+    pub(crate) size: u64,
+    pub(crate) max_timestamp: u64,     // largest record timestamp (ms), 0 if empty
+    pub(crate) last_write_ns: u64,
+    pub(crate) index: BTreeMap<u64, u64>, // offset → file position
+    pub(crate) index_strategy: Arc<dyn IndexStrategy>,
+    pub(crate) header_index: HeaderIndex,
+    pub(crate) header_index_path: PathBuf,
+    /// Earliest record expiry (Unix ms) compaction has not dealt with yet;
+    /// `u64::MAX` = none, 0 = not known, e.g. after recovery.
+    pub(crate) next_expiry: AtomicU64,
+    /// Set once the partition swapped in a compacted generation of this
+    /// segment; its files go with the last reader, like deleted ones.
+    pub(crate) replaced: AtomicBool,
+    pub(crate) segment_path: PathBuf,
+    pub(crate) index_path: PathBuf,
+    pub(crate) time_index_path: PathBuf,
+    pub mark_deleted: AtomicBool,
+}
+
+impl SealedSegment {
+    pub fn stream_from_offset(&self, offset: u64) -> Result<SegmentIterator, DeserializeError> {
+        let file = File::open(&self.segment_path)
+            .map_err(|e| DeserializeError::InvalidFormat(e.to_string()))?;
+        SegmentIterator::seek(file, self.index_strategy.lookup(&self.index, offset), offset)
+    }
+
+    /// Records at or below `offset` in descending offset order.
+    pub fn stream_backward_from(&self, offset: u64) -> Result<SegmentBackwardIterator, DeserializeError> {
+        SegmentBackwardIterator::open(&self.segment_path, &self.index, offset)
+    }
+
+    /// Point in time that time-based retention measures this segment's age from.
+    /// See [`Segment::retention_timestamp`](crate::core::segment::Segment::retention_timestamp).
+    pub fn retention_timestamp(&self) -> SystemTime {
+        if self.max_timestamp > 0 {
+            UNIX_EPOCH + Duration::from_millis(self.max_timestamp)
+        } else {
+            UNIX_EPOCH + Duration::from_nanos(self.last_write_ns)
+        }
+    }
+
+    /// How often compaction rewrote the segment, from its file name.
+    pub fn generation(&self) -> u32 {
+        Segment::scan_path(&self.segment_path)
+            .and_then(|name| Segment::parse_generation(&name))
+            .map_or(0, |(_, generation)| generation)
+    }
+
+    pub fn mark_deleted(&self) {
+        self.mark_deleted.store(true, Ordering::Release);
+    }
+
+    fn delete_files(&self) -> std::io::Result<()> {
+        for path in [&self.segment_path, &self.index_path, &self.time_index_path, &self.header_index_path] {
+            if path.exists() {
+                fs::remove_file(path)?;
+                tracing::info!("Deleted segment file: {:?}", path);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SealedSegment {
+    fn drop(&mut self) {
+        // Readers holding the Arc keep the files alive until they are done
+        if self.mark_deleted.load(Ordering::Acquire) || self.replaced.load(Ordering::Acquire) {
+            if let Err(e) = self.delete_files() {
+                tracing::warn!(
+                    error = ?e,
+                    segment = ?self.segment_path,
+                    "Failed to delete segment files during drop"
+                );
+            }
+        }
+    }
+}
@@ -1,15 +1,20 @@
-use crate::core::constants::DEFAULT_INDEX_INTERVAL;
+use crate::core::constants::BACKWARD_CHUNK_BYTES;
+use crate::core::header_index::HeaderIndex;
+use crate::core::index_strategy::{IndexStrategy, IndexStrategyConfig, INDEX_HEADER_LEN};
+use crate::core::sealed_segment::SealedSegment;
 use crate::core::storage::Storage;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt;
-use std::fs::File;
+use std::fs::{File, Metadata};
 use std::io::{BufReader, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use flyq_protocol::errors::DeserializeError;
 use flyq_protocol::message::Message;
-use crate::core::stored_record::StoredRecord;
+use flyq_protocol::record_batch::{BatchHeader, RecordBatch, BATCH_FLAG};
+use crate::core::stored_record::{entry_len, StoredRecord};
 
 pub struct Segment {
     pub(crate) base_offset: u64,
@@ -19,21 +24,33 @@ pub struct Segment {
     pub(crate) index_file: File,
     pub(crate) size: u64,
     pub(crate) index: BTreeMap<u64, u64>, // offset → local file position
+    pub(crate) time_index_path: PathBuf,
+    time_index_file: File,
+    pub(crate) max_timestamp: u64,        // largest record timestamp (ms), 0 while empty
+    pub(crate) first_append_ns: Option<u64>, // broker clock at the first append, drives time-based rolling
+    time_index_max: u64,                  // largest timestamp already in the time index
     pub last_offset: u64,                 // inclusive, or offset of last message
-    index_interval: u32,
-    index_counter: u32,
+    pub(crate) index_strategy: Arc<dyn IndexStrategy>, // as recorded in the index header
+    pub(crate) header_index: HeaderIndex,
+    pub(crate) header_index_path: PathBuf,
+    header_index_file: Option<File>, // only while some header is indexed
+    pub(crate) next_expiry: u64, // earliest record expiry (Unix ms); u64::MAX = none, 0 = not known after recovery
     pub last_write_ns: AtomicU64,
     pub mark_deleted: AtomicBool
 
 }
 
 impl Segment {
-    pub fn new(base_offset: u64, storage: &Storage) -> Self {
+    pub fn new(base_offset: u64, storage: &Storage, index_strategy: Arc<dyn IndexStrategy>) -> Self {
         let file_name = Self::segment_filename(base_offset);
         let (segment_path, file) = storage.open_file(&file_name);
         let index_file_name = Segment::index_filename(base_offset);
         let index_path = segment_path.parent().unwrap().join(index_file_name);
-        let (_, index_file) = Storage::open_file_from_path(&index_path);
+        let (_, mut index_file) = Storage::open_file_from_path(&index_path);
+        Self::write_index_header(&mut index_file, index_strategy.config());
+        let time_index_path = segment_path.parent().unwrap().join(Segment::time_index_filename(base_offset));
+        let (_, time_index_file) = Storage::open_file_from_path(&time_index_path);
+        let header_index_path = segment_path.parent().unwrap().join(Segment::header_index_filename(base_offset));
 
         Self {
             base_offset,
@@ -43,9 +60,17 @@ impl Segment {
             size: 0,
             index: BTreeMap::new(),
             index_file,
+            time_index_path,
+            time_index_file,
+            max_timestamp: 0,
+            first_append_ns: None,
+            time_index_max: 0,
             last_offset: 0,
-            index_interval: DEFAULT_INDEX_INTERVAL,
-            index_counter: DEFAULT_INDEX_INTERVAL,
+            index_strategy,
+            header_index: HeaderIndex::default(),
+            header_index_path,
+            header_index_file: None,
+            next_expiry: u64::MAX,
             last_write_ns: AtomicU64::new(now_ns()),
             mark_deleted: AtomicBool::new(false),
         }
@@ -59,22 +84,69 @@ impl Segment {
         format!("segment_{:020}.index", base_offset)
     }
 
-    fn index_path_from_base(base_offset: u64, dir: &Path) -> PathBuf {
-        dir.join(Self::index_filename(base_offset))
+    pub fn header_index_filename(base_offset: u64) -> String {
+        format!("segment_{:020}.hindex", base_offset)
+    }
+
+    pub fn time_index_filename(base_offset: u64) -> String {
+        format!("segment_{:020}.timeindex", base_offset)
+    }
+
+    /// Log file of a rewrite of the segment at `base_offset`; generation 0 is
+    /// the segment as first written. Its index files take the same stem.
+    pub fn generation_filename(base_offset: u64, generation: u32) -> String {
+        match generation {
+            0 => Self::segment_filename(base_offset),
+            _ => format!("segment_{:020}.{}.log", base_offset, generation),
+        }
+    }
+
+    /// The offset, time and header index files next to the log at `log_path`.
+    pub fn index_paths(log_path: &Path) -> [PathBuf; 3] {
+        ["index", "timeindex", "hindex"].map(|extension| log_path.with_extension(extension))
+    }
+
+    /// Removes the log at `log_path` and whichever of its index files exist.
+    pub fn remove_files(log_path: &Path) -> std::io::Result<()> {
+        let [index_path, time_index_path, header_index_path] = Self::index_paths(log_path);
+        for path in [log_path.to_path_buf(), index_path, time_index_path, header_index_path] {
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
     }
 
     pub fn parse_base_offset(filename: &str) -> Option<u64> {
-        filename
-            .strip_prefix("segment_")?
-            .strip_suffix(".log")
-            .or_else(|| filename.strip_suffix(".index"))
-            .and_then(|s| s.parse::<u64>().ok())
+        Self::parse_generation(filename).map(|(base_offset, _)| base_offset)
+    }
+
+    /// Base offset and generation of a segment log file name.
+    pub fn parse_generation(filename: &str) -> Option<(u64, u32)> {
+        let stem = filename.strip_prefix("segment_")?.strip_suffix(".log")?;
+        match stem.split_once('.') {
+            None => Some((stem.parse().ok()?, 0)),
+            Some((base_offset, generation)) => Some((base_offset.parse().ok()?, generation.parse().ok()?)),
+        }
     }
 
     pub fn last_write(&self) -> SystemTime {
         UNIX_EPOCH + Duration::from_nanos(self.last_write_ns.load(Ordering::Acquire))
     }
 
+    /// Point in time that time-based retention measures this segment's age from.
+    /// Uses the largest record timestamp so the clock survives restarts; falls back
+    /// to the last write for segments without record timestamps (no records yet,
+    /// or written before timestamps were kept), which starts from the file mtime
+    /// on reopen.
+    pub fn retention_timestamp(&self) -> SystemTime {
+        if self.max_timestamp > 0 {
+            UNIX_EPOCH + Duration::from_millis(self.max_timestamp)
+        } else {
+            self.last_write()
+        }
+    }
+
     pub fn mark_deleted(&self )-> std::io::Result<()>{
         self.mark_deleted.store(true, Ordering::Release);
         Ok(())
@@ -100,12 +172,51 @@ impl Segment {
             tracing::info!("Deleted index file: {:?}", self.index_path);
         }
 
+        if self.time_index_path.exists() {
+            fs::remove_file(&self.time_index_path)?;
+            tracing::info!("Deleted time index file: {:?}", self.time_index_path);
+        }
+
+        if self.header_index_path.exists() {
+            fs::remove_file(&self.header_index_path)?;
+            tracing::info!("Deleted header index file: {:?}", self.header_index_path);
+        }
+
         Ok(())
     }
 
-    pub fn append(&mut self, offset: u64, bytes: &[u8]) -> std::io::Result<u64> {
+    /// Forces the records written so far to disk. Indexes are left to the
+    /// OS, recovery rebuilds them from the log.
+    pub fn sync(&self) -> std::io::Result<()> {
+        self.file.sync_data()
+    }
+
+    pub fn append(&mut self, offset: u64, timestamp: u64, bytes: &[u8]) -> std::io::Result<u64> {
+        self.append_entry(offset, offset, timestamp, bytes)
+    }
+
+    /// Appends a v2 batch, `entry` being its length-prefixed bytes. The
+    /// batch is indexed by its base offset, like a single record.
+    pub fn append_batch(&mut self, header: &BatchHeader, entry: &[u8]) -> std::io::Result<u64> {
+        self.append_entry(
+            header.base_offset,
+            header.last_offset(),
+            header.max_timestamp,
+            entry,
+        )
+    }
+
+    fn append_entry(
+        &mut self,
+        offset: u64,
+        last_offset: u64,
+        max_timestamp: u64,
+        bytes: &[u8],
+    ) -> std::io::Result<u64> {
         // Update last write timestamp
-        self.last_write_ns.store(now_ns(), Ordering::Release);
+        let now = now_ns();
+        self.last_write_ns.store(now, Ordering::Release);
+        self.first_append_ns.get_or_insert(now);
         
         // Seek to end first (optional, but clean)
         self.file.seek(SeekFrom::End(0))?;
@@ -115,9 +226,15 @@ impl Segment {
         self.file.flush()?; // or leave it for batch control
 
         self.size += bytes.len() as u64;
-        self.last_offset = self.last_offset.max(offset); // protects against incorrect overwrites
-        if self.should_index(offset) {
+        self.last_offset = self.last_offset.max(last_offset); // protects against incorrect overwrites
+        self.max_timestamp = self.max_timestamp.max(max_timestamp);
+        if self.should_index(offset, pos) {
             self.create_index(offset, pos);
+            // records past the last index entry are rescanned on recovery,
+            // so the time index only needs to keep pace with the offset index
+            if self.max_timestamp > self.time_index_max {
+                self.write_time_index_entry(self.max_timestamp, offset);
+            }
         }
         Ok(offset)
     }
@@ -125,13 +242,10 @@ impl Segment {
     fn create_index(&mut self, offset: u64, pos: u64) {
         self.index.insert(offset, pos);
         self.write_index_entry(offset, pos);
-        self.index_counter = self.index_interval;
     }
 
     fn write_index_entry(&mut self, offset: u64, pos: u64) {
-        let mut entry = [0u8; 16];
-        entry[0..8].copy_from_slice(&offset.to_be_bytes());
-        entry[8..16].copy_from_slice(&pos.to_be_bytes());
+        let entry = self.index_strategy.encode_entry(offset, pos);
 
         self.index_file
             .write_all(&entry)
@@ -139,43 +253,118 @@ impl Segment {
         self.index_file.flush().expect("index flush failed");
     }
 
-    fn should_index(&mut self, offset: u64) -> bool {
+    fn write_time_index_entry(&mut self, timestamp: u64, offset: u64) {
+        let mut entry = [0u8; 16];
+        entry[0..8].copy_from_slice(&timestamp.to_be_bytes());
+        entry[8..16].copy_from_slice(&offset.to_be_bytes());
+
+        self.time_index_file
+            .write_all(&entry)
+            .expect("time index write failed");
+        self.time_index_file.flush().expect("time index flush failed");
+        self.time_index_max = timestamp;
+    }
+
+    fn should_index(&self, offset: u64, pos: u64) -> bool {
         if offset == self.base_offset {
             return true; // Always index first message in segment
         }
+        let last = self.index.last_key_value().map(|(&offset, &pos)| (offset, pos));
+        self.index_strategy.should_index(last, offset, pos)
+    }
+
+    fn write_index_header(index_file: &mut File, strategy: IndexStrategyConfig) {
+        index_file
+            .write_all(&strategy.encode_header())
+            .expect("index header write failed");
+        index_file.flush().expect("index flush failed");
+    }
 
-        if self.index_counter == 0 {
-            self.index_counter = self.index_interval;
-            return true;
+    /// Headers to build the secondary index for. Like the index strategy this
+    /// only changes while the segment is empty; the names are recorded in the
+    /// `.hindex` header so recovery rebuilds the same index.
+    pub fn set_indexed_headers(&mut self, names: &[String]) -> std::io::Result<()> {
+        if self.size > 0 || self.header_index.names() == names {
+            return Ok(());
+        }
+        self.header_index = HeaderIndex::new(names.to_vec());
+        self.header_index_file = None;
+        if names.is_empty() {
+            if self.header_index_path.exists() {
+                std::fs::remove_file(&self.header_index_path)?;
+            }
+            return Ok(());
         }
 
-        self.index_counter -= 1;
-        false
+        let (_, mut file) = Storage::open_file_from_path(&self.header_index_path);
+        file.set_len(0)?;
+        file.write_all(&self.header_index.encode_file_header())?;
+        file.flush()?;
+        self.header_index_file = Some(file);
+        Ok(())
     }
 
-    pub fn stream_from_offset(&self, offset: u64) -> Result<SegmentIterator, DeserializeError> {
-        let closest_pos = if self.index.is_empty() {
-            0 // fallback: start of file
-        } else {
-            self.index
-                .range(..=offset)
-                .next_back()
-                .map(|(_, &v)| v)
-                .unwrap_or(0) // fallback if no index entry ≤ offset
+    /// Adds the record's indexed headers to the secondary index. Called right
+    /// after `append` for the same record.
+    pub fn index_headers(&mut self, offset: u64, message: &Message) -> std::io::Result<()> {
+        let Some(file) = self.header_index_file.as_mut() else {
+            return Ok(());
         };
+        let entries = self.header_index.record(offset, message);
+        if !entries.is_empty() {
+            file.write_all(&entries)?;
+            file.flush()?;
+        }
+        Ok(())
+    }
 
-        let mut file = self
-            .file
-            .try_clone()
-            .map_err(|e| DeserializeError::InvalidFormat(e.to_string()))?;
-        file.seek(SeekFrom::Start(closest_pos))
+    /// Switches an empty segment to another index strategy, e.g. once the
+    /// topic config is known for a freshly created partition. Segments with
+    /// records keep the strategy their index was written with.
+    pub fn reset_index_strategy(&mut self, index_strategy: Arc<dyn IndexStrategy>) -> std::io::Result<()> {
+        if self.size > 0 || self.index_strategy.config() == index_strategy.config() {
+            return Ok(());
+        }
+        self.index_file.set_len(0)?; // appends still land at the new end
+        Self::write_index_header(&mut self.index_file, index_strategy.config());
+        self.index.clear();
+        self.index_strategy = index_strategy;
+        Ok(())
+    }
+
+    pub fn stream_from_offset(&self, offset: u64) -> Result<SegmentIterator, DeserializeError> {
+        // a handle of its own: a cloned one shares the file position with appends,
+        // and readers keep going after the segment lock is released
+        let file = File::open(&self.segment_path)
             .map_err(|e| DeserializeError::InvalidFormat(e.to_string()))?;
+        SegmentIterator::seek(file, self.index_strategy.lookup(&self.index, offset), offset)
+    }
 
-        Ok(SegmentIterator {
-            reader: BufReader::new(file),
-            offset,
-            end_of_file: false,
-        })
+    /// Records at or below `offset` in descending offset order.
+    pub fn stream_backward_from(&self, offset: u64) -> Result<SegmentBackwardIterator, DeserializeError> {
+        SegmentBackwardIterator::open(&self.segment_path, &self.index, offset)
+    }
+
+    /// Freezes the segment into its read-only form. Called when the partition
+    /// rolls to a new active segment; nothing is appended here afterwards.
+    pub fn seal(&self) -> SealedSegment {
+        SealedSegment {
+            base_offset: self.base_offset,
+            last_offset: self.last_offset,
+            size: self.size,
+            max_timestamp: self.max_timestamp,
+            last_write_ns: self.last_write_ns.load(Ordering::Acquire),
+            index: self.index.clone(),
+            index_strategy: Arc::clone(&self.index_strategy),
+            header_index: self.header_index.clone(),
+            header_index_path: self.header_index_path.clone(),
+            next_expiry: AtomicU64::new(self.next_expiry),
+            replaced: AtomicBool::new(false),
+            segment_path: self.segment_path.clone(),
+            index_path: self.index_path.clone(),
+            time_index_path: self.time_index_path.clone(),
+            mark_deleted: AtomicBool::new(false),
+        }
     }
 
     pub fn scan_path(path: &Path) -> Option<String> {
@@ -189,34 +378,59 @@ impl Segment {
         if let Some(base_offset) = Self::parse_base_offset(filename) {
             let (_, file) = Storage::open_file_from_path(&path);
 
-            let size = file.metadata().ok()?.len();
+            let metadata = file.metadata().ok()?;
+            let size = metadata.len();
+            // the file's own mtime, so reopening does not make the segment new again
+            let last_write_ns = metadata
+                .modified()
+                .ok()
+                .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+                .map_or_else(now_ns, |since_epoch| since_epoch.as_nanos() as u64);
 
-            let dir = path.parent().unwrap();
-            let (index, index_file, mut last_offset) = Self::load_index_from_file(dir, base_offset);
+            let [index_path, time_index_path, header_index_path] = Self::index_paths(&path);
+            let (index, index_file, index_strategy, mut last_offset) = Self::load_index_from_file(&index_path);
+            let (time_index_file, max_timestamp) = Self::load_time_index_from_file(&time_index_path);
+            let (header_index, header_index_file) = Self::load_header_index_from_file(&header_index_path);
 
             let mut segment = Segment {
                 base_offset,
                 segment_path: path.clone(),
-                index_path: Self::index_path_from_base(base_offset, dir),
+                index_path,
                 file,
                 size,
                 index,
                 last_offset,
                 index_file,
-                index_interval: DEFAULT_INDEX_INTERVAL,
-                index_counter: DEFAULT_INDEX_INTERVAL,
-                last_write_ns: AtomicU64::new(now_ns()),
+                time_index_path,
+                time_index_file,
+                max_timestamp: max_timestamp.unwrap_or(0),
+                // when the file was created: record timestamps come from
+                // producers and say nothing about how long it has been written to
+                first_append_ns: (size > 0).then(|| created_ns(&metadata).unwrap_or(last_write_ns)),
+                time_index_max: max_timestamp.unwrap_or(0),
+                index_strategy,
+                header_index,
+                header_index_path,
+                header_index_file,
+                next_expiry: 0,
+                last_write_ns: AtomicU64::new(last_write_ns),
                 mark_deleted: AtomicBool::new(false),
             };
 
-            // Try recovering from beyond the last known offset
-            let resume_offset = last_offset + 1;
+            // Try recovering from the last indexed record on (it may be the one whose
+            // header index entry a crash cut off). Without a time index (segments
+            // written before it existed) rescan everything for timestamps.
+            let resume_offset = match max_timestamp {
+                Some(_) => last_offset,
+                None => base_offset,
+            };
 
-            if let Ok(iter) = segment.stream_from_offset(resume_offset) {
-                for msg in iter {
+            if let Ok(mut iter) = segment.stream_from_offset(resume_offset).map(SegmentIterator::with_expired) {
+                while let Some(msg) = iter.next() {
                     match msg {
-                        Ok((offset, _msg)) => {
-                            segment.last_offset = segment.last_offset.max(offset);
+                        Ok((offset, msg)) => {
+                            let entry_pos = iter.starts_entry().then(|| iter.record_position());
+                            segment.replay(offset, entry_pos, &msg);
                             last_offset = segment.last_offset;
                         }
                         Err(e) => {
@@ -236,79 +450,433 @@ impl Segment {
         }
     }
 
-    fn load_index_from_file(dir: &Path, base_offset: u64) -> (BTreeMap<u64, u64>, File, u64) {
-        let index_path = Self::index_path_from_base(base_offset, dir);
-        let (index_path_exists, index_file) = Storage::open_file_from_path(&index_path);
+    /// Catches the segment's state and index files up with a record that is
+    /// already in the log. `entry_pos` is the file position of the entry the
+    /// record starts, `None` for the later records of a batch, which are
+    /// never indexed. Entries lost in a crash are rebuilt with the segment's
+    /// own strategy, the same way `append` would have written them.
+    fn replay(&mut self, offset: u64, entry_pos: Option<u64>, message: &Message) {
+        self.last_offset = self.last_offset.max(offset);
+        self.max_timestamp = self.max_timestamp.max(message.timestamp);
+
+        let indexed = self.index.last_key_value().is_some_and(|(&last, _)| offset <= last);
+        if let Some(pos) = entry_pos.filter(|&pos| !indexed && self.should_index(offset, pos)) {
+            self.create_index(offset, pos);
+            if self.max_timestamp > self.time_index_max {
+                self.write_time_index_entry(self.max_timestamp, offset);
+            }
+        }
+        if !self.header_index.has_seen(offset) {
+            let _ = self.index_headers(offset, message);
+        }
+    }
+
+    /// Throws away the index files of the segment at `path` and rebuilds them
+    /// from its records with `index_strategy`, by the replay broker startup
+    /// uses. The header index keeps its names. Records past an unreadable one
+    /// are not indexed; truncate the log first to get rid of them.
+    pub fn rebuild_index_files(path: &Path, index_strategy: IndexStrategyConfig) -> std::io::Result<()> {
+        let invalid = |what: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{:?}: {}", path, what));
+        let filename = Self::scan_path(path).ok_or_else(|| invalid("not a segment file"))?;
+        Self::parse_base_offset(&filename).ok_or_else(|| invalid("no base offset in the name"))?;
+        let [index_path, time_index_path, header_index_path] = Self::index_paths(path);
+
+        let mut index_file = File::create(index_path)?;
+        Self::write_index_header(&mut index_file, index_strategy);
+
+        // without a time index the replay starts at the first record
+        if time_index_path.exists() {
+            std::fs::remove_file(&time_index_path)?;
+        }
+
+        if header_index_path.exists() {
+            match HeaderIndex::decode_file(&std::fs::read(&header_index_path)?) {
+                Some(index) => {
+                    let names = HeaderIndex::new(index.names().to_vec());
+                    std::fs::write(&header_index_path, names.encode_file_header())?;
+                }
+                // header queries scan this segment from now on
+                None => std::fs::remove_file(&header_index_path)?,
+            }
+        }
+
+        Self::recover_from_disk(path.to_path_buf(), &filename)
+            .map(|_| ())
+            .ok_or_else(|| invalid("could not reopen the segment"))
+    }
+
+    /// Loads the offset index and the strategy recorded in its header. Index
+    /// files from before headers existed were written every
+    /// `DEFAULT_INDEX_INTERVAL` messages; a missing or empty file gets a fresh
+    /// header with the default strategy.
+    fn load_index_from_file(index_path: &PathBuf) -> (BTreeMap<u64, u64>, File, Arc<dyn IndexStrategy>, u64) {
+        let (_, mut index_file) = Storage::open_file_from_path(index_path);
         let mut index = BTreeMap::new();
         let mut last_offset = 0;
 
-        if index_path_exists {
-            let mut reader = BufReader::new(&index_file);
+        let mut bytes = Vec::new();
+        let _ = (&index_file).read_to_end(&mut bytes);
+        if bytes.len() < INDEX_HEADER_LEN {
+            // nothing worth keeping, a torn entry at most
+            let strategy = IndexStrategyConfig::default();
+            let _ = index_file.set_len(0);
+            Self::write_index_header(&mut index_file, strategy);
+            return (index, index_file, strategy.build(), last_offset);
+        }
+
+        let decoded = IndexFile::decode(&bytes);
+        for &(offset, pos) in &decoded.entries {
+            index.insert(offset, pos);
+            last_offset = offset;
+        }
+
+        (index, index_file, decoded.build_strategy(), last_offset)
+    }
+
+    /// Reads an `.index` file as it is on disk, without fixing anything up.
+    pub fn read_index_file(path: &Path) -> std::io::Result<IndexFile> {
+        Ok(IndexFile::decode(&std::fs::read(path)?))
+    }
+
+    /// Loads the secondary header index, if the segment was written with one.
+    fn load_header_index_from_file(path: &Path) -> (HeaderIndex, Option<File>) {
+        if !path.exists() {
+            return (HeaderIndex::default(), None);
+        }
+        let (_, file) = Storage::open_file_from_path(&path.to_path_buf());
+        let mut bytes = Vec::new();
+        let _ = (&file).read_to_end(&mut bytes);
+        match HeaderIndex::decode_file(&bytes) {
+            Some(index) => (index, Some(file)),
+            None => {
+                tracing::warn!(path = ?path, "Unreadable header index, header queries will scan this segment");
+                (HeaderIndex::default(), None)
+            }
+        }
+    }
+
+    /// Returns the time index file and the largest timestamp recorded in it,
+    /// or `None` if the segment has no time index entries yet.
+    fn load_time_index_from_file(time_index_path: &PathBuf) -> (File, Option<u64>) {
+        let (exists, time_index_file) = Storage::open_file_from_path(time_index_path);
+        let mut max_timestamp = None;
+
+        if exists {
+            let mut reader = BufReader::new(&time_index_file);
             let mut buf = [0u8; 16];
             while reader.read_exact(&mut buf).is_ok() {
-                let offset = u64::from_be_bytes(
+                let timestamp = u64::from_be_bytes(
                     buf[0..8]
                         .try_into()
-                        .expect("index offset slice was not 8 bytes"),
-                );
-                let pos = u64::from_be_bytes(
-                    buf[8..16]
-                        .try_into()
-                        .expect("index position slice must be 8 bytes"),
+                        .expect("time index timestamp slice must be 8 bytes"),
                 );
-                index.insert(offset, pos);
-                last_offset = offset;
+                max_timestamp = Some(max_timestamp.unwrap_or(0).max(timestamp));
             }
         }
 
-        (index, index_file, last_offset)
+        (time_index_file, max_timestamp)
+    }
+}
+
+/// Decoded contents of an `.index` file.
+#[derive(Debug, Clone)]
+pub struct IndexFile {
+    pub strategy: Option<IndexStrategyConfig>, // None for files written before index headers
+    pub entries: Vec<(u64, u64)>,             // (offset, file position), in file order
+    pub trailing_bytes: usize,                // a torn entry at the end
+}
+
+impl IndexFile {
+    pub fn decode(bytes: &[u8]) -> IndexFile {
+        let header = bytes
+            .get(..INDEX_HEADER_LEN)
+            .and_then(|header| IndexStrategyConfig::decode_header(header.try_into().unwrap()));
+        let entries = match header {
+            Some(_) => &bytes[INDEX_HEADER_LEN..],
+            None => bytes,
+        };
+        let strategy = header.unwrap_or_default().build();
+        let chunks = entries.chunks_exact(strategy.entry_len());
+        let trailing_bytes = chunks.remainder().len();
+
+        IndexFile {
+            strategy: header,
+            entries: chunks.map(|entry| strategy.decode_entry(entry)).collect(),
+            trailing_bytes,
+        }
+    }
+
+    /// The strategy the file was written with; legacy files used the default.
+    pub fn build_strategy(&self) -> Arc<dyn IndexStrategy> {
+        self.strategy.unwrap_or_default().build()
     }
 }
 
+/// One entry of a segment file, as [`SegmentIterator::next_entry`] reads it.
+pub(crate) enum SegmentEntry {
+    Record(StoredRecord),
+    Batch(RecordBatch),
+}
+
 pub struct SegmentIterator {
     reader: BufReader<File>,
     offset: u64,
+    expired_at: Option<u64>, // records expired at this time (Unix ms) are skipped
     end_of_file: bool,
+    pos: u64,        // file position of the next entry
+    record_pos: u64, // file position of the entry the record returned last is in
+    pending: VecDeque<StoredRecord>, // records of the current entry not handed out yet
+    entry_unread: bool, // nothing of the current entry was handed out or skipped yet
+    starts_entry: bool, // the record returned last is the first of its entry
 }
 
-impl Iterator for SegmentIterator {
-    type Item = Result<(u64, Message), DeserializeError>;
+impl SegmentIterator {
+    /// Positions `file` at `start_pos`, as found by the segment's
+    /// [`IndexStrategy::lookup`]; records before `offset`, and records
+    /// expired by now, are skipped while iterating.
+    pub(crate) fn seek(mut file: File, start_pos: u64, offset: u64) -> Result<Self, DeserializeError> {
+        file.seek(SeekFrom::Start(start_pos))
+            .map_err(|e| DeserializeError::InvalidFormat(e.to_string()))?;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        while !self.end_of_file {
-            let mut len_buf = [0u8; 4];
-            if let Err(e) = self.reader.read_exact(&mut len_buf) {
+        Ok(SegmentIterator {
+            reader: BufReader::new(file),
+            offset,
+            expired_at: Some(now_ms()),
+            end_of_file: false,
+            pos: start_pos,
+            record_pos: start_pos,
+            pending: VecDeque::new(),
+            entry_unread: false,
+            starts_entry: false,
+        })
+    }
+
+    /// Returns expired records too, for recovery: offsets and indexes
+    /// account for every record in the file.
+    pub(crate) fn with_expired(mut self) -> Self {
+        self.expired_at = None;
+        self
+    }
+
+    /// File position where the entry holding the record returned by the last
+    /// `next()` starts: the record itself, or the batch it came in.
+    pub(crate) fn record_position(&self) -> u64 {
+        self.record_pos
+    }
+
+    /// Whether the record returned by the last `next()` is the first of its
+    /// entry, i.e. the one an index entry at `record_position` would name.
+    pub(crate) fn starts_entry(&self) -> bool {
+        self.starts_entry
+    }
+
+    /// Reads the next entry into `pending`. `Err(None)` at the end of the file.
+    fn read_entry(&mut self) -> Result<(), Option<DeserializeError>> {
+        let (len_word, entry) = self.read_raw_entry()?;
+        let (records, _) = StoredRecord::deserialize_entry(len_word, &entry).map_err(Some)?;
+        self.pending.extend(records);
+        self.entry_unread = true;
+        Ok(())
+    }
+
+    /// Reads the next entry as it is on disk: length prefix and bytes.
+    fn read_raw_entry(&mut self) -> Result<(u32, Vec<u8>), Option<DeserializeError>> {
+        let mut len_buf = [0u8; 4];
+        if let Err(e) = self.reader.read_exact(&mut len_buf) {
+            return Err(match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => None,
+                _ => Some(DeserializeError::InvalidFormat(e.to_string())),
+            });
+        }
+
+        let len_word = u32::from_be_bytes(len_buf);
+        let mut entry = vec![0u8; entry_len(len_word)];
+        self.reader
+            .read_exact(&mut entry)
+            .map_err(|e| Some(DeserializeError::InvalidFormat(e.to_string())))?;
+        self.record_pos = self.pos;
+        self.pos += 4 + entry.len() as u64;
+        Ok((len_word, entry))
+    }
+
+    /// Reads the next entry without decoding batches, for fetches that hand
+    /// them out as they are. Offsets, control records and expiry are left
+    /// to the caller; not to be mixed with `next()`.
+    pub(crate) fn next_entry(&mut self) -> Option<Result<SegmentEntry, DeserializeError>> {
+        if self.end_of_file {
+            return None;
+        }
+        let read = self.read_raw_entry().and_then(|(len_word, entry)| match len_word & BATCH_FLAG {
+            0 => StoredRecord::deserialize(&entry).map(SegmentEntry::Record).map_err(Some),
+            _ => RecordBatch::from_bytes(entry).map(SegmentEntry::Batch).map_err(Some),
+        });
+        match read {
+            Ok(entry) => Some(Ok(entry)),
+            Err(e) => {
                 self.end_of_file = true;
-                return match e.kind() {
-                    std::io::ErrorKind::UnexpectedEof => None,
-                    _ => Some(Err(DeserializeError::InvalidFormat(e.to_string()))),
-                };
+                e.map(Err)
             }
+        }
+    }
+}
 
-            let msg_len = u32::from_be_bytes(len_buf) as usize;
-            let mut msg_buf = vec![0u8; msg_len];
-            if let Err(e) = self.reader.read_exact(&mut msg_buf) {
-                self.end_of_file = true;
-                return Some(Err(DeserializeError::InvalidFormat(e.to_string())));
+/// Reads a segment from the end towards its start. The sparse index splits the
+/// file into chunks of about `DEFAULT_INDEX_INTERVAL` records; each chunk is
+/// read forward in one go and handed out newest first, then the iterator steps
+/// back to the previous index entry.
+pub struct SegmentBackwardIterator {
+    file: File,
+    chunk_starts: Vec<u64>, // file positions still to read, ascending, consumed from the back
+    chunk_end: u64,         // file position the next chunk to read stops at
+    max_offset: u64,        // inclusive
+    expired_at: u64,        // records expired at this time (Unix ms) are skipped
+    max_chunk_bytes: u64,
+    buffer: Vec<(u64, Message)>, // current chunk, ascending, consumed from the back
+}
+
+impl SegmentBackwardIterator {
+    pub(crate) fn open(path: &Path, index: &BTreeMap<u64, u64>, max_offset: u64) -> Result<Self, DeserializeError> {
+        let file = File::open(path).map_err(|e| DeserializeError::InvalidFormat(e.to_string()))?;
+        // the first index entry past `max_offset` bounds the read, records after it are never needed
+        let chunk_end = match index.range(max_offset.saturating_add(1)..).next() {
+            Some((_, &pos)) => pos,
+            None => file
+                .metadata()
+                .map_err(|e| DeserializeError::InvalidFormat(e.to_string()))?
+                .len(),
+        };
+        let mut chunk_starts: Vec<u64> = index.range(..=max_offset).map(|(_, &pos)| pos).collect();
+        if chunk_starts.first() != Some(&0) {
+            chunk_starts.insert(0, 0); // records before the first index entry
+        }
+
+        Ok(SegmentBackwardIterator {
+            file,
+            chunk_starts,
+            chunk_end,
+            max_offset,
+            expired_at: now_ms(),
+            max_chunk_bytes: BACKWARD_CHUNK_BYTES,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Record boundaries that cut the chunk from `start` into pieces of at most
+    /// `max_chunk_bytes`, ascending; a single larger record stays whole. Only
+    /// reads the length prefixes.
+    fn split_chunk(&self, start: u64) -> Result<Vec<u64>, DeserializeError> {
+        let io_error = |e: std::io::Error| DeserializeError::InvalidFormat(e.to_string());
+        let mut reader = BufReader::new(&self.file);
+        reader.seek(SeekFrom::Start(start)).map_err(io_error)?;
+
+        let mut boundaries = Vec::new();
+        let (mut pos, mut piece_start) = (start, start);
+        let mut len_bytes = [0u8; 4];
+        while pos < self.chunk_end {
+            reader.read_exact(&mut len_bytes).map_err(io_error)?;
+            let msg_len = entry_len(u32::from_be_bytes(len_bytes)) as u64;
+            let end = pos + 4 + msg_len;
+            if end - piece_start > self.max_chunk_bytes && pos > piece_start {
+                boundaries.push(pos);
+                piece_start = pos;
             }
-            return match StoredRecord::deserialize(&msg_buf) {
-                Ok(record) => {
-                    if record.offset < self.offset {
-                        continue; // skip stale message
+            reader.seek_relative(msg_len as i64).map_err(io_error)?;
+            pos = end;
+        }
+        Ok(boundaries)
+    }
+
+    fn read_chunk(&mut self, start: u64) -> Result<(), DeserializeError> {
+        let mut bytes = vec![0u8; self.chunk_end.saturating_sub(start) as usize];
+        self.file
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| self.file.read_exact(&mut bytes))
+            .map_err(|e| DeserializeError::InvalidFormat(e.to_string()))?;
+
+        let mut rest = &bytes[..];
+        while rest.len() >= 4 {
+            let len_word = u32::from_be_bytes(rest[..4].try_into().unwrap());
+            let msg_len = entry_len(len_word);
+            if rest.len() < 4 + msg_len {
+                return Err(DeserializeError::InvalidFormat(format!(
+                    "record at {} runs past the end of its chunk",
+                    self.chunk_end - rest.len() as u64
+                )));
+            }
+            let (records, _) = StoredRecord::deserialize_entry(len_word, &rest[4..4 + msg_len])?;
+            for record in records {
+                if record.offset <= self.max_offset && !record.is_control() && !record.message.is_expired(self.expired_at) {
+                    self.buffer.push((record.offset, record.message));
+                }
+            }
+            rest = &rest[4 + msg_len..];
+        }
+        self.chunk_end = start;
+        Ok(())
+    }
+}
+
+impl Iterator for SegmentBackwardIterator {
+    type Item = Result<(u64, Message), DeserializeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.buffer.pop() {
+                return Some(Ok(record));
+            }
+            let start = self.chunk_starts.pop()?;
+            if self.chunk_end.saturating_sub(start) > self.max_chunk_bytes {
+                match self.split_chunk(start) {
+                    Ok(boundaries) if !boundaries.is_empty() => {
+                        self.chunk_starts.push(start);
+                        self.chunk_starts.extend(boundaries);
+                        continue;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        self.chunk_starts.clear();
+                        return Some(Err(e));
                     }
+                }
+            }
+            if let Err(e) = self.read_chunk(start) {
+                self.chunk_starts.clear();
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl Iterator for SegmentIterator {
+    type Item = Result<(u64, Message), DeserializeError>;
 
-                    self.offset = record.offset + 1;
-                    Some(Ok((record.offset, record.message)))
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(record) = self.pending.pop_front() else {
+                if self.end_of_file {
+                    return None;
                 }
-                Err(e) => {
+                if let Err(e) = self.read_entry() {
                     self.end_of_file = true;
-                    Some(Err(e))
+                    return e.map(Err);
                 }
+                continue;
+            };
+            let first_in_entry = std::mem::replace(&mut self.entry_unread, false);
+            if record.offset < self.offset {
+                continue; // skip stale message
             }
 
+            self.offset = record.offset + 1;
+            if record.is_control() {
+                continue; // transaction markers are for the broker only
+            }
+            if self.expired_at.is_some_and(|now| record.message.is_expired(now)) {
+                continue;
+            }
+            self.starts_entry = first_in_entry;
+            return Some(Ok((record.offset, record.message)));
         }
-
-        None
     }
 }
 
@@ -326,6 +894,13 @@ impl fmt::Debug for Segment {
 }
 
 #[inline(always)]
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
 fn now_ns() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -333,6 +908,12 @@ fn now_ns() -> u64 {
         .as_nanos() as u64
 }
 
+/// Creation time of a file, where the filesystem keeps one.
+fn created_ns(metadata: &Metadata) -> Option<u64> {
+    let created = metadata.created().ok()?;
+    Some(created.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
+
 impl Drop for Segment {
     fn drop(&mut self) {
         // Only delete files if marked for deletion
@@ -352,8 +933,10 @@ impl Drop for Segment {
 
 #[cfg(test)]
 mod tests {
+    use crate::core::index_strategy::{DenseIndex, EveryNBytes, EveryNMessages, IndexStrategyConfig};
     use crate::core::storage::Storage;
     use std::io::Write;
+    use std::sync::Arc;
     use std::path::PathBuf;
     use flyq_protocol::message::Message;
     use crate::core::stored_record::StoredRecord;
@@ -376,7 +959,7 @@ mod tests {
         let log_path = dir.path().join("segment_00000000000000000000.log");
         let storage = Storage::new(PathBuf::from(dir.path()));
         // Append a few messages
-        let mut segment = Segment::new(0, &storage);
+        let mut segment = Segment::new(0, &storage, IndexStrategyConfig::default().build());
         for i in 0..3 {
             let msg = Message {
                 key: Some(format!("key-{}", i).into_bytes()),
@@ -384,9 +967,9 @@ mod tests {
                 timestamp: 1000 + i,
                 headers: None,
             };
-            let record = StoredRecord { offset: i, message: msg };
+            let record = StoredRecord { offset: i, message: msg, producer: None };
             let bytes = record.serialize();
-            segment.append(i, &bytes).unwrap();
+            segment.append(i, 1000 + i, &bytes).unwrap();
         }
 
         // Simulate crash — delete the index file
@@ -427,9 +1010,7 @@ mod tests {
         let storage = Storage::new(dir.path());
 
         // Create a segment with sparse index (index every 3 messages)
-        let mut segment = Segment::new(0, &storage);
-        segment.index_interval = 3;
-        segment.index_counter = 3;
+        let mut segment = Segment::new(0, &storage, Arc::new(EveryNMessages { interval: 3 }));
 
         for i in 0..5 {
             let msg = Message {
@@ -438,9 +1019,9 @@ mod tests {
                 timestamp: 1000 + i,
                 headers: None,
             };
-            let record = StoredRecord { offset: i, message: msg };
+            let record = StoredRecord { offset: i, message: msg, producer: None };
             let bytes = record.serialize();
-            segment.append(i, &bytes).unwrap();
+            segment.append(i, 1000 + i, &bytes).unwrap();
         }
 
         // Force flush index file
@@ -464,4 +1045,201 @@ mod tests {
 
         assert_eq!(messages, expected);
     }
+    /// Test: Backward scans of an unindexed stretch read it piece by piece
+    ///
+    /// ✅ Verifies:
+    ///    - No more than `max_chunk_bytes` of records are held at once
+    ///    - Records still come out newest first, none skipped
+    ///
+    #[test]
+    fn test_backward_chunks_are_bounded_without_index() {
+        use crate::core::segment::Segment;
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path());
+        let mut segment = Segment::new(0, &storage, Arc::new(EveryNMessages { interval: 1000 }));
+        let mut record_len = 0;
+        for i in 0..20 {
+            let msg = Message { key: None, value: format!("val-{:02}", i).into_bytes(), timestamp: 1000, headers: None };
+            let bytes = StoredRecord { offset: i, message: msg, producer: None }.serialize();
+            record_len = bytes.len() as u64;
+            segment.append(i, 1000, &bytes).unwrap();
+        }
+
+        let mut iter = segment.stream_backward_from(19).unwrap();
+        iter.max_chunk_bytes = 3 * record_len;
+        let mut offsets = Vec::new();
+        while let Some(item) = iter.next() {
+            offsets.push(item.unwrap().0);
+            assert!(iter.buffer.len() < 3);
+        }
+        assert_eq!(offsets, (0..20).rev().collect::<Vec<_>>());
+    }
+
+    /// Test: Largest record timestamp survives a restart
+    ///
+    /// Appends records with increasing timestamps, including one past the last sparse
+    /// index entry, then recovers the segment from disk.
+    ///
+    /// ✅ Verifies:
+    ///    - The time index persists the max timestamp at index points
+    ///    - Records after the last index entry are rescanned on recovery
+    ///    - Retention is measured from record time, not from the reload
+    ///
+    #[test]
+    fn test_segment_max_timestamp_survives_recovery() {
+        use crate::core::segment::Segment;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("segment_00000000000000000000.log");
+        let storage = Storage::new(dir.path());
+
+        let mut segment = Segment::new(0, &storage, Arc::new(EveryNMessages { interval: 2 }));
+
+        for i in 0..5 {
+            let msg = Message {
+                key: None,
+                value: format!("val-{}", i).into_bytes(),
+                timestamp: 5000 + i,
+                headers: None,
+            };
+            let record = StoredRecord { offset: i, message: msg, producer: None };
+            segment.append(i, 5000 + i, &record.serialize()).unwrap();
+        }
+        drop(segment);
+
+        let (_, _, recovered) =
+            Segment::recover_from_disk(log_path, "segment_00000000000000000000.log").unwrap();
+
+        assert_eq!(recovered.max_timestamp, 5004);
+        assert_eq!(
+            recovered.retention_timestamp(),
+            UNIX_EPOCH + Duration::from_millis(5004)
+        );
+    }
+
+    /// Test: Segments without record timestamps keep their age across a restart
+    ///
+    /// ✅ Verifies:
+    ///    - Retention falls back to the file mtime, not to the time of the reload
+    ///
+    #[test]
+    fn test_untimestamped_segment_ages_from_file_mtime() {
+        use crate::core::segment::Segment;
+        use std::time::{Duration, SystemTime};
+
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("segment_00000000000000000000.log");
+        let storage = Storage::new(dir.path());
+        let mut segment = Segment::new(0, &storage, Arc::new(DenseIndex));
+        let msg = Message { key: None, value: b"legacy".to_vec(), timestamp: 0, headers: None };
+        segment.append(0, 0, &StoredRecord { offset: 0, message: msg, producer: None }.serialize()).unwrap();
+        drop(segment);
+
+        let written = SystemTime::now() - Duration::from_secs(3 * 24 * 3600);
+        std::fs::File::options().write(true).open(&log_path).unwrap().set_modified(written).unwrap();
+
+        let (_, _, recovered) = Segment::recover_from_disk(log_path, "segment_00000000000000000000.log").unwrap();
+        assert_eq!(recovered.max_timestamp, 0);
+        let drift = recovered.retention_timestamp().duration_since(written).unwrap_or_default();
+        assert!(drift < Duration::from_secs(1), "aged from the reload: {:?}", drift);
+    }
+
+    /// Test: Index strategies decide density and survive recovery
+    ///
+    /// Writes the same records with each strategy, then simulates a crash that
+    /// loses the tail of a byte-interval index and recovers the segment.
+    ///
+    /// ✅ Verifies:
+    ///    - Dense indexes every record, message and byte intervals space entries out
+    ///    - The strategy is read back from the index header, not the defaults
+    ///    - Entries lost in the crash are rebuilt with the recorded strategy
+    ///
+    #[test]
+    fn test_index_strategies_recorded_in_segment() {
+        use crate::core::segment::Segment;
+
+        let write = |dir: &std::path::Path, strategy: Arc<dyn crate::core::index_strategy::IndexStrategy>| {
+            let storage = Storage::new(dir);
+            let mut segment = Segment::new(0, &storage, strategy);
+            for i in 0..20 {
+                let msg = Message {
+                    key: None,
+                    value: vec![b'x'; 50],
+                    timestamp: 1000 + i,
+                    headers: None,
+                };
+                let record = StoredRecord { offset: i, message: msg, producer: None };
+                segment.append(i, 1000 + i, &record.serialize()).unwrap();
+            }
+            segment
+        };
+
+        let dense_dir = tempfile::tempdir().unwrap();
+        assert_eq!(write(dense_dir.path(), Arc::new(DenseIndex)).index.len(), 20);
+        let messages_dir = tempfile::tempdir().unwrap();
+        let every_five = write(messages_dir.path(), Arc::new(EveryNMessages { interval: 5 }));
+        assert_eq!(every_five.index.keys().copied().collect::<Vec<_>>(), vec![0, 5, 10, 15]);
+
+        let bytes_dir = tempfile::tempdir().unwrap();
+        let by_bytes = write(bytes_dir.path(), Arc::new(EveryNBytes { interval: 300 }));
+        let positions: Vec<u64> = by_bytes.index.values().copied().collect();
+        assert!(positions.len() > 2 && positions.len() < 20);
+        assert!(positions.windows(2).all(|w| w[1] - w[0] >= 300));
+        let index_before = by_bytes.index.clone();
+        let index_path = by_bytes.index_path.clone();
+        drop(by_bytes);
+
+        // keep the header and the first entry only
+        let file = std::fs::OpenOptions::new().write(true).open(&index_path).unwrap();
+        file.set_len(32).unwrap();
+
+        let (_, next_offset, recovered) = Segment::recover_from_disk(
+            bytes_dir.path().join("segment_00000000000000000000.log"),
+            "segment_00000000000000000000.log",
+        )
+        .unwrap();
+        assert_eq!(next_offset, 20);
+        assert_eq!(recovered.index_strategy.config(), IndexStrategyConfig::EveryBytes { interval: 300 });
+        assert_eq!(recovered.index, index_before);
+
+        let (offset, _) = recovered.stream_from_offset(13).unwrap().next().unwrap().unwrap();
+        assert_eq!(offset, 13);
+    }
+
+    /// Test: Index files written before headers existed still load
+    ///
+    /// ✅ Verifies:
+    ///    - A header-less file is read as plain 16 byte entries
+    ///    - Such segments fall back to the default strategy
+    ///
+    #[test]
+    fn test_legacy_index_without_header() {
+        use crate::core::segment::Segment;
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path());
+        let mut segment = Segment::new(0, &storage, Arc::new(EveryNMessages { interval: 2 }));
+        for i in 0..5 {
+            let msg = Message { key: None, value: format!("val-{}", i).into_bytes(), timestamp: 1000 + i, headers: None };
+            let record = StoredRecord { offset: i, message: msg, producer: None };
+            segment.append(i, 1000 + i, &record.serialize()).unwrap();
+        }
+        let index_path = segment.index_path.clone();
+        let index_before = segment.index.clone();
+        drop(segment);
+
+        let bytes = std::fs::read(&index_path).unwrap();
+        std::fs::write(&index_path, &bytes[16..]).unwrap();
+
+        let (_, _, recovered) = Segment::recover_from_disk(
+            dir.path().join("segment_00000000000000000000.log"),
+            "segment_00000000000000000000.log",
+        )
+        .unwrap();
+        assert_eq!(recovered.index_strategy.config(), IndexStrategyConfig::default());
+        assert_eq!(recovered.index, index_before);
+        assert_eq!(recovered.stream_from_offset(3).unwrap().next().unwrap().unwrap().1.value, b"val-3");
+    }
 }
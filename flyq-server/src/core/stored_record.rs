@@ -1,4 +1,5 @@
 use flyq_protocol::errors::DeserializeError;
+use flyq_protocol::record_batch::{BatchHeader, RecordBatch, BATCH_FLAG};
 use flyq_protocol::{read_bytes, Message};
 
 /// Represents a message stored in a segment file, including its offset.
@@ -6,6 +7,45 @@ use flyq_protocol::{read_bytes, Message};
 pub struct StoredRecord {
     pub offset: u64,
     pub message: Message,
+    /// Set for records from idempotent producers, so their sequence state can
+    /// be rebuilt from the log on recovery.
+    pub producer: Option<ProducerStamp>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProducerStamp {
+    pub producer_id: u64,
+    pub producer_epoch: u16,
+    pub sequence: u32,
+    /// Written inside a transaction, invisible to `read_committed` readers
+    /// until the transaction commits.
+    pub transactional: bool,
+    /// A commit or abort marker the broker wrote for the producer, never
+    /// handed out to consumers.
+    pub control: bool,
+}
+
+/// Bytes a segment entry covers after its length prefix.
+pub fn entry_len(len_word: u32) -> usize {
+    (len_word & !BATCH_FLAG) as usize
+}
+
+const PRODUCER_STAMP_LEN: usize = 14;
+const TRANSACTIONAL_FLAG: u8 = 1;
+const CONTROL_FLAG: u8 = 2;
+
+/// How a transaction ended in a partition. Stored as the one-byte value of a
+/// control record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlMarker {
+    Abort = 0,
+    Commit = 1,
+}
+
+impl ControlMarker {
+    pub fn to_message(self, timestamp: u64) -> Message {
+        Message { key: None, value: vec![self as u8], timestamp, headers: None }
+    }
 }
 
 impl StoredRecord {
@@ -15,14 +55,27 @@ impl StoredRecord {
     /// [ record_len: u32 ]
     /// [ offset      : u64 ]
     /// [ message     : bytes from Message::serialize_for_disk() ]
+    /// [ producer_id: u64, producer_epoch: u16, sequence: u32, flags: u8 ] (idempotent producers only)
     pub fn serialize(&self) -> Vec<u8> {
         let message_bytes = self.message.serialize_for_wire();
-        let total_len = 8 + message_bytes.len(); // offset (8) + message content
+        let stamp_len = self.producer.map_or(0, |_| PRODUCER_STAMP_LEN + 1);
+        let total_len = 8 + message_bytes.len() + stamp_len; // offset (8) + message content + stamp
 
         let mut buf = Vec::with_capacity(4 + total_len);
         buf.extend_from_slice(&(total_len as u32).to_be_bytes()); // record length prefix
         buf.extend_from_slice(&self.offset.to_be_bytes());        // offset
         buf.extend_from_slice(&message_bytes);                    // message
+        if let Some(stamp) = &self.producer {
+            buf.extend_from_slice(&stamp.producer_id.to_be_bytes());
+            buf.extend_from_slice(&stamp.producer_epoch.to_be_bytes());
+            buf.extend_from_slice(&stamp.sequence.to_be_bytes());
+            let flags = match (stamp.transactional, stamp.control) {
+                (false, false) => 0,
+                (true, false) => TRANSACTIONAL_FLAG,
+                (_, true) => TRANSACTIONAL_FLAG | CONTROL_FLAG,
+            };
+            buf.push(flags);
+        }
         buf
     }
 
@@ -36,9 +89,59 @@ impl StoredRecord {
         let offset_bytes = read_bytes(&mut buf, 8)?;
         let offset = u64::from_be_bytes(offset_bytes.try_into().unwrap());
 
-        // message
-        let message = Message::deserialize_body(buf)?;
+        // message, then the producer stamp if the record has one
+        let (message, mut rest) = Message::deserialize_prefix(buf)?;
+        let producer = if rest.len() >= PRODUCER_STAMP_LEN {
+            let producer_id = u64::from_be_bytes(read_bytes(&mut rest, 8)?.try_into().unwrap());
+            let producer_epoch = u16::from_be_bytes(read_bytes(&mut rest, 2)?.try_into().unwrap());
+            let sequence = u32::from_be_bytes(read_bytes(&mut rest, 4)?.try_into().unwrap());
+            // records from before transactions have no flags
+            let flags = rest.first().copied().unwrap_or(0);
+            Some(ProducerStamp {
+                producer_id,
+                producer_epoch,
+                sequence,
+                transactional: flags & TRANSACTIONAL_FLAG != 0,
+                control: flags & CONTROL_FLAG != 0,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self { offset, message, producer })
+    }
+
+    /// Decodes one segment entry, `len_word` being its length prefix and
+    /// `buf` the bytes it covers: a single record, or every record of a v2
+    /// batch along with the batch header.
+    pub fn deserialize_entry(
+        len_word: u32,
+        buf: &[u8],
+    ) -> Result<(Vec<StoredRecord>, Option<BatchHeader>), DeserializeError> {
+        if len_word & BATCH_FLAG == 0 {
+            return Ok((vec![Self::deserialize(buf)?], None));
+        }
+        let batch = RecordBatch::from_bytes(buf.to_vec())?;
+        let records = batch
+            .records()?
+            .into_iter()
+            .map(|(offset, message)| StoredRecord { offset, message, producer: None })
+            .collect();
+        Ok((records, Some(batch.header())))
+    }
+
+    pub fn is_control(&self) -> bool {
+        self.producer.is_some_and(|stamp| stamp.control)
+    }
 
-        Ok(Self { offset, message })
+    /// The marker a control record carries.
+    pub fn control_marker(&self) -> Option<ControlMarker> {
+        if !self.is_control() {
+            return None;
+        }
+        match self.message.value.first() {
+            Some(1) => Some(ControlMarker::Commit),
+            _ => Some(ControlMarker::Abort),
+        }
     }
 }
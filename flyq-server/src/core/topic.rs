@@ -1,18 +1,23 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::RwLock;
 use xxhash_rust::xxh3::xxh3_64;
 use flyq_protocol::message::Message;
 use crate::core::partition::Partition;
+use crate::core::remote_storage::LocalFsRemoteStorage;
 use crate::core::storage::Storage;
-pub type SharedPartition = Arc<Mutex<Partition>>;
+use crate::broker_config;
+/// Readers (consume, watermarks, health) share the lock; appends, rolls and
+/// cleanup take it exclusively.
+pub type SharedPartition = Arc<RwLock<Partition>>;
 pub struct Topic {
     pub(crate) name: String,
-    pub partitions: HashMap<u32,Arc<Mutex<Partition>>>,
+    pub partitions: HashMap<u32,SharedPartition>,
     storage: Storage,
     partition_count: u32,
-    next_partition:u32, // used for partition tracking in round robin allocation
+    next_partition:AtomicU32, // used for partition tracking in round robin allocation
 }
 
 impl Topic {
@@ -23,8 +28,9 @@ impl Topic {
         let mut partitions: HashMap<u32,SharedPartition> =HashMap::new();
         for partition_id in 0..partition_count {
             let partition_path =  topic_path.join(format!("partition_{}",partition_id));
-            let p =Partition::open( partition_path, partition_id, max_segment_bytes).expect("could not create partition");
-            let shared_partition = Arc::new(Mutex::new(p));
+            let mut p =Partition::open( partition_path, partition_id, max_segment_bytes).expect("could not create partition");
+            Self::configure_partition(&name, &mut p);
+            let shared_partition = Arc::new(RwLock::new(p));
             partitions.insert(partition_id, shared_partition);
         }
         Topic {
@@ -32,7 +38,7 @@ impl Topic {
             partitions,
             storage,
             partition_count,
-            next_partition:0
+            next_partition:AtomicU32::new(0)
         }
     }
     
@@ -54,9 +60,10 @@ impl Topic {
             let entries = storage.scan_base();
             for entry in entries{
                 let path = entry.expect("could not open entry").path();
-                if let Some(partition) = Partition::scan_existing(path, max_segment_bytes){
+                if let Some(mut partition) = Partition::scan_existing(path, max_segment_bytes){
+                    Self::configure_partition(&name, &mut partition);
                     let part_id = partition.id;
-                    let shared_partition = Arc::new(Mutex::new(partition));
+                    let shared_partition = Arc::new(RwLock::new(partition));
                     partitions.insert(part_id, shared_partition);
                 }
             }
@@ -66,31 +73,66 @@ impl Topic {
                 partitions,
                 storage,
                 partition_count,
-                next_partition:0
+                next_partition:AtomicU32::new(0)
             })
         }else { 
             None
         }
     }
 
-    pub async fn produce(&mut self, msg: Message) -> std::io::Result<(u32, u64)> {
-        let partition_id = if let Some(key) = &msg.key{
+    pub async fn produce(&self, msg: Message) -> std::io::Result<(u32, u64)> {
+        let partition_id = self.route(&msg);
+        let partition = self.partitions.get(&partition_id).expect("Malformed partition map");
+        let offset = partition.write().await.append(&msg)?;
+        Ok((partition_id, offset))
+    }
+
+    /// Routes `msg` like [`Self::produce`] but holds it back until
+    /// `deliver_at`; returns the partition it will be appended to.
+    pub async fn schedule(&self, msg: Message, deliver_at: u64) -> std::io::Result<u32> {
+        let partition_id = self.route(&msg);
+        let partition = self.partitions.get(&partition_id).expect("Malformed partition map");
+        partition.write().await.schedule(&msg, deliver_at)?;
+        Ok(partition_id)
+    }
+
+    fn route(&self, msg: &Message) -> u32 {
+        if let Some(key) = &msg.key{
             self.hash_key_to_partition(key)
         }else {
-            let partition_id = self.next_partition;
-            self.next_partition = (self.next_partition + 1) % self.partition_count;
-            partition_id
-        };
-        
-        let partition = self.partitions.get_mut(&partition_id).expect("Malformed partition map");
-        let offset = partition.lock().await.append(&msg)?;
-        Ok((partition_id, offset))
+            self.next_partition.fetch_add(1, Ordering::Relaxed) % self.partition_count
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     pub fn hash_key_to_partition(&self, key: &[u8]) -> u32 {
         let hash = xxh3_64(key);
         (hash as u32) % self.partition_count
     }
+    /// Applies the broker config that partitions can't work out on their own.
+    fn configure_partition(name: &String, partition: &mut Partition) {
+        let cfg = broker_config();
+        partition.segment_roll_interval = cfg.segment_roll_interval_for(name);
+        partition.compression = cfg.compression_for(name);
+        partition
+            .set_index_strategy(cfg.index_strategy_for(name).build())
+            .expect("could not set index strategy");
+        partition
+            .set_indexed_headers(cfg.indexed_headers_for(name).to_vec())
+            .expect("could not set indexed headers");
+        if let Some(tiered) = &cfg.tiered_storage {
+            let store = LocalFsRemoteStorage::new(&tiered.remote_dir).expect("could not open remote storage");
+            // remote keys mirror the local directory layout
+            let prefix = format!("{}/partition_{}", Self::get_dir_name(name), partition.id);
+            partition
+                .attach_remote(Arc::new(store), prefix)
+                .expect("could not attach remote storage");
+        }
+    }
+
     fn get_dir_name(name: &String) -> String {
         format!("topic_{}", name)
     }
@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::core::error::EngineError;
+use crate::core::producer_state::{write_json, ProduceOutcome};
+use crate::core::stored_record::ControlMarker;
+
+pub const TRANSACTIONS_FILE: &str = "transactions.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionState {
+    Empty,
+    Ongoing,
+    /// Decided, markers not yet written to every partition. Finished on startup.
+    PrepareCommit,
+    PrepareAbort,
+}
+
+/// The one producer currently owning a transactional id, and its transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionEntry {
+    pub producer_id: u64,
+    pub producer_epoch: u16,
+    pub state: TransactionState,
+    pub partitions: BTreeSet<(String, u32)>, // (topic, partition)
+    /// Consumer group offsets applied to the offset tracker on commit.
+    #[serde(default)]
+    pub offsets: Vec<TxnOffset>,
+    /// When the open transaction began (Unix millis), for the transaction
+    /// timeout.
+    #[serde(default)]
+    pub started_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxnOffset {
+    pub group: String,
+    pub topic: String,
+    pub partition: u32,
+    pub offset: u64,
+}
+
+impl TransactionEntry {
+    /// The marker still owed to the partitions, if any.
+    pub fn pending_marker(&self) -> Option<ControlMarker> {
+        match self.state {
+            TransactionState::PrepareCommit => Some(ControlMarker::Commit),
+            TransactionState::PrepareAbort => Some(ControlMarker::Abort),
+            TransactionState::Empty | TransactionState::Ongoing => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TransactionError {
+    #[error("Producer fenced by a newer epoch")]
+    ProducerFenced,
+
+    #[error("Invalid transaction state: {0}")]
+    InvalidState(String),
+
+    #[error("Unknown transactional id")]
+    UnknownTransactionalId,
+
+    #[error(transparent)]
+    Engine(#[from] EngineError),
+}
+
+impl From<std::io::Error> for TransactionError {
+    fn from(e: std::io::Error) -> Self {
+        TransactionError::Engine(e.into())
+    }
+}
+
+/// Broker-wide table of transactional ids, persisted to `transactions.json`
+/// on every change. The markers themselves are written by the
+/// [`LogEngine`](crate::core::log_engine::LogEngine), which holds the
+/// coordinator lock while it does, so a transaction's state only moves on
+/// once every partition has its marker.
+#[derive(Debug)]
+pub struct TransactionCoordinator {
+    path: PathBuf,
+    transactions: BTreeMap<String, TransactionEntry>,
+}
+
+impl TransactionCoordinator {
+    pub fn load(base_dir: &Path) -> std::io::Result<Self> {
+        let path = base_dir.join(TRANSACTIONS_FILE);
+        let mut transactions: BTreeMap<String, TransactionEntry> = match path.exists() {
+            true => serde_json::from_reader(File::open(&path)?)?,
+            false => BTreeMap::new(),
+        };
+        // tables from before start times were kept: the clock starts now
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        for entry in transactions.values_mut() {
+            if entry.state == TransactionState::Ongoing && entry.started_at == 0 {
+                entry.started_at = now;
+            }
+        }
+        Ok(TransactionCoordinator { path, transactions })
+    }
+
+    pub fn get(&self, transactional_id: &str) -> Option<&TransactionEntry> {
+        self.transactions.get(transactional_id)
+    }
+
+    /// Transactions whose markers were not all written before the broker stopped.
+    pub fn prepared(&self) -> Vec<(String, TransactionEntry)> {
+        self.transactions
+            .iter()
+            .filter(|(_, entry)| entry.pending_marker().is_some())
+            .map(|(id, entry)| (id.clone(), entry.clone()))
+            .collect()
+    }
+
+    /// Transactions still open `timeout` after they began, at `now` (Unix millis).
+    pub fn expired(&self, now: u64, timeout: Duration) -> Vec<(String, TransactionEntry)> {
+        self.transactions
+            .iter()
+            .filter(|(_, entry)| {
+                entry.state == TransactionState::Ongoing
+                    && entry.started_at.saturating_add(timeout.as_millis() as u64) <= now
+            })
+            .map(|(id, entry)| (id.clone(), entry.clone()))
+            .collect()
+    }
+
+    /// Stores `entry` for `transactional_id` and persists the table.
+    pub fn put(&mut self, transactional_id: &str, entry: TransactionEntry) -> std::io::Result<()> {
+        self.transactions.insert(transactional_id.to_string(), entry);
+        write_json(&self.path, &self.transactions)
+    }
+
+    /// The entry of `transactional_id` if it is owned by this producer epoch.
+    pub fn owned(
+        &self,
+        transactional_id: &str,
+        producer_id: u64,
+        producer_epoch: u16,
+    ) -> Result<&TransactionEntry, TransactionError> {
+        let entry = self.get(transactional_id).ok_or(TransactionError::UnknownTransactionalId)?;
+        if entry.producer_id != producer_id || entry.producer_epoch != producer_epoch {
+            return Err(TransactionError::ProducerFenced);
+        }
+        Ok(entry)
+    }
+
+    /// Why the producer may not write a transactional record to the
+    /// partition right now, or `None` if it may.
+    pub fn check_produce(&self, producer_id: u64, producer_epoch: u16, topic: &str, partition: u32) -> Option<ProduceOutcome> {
+        let Some(entry) = self.transactions.values().find(|entry| entry.producer_id == producer_id) else {
+            return Some(ProduceOutcome::NotInTransaction);
+        };
+        if entry.producer_epoch > producer_epoch {
+            return Some(ProduceOutcome::Fenced { current_epoch: entry.producer_epoch });
+        }
+        let member = entry.partitions.contains(&(topic.to_string(), partition));
+        let open = entry.state == TransactionState::Ongoing && entry.producer_epoch == producer_epoch;
+        (!open || !member).then_some(ProduceOutcome::NotInTransaction)
+    }
+}
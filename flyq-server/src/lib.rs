@@ -3,7 +3,7 @@ mod config;
 
 use std::sync::OnceLock;
 
-pub use config::BrokerConfig;
+pub use config::{BrokerConfig, CompressionType, DeadLetterConfig, ScrubConfig, TieredStorageConfig, TimestampType, TopicConfig};
 
 /// is Filled by `main()` **once**; thereafter read-only everywhere.
 pub static BROKER_CONFIG: OnceLock<BrokerConfig> = OnceLock::new();
@@ -3,14 +3,12 @@ use anyhow::Result;
 use clap::Parser;
 use flyQ::core::log_engine::LogEngine;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tracing::info;
 use flyQ::{BROKER_CONFIG, BrokerConfig};
 
 mod runtime;
 mod server;
 pub mod types;
-mod config;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -29,7 +27,7 @@ async fn main() -> Result<()> {
 
     BROKER_CONFIG.set(cfg).expect("config initialised twice");
 
-    let engine = Arc::new(Mutex::new(LogEngine::load(&params.base_dir).await));
+    let engine = Arc::new(LogEngine::load(&params.base_dir).await);
 
     runtime::run(engine.clone(), shutdown_rx).await;
     tokio::select! {
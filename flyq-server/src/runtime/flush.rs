@@ -1,3 +1,5 @@
+use flyQ::core::compaction::compact_partition;
+use flyQ::core::remote_storage::offload_partition;
 use flyQ::core::offset_tracker::OffsetTracker;
 use std::sync::Arc;
 use std::sync::atomic::Ordering;
@@ -45,10 +47,9 @@ pub async fn run_periodic_metadata_flush(
     let mut ticker = tokio::time::interval(interval);
     
     async fn flush_meta_data(engine: &SharedLogEngine){
-        let engine_guard = engine.lock().await;
-        for topic in engine_guard.topics.values(){
+        for topic in engine.topics_snapshot(){
             for partition in topic.partitions.values(){
-                let partition = partition.lock().await;
+                let partition = partition.read().await;
                 if partition.meta_flush_pending.swap(false, Ordering::Relaxed){
                     if let Err(e) = partition.persist_meta() {
                         tracing::warn!(error = ?e, "Failed to persist metadata");
@@ -72,6 +73,89 @@ pub async fn run_periodic_metadata_flush(
     }
 }
 
+/// Appends scheduled messages to their partitions once due. Partitions with
+/// nothing due are only read-locked.
+pub async fn run_periodic_delivery(
+    engine: SharedLogEngine,
+    mut shutdown_rx: Receiver<()>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let now = chrono::Utc::now().timestamp_millis() as u64;
+                for topic in engine.topics_snapshot() {
+                    for partition in topic.partitions.values() {
+                        let due = partition.read().await.next_delivery().is_some_and(|at| at <= now);
+                        if !due {
+                            continue;
+                        }
+                        let mut partition = partition.write().await;
+                        if let Err(e) = partition.deliver_due(now) {
+                            tracing::warn!(error = ?e, partition = partition.id, "Failed to deliver delayed messages");
+                        }
+                    }
+                }
+            }
+            _ = shutdown_rx.changed() => {
+                break;
+            }
+        }
+    }
+}
+
+/// Aborts transactions open past the transaction timeout, fencing their
+/// producers.
+pub async fn run_periodic_transaction_expiry(
+    engine: SharedLogEngine,
+    mut shutdown_rx: Receiver<()>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let now = chrono::Utc::now().timestamp_millis() as u64;
+                if let Err(e) = engine.abort_expired_transactions(now).await {
+                    tracing::warn!(error = ?e, "Failed to abort timed out transactions");
+                }
+            }
+            _ = shutdown_rx.changed() => {
+                break;
+            }
+        }
+    }
+}
+
+pub async fn run_periodic_offload(
+    engine: SharedLogEngine,
+    mut shutdown_rx: Receiver<()>,
+    interval: Duration,
+    local_retention: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                for topic in engine.topics_snapshot() {
+                    for (id, partition) in &topic.partitions {
+                        if let Err(e) = offload_partition(partition, local_retention).await {
+                            tracing::warn!(error = ?e, partition = id, "Failed to offload segments");
+                        }
+                    }
+                }
+            }
+            _ = shutdown_rx.changed() => {
+                break;
+            }
+        }
+    }
+}
+
 pub async fn run_periodic_cleanup(
     engine: SharedLogEngine,
     mut shutdown_rx: Receiver<()>,
@@ -80,15 +164,26 @@ pub async fn run_periodic_cleanup(
     let mut ticker = tokio::time::interval(interval);
     
     async fn cleanup_partitions(engine: &SharedLogEngine) {
-        let mut engine_guard = engine.lock().await;
-        for topic in engine_guard.topics.values_mut() {
-            for partition in topic.partitions.values_mut() {
-                let mut partition = partition.lock().await;
-                if let Err(e) = partition.maybe_cleanup() {
+        // only the partition being cleaned is locked, produce/consume on the rest carry on
+        for topic in engine.topics_snapshot() {
+            for (&partition_id, partition) in &topic.partitions {
+                let protection = engine.consumer_protection(topic.name(), partition_id).await;
+                let mut partition = partition.write().await;
+                // seal idle active segments first so retention can reclaim them
+                if let Err(e) = partition.maybe_roll() {
+                    tracing::warn!(error = ?e, "Failed to roll segment");
+                }
+                if let Err(e) = partition.maybe_cleanup_protected(protection.as_ref()) {
                     tracing::warn!(error = ?e, "Failed to cleanup partition");
                 } else {
                     tracing::debug!("Partition cleanup completed");
                 }
+                drop(partition);
+
+                let now = chrono::Utc::now().timestamp_millis() as u64;
+                if let Err(e) = compact_partition(&topic.partitions[&partition_id], now).await {
+                    tracing::warn!(error = ?e, partition = partition_id, "Failed to compact expired records");
+                }
             }
         }
     }
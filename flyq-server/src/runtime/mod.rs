@@ -12,12 +12,13 @@ use crate::types::SharedLogEngine;
 use flyQ::broker_config;
 
 mod flush;
+mod scrub;
 
 pub async fn run(engine:SharedLogEngine ,shutdown_rx:Receiver<()>){
     let cfg = broker_config();
     
     // 1. Offset flush
-    let store = engine.lock().await.offset_tracker_handle();
+    let store = engine.offset_tracker_handle();
     tokio::spawn(flush::run_periodic_offset_flush(
         store,
         shutdown_rx.clone(),
@@ -36,7 +37,45 @@ pub async fn run(engine:SharedLogEngine ,shutdown_rx:Receiver<()>){
     let engine_clone_cleanup = Arc::clone(&engine);
     tokio::spawn(flush::run_periodic_cleanup(
         engine_clone_cleanup,
-        shutdown_rx,
+        shutdown_rx.clone(),
         cfg.cleanup_interval,
     ));
+
+    // 4. Segment offload (tiered storage)
+    if let Some(tiered) = &cfg.tiered_storage {
+        let engine_clone_offload = Arc::clone(&engine);
+        tokio::spawn(flush::run_periodic_offload(
+            engine_clone_offload,
+            shutdown_rx.clone(),
+            tiered.offload_interval,
+            tiered.local_retention,
+        ));
+    }
+
+    // 5. Delivery of delayed messages
+    let engine_clone_delivery = Arc::clone(&engine);
+    tokio::spawn(flush::run_periodic_delivery(
+        engine_clone_delivery,
+        shutdown_rx.clone(),
+        Duration::from_millis(100),
+    ));
+
+    // 6. Abort of timed out transactions
+    let engine_clone_txn = Arc::clone(&engine);
+    tokio::spawn(flush::run_periodic_transaction_expiry(
+        engine_clone_txn,
+        shutdown_rx.clone(),
+        Duration::from_secs(1),
+    ));
+
+    // 7. Integrity scrub of sealed segments
+    if let Some(scrub) = &cfg.scrub {
+        let engine_clone_scrub = Arc::clone(&engine);
+        tokio::spawn(scrub::run_periodic_scrub(
+            engine_clone_scrub,
+            shutdown_rx,
+            scrub.interval,
+            scrub.bytes_per_sec,
+        ));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,63 @@
+use flyQ::core::scrub::{scrub_partition, RateLimiter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch::Receiver;
+use tokio::time::MissedTickBehavior;
+use crate::types::SharedLogEngine;
+
+/// Re-reads every sealed segment once per `interval`, at no more than
+/// `bytes_per_sec`, and logs whatever looks corrupt. Results also show up in
+/// the partition health responses.
+pub async fn run_periodic_scrub(
+    engine: SharedLogEngine,
+    mut shutdown_rx: Receiver<()>,
+    interval: Duration,
+    bytes_per_sec: u64,
+) {
+    let limiter = Arc::new(RateLimiter::new(bytes_per_sec));
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut ticker = tokio::time::interval(interval);
+    // a slow pass delays the next one instead of starting them back to back
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let passes = async {
+        loop {
+            ticker.tick().await;
+            scrub_all(&engine, &limiter, &stop).await;
+        }
+    };
+    tokio::select! {
+        _ = passes => {}
+        _ = shutdown_rx.changed() => {
+            // the segment being read notices at its next record
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+async fn scrub_all(engine: &SharedLogEngine, limiter: &Arc<RateLimiter>, stop: &Arc<AtomicBool>) {
+    for topic in engine.topics_snapshot() {
+        for (&partition_id, partition) in &topic.partitions {
+            let Some(pass) = scrub_partition(partition, limiter, stop).await else {
+                return;
+            };
+            for problem in &pass.problems {
+                tracing::warn!(
+                    topic = topic.name(),
+                    partition = partition_id,
+                    segment = problem.segment,
+                    "Scrub found a problem: {}",
+                    problem.description
+                );
+            }
+            tracing::debug!(
+                topic = topic.name(),
+                partition = partition_id,
+                segments = pass.segments_scrubbed,
+                bytes = pass.bytes_scrubbed,
+                "Scrub pass completed"
+            );
+        }
+    }
+}
@@ -1,17 +1,29 @@
+use flyQ::broker_config;
+use flyQ::core::error::EngineError;
+use flyQ::core::header_index::HeaderQuery;
+use flyQ::core::producer_state::ProduceOutcome;
+use flyQ::core::retention::RetentionPolicy;
+use flyQ::core::stored_record::{ControlMarker, ProducerStamp};
+use flyQ::core::transaction::TransactionError;
 use crate::server::params::Params;
 use crate::types::SharedLogEngine;
 use anyhow::{Context, Result};
 use bytes::{Bytes, BytesMut};
-use flyq_protocol::message::Message;
+use flyq_protocol::message::{Message, RESERVED_HEADER_PREFIX};
 use flyq_protocol::{
-    CommitOffsetRequest, ConsumerLagRequest, ConsumerLagResponse, ConsumeRequest, ConsumeResponse,
-    ConsumeWithGroupRequest, Frame, FrameType, OpCode, PartitionHealthRequest,
-    PartitionHealthResponse, PartitionLag, ProduceAck, ProduceRequest, ProtocolError,
-    RequestPayload, ResponsePayload, WatermarkRequest, WatermarkResponse,
+    Acks, AddPartitionsToTxnRequest, CleanupRecord, CommitOffsetRequest, ConsumeBatchResponse, ConsumerLagRequest,
+    ConsumerLagResponse, ConsumeRequest, ConsumeResponse, ConsumeWithGroupRequest, DeleteRecordsRequest, DeleteRecordsResponse,
+    DryRunSegment, FetchRequest, FetchResponse, Frame, FrameType, HeaderQueryRequest, InitProducerIdRequest, InitProducerIdResponse,
+    LeaseRecordsRequest, LeaseRecordsResponse, LeasedRecord, OpCode,
+    PartitionHealthRequest, PartitionHealthResponse, PartitionLag, ProduceAck, ProduceBatchRequest, ProduceRequest, ProduceStatus, ProtocolError, RequestPayload, ResponsePayload,
+    RetentionDryRunRequest, RetentionDryRunResponse, ScrubProblemRecord, ScrubReport, SettleRecordsRequest,
+    SettleRecordsResponse, TransactionRequest,
+    TransactionResponse, TransactionStatus, TxnOffsetCommitRequest, WatermarkRequest, WatermarkResponse,
 };
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 pub async fn start(params: Params, engine: SharedLogEngine) -> Result<()> {
     info!("log engine initiated");
@@ -51,7 +63,9 @@ async fn handle_connection(mut stream: TcpStream, engine: SharedLogEngine) -> an
             }
 
             let request_payload = RequestPayload::deserialize(Bytes::from(frame.payload))?;
-            let response_payload = dispatch_request(request_payload, &engine).await?;
+            let Some(response_payload) = dispatch_request(request_payload, &engine).await? else {
+                continue; // acks=none produce, the client is not waiting for an answer
+            };
 
             let response_frame = Frame {
                 version: 1,
@@ -71,43 +85,194 @@ async fn handle_connection(mut stream: TcpStream, engine: SharedLogEngine) -> an
 async fn dispatch_request(
     request: RequestPayload,
     engine: &SharedLogEngine,
-) -> Result<ResponsePayload, ProtocolError> {
-    match request.op_code {
-        OpCode::Produce => handle_produce(request.data, engine).await,
+) -> Result<Option<ResponsePayload>, ProtocolError> {
+    let response = match request.op_code {
+        OpCode::Produce => return handle_produce(request.data, engine).await,
+        OpCode::ProduceBatch => return handle_produce_batch(request.data, engine).await,
+        OpCode::Fetch => handle_fetch(request.data, engine).await,
         OpCode::Consume => handle_consume(request.data, engine).await,
         OpCode::ConsumeWithGroup => handle_consume_with_group(request.data, engine).await,
         OpCode::CommitOffset => handle_commit_offset(request.data, engine).await,
         OpCode::Watermark => handle_watermark(request.data, engine).await,
+        OpCode::DeleteRecords => handle_delete_records(request.data, engine).await,
         OpCode::GetConsumerLag => handle_consumer_lag(request.data, engine).await,
         OpCode::GetPartitionHealth => handle_partition_health(request.data, engine).await,
-    }
+        OpCode::RetentionDryRun => handle_retention_dry_run(request.data, engine).await,
+        OpCode::QueryByHeader => handle_query_by_header(request.data, engine).await,
+        OpCode::InitProducerId => handle_init_producer_id(request.data, engine).await,
+        OpCode::BeginTransaction
+        | OpCode::AddPartitionsToTxn
+        | OpCode::TxnOffsetCommit
+        | OpCode::CommitTransaction
+        | OpCode::AbortTransaction => handle_transaction(request.op_code, request.data, engine).await,
+        OpCode::LeaseRecords => handle_lease_records(request.data, engine).await,
+        OpCode::AckRecords | OpCode::NackRecords => handle_settle_records(request.op_code, request.data, engine).await,
+    };
+    response.map(Some)
 }
 
 async fn handle_produce(
     data: Bytes,
     engine: &SharedLogEngine,
-) -> Result<ResponsePayload, ProtocolError> {
+) -> Result<Option<ResponsePayload>, ProtocolError> {
     let produce_req = ProduceRequest::deserialize(data)?;
-    let message = Message {
+    if produce_req.deliver_at.is_some() && produce_req.producer.is_some() {
+        return Err(ProtocolError::PayloadError("Delayed delivery is not supported for idempotent produces".into()));
+    }
+    let mut headers = produce_req.headers.iter().flatten();
+    if let Some((name, _)) = headers.find(|(name, _)| name.starts_with(RESERVED_HEADER_PREFIX)) {
+        return Err(ProtocolError::PayloadError(format!("Header {} is reserved for the broker", name)));
+    }
+    let (acks, timeout_ms) = (produce_req.acks, produce_req.timeout_ms);
+    let partition = produce_req.producer.map_or(0, |producer| producer.partition);
+
+    let write = tokio::spawn(write_produce(produce_req, engine.clone()));
+    answer_produce(OpCode::Produce, write, acks, timeout_ms, partition).await
+}
+
+async fn handle_produce_batch(
+    data: Bytes,
+    engine: &SharedLogEngine,
+) -> Result<Option<ResponsePayload>, ProtocolError> {
+    let req = ProduceBatchRequest::deserialize(data)?;
+    if req.producer.is_some() {
+        return Err(ProtocolError::PayloadError("Idempotent and transactional produces are not supported for record batches".into()));
+    }
+    let (acks, timeout_ms, partition) = (req.acks, req.timeout_ms, req.partition);
+
+    let engine = engine.clone();
+    let write = tokio::spawn(async move {
+        let offset = engine
+            .produce_batch(&req.topic, req.partition, req.batch)
+            .await
+            .map_err(|e| match e {
+                EngineError::Unsupported(_) => ProtocolError::PayloadError(e.to_string()),
+                e => ProtocolError::EngineErrorMapped(e.to_string()),
+            })?;
+        if req.acks == Acks::Fsync {
+            engine
+                .sync(&req.topic, req.partition)
+                .await
+                .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+        }
+        Ok(ProduceAck { partition: req.partition, offset, status: ProduceStatus::Ok })
+    });
+    answer_produce(OpCode::ProduceBatch, write, acks, timeout_ms, partition).await
+}
+
+/// Answers once the requested acks hold, or with a timeout status once the
+/// produce timeout passes. The write is not cancelled by the timeout: it runs
+/// in its own task and may still land. acks=none produces get no answer,
+/// not even when they fail: the error is only logged.
+async fn answer_produce(
+    op_code: OpCode,
+    write: tokio::task::JoinHandle<Result<ProduceAck, ProtocolError>>,
+    acks: Acks,
+    timeout_ms: u32,
+    partition: u32,
+) -> Result<Option<ResponsePayload>, ProtocolError> {
+    let timeout = match timeout_ms {
+        0 => broker_config().produce_timeout,
+        ms => Duration::from_millis(ms as u64),
+    };
+    let ack = match acks {
+        Acks::None => {
+            // nobody waits for an answer, and an error would close the connection
+            match write.await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!(error = %e, ?op_code, "acks=none produce failed"),
+                Err(e) => warn!(error = %e, ?op_code, "acks=none produce task failed"),
+            }
+            return Ok(None);
+        }
+        Acks::Leader | Acks::Fsync => match tokio::time::timeout(timeout, write).await {
+            Ok(written) => written.map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))??,
+            Err(_) => {
+                debug!(?acks, ?timeout, "produce timed out");
+                ProduceAck { partition, offset: 0, status: ProduceStatus::Timeout }
+            }
+        },
+    };
+
+    Ok(Some(ResponsePayload {
+        op_code,
+        data: ack.serialize(),
+    }))
+}
+
+async fn write_produce(produce_req: ProduceRequest, engine: SharedLogEngine) -> Result<ProduceAck, ProtocolError> {
+    let mut message = Message {
         key: None,
         value: produce_req.message.to_vec(),
         timestamp: chrono::Utc::now().timestamp_millis() as u64,
-        headers: None,
+        headers: produce_req.headers.filter(|headers| !headers.is_empty()),
     };
+    if let Some(expires_at) = produce_req.expires_at {
+        message.set_expires_at(expires_at);
+    }
 
-    //println!("{}", message.clone().serialize().len());
-    let (partition, offset) = engine
-        .lock()
-        .await
-        .produce(&produce_req.topic, message)
-        .await
-        .map_err(ProtocolError::IoError)?;
+    let now = message.timestamp;
+    let ack = match (produce_req.producer, produce_req.deliver_at) {
+        (None, Some(deliver_at)) if deliver_at > now => {
+            let partition = engine
+                .schedule(&produce_req.topic, message, deliver_at)
+                .await
+                .map_err(ProtocolError::IoError)?;
+            // the journal entry is synced already, whatever the acks
+            return Ok(ProduceAck { partition, offset: 0, status: ProduceStatus::Scheduled });
+        }
+        (Some(producer), _) => {
+            let stamp = ProducerStamp {
+                producer_id: producer.producer_id,
+                producer_epoch: producer.producer_epoch,
+                sequence: producer.sequence,
+                transactional: producer.transactional,
+                control: false,
+            };
+            let outcome = engine
+                .produce_idempotent(&produce_req.topic, producer.partition, message, stamp)
+                .await
+                .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+            let (offset, status) = match outcome {
+                ProduceOutcome::Appended(offset) => (offset, ProduceStatus::Ok),
+                ProduceOutcome::Duplicate(offset) => (offset, ProduceStatus::Duplicate),
+                ProduceOutcome::OutOfOrder { .. } => (0, ProduceStatus::OutOfOrderSequence),
+                ProduceOutcome::Fenced { .. } => (0, ProduceStatus::ProducerFenced),
+                ProduceOutcome::NotInTransaction => (0, ProduceStatus::NotInTransaction),
+            };
+            ProduceAck { partition: producer.partition, offset, status }
+        }
+        (None, _) => {
+            //println!("{}", message.clone().serialize().len());
+            let (partition, offset) = engine
+                .produce(&produce_req.topic, message)
+                .await
+                .map_err(ProtocolError::IoError)?;
+            ProduceAck { partition, offset, status: ProduceStatus::Ok }
+        }
+    };
 
-    let ack = ProduceAck { partition, offset };
+    // a duplicate's first append may not be on disk yet either
+    let written = matches!(ack.status, ProduceStatus::Ok | ProduceStatus::Duplicate);
+    if produce_req.acks == Acks::Fsync && written {
+        engine
+            .sync(&produce_req.topic, ack.partition)
+            .await
+            .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+    }
+    Ok(ack)
+}
 
+async fn handle_fetch(data: Bytes, engine: &SharedLogEngine) -> Result<ResponsePayload, ProtocolError> {
+    let req = FetchRequest::deserialize(data)?;
+    let (batches, log_end_offset) = engine
+        .fetch(&req.topic, req.partition, req.offset, req.max_bytes as usize, req.read_committed)
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+    let resp = FetchResponse { log_end_offset, batches: Bytes::from(batches) };
     Ok(ResponsePayload {
-        op_code: OpCode::Produce,
-        data: ack.serialize(),
+        op_code: OpCode::Fetch,
+        data: resp.serialize(),
     })
 }
 
@@ -116,15 +281,37 @@ async fn handle_consume(
     engine: &SharedLogEngine,
 ) -> Result<ResponsePayload, ProtocolError> {
     let consume_req = ConsumeRequest::deserialize(data)?;
+    if let Some(count) = consume_req.fetch_last {
+        let records = engine
+            .consume_backward(
+                &consume_req.topic,
+                consume_req.partition,
+                consume_req.offset,
+                count as usize,
+                consume_req.read_committed,
+            )
+            .await
+            .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+        let resp = ConsumeBatchResponse {
+            records: records
+                .into_iter()
+                .map(|(offset, message)| ConsumeResponse { offset, message })
+                .collect(),
+        };
+        return Ok(ResponsePayload {
+            op_code: OpCode::Consume,
+            data: resp.serialize(),
+        });
+    }
+
     let maybe_msg = engine
-        .lock()
-        .await
-        .consume(&consume_req.topic, 0, consume_req.offset)
+        .consume_record(&consume_req.topic, 0, consume_req.offset, consume_req.read_committed)
         .await
         .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
-    if let Some(msg) = maybe_msg {
+    // the record may sit past `offset`, e.g. after a transaction marker
+    if let Some((offset, msg)) = maybe_msg {
         let resp = ConsumeResponse {
-            offset: consume_req.offset,
+            offset,
             message: msg,
         };
         Ok(ResponsePayload {
@@ -145,12 +332,11 @@ async fn handle_consume_with_group(
 ) -> Result<ResponsePayload, ProtocolError> {
     let consume_req = ConsumeWithGroupRequest::deserialize(data)?;
     let maybe_msg = engine
-        .lock()
-        .await
         .consume_with_group(
             &consume_req.topic,
             consume_req.partition,
             &consume_req.group,
+            consume_req.read_committed,
         )
         .await
         .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
@@ -186,8 +372,6 @@ async fn handle_commit_offset(
 ) -> Result<ResponsePayload, ProtocolError> {
     let req = CommitOffsetRequest::deserialize(data)?;
     engine
-        .lock()
-        .await
         .commit_offset(&req.topic, req.partition, &req.group, req.offset)
         .await
         .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
@@ -209,15 +393,18 @@ async fn handle_watermark(
 ) -> Result<ResponsePayload, ProtocolError> {
     let req = WatermarkRequest::deserialize(data)?;
     let w = engine
-        .lock()
-        .await
         .get_watermark(&req.topic, req.partition)
         .await
         .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+    let last_stable_offset = engine
+        .last_stable_offset(&req.topic, req.partition)
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
     let resp = WatermarkResponse {
         low_watermark: w.0,
         high_watermark: w.1,
         log_end_offset: w.2,
+        last_stable_offset,
     };
     Ok(ResponsePayload {
         op_code: OpCode::Watermark,
@@ -225,14 +412,34 @@ async fn handle_watermark(
     })
 }
 
+async fn handle_delete_records(
+    data: Bytes,
+    engine: &SharedLogEngine,
+) -> Result<ResponsePayload, ProtocolError> {
+    let req = DeleteRecordsRequest::deserialize(data)?;
+    let low_watermark = engine
+        .delete_records(&req.topic, req.partition, req.offset)
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+
+    info!(
+        "Deleted records below offset={} for topic={}, partition={} (low watermark now {})",
+        req.offset, req.topic, req.partition, low_watermark
+    );
+
+    let resp = DeleteRecordsResponse { low_watermark };
+    Ok(ResponsePayload {
+        op_code: OpCode::DeleteRecords,
+        data: resp.serialize(),
+    })
+}
+
 async fn handle_consumer_lag(
     data: Bytes,
     engine: &SharedLogEngine,
 ) -> Result<ResponsePayload, ProtocolError> {
     let req = ConsumerLagRequest::deserialize(data)?;
     let (total_lag, partition_lags) = engine
-        .lock()
-        .await
         .get_consumer_lag(&req.consumer_group, req.topics)
         .await
         .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
@@ -265,23 +472,48 @@ async fn handle_partition_health(
     engine: &SharedLogEngine,
 ) -> Result<ResponsePayload, ProtocolError> {
     let req = PartitionHealthRequest::deserialize(data)?;
-    let (segment_count, total_size_bytes, low_watermark, high_watermark, log_end_offset, last_cleanup) =
-        engine
-            .lock()
-            .await
-            .get_partition_health(&req.topic, req.partition)
-            .await
-            .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+    let health = engine
+        .get_partition_health(&req.topic, req.partition)
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
     
     let resp = PartitionHealthResponse {
         topic: req.topic,
         partition: req.partition,
-        segment_count,
-        total_size_bytes,
-        low_watermark,
-        high_watermark,
-        log_end_offset,
-        last_cleanup,
+        segment_count: health.segment_count,
+        total_size_bytes: health.total_size_bytes,
+        low_watermark: health.low_watermark,
+        high_watermark: health.high_watermark,
+        log_end_offset: health.log_end_offset,
+        last_cleanup: health.last_cleanup(),
+        cleanup_history: health
+            .cleanup_history
+            .into_iter()
+            .map(|run| CleanupRecord {
+                timestamp_ns: run.timestamp_ns,
+                segments_removed: run.segments_removed,
+                bytes_freed: run.bytes_freed,
+                reason: run.reason,
+                low_watermark: run.low_watermark,
+            })
+            .collect(),
+        scrub: health.scrub.last_pass_ns.map(|last_pass_ns| ScrubReport {
+            last_pass_ns,
+            segments_scrubbed: health.scrub.segments_scrubbed,
+            bytes_scrubbed: health.scrub.bytes_scrubbed,
+            problems: health
+                .scrub
+                .problems
+                .iter()
+                .map(|problem| ScrubProblemRecord {
+                    segment_base_offset: problem.segment,
+                    detected_ns: problem.detected_ns,
+                    description: problem.description.clone(),
+                })
+                .collect(),
+        }),
+        delayed_messages: health.delayed_messages,
+        expired_records: health.expired_records,
     };
     
     Ok(ResponsePayload {
@@ -289,3 +521,313 @@ async fn handle_partition_health(
         data: resp.serialize(),
     })
 }
+
+async fn handle_retention_dry_run(
+    data: Bytes,
+    engine: &SharedLogEngine,
+) -> Result<ResponsePayload, ProtocolError> {
+    let req = RetentionDryRunRequest::deserialize(data)?;
+
+    let mut policy = RetentionPolicy::from_config(broker_config());
+    if let Some(ms) = req.retention_ms {
+        policy.retention = Duration::from_millis(ms);
+    }
+    if req.retention_bytes.is_some() {
+        policy.retention_bytes = req.retention_bytes;
+    }
+
+    let candidates = engine
+        .retention_dry_run(&req.topic, req.partition, &policy)
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+    let (current_low, _, _) = engine
+        .get_watermark(&req.topic, req.partition)
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+
+    let resp = RetentionDryRunResponse {
+        bytes_freed: candidates.iter().map(|c| c.size).sum(),
+        low_watermark: candidates
+            .last()
+            .map_or(current_low, |c| current_low.max(c.last_offset + 1)),
+        segments: candidates
+            .into_iter()
+            .map(|c| DryRunSegment {
+                base_offset: c.base_offset,
+                last_offset: c.last_offset,
+                size_bytes: c.size,
+                reason: c.detail,
+            })
+            .collect(),
+    };
+
+    Ok(ResponsePayload {
+        op_code: OpCode::RetentionDryRun,
+        data: resp.serialize(),
+    })
+}
+
+async fn handle_query_by_header(
+    data: Bytes,
+    engine: &SharedLogEngine,
+) -> Result<ResponsePayload, ProtocolError> {
+    let req = HeaderQueryRequest::deserialize(data)?;
+    let query = HeaderQuery {
+        name: req.header,
+        value: req.value,
+        start_offset: req.start_offset,
+        end_offset: req.end_offset,
+        start_time: req.start_time,
+        end_time: req.end_time,
+        max_results: req.max_results as usize,
+    };
+    let records = engine
+        .query_headers(&req.topic, req.partition, &query)
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+
+    debug!(
+        "query_by_header topic={}, partition={}, header={} => {} records",
+        req.topic,
+        req.partition,
+        query.name,
+        records.len()
+    );
+
+    let resp = ConsumeBatchResponse {
+        records: records
+            .into_iter()
+            .map(|(offset, message)| ConsumeResponse { offset, message })
+            .collect(),
+    };
+    Ok(ResponsePayload {
+        op_code: OpCode::QueryByHeader,
+        data: resp.serialize(),
+    })
+}
+
+async fn handle_init_producer_id(
+    data: Bytes,
+    engine: &SharedLogEngine,
+) -> Result<ResponsePayload, ProtocolError> {
+    let req = InitProducerIdRequest::deserialize(data)?;
+    let (producer_id, producer_epoch) = engine
+        .init_producer_id(req.transactional_id.as_deref())
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+    debug!(producer_id, producer_epoch, transactional_id = ?req.transactional_id, "init_producer_id");
+
+    let resp = InitProducerIdResponse { producer_id, producer_epoch };
+    Ok(ResponsePayload {
+        op_code: OpCode::InitProducerId,
+        data: resp.serialize(),
+    })
+}
+
+async fn handle_lease_records(
+    data: Bytes,
+    engine: &SharedLogEngine,
+) -> Result<ResponsePayload, ProtocolError> {
+    let req = LeaseRecordsRequest::deserialize(data)?;
+    let deliveries = engine
+        .lease_records(
+            &req.group,
+            &req.topic,
+            req.partition,
+            req.max_records as usize,
+            Duration::from_millis(req.visibility_timeout_ms as u64),
+        )
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+
+    let resp = LeaseRecordsResponse {
+        records: deliveries
+            .into_iter()
+            .map(|delivery| LeasedRecord {
+                offset: delivery.offset,
+                delivery_count: delivery.delivery_count,
+                message: delivery.message,
+            })
+            .collect(),
+    };
+    Ok(ResponsePayload {
+        op_code: OpCode::LeaseRecords,
+        data: resp.serialize(),
+    })
+}
+
+async fn handle_settle_records(
+    op_code: OpCode,
+    data: Bytes,
+    engine: &SharedLogEngine,
+) -> Result<ResponsePayload, ProtocolError> {
+    let req = SettleRecordsRequest::deserialize(data)?;
+    let ack = op_code == OpCode::AckRecords;
+    let (settled, committed_offset) = engine
+        .settle_records(&req.group, &req.topic, req.partition, &req.records, ack, &req.error)
+        .await
+        .map_err(|e| ProtocolError::EngineErrorMapped(e.to_string()))?;
+
+    Ok(ResponsePayload {
+        op_code,
+        data: SettleRecordsResponse { settled, committed_offset }.serialize(),
+    })
+}
+
+async fn handle_transaction(
+    op_code: OpCode,
+    data: Bytes,
+    engine: &SharedLogEngine,
+) -> Result<ResponsePayload, ProtocolError> {
+    let result = match op_code {
+        OpCode::AddPartitionsToTxn => {
+            let req = AddPartitionsToTxnRequest::deserialize(data)?;
+            let txn = &req.transaction;
+            engine
+                .add_partitions_to_transaction(&txn.transactional_id, txn.producer_id, txn.producer_epoch, &req.partitions)
+                .await
+        }
+        OpCode::TxnOffsetCommit => {
+            let req = TxnOffsetCommitRequest::deserialize(data)?;
+            let txn = &req.transaction;
+            engine
+                .add_offsets_to_transaction(&txn.transactional_id, txn.producer_id, txn.producer_epoch, &req.group, &req.offsets)
+                .await
+        }
+        _ => {
+            let req = TransactionRequest::deserialize(data)?;
+            let (id, producer_id, epoch) = (&req.transactional_id, req.producer_id, req.producer_epoch);
+            match op_code {
+                OpCode::BeginTransaction => engine.begin_transaction(id, producer_id, epoch).await,
+                OpCode::CommitTransaction => engine.end_transaction(id, producer_id, epoch, ControlMarker::Commit).await,
+                _ => engine.end_transaction(id, producer_id, epoch, ControlMarker::Abort).await,
+            }
+        }
+    };
+
+    let status = match result {
+        Ok(()) => TransactionStatus::Ok,
+        Err(TransactionError::ProducerFenced) => TransactionStatus::ProducerFenced,
+        Err(TransactionError::InvalidState(reason)) => {
+            debug!(?op_code, reason, "transaction request rejected");
+            TransactionStatus::InvalidState
+        }
+        Err(TransactionError::UnknownTransactionalId) => TransactionStatus::UnknownTransactionalId,
+        Err(TransactionError::Engine(e)) => return Err(ProtocolError::EngineErrorMapped(e.to_string())),
+    };
+    Ok(ResponsePayload {
+        op_code,
+        data: TransactionResponse { status }.serialize(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flyQ::core::log_engine::LogEngine;
+    use flyq_protocol::message::EXPIRES_AT_HEADER;
+    use flyq_protocol::{ProducerSequence, RecordBatch};
+    use std::sync::Arc;
+
+    /// An engine over a fresh directory, removed once the returned guard drops.
+    async fn engine() -> (tempfile::TempDir, SharedLogEngine) {
+        let dir = tempfile::Builder::new().prefix("flyq_test_").tempdir().unwrap();
+        let engine = Arc::new(LogEngine::load(dir.path()).await);
+        (dir, engine)
+    }
+
+    fn message(value: &str) -> Message {
+        Message {
+            key: None,
+            value: value.as_bytes().to_vec(),
+            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            headers: None,
+        }
+    }
+
+    fn produce_batch(messages: &[Message], producer: Option<ProducerSequence>) -> RequestPayload {
+        let req = ProduceBatchRequest {
+            topic: "metrics".into(),
+            partition: 0,
+            acks: Acks::Leader,
+            timeout_ms: 0,
+            batch: RecordBatch::encode(0, messages),
+            producer,
+        };
+        RequestPayload { op_code: OpCode::ProduceBatch, data: req.serialize() }
+    }
+
+    #[tokio::test]
+    async fn test_produce_batch_refuses_producer_sequences_and_delayed_records() {
+        let (_dir, engine) = engine().await;
+        let producer = ProducerSequence { producer_id: 0, producer_epoch: 0, partition: 0, sequence: 0, transactional: false };
+        for producer in [producer, ProducerSequence { transactional: true, ..producer }] {
+            let refused = dispatch_request(produce_batch(&[message("a")], Some(producer)), &engine).await;
+            assert!(matches!(refused, Err(ProtocolError::PayloadError(_))));
+        }
+
+        let mut later = message("later");
+        later.set_deliver_at(chrono::Utc::now().timestamp_millis() as u64 + 60_000);
+        let refused = dispatch_request(produce_batch(&[message("a"), later], None), &engine).await;
+        assert!(matches!(refused, Err(ProtocolError::PayloadError(_))));
+
+        // nothing was written or scheduled for the refused batches
+        let response = dispatch_request(produce_batch(&[message("a")], None), &engine).await.unwrap().unwrap();
+        assert_eq!(ProduceAck::deserialize(response.data).unwrap().offset, 0);
+        let partition = engine.partition("metrics", 0).unwrap();
+        assert_eq!(partition.read().await.next_delivery(), None);
+    }
+
+    fn produce(value: &str, headers: Option<Vec<(String, Vec<u8>)>>) -> RequestPayload {
+        let req = ProduceRequest {
+            topic: "orders".into(),
+            message: Bytes::copy_from_slice(value.as_bytes()),
+            producer: None,
+            acks: Acks::Leader,
+            timeout_ms: 0,
+            deliver_at: None,
+            expires_at: None,
+            headers,
+        };
+        RequestPayload { op_code: OpCode::Produce, data: req.serialize() }
+    }
+
+    fn query_by_header(value: &str) -> RequestPayload {
+        let req = HeaderQueryRequest {
+            topic: "orders".into(),
+            partition: 0,
+            header: "tenant".into(),
+            value: value.as_bytes().to_vec(),
+            start_offset: 0,
+            end_offset: None,
+            start_time: None,
+            end_time: None,
+            max_results: 100,
+        };
+        RequestPayload { op_code: OpCode::QueryByHeader, data: req.serialize() }
+    }
+
+    #[tokio::test]
+    async fn test_produced_headers_can_be_queried() {
+        let (_dir, engine) = engine().await;
+        let tenant = |name: &str| Some(vec![("tenant".to_string(), name.as_bytes().to_vec())]);
+        dispatch_request(produce("first", None), &engine).await.unwrap();
+        let partition = engine.partition("orders", 0).unwrap();
+        partition.write().await.set_indexed_headers(vec!["tenant".to_string()]).unwrap();
+        for (value, name) in [("a", "acme"), ("b", "globex"), ("c", "acme")] {
+            dispatch_request(produce(value, tenant(name)), &engine).await.unwrap();
+        }
+
+        let response = dispatch_request(query_by_header("acme"), &engine).await.unwrap().unwrap();
+        let records = ConsumeBatchResponse::deserialize(response.data).unwrap().records;
+        let found: Vec<(u64, &[u8])> = records.iter().map(|r| (r.offset, r.message.value.as_slice())).collect();
+        assert_eq!(found, vec![(1, b"a".as_slice()), (3, b"c".as_slice())]);
+        assert_eq!(records[0].message.headers, tenant("acme"));
+
+        // headers the broker sets itself cannot be sent
+        let forged = Some(vec![(EXPIRES_AT_HEADER.to_string(), 0u64.to_be_bytes().to_vec())]);
+        let refused = dispatch_request(produce("d", forged), &engine).await;
+        assert!(matches!(refused, Err(ProtocolError::PayloadError(_))));
+        assert_eq!(engine.partition("orders", 0).unwrap().read().await.get_watermark().2, 4);
+    }
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use flyQ::core::log_engine::LogEngine;
 
-pub type SharedLogEngine = Arc<Mutex<LogEngine>>;
\ No newline at end of file
+/// The engine locks internally (topic map, then per partition), so connections
+/// share it directly.
+pub type SharedLogEngine = Arc<LogEngine>;
\ No newline at end of file
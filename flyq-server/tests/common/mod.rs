@@ -1,3 +1,4 @@
+use flyq_protocol::Message;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -21,3 +22,15 @@ pub fn folder_to_use() -> PathBuf {
         .expect("failed to create temp dir")
         .into_path()
 }
+
+/// Record `i` of a test log: value `record-{i}`, no key, and a create time
+/// in 1970, so every sealed segment is past any sane retention.
+#[allow(dead_code)] // not every test crate writes these
+pub fn message(i: u64) -> Message {
+    Message {
+        key: None,
+        value: format!("record-{}", i).into_bytes(),
+        timestamp: 1000 + i,
+        headers: None,
+    }
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use std::collections::HashMap;
+use std::time::Duration;
+use common::folder_to_use;
+use flyQ::core::log_engine::LogEngine;
+use flyQ::{BrokerConfig, DeadLetterConfig, TopicConfig, BROKER_CONFIG};
+use flyq_protocol::{DeadLetter, Message};
+
+// Every test in this file installs the same config, so the OnceLock race is harmless
+fn install_config() {
+    let mut dead_letter = HashMap::new();
+    dead_letter.insert("workers".to_string(), DeadLetterConfig { max_attempts: 2, topic: None });
+    dead_letter.insert(
+        "billing".to_string(),
+        DeadLetterConfig { max_attempts: 1, topic: Some("billing-failures".to_string()) },
+    );
+    let mut topics = HashMap::new();
+    topics.insert("jobs".to_string(), TopicConfig { dead_letter, ..Default::default() });
+    let _ = BROKER_CONFIG.set(BrokerConfig { topics, ..Default::default() });
+}
+
+fn message(value: &str) -> Message {
+    Message {
+        key: Some(b"customer-7".to_vec()),
+        value: value.as_bytes().to_vec(),
+        timestamp: 1000,
+        headers: None,
+    }
+}
+
+const VISIBILITY: Duration = Duration::from_secs(30);
+
+/// Every record of `topic`, whichever partition it was routed to.
+async fn read_all(engine: &LogEngine, topic_name: &str) -> Vec<Message> {
+    let mut records = Vec::new();
+    let Some(topic) = engine.topic(topic_name) else { return records };
+    for partition in topic.partitions.keys() {
+        let mut offset = 0;
+        while let Some(message) = engine.consume(topic_name, *partition, offset).await.unwrap() {
+            records.push(message);
+            offset += 1;
+        }
+    }
+    records
+}
+
+#[tokio::test]
+async fn test_record_moves_to_dlq_after_max_attempts() {
+    install_config();
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("jobs", Some(1));
+    engine.produce("jobs", message("poison")).await.unwrap();
+    engine.produce("jobs", message("fine")).await.unwrap();
+
+    let leased = engine.lease_records("workers", "jobs", 0, 2, VISIBILITY).await.unwrap();
+    assert_eq!(leased.len(), 2);
+    engine.settle_records("workers", "jobs", 0, &[(1, 1)], true, "").await.unwrap();
+    engine.settle_records("workers", "jobs", 0, &[(0, 1)], false, "parse error").await.unwrap();
+    assert!(read_all(&engine, "jobs.dlq").await.is_empty());
+
+    // second and last attempt
+    let leased = engine.lease_records("workers", "jobs", 0, 2, VISIBILITY).await.unwrap();
+    assert_eq!((leased[0].offset, leased[0].delivery_count), (0, 2));
+    let (settled, committed) = engine
+        .settle_records("workers", "jobs", 0, &[(0, 2)], false, "parse error again")
+        .await
+        .unwrap();
+    assert_eq!((settled, committed), (1, 2));
+    assert!(engine.lease_records("workers", "jobs", 0, 2, VISIBILITY).await.unwrap().is_empty());
+
+    let dead = read_all(&engine, "jobs.dlq").await;
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].value, b"poison");
+    assert_eq!(dead[0].key.as_deref(), Some(&b"customer-7"[..]));
+    assert_eq!(
+        dead[0].dead_letter(),
+        Some(DeadLetter {
+            topic: "jobs".into(),
+            partition: 0,
+            offset: 0,
+            attempts: 2,
+            error: "parse error again".into(),
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_expired_lease_counts_as_failed_attempt() {
+    install_config();
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("jobs", Some(1));
+    engine.produce("jobs", message("slow")).await.unwrap();
+
+    // a group without a policy keeps redelivering
+    let leased = engine.lease_records("auditors", "jobs", 0, 1, Duration::from_millis(10)).await.unwrap();
+    assert_eq!(leased.len(), 1);
+
+    let leased = engine.lease_records("billing", "jobs", 0, 1, Duration::from_millis(10)).await.unwrap();
+    assert_eq!(leased.len(), 1);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert!(engine.lease_records("billing", "jobs", 0, 1, VISIBILITY).await.unwrap().is_empty());
+    let dead = read_all(&engine, "billing-failures").await;
+    assert_eq!(dead.len(), 1);
+    let dead_letter = dead[0].dead_letter().unwrap();
+    assert_eq!((dead_letter.attempts, dead_letter.error.as_str()), (1, "visibility timeout expired"));
+    assert!(engine.topic("jobs.dlq").is_none());
+
+    let leased = engine.lease_records("auditors", "jobs", 0, 1, VISIBILITY).await.unwrap();
+    assert_eq!((leased[0].offset, leased[0].delivery_count), (0, 2));
+}
+
+#[tokio::test]
+async fn test_attempts_survive_a_restart() {
+    install_config();
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("jobs", Some(1));
+    engine.produce("jobs", message("poison")).await.unwrap();
+
+    engine.lease_records("workers", "jobs", 0, 1, VISIBILITY).await.unwrap();
+    engine.settle_records("workers", "jobs", 0, &[(0, 1)], false, "parse error").await.unwrap();
+    engine.offset_tracker_handle().lock().await.flush_dirty_offsets().unwrap();
+    drop(engine);
+
+    let engine = LogEngine::load(&base_dir).await;
+    let leased = engine.lease_records("workers", "jobs", 0, 1, VISIBILITY).await.unwrap();
+    assert_eq!((leased[0].offset, leased[0].delivery_count), (0, 2));
+    let (_, committed) = engine
+        .settle_records("workers", "jobs", 0, &[(0, 2)], false, "parse error again")
+        .await
+        .unwrap();
+    assert_eq!(committed, 1);
+    let dead = read_all(&engine, "jobs.dlq").await;
+    assert_eq!(dead[0].dead_letter().unwrap().attempts, 2);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::folder_to_use;
+use flyQ::core::log_engine::LogEngine;
+use flyq_protocol::Message;
+
+fn message(value: &str) -> Message {
+    Message {
+        key: None,
+        value: value.as_bytes().to_vec(),
+        timestamp: 1000,
+        headers: None,
+    }
+}
+
+#[tokio::test]
+async fn test_delayed_messages_appear_once_due() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("reminders", Some(1));
+
+    engine.schedule("reminders", message("in-15-minutes"), 900_000).await.unwrap();
+    engine.schedule("reminders", message("in-5-minutes"), 300_000).await.unwrap();
+    engine.produce("reminders", message("now")).await.unwrap();
+
+    // only the immediate record is in the log, for plain and group consumers
+    assert_eq!(engine.get_partition_health("reminders", 0).await.unwrap().delayed_messages, 2);
+    assert_eq!(engine.consume("reminders", 0, 0).await.unwrap().unwrap().value, b"now");
+    assert!(engine.consume("reminders", 0, 1).await.unwrap().is_none());
+    engine.commit_offset("reminders", 0, "mailer", 1).await.unwrap();
+    assert!(engine.consume_with_group("reminders", 0, "mailer", false).await.unwrap().is_none());
+
+    let partition = engine.partition("reminders", 0).unwrap();
+    assert_eq!(partition.read().await.next_delivery(), Some(300_000));
+    assert_eq!(partition.write().await.deliver_due(300_000).unwrap(), 1);
+    let (offset, delivered) = engine.consume_with_group("reminders", 0, "mailer", false).await.unwrap().unwrap();
+    assert_eq!(offset, 1);
+    assert_eq!(delivered.value, b"in-5-minutes");
+    assert_eq!(delivered.deliver_at(), Some(300_000));
+    drop(partition);
+    drop(engine);
+
+    // the other one survives a restart
+    let engine = LogEngine::load(&base_dir).await;
+    assert_eq!(engine.get_partition_health("reminders", 0).await.unwrap().delayed_messages, 1);
+
+    let partition = engine.partition("reminders", 0).unwrap();
+    assert_eq!(partition.write().await.deliver_due(899_999).unwrap(), 0);
+    assert_eq!(partition.write().await.deliver_due(900_000).unwrap(), 1);
+    assert_eq!(engine.consume("reminders", 0, 2).await.unwrap().unwrap().value, b"in-15-minutes");
+    assert_eq!(engine.get_partition_health("reminders", 0).await.unwrap().delayed_messages, 0);
+}
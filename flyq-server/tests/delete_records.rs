@@ -0,0 +1,66 @@
+mod common;
+
+use common::{folder_to_use, message};
+use flyQ::core::partition::Partition;
+use flyq_protocol::errors::DeserializeError;
+
+#[tokio::test]
+async fn test_delete_records_advances_low_watermark_and_drops_segments() {
+    let dir = folder_to_use();
+    // Small segments so the log spans several files
+    let mut partition = Partition::open(dir.clone(), 0, 100).unwrap();
+
+    for i in 0..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    let segments_before = partition.segment_count();
+    assert!(segments_before > 2, "Expected several segments");
+
+    let low = partition.delete_records(12).unwrap();
+    assert_eq!(low, 12);
+
+    let (low_watermark, high_watermark, log_end_offset) = partition.get_watermark();
+    assert_eq!(low_watermark, 12);
+    assert_eq!(high_watermark, 19);
+    assert_eq!(log_end_offset, 20);
+
+    // Whole segments below the new start are gone, the rest is still readable
+    assert!(partition.segment_count() < segments_before);
+    assert!(partition.sealed.values().all(|s| s.last_offset >= 12));
+    let messages = partition.read_from_offset(12).unwrap();
+    assert_eq!(messages.len(), 8);
+    assert_eq!(messages[0].value, b"record-12");
+
+    // Reads below the log start offset are rejected
+    match partition.read_from_offset(3) {
+        Err(DeserializeError::OffsetOutOfRange { offset, low_watermark }) => {
+            assert_eq!(offset, 3);
+            assert_eq!(low_watermark, 12);
+        }
+        other => panic!("expected out of range error, got {:?}", other.map(|m| m.len())),
+    }
+
+    // The new start offset is persisted and survives a reload
+    drop(partition);
+    let reopened = Partition::open(dir, 0, 100).unwrap();
+    assert_eq!(reopened.get_watermark().0, 12);
+}
+
+#[tokio::test]
+async fn test_delete_records_never_moves_backwards_or_past_log_end() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir, 0, 100).unwrap();
+
+    for i in 0..5 {
+        partition.append(&message(i)).unwrap();
+    }
+
+    assert_eq!(partition.delete_records(3).unwrap(), 3);
+    assert_eq!(partition.delete_records(1).unwrap(), 3);
+    assert!(partition.delete_records(6).is_err());
+
+    // Purging everything rolls the active segment so no record stays on disk
+    assert_eq!(partition.delete_records(5).unwrap(), 5);
+    assert_eq!(partition.segment_count(), 1);
+    assert_eq!(partition.total_size_bytes(), 0);
+}
@@ -9,7 +9,7 @@ use crate::common::folder_to_use;
 #[tokio::test]
 async fn test_create_topic_creates_expected_folders_and_metadata() {
     let base_dir = folder_to_use();
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
     let topic_name = "test";
     let partition_count = 2;
 
@@ -48,7 +48,7 @@ async fn test_create_topic_creates_expected_folders_and_metadata() {
     }
 
     assert_eq!(
-        engine.topics.len(),
+        engine.topics_snapshot().len(),
         1,
         "Expected exactly one topic in engine after creation"
     );
@@ -58,7 +58,7 @@ async fn test_create_topic_creates_expected_folders_and_metadata() {
 async fn produce_creates_topic_and_segment_if_missing() {
     
     let base_dir = folder_to_use();
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
 
     let topic_name = "clicks";
     let msg = Message {
@@ -84,14 +84,14 @@ async fn produce_creates_topic_and_segment_if_missing() {
     assert!(segment_file.is_file(), "Segment file not created");
 
     // ASSERT: in-memory topic is registered
-    assert!(engine.topics.contains_key(topic_name));
+    assert!(engine.topic(topic_name).is_some());
     assert_eq!(offset, 0);
 }
 
 #[tokio::test]
 async fn test_engine_consume_returns_produced_message() {
     let base_dir = folder_to_use();
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
 
 
     let topic = "events";
@@ -120,7 +120,7 @@ async fn test_engine_consume_returns_produced_message() {
 #[tokio::test]
 async fn test_consume_past_end_returns_none() {
     let base_dir = folder_to_use();
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
 
     let topic = "orders";
     let msg = Message {
@@ -145,6 +145,42 @@ async fn test_consume_past_end_returns_none() {
     );
 }
 
+#[tokio::test]
+async fn busy_partition_does_not_block_other_topics() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("slow", Some(1));
+    engine.create_topic("fast", Some(1));
+
+    let msg = Message {
+        key: None,
+        value: b"hello".to_vec(),
+        timestamp: 100,
+        headers: None,
+    };
+    engine.produce("fast", msg.clone()).await.expect("produce failed");
+
+    // Hold one partition exclusively, as a long cleanup pass would
+    let slow = engine.partition("slow", 0).unwrap();
+    let _cleanup_guard = slow.write().await;
+
+    let timeout = std::time::Duration::from_secs(1);
+    let (_, offset) = tokio::time::timeout(timeout, engine.produce("fast", msg))
+        .await
+        .expect("produce to another topic blocked")
+        .expect("produce failed");
+    assert_eq!(offset, 1);
+
+    // Readers of the same partition share it
+    let fast = engine.partition("fast", 0).unwrap();
+    let _reader = fast.read().await;
+    let consumed = tokio::time::timeout(timeout, engine.consume("fast", 0, 0))
+        .await
+        .expect("concurrent read blocked")
+        .expect("consume failed");
+    assert!(consumed.is_some());
+}
+
 /*
 TODO: add following cases
 1. consume() before any message is produced → Ok(None)
@@ -155,3 +191,33 @@ TODO: add following cases
 
  
 */
+
+#[tokio::test]
+async fn test_consume_backward_returns_latest_records_newest_first() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("events", Some(1));
+
+    for i in 0..30u64 {
+        let msg = Message {
+            key: None,
+            value: format!("event-{}", i).into_bytes(),
+            timestamp: 100 + i,
+            headers: None,
+        };
+        engine.produce("events", msg).await.expect("produce failed");
+    }
+
+    // "latest 5": ask from the log end
+    let (_, _, log_end) = engine.get_watermark("events", 0).await.unwrap();
+    let latest = engine.consume_backward("events", 0, log_end, 5, false).await.unwrap();
+    let offsets: Vec<u64> = latest.iter().map(|(offset, _)| *offset).collect();
+    assert_eq!(offsets, vec![29, 28, 27, 26, 25]);
+    assert_eq!(latest[0].1.value, b"event-29");
+
+    // the offset itself is excluded, and the scan stops at the log start
+    let before = engine.consume_backward("events", 0, 3, 10, false).await.unwrap();
+    let offsets: Vec<u64> = before.iter().map(|(offset, _)| *offset).collect();
+    assert_eq!(offsets, vec![2, 1, 0]);
+    assert!(engine.consume_backward("events", 0, 0, 10, false).await.unwrap().is_empty());
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::folder_to_use;
+use flyQ::core::header_index::HeaderQuery;
+use flyQ::core::partition::Partition;
+use flyq_protocol::Message;
+
+fn message(i: u64, tenant: &str) -> Message {
+    Message {
+        key: None,
+        value: format!("record-{}", i).into_bytes(),
+        timestamp: 1000 + i,
+        headers: Some(vec![
+            ("tenant".to_string(), tenant.as_bytes().to_vec()),
+            ("seq".to_string(), i.to_string().into_bytes()),
+        ]),
+    }
+}
+
+fn tenant_of(i: u64) -> &'static str {
+    if i.is_multiple_of(3) { "acme" } else { "globex" }
+}
+
+fn query(name: &str, value: &str) -> HeaderQuery {
+    HeaderQuery {
+        name: name.to_string(),
+        value: value.as_bytes().to_vec(),
+        start_offset: 0,
+        end_offset: None,
+        start_time: None,
+        end_time: None,
+        max_results: usize::MAX,
+    }
+}
+
+fn offsets(results: &[(u64, Message)]) -> Vec<u64> {
+    results.iter().map(|(offset, _)| *offset).collect()
+}
+
+#[test]
+fn test_query_headers_across_segments_and_ranges() {
+    // 100 byte segments, so the records spread over several of them
+    let mut partition = Partition::open(folder_to_use(), 0, 100).unwrap();
+    partition.set_indexed_headers(vec!["tenant".to_string()]).unwrap();
+    for i in 0..30 {
+        partition.append(&message(i, tenant_of(i))).unwrap();
+    }
+    assert!(partition.segment_count() > 2);
+
+    let acme: Vec<u64> = (0..30).filter(|i| i % 3 == 0).collect();
+    let results = partition.query_headers(&query("tenant", "acme")).unwrap();
+    assert_eq!(offsets(&results), acme);
+    assert_eq!(results[1].1.value, b"record-3");
+
+    let mut by_offset = query("tenant", "acme");
+    by_offset.start_offset = 5;
+    by_offset.end_offset = Some(15);
+    assert_eq!(offsets(&partition.query_headers(&by_offset).unwrap()), vec![6, 9, 12]);
+
+    let mut by_time = query("tenant", "acme");
+    by_time.start_time = Some(1010);
+    by_time.end_time = Some(1022);
+    assert_eq!(offsets(&partition.query_headers(&by_time).unwrap()), vec![12, 15, 18, 21]);
+
+    let mut limited = query("tenant", "acme");
+    limited.max_results = 2;
+    assert_eq!(offsets(&partition.query_headers(&limited).unwrap()), vec![0, 3]);
+
+    // not indexed: answered by scanning
+    assert_eq!(offsets(&partition.query_headers(&query("seq", "7")).unwrap()), vec![7]);
+    assert!(partition.query_headers(&query("tenant", "initech")).unwrap().is_empty());
+
+    // deleted records are not returned
+    partition.delete_records(10).unwrap();
+    assert_eq!(offsets(&partition.query_headers(&query("tenant", "acme")).unwrap()), vec![12, 15, 18, 21, 24, 27]);
+}
+
+#[test]
+fn test_header_index_survives_reopen() {
+    let dir = folder_to_use();
+    {
+        let mut partition = Partition::open(dir.clone(), 0, 100).unwrap();
+        partition.set_indexed_headers(vec!["tenant".to_string()]).unwrap();
+        for i in 0..20 {
+            partition.append(&message(i, tenant_of(i))).unwrap();
+        }
+    }
+
+    let mut partition = Partition::open(dir, 0, 100).unwrap();
+    let globex: Vec<u64> = (0..20).filter(|i| i % 3 != 0).collect();
+    assert_eq!(offsets(&partition.query_headers(&query("tenant", "globex")).unwrap()), globex);
+
+    // records appended after recovery land in the same index
+    partition.set_indexed_headers(vec!["tenant".to_string()]).unwrap();
+    partition.append(&message(20, "globex")).unwrap();
+    let results = partition.query_headers(&query("tenant", "globex")).unwrap();
+    assert_eq!(offsets(&results).last(), Some(&20));
+    assert_eq!(results.len(), globex.len() + 1);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::folder_to_use;
+use flyQ::core::log_engine::LogEngine;
+use flyQ::core::partition::Partition;
+use flyQ::core::producer_state::{ProduceOutcome, PRODUCER_STATE_FILE};
+use flyQ::core::stored_record::ProducerStamp;
+use flyq_protocol::Message;
+
+fn message(i: u32) -> Message {
+    Message {
+        key: None,
+        value: format!("payment-{}", i).into_bytes(),
+        timestamp: 1000 + i as u64,
+        headers: None,
+    }
+}
+
+fn stamp(sequence: u32) -> ProducerStamp {
+    ProducerStamp { producer_id: 7, producer_epoch: 0, sequence, transactional: false, control: false }
+}
+
+#[test]
+fn test_idempotent_appends_survive_restart() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 100).unwrap();
+    partition.append(&message(100)).unwrap(); // plain produce, not tracked
+    for i in 0..10 {
+        assert_eq!(partition.append_idempotent(&message(i), stamp(i)).unwrap(), ProduceOutcome::Appended(i as u64 + 1));
+    }
+    assert!(partition.sealed.len() > 1);
+
+    // a retry after a lost ack is acked again with the original offset
+    assert_eq!(partition.append_idempotent(&message(9), stamp(9)).unwrap(), ProduceOutcome::Duplicate(10));
+    assert_eq!(
+        partition.append_idempotent(&message(12), stamp(12)).unwrap(),
+        ProduceOutcome::OutOfOrder { expected: 10 }
+    );
+    assert_eq!(partition.get_watermark().2, 11);
+
+    // snapshot, then two more appends only the log knows about
+    partition.persist_meta().unwrap();
+    assert!(dir.join(PRODUCER_STATE_FILE).exists());
+    partition.append_idempotent(&message(10), stamp(10)).unwrap();
+    partition.append_idempotent(&message(11), stamp(11)).unwrap();
+    drop(partition);
+
+    let mut partition = Partition::open(dir.clone(), 0, 100).unwrap();
+    assert_eq!(partition.append_idempotent(&message(11), stamp(11)).unwrap(), ProduceOutcome::Duplicate(12));
+    assert_eq!(partition.append_idempotent(&message(10), stamp(10)).unwrap(), ProduceOutcome::Duplicate(11));
+    assert_eq!(partition.append_idempotent(&message(12), stamp(12)).unwrap(), ProduceOutcome::Appended(13));
+    drop(partition);
+
+    // without a snapshot the whole log is replayed
+    std::fs::remove_file(dir.join(PRODUCER_STATE_FILE)).unwrap();
+    let mut partition = Partition::open(dir, 0, 100).unwrap();
+    assert_eq!(partition.append_idempotent(&message(12), stamp(12)).unwrap(), ProduceOutcome::Duplicate(13));
+    assert_eq!(partition.append_idempotent(&message(13), stamp(13)).unwrap(), ProduceOutcome::Appended(14));
+
+    // a new epoch of the producer fences the old one
+    let new_epoch = ProducerStamp { producer_id: 7, producer_epoch: 1, sequence: 0, transactional: false, control: false };
+    assert_eq!(partition.append_idempotent(&message(0), new_epoch).unwrap(), ProduceOutcome::Appended(15));
+    assert_eq!(
+        partition.append_idempotent(&message(14), stamp(14)).unwrap(),
+        ProduceOutcome::Fenced { current_epoch: 1 }
+    );
+}
+
+#[tokio::test]
+async fn test_producer_ids_are_never_reused() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    let first = engine.init_producer_id(None).await.unwrap();
+    let second = engine.init_producer_id(None).await.unwrap();
+    assert_ne!(first.0, second.0);
+    drop(engine);
+
+    let engine = LogEngine::load(&base_dir).await;
+    let third = engine.init_producer_id(None).await.unwrap();
+    assert!(third.0 > second.0);
+
+    let outcome = engine
+        .produce_idempotent("payments", 0, message(0), ProducerStamp { producer_id: third.0, producer_epoch: third.1, sequence: 0, transactional: false, control: false })
+        .await
+        .unwrap();
+    assert_eq!(outcome, ProduceOutcome::Appended(0));
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::folder_to_use;
+use flyQ::core::inspect::{check_meta, DerivedWatermarks, Finding, RecordScanner, SegmentCheck};
+use flyQ::core::partiton_meta::PartitionMeta;
+use flyQ::core::partition::Partition;
+use flyQ::core::segment::Segment;
+use flyq_protocol::Message;
+use std::path::Path;
+
+fn message(i: u64) -> Message {
+    Message {
+        key: Some(format!("key-{}", i).into_bytes()),
+        value: format!(r#"{{"n":{}}}"#, i).into_bytes(),
+        timestamp: 1000 + i,
+        headers: None,
+    }
+}
+
+/// Checks every segment of `dir` and returns the findings with the derived watermarks.
+fn check_partition(dir: &Path) -> (Vec<Finding>, DerivedWatermarks) {
+    let mut bases: Vec<u64> = std::fs::read_dir(dir)
+        .unwrap()
+        .filter_map(|entry| Segment::scan_path(&entry.unwrap().path()))
+        .filter_map(|name| Segment::parse_base_offset(&name))
+        .collect();
+    bases.sort();
+
+    let mut findings = Vec::new();
+    let mut first_offset = None;
+    let mut log_end_offset = 0;
+    for base in bases {
+        let index = Segment::read_index_file(&dir.join(Segment::index_filename(base))).unwrap();
+        let mut check = SegmentCheck::new(base, Some(&index));
+        let mut scanner = RecordScanner::open(&dir.join(Segment::segment_filename(base))).unwrap();
+        let mut corrupt = None;
+        for item in scanner.by_ref() {
+            match item {
+                Ok(raw) => check.record(&raw),
+                Err(e) => corrupt = Some(e),
+            }
+        }
+        first_offset = first_offset.or(check.first_offset);
+        log_end_offset = check.last_offset.map_or(base, |last| last + 1);
+        findings.extend(check.finish(corrupt, scanner.file_len()));
+    }
+    let derived = DerivedWatermarks { first_offset: first_offset.unwrap_or(log_end_offset), log_end_offset };
+    (findings, derived)
+}
+
+#[test]
+fn test_inspect_healthy_partition_then_damage() {
+    let dir = folder_to_use();
+    {
+        let mut partition = Partition::open(dir.clone(), 0, 200).unwrap();
+        for i in 0..25 {
+            partition.append(&message(i)).unwrap();
+        }
+        partition.persist_meta().unwrap();
+    }
+
+    let (findings, derived) = check_partition(&dir);
+    assert_eq!(findings, vec![]);
+    assert_eq!(derived, DerivedWatermarks { first_offset: 0, log_end_offset: 25 });
+    let meta = PartitionMeta::load(&dir.join("meta.json")).unwrap().unwrap();
+    assert_eq!(check_meta(&meta, derived), vec![]);
+
+    // a crash mid-append leaves half a record behind
+    let newest = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(|entry| Segment::scan_path(&entry.unwrap().path()))
+        .max()
+        .unwrap();
+    let path = dir.join(newest);
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 5).unwrap();
+
+    let (findings, derived) = check_partition(&dir);
+    // plus the index entry of the torn record, if it had one
+    assert!(
+        matches!(findings.as_slice(), [.., Finding::CorruptTail { .. }])
+            && findings[..findings.len() - 1]
+                .iter()
+                .all(|f| matches!(f, Finding::IndexPastEnd { offset: 24, .. })),
+        "{:?}",
+        findings
+    );
+    assert_eq!(derived.log_end_offset, 24);
+    assert_eq!(
+        check_meta(&meta, derived),
+        vec![Finding::WatermarkMismatch { field: "log_end_offset", meta: 25, derived: 24 }]
+    );
+}
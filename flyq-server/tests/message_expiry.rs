@@ -0,0 +1,146 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use common::folder_to_use;
+use flyQ::core::compaction::compact_partition;
+use flyQ::core::inspect::list_segments;
+use flyQ::core::partition::Partition;
+use flyQ::core::segment::Segment;
+use flyq_protocol::Message;
+use tokio::sync::RwLock;
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+fn message(value: &str, expires_at: Option<u64>) -> Message {
+    let mut message = Message {
+        key: Some(b"user-42".to_vec()),
+        value: format!("{}-{}", value, "x".repeat(40)).into_bytes(),
+        timestamp: now_ms(),
+        headers: None,
+    };
+    if let Some(expires_at) = expires_at {
+        message.set_expires_at(expires_at);
+    }
+    message
+}
+
+fn offsets(partition: &Partition) -> Vec<u64> {
+    partition
+        .stream_from_offset(0)
+        .unwrap()
+        .map(|item| item.unwrap().0)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_expired_records_are_skipped_without_moving_offsets() {
+    let mut partition = Partition::open(folder_to_use(), 0, 1024).unwrap();
+    let now = now_ms();
+    partition.append(&message("plain", None)).unwrap();
+    partition.append(&message("otp", Some(now - 1))).unwrap();
+    partition.append(&message("soon", Some(now + 200))).unwrap();
+    partition.append(&message("later", Some(now + 3_600_000))).unwrap();
+
+    assert_eq!(offsets(&partition), vec![0, 2, 3]);
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(offsets(&partition), vec![0, 3]);
+    assert_eq!(partition.stream_backward_from(3).unwrap().map(|item| item.unwrap().0).collect::<Vec<_>>(), vec![3, 0]);
+
+    // the next record still gets the next offset
+    assert_eq!(partition.append(&message("after", None)).unwrap(), 4);
+}
+
+#[tokio::test]
+async fn test_compaction_drops_expired_records_of_sealed_segments() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 300).unwrap();
+    let now = now_ms();
+    for i in 0..12 {
+        let expires_at = match i % 3 {
+            0 => Some(now - 1),
+            1 => Some(now + 3_600_000),
+            _ => None,
+        };
+        partition.append(&message(&format!("record-{}", i), expires_at)).unwrap();
+    }
+    assert!(partition.segment_count() > 2);
+    let live: Vec<u64> = (0..12).filter(|i| i % 3 != 0).collect();
+    assert_eq!(offsets(&partition), live);
+    let size_before = partition.total_size_bytes();
+
+    let partition = Arc::new(RwLock::new(partition));
+    let dropped = compact_partition(&partition, now_ms()).await.unwrap();
+    assert!(dropped > 0);
+    {
+        let partition = partition.read().await;
+        assert_eq!(partition.health().expired_records, dropped);
+        assert!(partition.total_size_bytes() < size_before);
+        assert_eq!(offsets(&partition), live);
+        assert_eq!(partition.read_from_offset(4).unwrap()[0].value, message("record-4", None).value);
+    }
+    // nothing new has expired
+    assert_eq!(compact_partition(&partition, now_ms()).await.unwrap(), 0);
+    drop(partition);
+
+    // the rewritten segments recover like any other
+    let mut partition = Partition::open(dir, 0, 300).unwrap();
+    assert_eq!(offsets(&partition), live);
+    assert_eq!(partition.append(&message("record-12", None)).unwrap(), 12);
+    let partition = Arc::new(RwLock::new(partition));
+    assert_eq!(compact_partition(&partition, now_ms()).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_compaction_writes_a_new_generation_next_to_open_readers() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 300).unwrap();
+    let now = now_ms();
+    for i in 0..6 {
+        let expires_at = (i % 2 == 0).then_some(now - 1);
+        partition.append(&message(&format!("record-{}", i), expires_at)).unwrap();
+    }
+    // a reader that got hold of the first segment before compaction
+    let first = Arc::clone(partition.sealed.values().next().unwrap());
+    let original = dir.join(Segment::segment_filename(0));
+    let kept = dir.join("kept-original.log");
+    std::fs::copy(&original, &kept).unwrap();
+
+    let partition = Arc::new(RwLock::new(partition));
+    assert!(compact_partition(&partition, now_ms()).await.unwrap() > 0);
+    assert_eq!(list_segments(&dir).unwrap()[0].1, dir.join(Segment::generation_filename(0, 1)));
+    // the old files are the reader's until it lets go
+    assert!(original.exists());
+    let read: Vec<u64> = first.stream_from_offset(0).unwrap().map(|item| item.unwrap().0).collect();
+    assert_eq!(read, (0..=first.last_offset).filter(|offset| offset % 2 == 1).collect::<Vec<_>>());
+    drop(first);
+    assert!(!original.exists());
+    drop(partition);
+
+    // a crash between the rename and the removal leaves both generations
+    std::fs::rename(&kept, &original).unwrap();
+    let partition = Partition::open(dir.clone(), 0, 300).unwrap();
+    assert!(!original.exists());
+    assert_eq!(offsets(&partition), vec![1, 3, 5]);
+}
+
+#[tokio::test]
+async fn test_open_removes_rewrites_left_in_the_scratch_directory() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 300).unwrap();
+    for i in 0..6 {
+        partition.append(&message(&format!("record-{}", i), None)).unwrap();
+    }
+    drop(partition);
+
+    // a crash in the middle of writing a rewrite
+    let scratch = dir.join("compacting");
+    std::fs::create_dir_all(&scratch).unwrap();
+    std::fs::write(scratch.join(Segment::generation_filename(0, 1)), b"half written").unwrap();
+
+    let partition = Partition::open(dir.clone(), 0, 300).unwrap();
+    assert!(!scratch.exists());
+    assert_eq!(offsets(&partition), (0..6).collect::<Vec<_>>());
+}
@@ -7,7 +7,7 @@ mod common;
 async fn test_consumer_group_offset_tracking() {
 
     let base_dir = folder_to_use();
-    let mut engine = LogEngine::load(base_dir).await;
+    let engine = LogEngine::load(base_dir).await;
 
     let topic = "logs";
     let group = "analytics";
@@ -25,7 +25,7 @@ async fn test_consumer_group_offset_tracking() {
 
     // Consume using group (should default to offset 0)
     let result = engine
-        .consume_with_group(topic, partition, group)
+        .consume_with_group(topic, partition, group, false)
         .await
         .expect("consume_with_group failed");
 
@@ -42,7 +42,7 @@ async fn test_consumer_group_offset_tracking() {
 
     // Consume again from same group (should get nothing)
     let result = engine
-        .consume_with_group(topic, partition, group)
+        .consume_with_group(topic, partition, group, false)
         .await
         .expect("consume_with_group after commit failed");
 
@@ -53,7 +53,7 @@ async fn test_consumer_group_offset_tracking() {
 async fn test_multiple_consumer_groups_track_offsets_independently() {
 
     let base_dir = folder_to_use();
-    let mut engine = LogEngine::load(base_dir).await;
+    let engine = LogEngine::load(base_dir).await;
 
     let topic = "events";
     let group_a = "group-a";
@@ -74,7 +74,7 @@ async fn test_multiple_consumer_groups_track_offsets_independently() {
 
     // Group A consumes and commits offset 1
     let msg_a1 = engine
-        .consume_with_group(topic, partition, group_a)
+        .consume_with_group(topic, partition, group_a, false)
         .await
         .expect("consume group-a #1")
         .unwrap();
@@ -86,7 +86,7 @@ async fn test_multiple_consumer_groups_track_offsets_independently() {
 
     // Group B consumes and commits offset 1 (still starts from 0)
     let msg_b1 = engine
-        .consume_with_group(topic, partition, group_b)
+        .consume_with_group(topic, partition, group_b, false)
         .await
         .expect("consume group-b #1")
         .unwrap();
@@ -98,7 +98,7 @@ async fn test_multiple_consumer_groups_track_offsets_independently() {
 
     // Group A consumes and commits offset 2
     let msg_a2 = engine
-        .consume_with_group(topic, partition, group_a)
+        .consume_with_group(topic, partition, group_a, false)
         .await
         .expect("consume group-a #2")
         .unwrap();
@@ -110,7 +110,7 @@ async fn test_multiple_consumer_groups_track_offsets_independently() {
 
     // Group B consumes and commits offset 2 independently
     let msg_b2 = engine
-        .consume_with_group(topic, partition, group_b)
+        .consume_with_group(topic, partition, group_b, false)
         .await
         .expect("consume group-b #2")
         .unwrap();
@@ -125,17 +125,34 @@ async fn test_multiple_consumer_groups_track_offsets_independently() {
         .offset_tracker
         .lock()
         .await
-        .fetch(group_a, partition)
+        .fetch(group_a, topic, partition)
         .expect("group-a offset");
 
     let b_offset = engine
         .offset_tracker
         .lock()
         .await
-        .fetch(group_b, partition)
+        .fetch(group_b, topic, partition)
         .expect("group-b offset");
 
     assert_eq!(a_offset, 2);
     assert_eq!(b_offset, 2);
 }
 
+
+#[tokio::test]
+async fn test_group_behind_the_log_start_resumes_there() {
+    let engine = LogEngine::load(folder_to_use()).await;
+    let topic = "audit";
+    engine.create_topic(topic, Some(1));
+    for i in 0..5 {
+        let msg = Message { key: None, value: format!("entry-{}", i).into_bytes(), timestamp: 1, headers: None };
+        engine.produce(topic, msg).await.unwrap();
+    }
+    engine.commit_offset(topic, 0, "auditors", 1).await.unwrap();
+    assert_eq!(engine.delete_records(topic, 0, 3).await.unwrap(), 3);
+
+    let (offset, msg) = engine.consume_with_group(topic, 0, "auditors", false).await.unwrap().unwrap();
+    assert_eq!(offset, 3);
+    assert_eq!(msg.value, b"entry-3");
+}
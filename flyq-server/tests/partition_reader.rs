@@ -0,0 +1,82 @@
+mod common;
+
+use common::{folder_to_use, message};
+use flyQ::core::partition::Partition;
+use flyQ::core::partition_reader::PartitionReader;
+use flyq_protocol::errors::DeserializeError;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn test_reader_follows_rotation_and_waits_for_appends() {
+    // 100 byte segments, so the log rolls every few records
+    let partition = Arc::new(RwLock::new(Partition::open(folder_to_use(), 0, 100).unwrap()));
+    for i in 0..10 {
+        partition.write().await.append(&message(i)).unwrap();
+    }
+    assert!(partition.read().await.segment_count() > 1);
+
+    let mut reader = PartitionReader::new(Arc::clone(&partition), 3);
+    for i in 3..10 {
+        let (offset, msg) = reader.next().await.unwrap().unwrap();
+        assert_eq!(offset, i);
+        assert_eq!(msg.value, format!("record-{}", i).into_bytes());
+    }
+
+    // caught up: the reader parks instead of ending
+    assert!(timeout(Duration::from_millis(50), reader.next()).await.is_err());
+
+    let producer = {
+        let partition = Arc::clone(&partition);
+        tokio::spawn(async move {
+            for i in 10..20 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                partition.write().await.append(&message(i)).unwrap();
+            }
+        })
+    };
+
+    // the reader is held across awaits while the producer rolls more segments
+    for i in 10..20 {
+        let next = timeout(Duration::from_secs(5), reader.next()).await.expect("reader woke up");
+        let (offset, msg) = next.unwrap().unwrap();
+        assert_eq!(offset, i);
+        assert_eq!(msg.value, format!("record-{}", i).into_bytes());
+    }
+    producer.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_reader_finishes_deleted_segment_then_reports_out_of_range() {
+    let partition = Arc::new(RwLock::new(Partition::open(folder_to_use(), 0, 100).unwrap()));
+    for i in 0..20 {
+        partition.write().await.append(&message(i)).unwrap();
+    }
+    let first_segment_last = partition.read().await.sealed.values().next().unwrap().last_offset;
+    assert!(first_segment_last > 0, "first segment holds more than one record");
+
+    let mut reader = PartitionReader::new(Arc::clone(&partition), 0);
+    assert_eq!(reader.next().await.unwrap().unwrap().0, 0);
+
+    partition.write().await.delete_records(12).unwrap();
+
+    // the open segment is gone from the partition but still readable to the end
+    for i in 1..=first_segment_last {
+        assert_eq!(reader.next().await.unwrap().unwrap().0, i);
+    }
+    match reader.next().await {
+        Some(Err(DeserializeError::OffsetOutOfRange { offset, low_watermark })) => {
+            assert_eq!(offset, first_segment_last + 1);
+            assert_eq!(low_watermark, 12);
+        }
+        other => panic!("expected out of range, got {:?}", other.map(|r| r.map(|(o, _)| o))),
+    }
+    assert!(reader.next().await.is_none());
+
+    // a new reader from the log start picks up where retention left off
+    let mut reader = PartitionReader::new(Arc::clone(&partition), 12);
+    assert_eq!(reader.next().await.unwrap().unwrap().0, 12);
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::folder_to_use;
+use flyQ::core::partition::Partition;
+use flyq_protocol::Message;
+
+fn message(i: u64) -> Message {
+    Message {
+        key: None,
+        value: format!("order-{}", i).into_bytes(),
+        timestamp: 1000 + i,
+        headers: None,
+    }
+}
+
+#[test]
+fn test_sync_covers_appends_and_rolled_segments() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 100).unwrap();
+    assert_eq!(partition.synced_offset(), 0);
+
+    partition.append(&message(0)).unwrap();
+    assert_eq!(partition.synced_offset(), 0);
+    partition.sync().unwrap();
+    assert_eq!(partition.synced_offset(), 1);
+
+    // rolling syncs the segment it seals, only the active one is left
+    for i in 1..10 {
+        partition.append(&message(i)).unwrap();
+    }
+    assert!(partition.segment_count() > 1);
+    let active_base = partition.active_segment;
+    assert_eq!(partition.synced_offset(), active_base);
+    partition.sync().unwrap();
+    assert_eq!(partition.synced_offset(), 10);
+    drop(partition);
+
+    let reopened = Partition::open(dir, 0, 100).unwrap();
+    assert_eq!(reopened.synced_offset(), 10);
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use std::time::Duration;
+use common::folder_to_use;
+use flyQ::core::log_engine::LogEngine;
+use flyQ::core::stored_record::{ControlMarker, ProducerStamp};
+use flyq_protocol::Message;
+
+fn message(value: &str) -> Message {
+    Message {
+        key: None,
+        value: value.as_bytes().to_vec(),
+        timestamp: 1000,
+        headers: None,
+    }
+}
+
+const VISIBILITY: Duration = Duration::from_secs(30);
+
+#[tokio::test]
+async fn test_members_lease_disjoint_records_and_ack_moves_committed_offset() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("jobs", Some(1));
+    for i in 0..5 {
+        engine.produce("jobs", message(&format!("job-{}", i))).await.unwrap();
+    }
+
+    let first = engine.lease_records("workers", "jobs", 0, 2, VISIBILITY).await.unwrap();
+    let second = engine.lease_records("workers", "jobs", 0, 2, VISIBILITY).await.unwrap();
+    let offsets: Vec<u64> = first.iter().chain(&second).map(|d| d.offset).collect();
+    assert_eq!(offsets, vec![0, 1, 2, 3]);
+    assert!(first.iter().chain(&second).all(|d| d.delivery_count == 1));
+    assert_eq!(second[0].message.value, b"job-2");
+
+    // the second member finishes first: nothing is committed past offset 0
+    let (settled, committed) = engine
+        .settle_records("workers", "jobs", 0, &[(2, 1), (3, 1)], true, "")
+        .await
+        .unwrap();
+    assert_eq!((settled, committed), (2, 0));
+
+    let (settled, committed) = engine
+        .settle_records("workers", "jobs", 0, &[(0, 1), (1, 1)], true, "")
+        .await
+        .unwrap();
+    assert_eq!((settled, committed), (2, 4));
+    assert_eq!(engine.offset_tracker_handle().lock().await.fetch("workers", "jobs", 0), Some(4));
+
+    // another group sees every record
+    let others = engine.lease_records("auditors", "jobs", 0, 10, VISIBILITY).await.unwrap();
+    assert_eq!(others.len(), 5);
+}
+
+#[tokio::test]
+async fn test_nacked_and_expired_records_are_redelivered() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("jobs", Some(1));
+    for i in 0..3 {
+        engine.produce("jobs", message(&format!("job-{}", i))).await.unwrap();
+    }
+
+    let leased = engine.lease_records("workers", "jobs", 0, 2, VISIBILITY).await.unwrap();
+    assert_eq!(leased.len(), 2);
+    let (settled, _) = engine.settle_records("workers", "jobs", 0, &[(1, 1)], false, "").await.unwrap();
+    assert_eq!(settled, 1);
+
+    // redeliveries come before records never leased
+    let leased = engine.lease_records("workers", "jobs", 0, 2, VISIBILITY).await.unwrap();
+    let leased: Vec<(u64, u32)> = leased.iter().map(|d| (d.offset, d.delivery_count)).collect();
+    assert_eq!(leased, vec![(1, 2), (2, 1)]);
+
+    assert!(engine.lease_records("workers", "jobs", 0, 1, VISIBILITY).await.unwrap().is_empty());
+
+    // handed back, then leased again with a short visibility timeout that runs out
+    let short = Duration::from_millis(50);
+    let (settled, _) = engine.settle_records("workers", "jobs", 0, &[(0, 1)], false, "").await.unwrap();
+    assert_eq!(settled, 1);
+    let leased = engine.lease_records("workers", "jobs", 0, 1, short).await.unwrap();
+    assert_eq!((leased[0].offset, leased[0].delivery_count), (0, 2));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let leased = engine.lease_records("workers", "jobs", 0, 1, VISIBILITY).await.unwrap();
+    assert_eq!((leased[0].offset, leased[0].delivery_count), (0, 3));
+    assert_eq!(leased[0].message.value, b"job-0");
+
+    // the member whose lease expired can no longer ack the record
+    let (settled, committed) = engine.settle_records("workers", "jobs", 0, &[(0, 2)], true, "").await.unwrap();
+    assert_eq!((settled, committed), (0, 0));
+    let (settled, committed) = engine
+        .settle_records("workers", "jobs", 0, &[(0, 3), (1, 2), (2, 1)], true, "")
+        .await
+        .unwrap();
+    assert_eq!((settled, committed), (3, 3));
+}
+
+#[tokio::test]
+async fn test_group_progress_is_kept_per_topic() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.create_topic("jobs", Some(1));
+    engine.create_topic("emails", Some(1));
+    for i in 0..4 {
+        engine.produce("jobs", message(&format!("job-{}", i))).await.unwrap();
+        engine.produce("emails", message(&format!("email-{}", i))).await.unwrap();
+    }
+
+    let jobs = engine.lease_records("workers", "jobs", 0, 3, VISIBILITY).await.unwrap();
+    let acked: Vec<(u64, u32)> = jobs.iter().map(|d| (d.offset, d.delivery_count)).collect();
+    engine.settle_records("workers", "jobs", 0, &acked, true, "").await.unwrap();
+    let emails = engine.lease_records("workers", "emails", 0, 1, VISIBILITY).await.unwrap();
+    let (_, committed) = engine.settle_records("workers", "emails", 0, &[(emails[0].offset, 1)], true, "").await.unwrap();
+    assert_eq!(committed, 1);
+    engine.offset_tracker_handle().lock().await.flush_dirty_offsets().unwrap();
+    drop(engine);
+
+    // after a restart each topic resumes where the group left it
+    let engine = LogEngine::load(&base_dir).await;
+    let jobs = engine.lease_records("workers", "jobs", 0, 10, VISIBILITY).await.unwrap();
+    assert_eq!(jobs.iter().map(|d| d.offset).collect::<Vec<_>>(), vec![3]);
+    let emails = engine.lease_records("workers", "emails", 0, 10, VISIBILITY).await.unwrap();
+    assert_eq!(emails.iter().map(|d| d.offset).collect::<Vec<_>>(), vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn test_lease_moves_past_a_trailing_aborted_transaction() {
+    let engine = LogEngine::load(folder_to_use()).await;
+    engine.create_topic("jobs", Some(1));
+    for i in 0..2 {
+        engine.produce("jobs", message(&format!("job-{}", i))).await.unwrap();
+    }
+    let (producer_id, producer_epoch) = engine.init_producer_id(Some("jobs-tx")).await.unwrap();
+    engine.begin_transaction("jobs-tx", producer_id, producer_epoch).await.unwrap();
+    engine
+        .add_partitions_to_transaction("jobs-tx", producer_id, producer_epoch, &[("jobs".to_string(), 0)])
+        .await
+        .unwrap();
+    let stamp = ProducerStamp { producer_id, producer_epoch, sequence: 0, transactional: true, control: false };
+    engine.produce_idempotent("jobs", 0, message("aborted"), stamp).await.unwrap();
+    engine.end_transaction("jobs-tx", producer_id, producer_epoch, ControlMarker::Abort).await.unwrap();
+
+    let leased = engine.lease_records("workers", "jobs", 0, 10, VISIBILITY).await.unwrap();
+    assert_eq!(leased.iter().map(|d| d.offset).collect::<Vec<_>>(), vec![0, 1]);
+    // the aborted record and its marker hold nothing back
+    let (_, committed) = engine
+        .settle_records("workers", "jobs", 0, &[(0, 1), (1, 1)], true, "")
+        .await
+        .unwrap();
+    assert_eq!(committed, 4);
+    assert!(engine.lease_records("workers", "jobs", 0, 10, VISIBILITY).await.unwrap().is_empty());
+}
@@ -0,0 +1,243 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use common::folder_to_use;
+use flyQ::core::compaction::compact_partition;
+use flyQ::core::error::EngineError;
+use flyQ::core::index_strategy::DenseIndex;
+use flyQ::core::inspect::{RecordScanner, SegmentCheck};
+use flyQ::core::partition::Partition;
+use flyQ::core::segment::Segment;
+use flyq_protocol::record_batch::read_batches;
+use flyq_protocol::{Compression, Message, RecordBatch};
+use tokio::sync::RwLock;
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+fn message(value: &str) -> Message {
+    Message {
+        key: Some(b"sensor-7".to_vec()),
+        value: value.as_bytes().to_vec(),
+        timestamp: now_ms(),
+        headers: None,
+    }
+}
+
+fn batch(values: &[&str]) -> RecordBatch {
+    let messages: Vec<Message> = values.iter().map(|v| message(v)).collect();
+    RecordBatch::encode(0, &messages)
+}
+
+fn values(partition: &Partition, offset: u64) -> Vec<(u64, String)> {
+    partition
+        .stream_from_offset(offset)
+        .unwrap()
+        .map(|item| item.unwrap())
+        .map(|(offset, message)| (offset, String::from_utf8(message.value).unwrap()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_batches_mix_with_single_records_and_survive_restart() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 1 << 20).unwrap();
+    partition.set_index_strategy(Arc::new(DenseIndex)).unwrap();
+
+    assert_eq!(partition.append(&message("single-0")).unwrap(), 0);
+    assert_eq!(partition.append_batch(batch(&["b-1", "b-2", "b-3", "b-4"])).unwrap(), 1);
+    assert_eq!(partition.append(&message("single-5")).unwrap(), 5);
+    assert_eq!(partition.append_batch(batch(&["b-6", "b-7"])).unwrap(), 6);
+    assert_eq!(partition.get_watermark().2, 8);
+
+    let all: Vec<u64> = values(&partition, 0).into_iter().map(|(offset, _)| offset).collect();
+    assert_eq!(all, (0..8).collect::<Vec<_>>());
+    // reads can start in the middle of a batch
+    assert_eq!(values(&partition, 3)[0], (3, "b-3".to_string()));
+    let backward: Vec<u64> = partition.stream_backward_from(7).unwrap().map(|item| item.unwrap().0).collect();
+    assert_eq!(backward, (0..8).rev().collect::<Vec<_>>());
+    drop(partition);
+
+    // the dense index names each batch by its base offset only
+    let segment = dir.join(Segment::segment_filename(0));
+    let index = Segment::read_index_file(&dir.join(Segment::index_filename(0))).unwrap();
+    assert_eq!(index.entries.iter().map(|(offset, _)| *offset).collect::<Vec<_>>(), vec![0, 1, 5, 6]);
+    let mut check = SegmentCheck::new(0, Some(&index));
+    let mut scanner = RecordScanner::open(&segment).unwrap();
+    for raw in scanner.by_ref() {
+        check.record(&raw.unwrap());
+    }
+    assert_eq!(check.records, 8);
+    assert_eq!(check.finish(None, scanner.file_len()), vec![]);
+
+    // an index lost in a crash is rebuilt the same way
+    std::fs::remove_file(dir.join(Segment::index_filename(0))).unwrap();
+    let mut partition = Partition::open(dir.clone(), 0, 1 << 20).unwrap();
+    assert_eq!(values(&partition, 7), vec![(7, "b-7".to_string())]);
+    assert_eq!(partition.append(&message("single-8")).unwrap(), 8);
+}
+
+#[tokio::test]
+async fn test_fetch_serves_stored_batches_and_wraps_single_records() {
+    let mut partition = Partition::open(folder_to_use(), 0, 1 << 20).unwrap();
+    let produced = batch(&["b-0", "b-1", "b-2"]);
+    partition.append_batch(produced.clone()).unwrap();
+    partition.append(&message("single-3")).unwrap();
+    let mut expired = message("single-4");
+    expired.set_expires_at(now_ms() - 1);
+    partition.append(&expired).unwrap();
+    partition.append(&message("single-5")).unwrap();
+
+    let fetched = read_batches(&partition.fetch(1, 1 << 20, false).unwrap()).unwrap();
+    assert_eq!(fetched.len(), 2);
+    // the stored batch is handed out byte for byte, starting below the fetch offset
+    assert_eq!(fetched[0], produced);
+    let wrapped: Vec<u64> = fetched[1].records().unwrap().into_iter().map(|(offset, _)| offset).collect();
+    assert_eq!(wrapped, vec![3, 5]);
+
+    // the first batch alone fills a small response
+    assert_eq!(read_batches(&partition.fetch(0, 16, false).unwrap()).unwrap().len(), 1);
+    assert!(partition.fetch(6, 1 << 20, false).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_compaction_keeps_batches_together() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 200).unwrap();
+    let mut messages: Vec<Message> = (0..4).map(|i| message(&format!("record-{}-{}", i, "x".repeat(30)))).collect();
+    messages[1].set_expires_at(now_ms() - 1);
+    partition.append_batch(RecordBatch::encode(0, &messages)).unwrap();
+    partition.append_batch(batch(&["next"])).unwrap(); // rolls, sealing the first batch
+    assert!(partition.segment_count() > 1);
+
+    let partition = Arc::new(RwLock::new(partition));
+    assert_eq!(compact_partition(&partition, now_ms()).await.unwrap(), 1);
+
+    let raws: Vec<_> = RecordScanner::open(&dir.join(Segment::generation_filename(0, 1)))
+        .unwrap()
+        .map(|raw| raw.unwrap())
+        .collect();
+    assert_eq!(raws.len(), 4);
+    assert!(raws.iter().all(|raw| raw.batch.is_some() && raw.position == 0));
+    assert!(raws[1].record.message.value.is_empty());
+
+    let partition = partition.read().await;
+    let live: Vec<u64> = values(&partition, 0).into_iter().map(|(offset, _)| offset).collect();
+    assert_eq!(live, vec![0, 2, 3, 4]);
+}
+
+#[tokio::test]
+async fn test_compressed_batches_are_stored_as_produced_or_recompressed() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 1 << 20).unwrap();
+    let events: Vec<String> = (0..50).map(|i| format!(r#"{{"event":"page_view","user":{},"path":"/home"}}"#, i)).collect();
+    let events: Vec<&str> = events.iter().map(|e| e.as_str()).collect();
+    let plain = batch(&events);
+    let lz4 = plain.clone().with_compression(Compression::Lz4).unwrap();
+    assert!(lz4.as_bytes().len() * 3 < plain.as_bytes().len());
+
+    partition.append_batch(lz4.clone()).unwrap();
+    partition.compression = Some(Compression::Zstd); // topic setting
+    partition.append_batch(plain).unwrap();
+    partition.append_batch(lz4.clone()).unwrap();
+
+    // records are decompressed only where a reader needs them
+    assert_eq!(values(&partition, 75)[0], (75, events[25].to_string()));
+    let fetched = read_batches(&partition.fetch(0, 1 << 20, false).unwrap()).unwrap();
+    assert_eq!(fetched[0], lz4);
+    let codecs: Vec<Compression> = fetched.iter().map(|b| b.compression()).collect();
+    assert_eq!(codecs, vec![Compression::Lz4, Compression::Zstd, Compression::Zstd]);
+    assert_eq!(fetched[2].records().unwrap()[0].1.value, events[0].as_bytes());
+    drop(partition);
+
+    let raws: Vec<_> = RecordScanner::open(&dir.join(Segment::segment_filename(0)))
+        .unwrap()
+        .map(|raw| raw.unwrap())
+        .collect();
+    assert_eq!(raws.len(), 150);
+    assert_eq!(raws[0].len as usize, lz4.to_entry().len()); // stored compressed
+    let partition = Partition::open(dir, 0, 1 << 20).unwrap();
+    assert_eq!(values(&partition, 149), vec![(149, events[49].to_string())]);
+}
+
+#[tokio::test]
+async fn test_fetch_leaves_expired_records_out_of_stored_batches() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 200).unwrap();
+    let mut messages: Vec<Message> = (0..3).map(|i| message(&format!("record-{}-{}", i, "x".repeat(30)))).collect();
+    messages[1].set_expires_at(now_ms() - 1);
+    let mixed = RecordBatch::encode(0, &messages).with_compression(Compression::Snappy).unwrap();
+    partition.append_batch(mixed).unwrap();
+    let mut gone = message("gone");
+    gone.set_expires_at(now_ms() - 1);
+    partition.append_batch(RecordBatch::encode(0, &[gone])).unwrap(); // rolls, sealing the first batch
+    partition.append_batch(batch(&["kept"])).unwrap();
+    assert!(partition.segment_count() > 1);
+
+    let fetch_offsets = |partition: &Partition| -> Vec<Vec<u64>> {
+        read_batches(&partition.fetch(0, 1 << 20, false).unwrap())
+            .unwrap()
+            .iter()
+            .map(|batch| batch.records().unwrap().into_iter().map(|(offset, _)| offset).collect())
+            .collect()
+    };
+    assert_eq!(fetch_offsets(&partition), vec![vec![0, 2], vec![4]]);
+    let fetched = read_batches(&partition.fetch(0, 1 << 20, false).unwrap()).unwrap();
+    assert_eq!(fetched[0].compression(), Compression::Snappy);
+
+    // tombstones left by compaction stay out as well
+    let partition = Arc::new(RwLock::new(partition));
+    assert_eq!(compact_partition(&partition, now_ms()).await.unwrap(), 1);
+    assert_eq!(fetch_offsets(&*partition.read().await), vec![vec![0, 2], vec![4]]);
+}
+
+#[tokio::test]
+async fn test_batches_with_delayed_records_are_refused() {
+    let mut partition = Partition::open(folder_to_use(), 0, 1 << 20).unwrap();
+    let mut later = message("later");
+    later.set_deliver_at(now_ms() + 60_000);
+    let err = partition.append_batch(RecordBatch::encode(0, &[message("now"), later])).unwrap_err();
+    assert!(matches!(err, EngineError::Unsupported(_)));
+    // no offsets were taken for it
+    assert_eq!(partition.get_watermark().2, 0);
+
+    // a delivery time already passed is appended like any other record
+    let mut due = message("due");
+    due.set_deliver_at(now_ms() - 1);
+    assert_eq!(partition.append_batch(RecordBatch::encode(0, &[message("now"), due])).unwrap(), 0);
+    assert_eq!(values(&partition, 0).len(), 2);
+}
+
+#[tokio::test]
+async fn test_fetch_serves_compacted_batches_without_tombstones_as_stored() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 300).unwrap();
+    let mut messages: Vec<Message> = (0..3).map(|i| message(&format!("record-{}-{}", i, "x".repeat(30)))).collect();
+    messages[0].set_expires_at(now_ms() + 3_600_000);
+    messages[1].set_expires_at(now_ms() - 1);
+    partition.append_batch(RecordBatch::encode(0, &messages)).unwrap();
+    let untouched = batch(&["plain-3", "plain-4"]);
+    partition.append_batch(untouched.clone()).unwrap();
+    partition.append_batch(batch(&[&"y".repeat(200)])).unwrap(); // rolls, sealing both
+    assert!(partition.segment_count() > 1);
+
+    let partition = Arc::new(RwLock::new(partition));
+    assert_eq!(compact_partition(&partition, now_ms()).await.unwrap(), 1);
+    let partition = partition.read().await;
+    // the rewritten segment knows nothing expires for an hour
+    assert!(partition.expiring_segments(now_ms()).is_empty());
+
+    let fetched = read_batches(&partition.fetch(0, 1 << 20, false).unwrap()).unwrap();
+    let offsets: Vec<Vec<u64>> = fetched
+        .iter()
+        .map(|batch| batch.records().unwrap().into_iter().map(|(offset, _)| offset).collect())
+        .collect();
+    // the tombstone stays out, the batch without one is handed out as it is
+    assert_eq!(offsets, vec![vec![0, 2], vec![3, 4], vec![5]]);
+    assert!(!fetched[0].header().has_tombstones());
+    let mut stored = untouched;
+    stored.set_base_offset(3);
+    assert_eq!(fetched[1], stored);
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use common::{folder_to_use, message};
+use flyQ::core::index_strategy::IndexStrategyConfig;
+use flyQ::core::inspect::{list_segments, Finding};
+use flyQ::core::partition::Partition;
+use flyQ::core::partiton_meta::PartitionMeta;
+use flyQ::core::repair::{repair_partition, RepairAction, RepairOptions};
+use flyQ::core::segment::Segment;
+use std::path::{Path, PathBuf};
+
+fn write_partition(records: u64) -> PathBuf {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 100).unwrap();
+    for i in 0..records {
+        partition.append(&message(i)).unwrap();
+    }
+    partition.persist_meta().unwrap();
+    dir
+}
+
+fn options(check: bool) -> RepairOptions {
+    RepairOptions {
+        index_strategy: IndexStrategyConfig::default(),
+        truncate: true,
+        check,
+    }
+}
+
+fn snapshot(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .map(|path| (path.file_name().unwrap().to_string_lossy().into_owned(), std::fs::read(&path).unwrap()))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_repair_rebuilds_indexes_truncates_and_rewrites_meta() {
+    let dir = write_partition(20);
+    let segments = list_segments(&dir).unwrap();
+    assert!(segments.len() > 2);
+
+    // lose one index, scramble another, tear the newest record, and make meta.json lie
+    let (first_base, _) = segments[0];
+    std::fs::remove_file(dir.join(Segment::index_filename(first_base))).unwrap();
+    let (second_base, _) = segments[1];
+    let mut scrambled = std::fs::read(dir.join(Segment::index_filename(second_base))).unwrap();
+    scrambled.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 99, 0, 0, 0, 0, 0, 0, 0, 3]);
+    std::fs::write(dir.join(Segment::index_filename(second_base)), scrambled).unwrap();
+    let (_, newest) = segments.last().unwrap();
+    let len = std::fs::metadata(newest).unwrap().len();
+    std::fs::OpenOptions::new().write(true).open(newest).unwrap().set_len(len - 3).unwrap();
+    let bogus = PartitionMeta { low_watermark: 0, high_watermark: 40, log_end_offset: 41 };
+    PartitionMeta::save(&dir.join("meta.json"), &bogus).unwrap();
+
+    // --check changes nothing
+    let before = snapshot(&dir);
+    let report = repair_partition(&dir, &options(true)).unwrap();
+    assert_eq!(snapshot(&dir), before);
+    assert!(report.findings.iter().any(|(_, f)| *f == Finding::IndexMissing));
+    assert!(report.findings.iter().any(|(_, f)| matches!(f, Finding::CorruptTail { .. })));
+    assert!(report.unrepaired.is_empty());
+    let rebuilt: Vec<&PathBuf> = report
+        .actions
+        .iter()
+        .filter_map(|a| match a {
+            RepairAction::RebuildIndexes { segment } => Some(segment),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(rebuilt, vec![&segments[0].1, &segments[1].1, newest]);
+    let expected_meta = PartitionMeta { low_watermark: 0, high_watermark: 18, log_end_offset: 19 };
+    assert!(report.actions.contains(&RepairAction::WriteMeta { old: Some(bogus), new: expected_meta.clone() }));
+
+    let report = repair_partition(&dir, &options(false)).unwrap();
+    assert!(report.unrepaired.is_empty());
+    assert_eq!(PartitionMeta::load(&dir.join("meta.json")).unwrap(), Some(expected_meta));
+
+    // repaired: a second pass finds nothing and the broker reads everything back
+    let report = repair_partition(&dir, &options(true)).unwrap();
+    assert_eq!(report.findings, vec![]);
+    assert_eq!(report.actions, vec![]);
+
+    let mut partition = Partition::open(dir, 0, 100).unwrap();
+    let values: Vec<Vec<u8>> = partition.read_from_offset(0).unwrap().into_iter().map(|m| m.value).collect();
+    let expected: Vec<Vec<u8>> = (0..19).map(|i| format!("record-{}", i).into_bytes()).collect();
+    assert_eq!(values, expected);
+    assert_eq!(partition.append(&message(19)).unwrap(), 19);
+}
+
+#[test]
+fn test_repair_switches_index_strategy_and_keeps_out_of_order_findings() {
+    let dir = write_partition(12);
+    let dense = RepairOptions { index_strategy: IndexStrategyConfig::Dense, ..options(false) };
+
+    let report = repair_partition(&dir, &dense).unwrap();
+    assert_eq!(report.findings, vec![]);
+    assert_eq!(
+        report.actions.len(),
+        list_segments(&dir).unwrap().len(),
+        "every segment is reindexed, meta.json is already right"
+    );
+    for (base, _) in list_segments(&dir).unwrap() {
+        let index = Segment::read_index_file(&dir.join(Segment::index_filename(base))).unwrap();
+        assert_eq!(index.strategy, Some(IndexStrategyConfig::Dense));
+        assert!(!index.entries.is_empty());
+    }
+    assert_eq!(repair_partition(&dir, &RepairOptions { check: true, ..dense }).unwrap().actions, vec![]);
+
+    // offsets written twice can't be repaired, only reported
+    let (_, newest) = list_segments(&dir).unwrap().pop().unwrap();
+    let mut bytes = std::fs::read(&newest).unwrap();
+    bytes.extend_from_slice(&bytes.clone());
+    std::fs::write(&newest, bytes).unwrap();
+    let report = repair_partition(&dir, &dense).unwrap();
+    assert!(!report.unrepaired.is_empty());
+    assert!(report
+        .unrepaired
+        .iter()
+        .all(|(_, f)| matches!(f, Finding::Duplicate { .. } | Finding::OutOfOrder { .. })));
+}
@@ -6,20 +6,26 @@ use flyq_protocol::Message;
 use crate::common::folder_to_use;
 use flyQ::{BROKER_CONFIG, BrokerConfig};
 
+/// The broker config is set once per process, by whichever test runs first,
+/// so every test here sets the same one: small segments, a short retention
+/// and a 2KB size limit.
+fn config() -> BrokerConfig {
+    BrokerConfig {
+        retention: Duration::from_millis(50),
+        retention_bytes: Some(2048),
+        cleanup_interval: Duration::from_secs(1),
+        segment_max_bytes: 100,
+        ..Default::default()
+    }
+}
+
 #[tokio::test]
 async fn test_time_based_retention_cleanup() {
     let base_dir = folder_to_use();
     
-    // Set short retention for testing
-    let cfg = BrokerConfig {
-        retention: Duration::from_millis(100), // 100ms retention
-        retention_bytes: None,
-        cleanup_interval: Duration::from_secs(1),
-        segment_max_bytes: 1024,
-    };
-    let _ = BROKER_CONFIG.set(cfg); // Ignore if already set
+    let _ = BROKER_CONFIG.set(config()); // Ignore if already set
     
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
     let topic_name = "test-retention";
     
     engine.create_topic(topic_name, Some(1));
@@ -56,14 +62,14 @@ async fn test_time_based_retention_cleanup() {
     }
     
     // Get partition and run cleanup
-    let topic = engine.topics.get_mut(topic_name).unwrap();
-    let mut partition = topic.partitions.get_mut(&0).unwrap().lock().await;
-    let segments_before = partition.segments.len();
+    let partition = engine.partition(topic_name, 0).unwrap();
+    let mut partition = partition.write().await;
+    let segments_before = partition.segment_count() as usize;
     
     // Run cleanup
     partition.maybe_cleanup().unwrap();
     
-    let segments_after = partition.segments.len();
+    let segments_after = partition.segment_count() as usize;
     
     // Should have fewer segments after cleanup
     assert!(segments_after <= segments_before, 
@@ -75,16 +81,9 @@ async fn test_time_based_retention_cleanup() {
 async fn test_size_based_retention_cleanup() {
     let base_dir = folder_to_use();
     
-    // Set size-based retention
-    let cfg = BrokerConfig {
-        retention: Duration::from_millis(1), // Very short time to ensure size-based kicks in
-        retention_bytes: Some(2048), // 2KB limit
-        cleanup_interval: Duration::from_secs(1),
-        segment_max_bytes: 512, // Small segments to trigger rotation
-    };
-    let _ = BROKER_CONFIG.set(cfg); // Ignore if already set
+    let _ = BROKER_CONFIG.set(config()); // Ignore if already set
     
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
     let topic_name = "test-size-retention";
     
     engine.create_topic(topic_name, Some(1));
@@ -107,15 +106,15 @@ async fn test_size_based_retention_cleanup() {
     }
     
     // Get partition and check size before cleanup
-    let topic = engine.topics.get_mut(topic_name).unwrap();
-    let mut partition = topic.partitions.get_mut(&0).unwrap().lock().await;
-    let segments_before = partition.segments.len();
+    let partition = engine.partition(topic_name, 0).unwrap();
+    let mut partition = partition.write().await;
+    let segments_before = partition.segment_count() as usize;
     let size_before = partition.total_bytes();
     
     // Run cleanup
     partition.maybe_cleanup().unwrap();
     
-    let segments_after = partition.segments.len();
+    let segments_after = partition.segment_count() as usize;
     let size_after = partition.total_bytes();
     
     // Should have cleaned up some segments and reduced size
@@ -128,15 +127,9 @@ async fn test_size_based_retention_cleanup() {
 async fn test_segment_deletion_marks_and_removes_files() {
     let base_dir = folder_to_use();
     
-    let cfg = BrokerConfig {
-        retention: Duration::from_millis(50),
-        retention_bytes: None,
-        cleanup_interval: Duration::from_secs(1),
-        segment_max_bytes: 100, // Very small to force multiple segments
-    };
-    let _ = BROKER_CONFIG.set(cfg); // Ignore if already set
+    let _ = BROKER_CONFIG.set(config()); // Ignore if already set
     
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
     let topic_name = "test-file-deletion";
     
     engine.create_topic(topic_name, Some(1));
@@ -179,8 +172,8 @@ async fn test_segment_deletion_marks_and_removes_files() {
         .collect();
     
     // Run cleanup
-    let topic = engine.topics.get_mut(topic_name).unwrap();
-    let mut partition = topic.partitions.get_mut(&0).unwrap().lock().await;
+    let partition = engine.partition(topic_name, 0).unwrap();
+    let mut partition = partition.write().await;
     partition.maybe_cleanup().unwrap();
     
     // Check files after cleanup
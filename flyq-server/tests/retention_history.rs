@@ -0,0 +1,96 @@
+mod common;
+
+use common::{folder_to_use, message};
+use flyQ::core::partition::Partition;
+use flyQ::core::retention::RetentionPolicy;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_retention_dry_run_reports_without_deleting() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir, 0, 100).unwrap();
+    for i in 0..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    let segments_before = partition.segment_count();
+    let sealed = segments_before as usize - 1;
+
+    let expired = RetentionPolicy {
+        retention: Duration::from_secs(3600),
+        retention_bytes: None,
+    };
+    let candidates = partition.plan_cleanup(&expired, None);
+    assert_eq!(candidates.len(), sealed, "Every sealed segment is expired");
+    assert!(candidates.iter().all(|c| c.time_expired && !c.size_exceeded));
+    assert!(candidates.windows(2).all(|w| w[0].base_offset < w[1].base_offset));
+    assert!(candidates.iter().all(|c| c.base_offset != partition.active_segment));
+
+    // A size cap only selects enough of the oldest segments to get under it
+    let total = partition.total_size_bytes();
+    let oldest_size = candidates[0].size;
+    let by_size = RetentionPolicy {
+        retention: Duration::from_secs(365 * 24 * 3600 * 100),
+        retention_bytes: Some(total - oldest_size),
+    };
+    let candidates = partition.plan_cleanup(&by_size, None);
+    assert_eq!(candidates.len(), 1);
+    assert!(candidates[0].size_exceeded);
+    assert_eq!(candidates[0].labels(), vec!["size-based"]);
+
+    // Nothing was touched
+    assert_eq!(partition.segment_count(), segments_before);
+    assert_eq!(partition.get_watermark().0, 0);
+    assert!(partition.health().cleanup_history.is_empty());
+}
+
+#[tokio::test]
+async fn test_delete_records_is_recorded_in_cleanup_history() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir, 0, 100).unwrap();
+    for i in 0..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    let size_before = partition.total_size_bytes();
+
+    // Nothing removed, nothing recorded
+    partition.delete_records(0).unwrap();
+    assert!(partition.health().last_cleanup().is_none());
+
+    partition.delete_records(12).unwrap();
+
+    let health = partition.health();
+    assert_eq!(health.cleanup_history.len(), 1);
+    let run = &health.cleanup_history[0];
+    assert_eq!(run.reason, "delete-records");
+    assert!(run.segments_removed > 0);
+    assert_eq!(run.bytes_freed, size_before - health.total_size_bytes);
+    assert_eq!(run.low_watermark, 12);
+    assert_eq!(health.last_cleanup(), Some(run.timestamp_ns));
+}
+
+#[tokio::test]
+async fn test_retention_stops_at_the_first_segment_it_keeps() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir, 0, 200).unwrap();
+    let tomorrow = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+        + 24 * 3600 * 1000;
+    // create times need not grow with offsets: the oldest records are stamped
+    // in the future, the ones after them in 1970
+    for i in 0..16 {
+        let mut record = message(i);
+        if i < 4 {
+            record.timestamp = tomorrow;
+        }
+        partition.append(&record).unwrap();
+    }
+    assert!(partition.segment_count() > 3);
+
+    let expired = RetentionPolicy {
+        retention: Duration::from_secs(3600),
+        retention_bytes: None,
+    };
+    assert!(partition.plan_cleanup(&expired, None).is_empty());
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use std::collections::HashMap;
+use std::time::Duration;
+use common::folder_to_use;
+use flyQ::core::log_engine::LogEngine;
+use flyQ::core::partition::{ConsumerProtection, Partition};
+use flyQ::{BrokerConfig, TopicConfig, BROKER_CONFIG};
+use flyq_protocol::Message;
+
+// Every test in this file installs the same config, so the OnceLock race is harmless
+fn install_config() {
+    let mut topics = HashMap::new();
+    topics.insert(
+        "work-queue".to_string(),
+        TopicConfig {
+            protected_groups: vec!["workers".to_string(), "auditors".to_string()],
+            protected_max_bytes: Some(4096),
+            ..Default::default()
+        },
+    );
+    topics.insert(
+        "audit-log".to_string(),
+        TopicConfig {
+            protected_groups: vec!["auditors".to_string()],
+            ..Default::default()
+        },
+    );
+    let cfg = BrokerConfig {
+        retention: Duration::from_millis(1),
+        retention_bytes: None,
+        segment_max_bytes: 100,
+        topics,
+        ..Default::default()
+    };
+    let _ = BROKER_CONFIG.set(cfg);
+}
+
+fn fill(partition: &mut Partition, count: u64) {
+    for i in 0..count {
+        let msg = Message {
+            key: None,
+            value: format!("job-{}", i).into_bytes(),
+            timestamp: 1, // ancient: time-based retention wants every sealed segment gone
+            headers: None,
+        };
+        partition.append(&msg).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_protected_floor_keeps_unconsumed_segments() {
+    install_config();
+    let mut partition = Partition::open(folder_to_use(), 0, 100).unwrap();
+    fill(&mut partition, 30);
+
+    let protection = ConsumerProtection { floor: 10, max_bytes: None };
+    partition.maybe_cleanup_protected(Some(&protection)).unwrap();
+
+    // Everything the protected groups have not consumed is still readable
+    let low_watermark = partition.get_watermark().0;
+    assert!(low_watermark > 0, "fully consumed segments should be deleted");
+    assert!(low_watermark <= 10, "offset 10 must survive, low watermark is {}", low_watermark);
+    assert_eq!(partition.read_from_offset(10).unwrap().len(), 20);
+    assert_eq!(partition.state.protection_overrides(), 0);
+}
+
+#[tokio::test]
+async fn test_hard_cap_overrides_protection() {
+    install_config();
+    let mut partition = Partition::open(folder_to_use(), 0, 100).unwrap();
+    fill(&mut partition, 30);
+    let size_before = partition.total_size_bytes();
+
+    let cap = size_before / 2;
+    let protection = ConsumerProtection { floor: 0, max_bytes: Some(cap) };
+    partition.maybe_cleanup_protected(Some(&protection)).unwrap();
+
+    assert!(partition.total_size_bytes() <= cap);
+    assert!(partition.state.protection_overrides() > 0);
+}
+
+#[tokio::test]
+async fn test_consumer_protection_uses_slowest_protected_group() {
+    install_config();
+    let engine = LogEngine::load(folder_to_use()).await;
+    engine.create_topic("work-queue", Some(1));
+    engine.create_topic("telemetry", Some(1));
+
+    // No commits yet: the whole partition is protected
+    let protection = engine.consumer_protection("work-queue", 0).await.unwrap();
+    assert_eq!(protection.floor, 0);
+    assert_eq!(protection.max_bytes, Some(4096));
+
+    engine.commit_offset("work-queue", 0, "workers", 40).await.unwrap();
+    engine.commit_offset("work-queue", 0, "auditors", 25).await.unwrap();
+    engine.commit_offset("work-queue", 0, "unrelated", 1).await.unwrap();
+    let protection = engine.consumer_protection("work-queue", 0).await.unwrap();
+    assert_eq!(protection.floor, 25);
+
+    // Topics without protected groups keep plain retention
+    assert!(engine.consumer_protection("telemetry", 0).await.is_none());
+}
+
+#[tokio::test]
+async fn test_consumer_protection_reads_each_topic_offsets() {
+    install_config();
+    let engine = LogEngine::load(folder_to_use()).await;
+    engine.create_topic("work-queue", Some(1));
+    engine.create_topic("audit-log", Some(1));
+
+    // "auditors" consumes both topics, at its own pace on each
+    engine.commit_offset("work-queue", 0, "workers", 40).await.unwrap();
+    engine.commit_offset("work-queue", 0, "auditors", 25).await.unwrap();
+    engine.commit_offset("audit-log", 0, "auditors", 3).await.unwrap();
+    assert_eq!(engine.consumer_protection("work-queue", 0).await.unwrap().floor, 25);
+    assert_eq!(engine.consumer_protection("audit-log", 0).await.unwrap().floor, 3);
+
+    engine.commit_offset("audit-log", 0, "auditors", 90).await.unwrap();
+    assert_eq!(engine.consumer_protection("work-queue", 0).await.unwrap().floor, 25);
+    assert_eq!(engine.consumer_protection("audit-log", 0).await.unwrap().floor, 90);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::{folder_to_use, message};
+use flyQ::core::partition::Partition;
+use flyQ::core::scrub::{scrub_partition, RateLimiter};
+use flyQ::core::segment::Segment;
+use std::io::{Seek, SeekFrom, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+#[tokio::test]
+async fn test_scrub_reports_corruption_in_sealed_segments() {
+    let dir = folder_to_use();
+    let mut partition = Partition::open(dir.clone(), 0, 100).unwrap();
+    for i in 0..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    let sealed: Vec<u64> = partition.sealed.keys().copied().collect();
+    assert!(sealed.len() > 1);
+    let partition = Arc::new(RwLock::new(partition));
+    let limiter = Arc::new(RateLimiter::new(u64::MAX));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let pass = scrub_partition(&partition, &limiter, &stop).await.unwrap();
+    assert_eq!(pass.segments_scrubbed, sealed.len() as u64);
+    assert_eq!(pass.problems, vec![]);
+    let health = partition.read().await.health();
+    assert_eq!(health.scrub.last_pass_ns, pass.last_pass_ns);
+    assert!(health.scrub.bytes_scrubbed > 0);
+
+    // bit rot in the offset of the second record of the second sealed segment
+    let rotten = sealed[1];
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(dir.join(Segment::segment_filename(rotten)))
+        .unwrap();
+    let first_record_len = 4 + 8 + message(rotten).serialize_for_wire().len() as u64;
+    file.seek(SeekFrom::Start(first_record_len + 4)).unwrap();
+    file.write_all(&[0x40]).unwrap();
+
+    scrub_partition(&partition, &limiter, &stop).await.unwrap();
+    let health = partition.read().await.health();
+    assert!(!health.scrub.problems.is_empty());
+    assert!(health.scrub.problems.iter().all(|p| p.segment == rotten), "{:?}", health.scrub.problems);
+    assert_eq!(health.scrub.segments_scrubbed, 2 * sealed.len() as u64);
+
+    // a stopped pass leaves the last result in place
+    stop.store(true, std::sync::atomic::Ordering::Relaxed);
+    assert!(scrub_partition(&partition, &limiter, &stop).await.is_none());
+    assert_eq!(partition.read().await.health().scrub, health.scrub);
+}
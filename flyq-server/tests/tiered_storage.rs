@@ -0,0 +1,181 @@
+mod common;
+
+use common::{folder_to_use, message};
+use flyQ::core::partition::Partition;
+use flyQ::core::producer_state::ProduceOutcome;
+use flyQ::core::remote_storage::{offload_partition, LocalFsRemoteStorage, RemoteStorage};
+use flyQ::core::retention::RetentionPolicy;
+use flyQ::core::stored_record::ProducerStamp;
+use flyq_protocol::Message;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+const PREFIX: &str = "topic_events/partition_0";
+
+fn tiered_partition(dir: std::path::PathBuf, store: &Arc<LocalFsRemoteStorage>) -> Partition {
+    let mut partition = Partition::open(dir, 0, 100).unwrap();
+    partition.attach_remote(store.clone(), PREFIX.to_string()).unwrap();
+    partition
+}
+
+#[tokio::test]
+async fn test_offloaded_segments_are_read_back_transparently() {
+    let dir = folder_to_use();
+    let store = Arc::new(LocalFsRemoteStorage::new(folder_to_use()).unwrap());
+    let mut partition = tiered_partition(dir.clone(), &store);
+
+    for i in 0..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    let segments_before = partition.segment_count();
+
+    let offloaded = partition.offload_sealed(Duration::from_secs(3600)).unwrap();
+    assert_eq!(offloaded, segments_before - 1, "All sealed segments are offloaded");
+    assert_eq!(partition.segment_count(), 1, "Only the active segment stays local");
+    assert_eq!(partition.remote_segment_count(), offloaded);
+    assert!(store.list(PREFIX).unwrap().iter().any(|k| k.ends_with(".log")));
+
+    // Offloading does not move the log start, reads below the local start hit the remote tier
+    assert_eq!(partition.get_watermark().0, 0);
+    let messages = partition.read_from_offset(3).unwrap();
+    assert_eq!(messages.len(), 17);
+    assert_eq!(messages[0].value, b"record-3");
+    assert_eq!(messages[16].value, b"record-19");
+
+    // backward scans walk from the active segment down into the remote tier
+    let backward: Vec<u64> = partition
+        .stream_backward_from(19)
+        .unwrap()
+        .map(|res| res.unwrap().0)
+        .collect();
+    assert_eq!(backward, (0..20).rev().collect::<Vec<_>>());
+
+    // The manifest survives a restart
+    drop(partition);
+    let reopened = tiered_partition(dir, &store);
+    assert_eq!(reopened.remote_segment_count(), offloaded);
+    assert_eq!(reopened.read_from_offset(0).unwrap().len(), 20);
+}
+
+#[tokio::test]
+async fn test_young_segments_stay_local() {
+    let dir = folder_to_use();
+    let store = Arc::new(LocalFsRemoteStorage::new(folder_to_use()).unwrap());
+    let mut partition = tiered_partition(dir, &store);
+
+    for i in 0..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    let segments_before = partition.segment_count();
+
+    // records from 1970 are younger than a century
+    let offloaded = partition.offload_sealed(Duration::from_secs(365 * 24 * 3600 * 100)).unwrap();
+    assert_eq!(offloaded, 0);
+    assert_eq!(partition.segment_count(), segments_before);
+    assert!(store.list(PREFIX).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_retention_and_delete_records_reach_remote_segments() {
+    let dir = folder_to_use();
+    let store = Arc::new(LocalFsRemoteStorage::new(folder_to_use()).unwrap());
+    let mut partition = tiered_partition(dir, &store);
+
+    for i in 0..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    partition.offload_sealed(Duration::from_secs(3600)).unwrap();
+    let remote_count = partition.remote_segment_count() as usize;
+
+    // Retention sees the whole log, oldest (remote) segments first
+    let expired = RetentionPolicy {
+        retention: Duration::from_secs(3600),
+        retention_bytes: None,
+    };
+    let candidates = partition.plan_cleanup(&expired, None);
+    assert_eq!(candidates.len(), remote_count);
+    assert_eq!(candidates[0].base_offset, 0);
+
+    let uploaded_objects = store.list(PREFIX).unwrap().len();
+    partition.delete_records(12).unwrap();
+
+    assert!(partition.remote_segment_count() < remote_count as u32);
+    assert!(store.list(PREFIX).unwrap().len() < uploaded_objects);
+    let messages = partition.read_from_offset(12).unwrap();
+    assert_eq!(messages.len(), 8);
+    assert_eq!(messages[0].value, b"record-12");
+}
+
+#[tokio::test]
+async fn test_offload_uploads_without_holding_the_partition() {
+    let dir = folder_to_use();
+    let store = Arc::new(LocalFsRemoteStorage::new(folder_to_use()).unwrap());
+    let mut partition = tiered_partition(dir, &store);
+    for i in 0..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    let sealed = partition.segment_count() - 1;
+    let partition = Arc::new(RwLock::new(partition));
+
+    let offloaded = offload_partition(&partition, Duration::from_secs(3600)).await.unwrap();
+    assert_eq!(offloaded, sealed);
+    let partition = partition.read().await;
+    assert_eq!(partition.segment_count(), 1);
+    assert_eq!(partition.remote_segment_count(), sealed);
+    assert_eq!(partition.read_from_offset(0).unwrap().len(), 20);
+}
+
+#[tokio::test]
+async fn test_segments_without_timestamps_are_not_expired_as_1970() {
+    let dir = folder_to_use();
+    let store = Arc::new(LocalFsRemoteStorage::new(folder_to_use()).unwrap());
+    let mut partition = tiered_partition(dir.clone(), &store);
+    for i in 0..20 {
+        partition.append(&Message { timestamp: 0, ..message(i) }).unwrap();
+    }
+    partition.offload_sealed(Duration::ZERO).unwrap();
+    assert!(partition.remote_segment_count() > 0);
+
+    let policy = RetentionPolicy {
+        retention: Duration::from_secs(3600),
+        retention_bytes: None,
+    };
+    // aged from the upload instead
+    assert!(partition.plan_cleanup(&policy, None).is_empty());
+
+    // manifests from before upload times were kept leave the age unknown
+    drop(partition);
+    let manifest = dir.join("remote.json");
+    let mut segments: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&manifest).unwrap()).unwrap();
+    for segment in &mut segments {
+        assert_eq!(segment["max_timestamp"], 0);
+        segment.as_object_mut().unwrap().remove("uploaded_at");
+    }
+    std::fs::write(&manifest, serde_json::to_vec(&segments).unwrap()).unwrap();
+    let partition = tiered_partition(dir, &store);
+    assert!(partition.plan_cleanup(&policy, None).is_empty());
+}
+
+#[tokio::test]
+async fn test_producer_state_survives_offload_of_its_segments() {
+    let dir = folder_to_use();
+    let store = Arc::new(LocalFsRemoteStorage::new(folder_to_use()).unwrap());
+    let mut partition = tiered_partition(dir.clone(), &store);
+    let stamp = |sequence| ProducerStamp { producer_id: 7, producer_epoch: 0, sequence, transactional: false, control: false };
+    for i in 0..10 {
+        partition.append_idempotent(&message(i), stamp(i as u32)).unwrap();
+    }
+    // push every record of the producer out of the active segment
+    for i in 10..20 {
+        partition.append(&message(i)).unwrap();
+    }
+    partition.offload_sealed(Duration::from_secs(3600)).unwrap();
+    assert_eq!(partition.segment_count(), 1);
+
+    // reopened without a metadata flush, only local segments to replay
+    drop(partition);
+    let mut reopened = tiered_partition(dir, &store);
+    assert_eq!(reopened.append_idempotent(&message(20), stamp(9)).unwrap(), ProduceOutcome::Duplicate(9));
+    assert_eq!(reopened.append_idempotent(&message(20), stamp(10)).unwrap(), ProduceOutcome::Appended(20));
+}
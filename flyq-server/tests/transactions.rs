@@ -0,0 +1,268 @@
+mod common;
+
+use common::folder_to_use;
+use flyQ::core::log_engine::LogEngine;
+use flyQ::core::producer_state::ProduceOutcome;
+use flyQ::core::stored_record::{ControlMarker, ProducerStamp};
+use flyQ::core::transaction::TransactionError;
+use flyq_protocol::Message;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn message(value: &str) -> Message {
+    Message {
+        key: None,
+        value: value.as_bytes().to_vec(),
+        timestamp: 1000,
+        headers: None,
+    }
+}
+
+fn stamp((producer_id, producer_epoch): (u64, u16), sequence: u32) -> ProducerStamp {
+    ProducerStamp { producer_id, producer_epoch, sequence, transactional: true, control: false }
+}
+
+async fn read_all(engine: &LogEngine, topic: &str, read_committed: bool) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut offset = 0;
+    while let Some((at, msg)) = engine.consume_record(topic, 0, offset, read_committed).await.unwrap() {
+        values.push(String::from_utf8(msg.value).unwrap());
+        offset = at + 1;
+    }
+    values
+}
+
+#[tokio::test]
+async fn test_commit_and_abort_across_partitions() {
+    let engine = LogEngine::load(folder_to_use()).await;
+    let producer = engine.init_producer_id(Some("payments-tx")).await.unwrap();
+    let partitions = vec![("orders".to_string(), 0), ("payments".to_string(), 0)];
+
+    engine.begin_transaction("payments-tx", producer.0, producer.1).await.unwrap();
+    engine.add_partitions_to_transaction("payments-tx", producer.0, producer.1, &partitions).await.unwrap();
+    engine.produce_idempotent("orders", 0, message("order-1"), stamp(producer, 0)).await.unwrap();
+    engine.produce_idempotent("payments", 0, message("payment-1"), stamp(producer, 0)).await.unwrap();
+
+    // nothing is stable until the transaction ends
+    assert_eq!(engine.last_stable_offset("orders", 0).await.unwrap(), 0);
+    assert!(read_all(&engine, "orders", true).await.is_empty());
+    assert_eq!(read_all(&engine, "orders", false).await, vec!["order-1"]);
+
+    engine.end_transaction("payments-tx", producer.0, producer.1, ControlMarker::Commit).await.unwrap();
+    assert_eq!(read_all(&engine, "orders", true).await, vec!["order-1"]);
+    assert_eq!(read_all(&engine, "payments", true).await, vec!["payment-1"]);
+    // the commit marker takes an offset but is never handed out
+    assert_eq!(engine.last_stable_offset("orders", 0).await.unwrap(), 2);
+
+    engine.begin_transaction("payments-tx", producer.0, producer.1).await.unwrap();
+    engine.add_partitions_to_transaction("payments-tx", producer.0, producer.1, &partitions).await.unwrap();
+    engine.produce_idempotent("orders", 0, message("order-2"), stamp(producer, 1)).await.unwrap();
+    engine.end_transaction("payments-tx", producer.0, producer.1, ControlMarker::Abort).await.unwrap();
+    engine.produce("orders", message("order-3")).await.unwrap();
+
+    assert_eq!(read_all(&engine, "orders", true).await, vec!["order-1", "order-3"]);
+    assert_eq!(read_all(&engine, "orders", false).await, vec!["order-1", "order-2", "order-3"]);
+    assert_eq!(engine.consume_backward("orders", 0, 10, 10, true).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_transactional_produce_needs_added_partition() {
+    let engine = LogEngine::load(folder_to_use()).await;
+    let producer = engine.init_producer_id(Some("orders-tx")).await.unwrap();
+
+    let outcome = engine.produce_idempotent("orders", 0, message("early"), stamp(producer, 0)).await.unwrap();
+    assert_eq!(outcome, ProduceOutcome::NotInTransaction);
+
+    engine.begin_transaction("orders-tx", producer.0, producer.1).await.unwrap();
+    let outcome = engine.produce_idempotent("orders", 0, message("unregistered"), stamp(producer, 0)).await.unwrap();
+    assert_eq!(outcome, ProduceOutcome::NotInTransaction);
+    assert!(read_all(&engine, "orders", false).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_reinit_fences_and_aborts_open_transaction() {
+    let engine = LogEngine::load(folder_to_use()).await;
+    let old = engine.init_producer_id(Some("orders-tx")).await.unwrap();
+    engine.begin_transaction("orders-tx", old.0, old.1).await.unwrap();
+    let partitions = [("orders".to_string(), 0), ("refunds".to_string(), 0)];
+    engine.add_partitions_to_transaction("orders-tx", old.0, old.1, &partitions).await.unwrap();
+    engine.produce_idempotent("orders", 0, message("zombie"), stamp(old, 0)).await.unwrap();
+
+    let new = engine.init_producer_id(Some("orders-tx")).await.unwrap();
+    assert_eq!(new, (old.0, old.1 + 1));
+    assert_eq!(engine.last_stable_offset("orders", 0).await.unwrap(), 2);
+    assert!(read_all(&engine, "orders", true).await.is_empty());
+
+    let err = engine.end_transaction("orders-tx", old.0, old.1, ControlMarker::Commit).await.unwrap_err();
+    assert!(matches!(err, TransactionError::ProducerFenced));
+    let outcome = engine.produce_idempotent("orders", 0, message("zombie-2"), stamp(old, 1)).await.unwrap();
+    assert_eq!(outcome, ProduceOutcome::Fenced { current_epoch: new.1 });
+
+    let err = engine.begin_transaction("unknown-tx", new.0, new.1).await.unwrap_err();
+    assert!(matches!(err, TransactionError::UnknownTransactionalId));
+
+    // an append that passed the coordinator before the fence is refused by
+    // the partition, also where the old owner had not written yet
+    for topic in ["orders", "refunds"] {
+        let partition = engine.topic(topic).unwrap().partitions[&0].clone();
+        let outcome = partition.write().await.append_idempotent(&message("late"), stamp(old, 1)).unwrap();
+        assert_eq!(outcome, ProduceOutcome::Fenced { current_epoch: new.1 });
+    }
+}
+
+#[tokio::test]
+async fn test_commit_waits_for_appends_past_the_coordinator() {
+    let engine = Arc::new(LogEngine::load(folder_to_use()).await);
+    let producer = engine.init_producer_id(Some("orders-tx")).await.unwrap();
+    let partitions = [("audit".to_string(), 0), ("orders".to_string(), 0)];
+    engine.begin_transaction("orders-tx", producer.0, producer.1).await.unwrap();
+    engine.add_partitions_to_transaction("orders-tx", producer.0, producer.1, &partitions).await.unwrap();
+    engine.produce_idempotent("audit", 0, message("audit-1"), stamp(producer, 0)).await.unwrap();
+
+    // the append passes the coordinator, then waits for the partition
+    let orders = engine.topic("orders").unwrap().partitions[&0].clone();
+    let held = orders.write().await;
+    let produce = tokio::spawn({
+        let engine = Arc::clone(&engine);
+        async move { engine.produce_idempotent("orders", 0, message("order-1"), stamp(producer, 0)).await.unwrap() }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let commit = tokio::spawn({
+        let engine = Arc::clone(&engine);
+        async move { engine.end_transaction("orders-tx", producer.0, producer.1, ControlMarker::Commit).await.unwrap() }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // no marker anywhere while the append is in flight
+    assert!(!commit.is_finished());
+    assert_eq!(engine.last_stable_offset("audit", 0).await.unwrap(), 0);
+    drop(held);
+
+    assert_eq!(produce.await.unwrap(), ProduceOutcome::Appended(0));
+    commit.await.unwrap();
+    for (topic, value) in [("audit", "audit-1"), ("orders", "order-1")] {
+        assert_eq!(engine.last_stable_offset(topic, 0).await.unwrap(), 2);
+        assert_eq!(read_all(&engine, topic, true).await, vec![value]);
+    }
+}
+
+#[tokio::test]
+async fn test_transaction_state_survives_restart() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    let producer = engine.init_producer_id(Some("ledger-tx")).await.unwrap();
+    let partitions = [("ledger".to_string(), 0)];
+
+    engine.begin_transaction("ledger-tx", producer.0, producer.1).await.unwrap();
+    engine.add_partitions_to_transaction("ledger-tx", producer.0, producer.1, &partitions).await.unwrap();
+    engine.produce_idempotent("ledger", 0, message("aborted"), stamp(producer, 0)).await.unwrap();
+    engine.end_transaction("ledger-tx", producer.0, producer.1, ControlMarker::Abort).await.unwrap();
+
+    engine.begin_transaction("ledger-tx", producer.0, producer.1).await.unwrap();
+    engine.add_partitions_to_transaction("ledger-tx", producer.0, producer.1, &partitions).await.unwrap();
+    engine.produce_idempotent("ledger", 0, message("open"), stamp(producer, 1)).await.unwrap();
+    drop(engine);
+
+    // the open transaction still holds back the stable offset after a restart
+    let engine = LogEngine::load(&base_dir).await;
+    assert_eq!(engine.last_stable_offset("ledger", 0).await.unwrap(), 2);
+    assert!(read_all(&engine, "ledger", true).await.is_empty());
+
+    engine.end_transaction("ledger-tx", producer.0, producer.1, ControlMarker::Commit).await.unwrap();
+    assert_eq!(read_all(&engine, "ledger", true).await, vec!["open"]);
+    assert_eq!(read_all(&engine, "ledger", false).await, vec!["aborted", "open"]);
+}
+
+#[tokio::test]
+async fn test_offsets_commit_with_the_transaction() {
+    let base_dir = folder_to_use();
+    let engine = LogEngine::load(&base_dir).await;
+    engine.produce("clicks", message("click-0")).await.unwrap();
+    engine.produce("clicks", message("click-1")).await.unwrap();
+    engine.commit_offset("clicks", 0, "enricher", 1).await.unwrap();
+
+    let producer = engine.init_producer_id(Some("enricher-tx")).await.unwrap();
+    let output = [("enriched".to_string(), 0)];
+    engine.begin_transaction("enricher-tx", producer.0, producer.1).await.unwrap();
+    engine.add_partitions_to_transaction("enricher-tx", producer.0, producer.1, &output).await.unwrap();
+    engine.produce_idempotent("enriched", 0, message("enriched-1"), stamp(producer, 0)).await.unwrap();
+    engine
+        .add_offsets_to_transaction("enricher-tx", producer.0, producer.1, "enricher", &[("clicks".to_string(), 0, 2)])
+        .await
+        .unwrap();
+    let tracker = engine.offset_tracker_handle();
+    assert_eq!(tracker.lock().await.fetch("enricher", "clicks", 0), Some(1));
+
+    // aborted: output and offsets are both dropped
+    engine.end_transaction("enricher-tx", producer.0, producer.1, ControlMarker::Abort).await.unwrap();
+    assert_eq!(tracker.lock().await.fetch("enricher", "clicks", 0), Some(1));
+    assert!(read_all(&engine, "enriched", true).await.is_empty());
+
+    engine.begin_transaction("enricher-tx", producer.0, producer.1).await.unwrap();
+    engine.add_partitions_to_transaction("enricher-tx", producer.0, producer.1, &output).await.unwrap();
+    engine.produce_idempotent("enriched", 0, message("enriched-1"), stamp(producer, 1)).await.unwrap();
+    engine
+        .add_offsets_to_transaction("enricher-tx", producer.0, producer.1, "enricher", &[("clicks".to_string(), 0, 2)])
+        .await
+        .unwrap();
+    engine.end_transaction("enricher-tx", producer.0, producer.1, ControlMarker::Commit).await.unwrap();
+    assert_eq!(tracker.lock().await.fetch("enricher", "clicks", 0), Some(2));
+    drop(tracker);
+    drop(engine);
+
+    let engine = LogEngine::load(&base_dir).await;
+    assert_eq!(engine.offset_tracker_handle().lock().await.fetch("enricher", "clicks", 0), Some(2));
+    assert_eq!(read_all(&engine, "enriched", true).await, vec!["enriched-1"]);
+
+    let err = engine
+        .add_offsets_to_transaction("enricher-tx", producer.0, producer.1, "enricher", &[("clicks".to_string(), 0, 3)])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, TransactionError::InvalidState(_)));
+}
+
+#[tokio::test]
+async fn test_transaction_offsets_commit_per_topic() {
+    let engine = LogEngine::load(folder_to_use()).await;
+    for topic in ["clicks", "views"] {
+        engine.produce(topic, message("event")).await.unwrap();
+    }
+    engine.commit_offset("views", 0, "enricher", 1).await.unwrap();
+
+    let producer = engine.init_producer_id(Some("enricher-tx")).await.unwrap();
+    engine.begin_transaction("enricher-tx", producer.0, producer.1).await.unwrap();
+    engine
+        .add_offsets_to_transaction("enricher-tx", producer.0, producer.1, "enricher", &[("clicks".to_string(), 0, 5)])
+        .await
+        .unwrap();
+    engine.end_transaction("enricher-tx", producer.0, producer.1, ControlMarker::Commit).await.unwrap();
+
+    // the same group and partition number on another topic is left alone
+    let tracker = engine.offset_tracker_handle();
+    assert_eq!(tracker.lock().await.fetch("enricher", "clicks", 0), Some(5));
+    assert_eq!(tracker.lock().await.fetch("enricher", "views", 0), Some(1));
+}
+
+#[tokio::test]
+async fn test_timed_out_transaction_is_aborted() {
+    let engine = LogEngine::load(folder_to_use()).await;
+    let producer = engine.init_producer_id(Some("stuck-tx")).await.unwrap();
+    engine.begin_transaction("stuck-tx", producer.0, producer.1).await.unwrap();
+    engine
+        .add_partitions_to_transaction("stuck-tx", producer.0, producer.1, &[("orders".to_string(), 0)])
+        .await
+        .unwrap();
+    engine.produce_idempotent("orders", 0, message("pending"), stamp(producer, 0)).await.unwrap();
+    assert_eq!(engine.last_stable_offset("orders", 0).await.unwrap(), 0);
+
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    assert_eq!(engine.abort_expired_transactions(now).await.unwrap(), 0);
+    // past the default 60s timeout
+    assert_eq!(engine.abort_expired_transactions(now + 61_000).await.unwrap(), 1);
+    assert_eq!(engine.last_stable_offset("orders", 0).await.unwrap(), 2);
+    assert!(read_all(&engine, "orders", true).await.is_empty());
+
+    let err = engine.end_transaction("stuck-tx", producer.0, producer.1, ControlMarker::Commit).await.unwrap_err();
+    assert!(matches!(err, TransactionError::ProducerFenced));
+    assert_eq!(engine.init_producer_id(Some("stuck-tx")).await.unwrap(), (producer.0, producer.1 + 2));
+}
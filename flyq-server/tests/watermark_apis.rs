@@ -4,21 +4,20 @@ use common::folder_to_use;
 use flyq_protocol::Message;
 use flyQ::core::log_engine::LogEngine;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
 #[tokio::test]
 async fn test_watermark_tracking() {
     let base_dir = folder_to_use();
-    let engine = Arc::new(Mutex::new(LogEngine::load(&base_dir).await));
+    let engine = Arc::new(LogEngine::load(&base_dir).await);
     
     let topic = "watermark-test";
     let partition = 0;
     
     // Create topic
-    engine.lock().await.create_topic(topic, Some(1));
+    engine.create_topic(topic, Some(1));
     
     // Initially, all watermarks should be 0
-    let (low, high, log_end) = engine.lock().await.get_watermark(topic, partition).await.unwrap();
+    let (low, high, log_end) = engine.get_watermark(topic, partition).await.unwrap();
     assert_eq!(low, 0);
     assert_eq!(high, 0);
     assert_eq!(log_end, 0);
@@ -31,11 +30,11 @@ async fn test_watermark_tracking() {
             timestamp: chrono::Utc::now().timestamp_millis() as u64,
             headers: None,
         };
-        engine.lock().await.produce(topic, msg).await.unwrap();
+        engine.produce(topic, msg).await.unwrap();
     }
     
     // Check watermarks after producing
-    let (low, high, log_end) = engine.lock().await.get_watermark(topic, partition).await.unwrap();
+    let (low, high, log_end) = engine.get_watermark(topic, partition).await.unwrap();
     assert_eq!(low, 0);  // Low watermark unchanged (no cleanup yet)
     assert_eq!(high, 9); // High watermark is last message offset (0-9)
     assert_eq!(log_end, 10); // Log end offset is next offset to be written
@@ -44,7 +43,7 @@ async fn test_watermark_tracking() {
 #[tokio::test]
 async fn test_consumer_lag_calculation() {
     let base_dir = folder_to_use();
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
     
     let topic = "lag-test";
     let partition = 0;
@@ -95,17 +94,23 @@ async fn test_consumer_lag_calculation() {
 #[tokio::test]
 async fn test_partition_health() {
     let base_dir = folder_to_use();
-    let engine = Arc::new(Mutex::new(LogEngine::load(&base_dir).await));
+    let engine = Arc::new(LogEngine::load(&base_dir).await);
     
     let topic = "health-test";
     let partition = 0;
     
     // Create topic
-    engine.lock().await.create_topic(topic, Some(1));
+    engine.create_topic(topic, Some(1));
     
     // Get initial health
-    let (segment_count, total_size, low, high, log_end, _) = 
-        engine.lock().await.get_partition_health(topic, partition).await.unwrap();
+    let health = engine.get_partition_health(topic, partition).await.unwrap();
+    let (segment_count, total_size, low, high, log_end) = (
+        health.segment_count,
+        health.total_size_bytes,
+        health.low_watermark,
+        health.high_watermark,
+        health.log_end_offset,
+    );
     
     assert_eq!(segment_count, 1); // Should have 1 segment initially
     assert_eq!(total_size, 0); // No data yet
@@ -121,12 +126,18 @@ async fn test_partition_health() {
             timestamp: chrono::Utc::now().timestamp_millis() as u64,
             headers: None,
         };
-        engine.lock().await.produce(topic, msg).await.unwrap();
+        engine.produce(topic, msg).await.unwrap();
     }
     
     // Check health after producing
-    let (segment_count, total_size, low, high, log_end, _) = 
-        engine.lock().await.get_partition_health(topic, partition).await.unwrap();
+    let health = engine.get_partition_health(topic, partition).await.unwrap();
+    let (segment_count, total_size, low, high, log_end) = (
+        health.segment_count,
+        health.total_size_bytes,
+        health.low_watermark,
+        health.high_watermark,
+        health.log_end_offset,
+    );
     
     assert_eq!(segment_count, 1); // Still 1 segment (not rotated)
     assert!(total_size > 100 * 1024); // Should be > 100KB (includes metadata)
@@ -138,7 +149,7 @@ async fn test_partition_health() {
 #[tokio::test]
 async fn test_consumer_lag_multiple_topics() {
     let base_dir = folder_to_use();
-    let mut engine = LogEngine::load(&base_dir).await;
+    let engine = LogEngine::load(&base_dir).await;
     
     let topic1 = "events";
     let topic2 = "logs";